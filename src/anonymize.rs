@@ -0,0 +1,247 @@
+use std::error;
+use std::fs;
+use std::path::PathBuf;
+
+use hmac::{Hmac, Mac};
+use regex::{Captures, Regex};
+use serde_json::{Map, Value};
+use sha2::Sha256;
+
+use crate::entity::Entity;
+use crate::format::{self, Format};
+use crate::lock;
+use crate::redact;
+use crate::snapshot;
+
+/// Hex digits of the HMAC kept in a pseudonym.
+const PSEUDONYM_LEN: usize = 12;
+/// Pseudonymous ids keep 53 bits, which JSON numbers hold exactly.
+const ID_MASK: u64 = (1 << 53) - 1;
+
+#[derive(Debug)]
+pub struct AnonymizeConfig {
+    /// Output directory of a fetch, or the directory of one repository.
+    pub input: PathBuf,
+    pub output: PathBuf,
+    /// Secret the pseudonyms are derived from, the same key gives the same
+    /// pseudonyms across runs.
+    pub key: String,
+    /// Drop the bodies of issues and pull requests altogether.
+    pub drop_bodies: bool,
+    pub format: Format,
+}
+
+/// Replaces logins, names, emails and user ids with pseudonyms derived from
+/// a key.
+pub struct Anonymizer {
+    mac: Hmac<Sha256>,
+    email: Regex,
+    drop_bodies: bool,
+}
+
+impl Anonymizer {
+    pub fn new(key: &str, drop_bodies: bool) -> Result<Self, Box<dyn error::Error>> {
+        Ok(Anonymizer {
+            mac: Hmac::<Sha256>::new_varkey(key.as_bytes()).map_err(|_| "invalid anonymization key")?,
+            email: Regex::new(redact::EMAIL).unwrap(),
+            drop_bodies: drop_bodies,
+        })
+    }
+
+    fn digest(&self, prefix: &str, value: &str) -> Vec<u8> {
+        let mut mac = self.mac.clone();
+        mac.input(prefix.as_bytes());
+        mac.input(b":");
+        mac.input(value.as_bytes());
+        mac.result().code().to_vec()
+    }
+
+    /// Stable pseudonym of `value` of the kind `prefix`, e.g. `user-1f3a…`.
+    pub fn pseudonym(&self, prefix: &str, value: &str) -> String {
+        let hex: String = self.digest(prefix, value).iter().map(|b| format!("{:02x}", b)).collect();
+        format!("{}-{}", prefix, &hex[..PSEUDONYM_LEN])
+    }
+
+    /// Stable pseudonymous number for the id `id` of the kind `prefix`, so
+    /// that ids can't be looked up through the API.
+    pub fn pseudonymous_id(&self, prefix: &str, id: u64) -> u64 {
+        let digest = self.digest(prefix, &id.to_string());
+        digest[..8].iter().fold(0, |id, b| id << 8 | u64::from(*b)) & ID_MASK
+    }
+
+    /// Anonymizes a record in place: users get pseudonymous logins, names,
+    /// emails and ids and lose their avatars and profile URLs, mentions and
+    /// emails in titles and bodies are replaced by the pseudonyms, and bodies
+    /// are dropped if asked to.
+    pub fn anonymize(&self, value: &mut Value) {
+        match *value {
+            Value::Object(ref mut fields) => {
+                if fields.contains_key("login") {
+                    self.anonymize_user(fields);
+                }
+                for (key, field) in fields.iter_mut() {
+                    match (&key[..], field) {
+                        ("body", field) | ("bodyText", field) if self.drop_bodies => *field = Value::Null,
+                        ("body", &mut Value::String(ref mut body)) | ("bodyText", &mut Value::String(ref mut body)) => {
+                            *body = self.anonymize_text(body)
+                        }
+                        ("title", &mut Value::String(ref mut title)) => *title = self.anonymize_text(title),
+                        (_, field) => self.anonymize(field),
+                    }
+                }
+            }
+            Value::Array(ref mut items) => for item in items {
+                self.anonymize(item);
+            },
+            _ => {}
+        }
+    }
+
+    fn anonymize_user(&self, fields: &mut Map<String, Value>) {
+        for (key, field) in fields.iter_mut() {
+            // the ids of users identify them as well as their logins
+            if let ("id", Some(id)) | ("databaseId", Some(id)) = (&key[..], field.as_u64()) {
+                *field = Value::from(self.pseudonymous_id("id", id));
+                continue;
+            }
+            let text = match *field {
+                Value::String(ref text) => text.clone(),
+                _ => continue,
+            };
+            match &key[..] {
+                "login" => *field = Value::from(self.pseudonym("user", &text)),
+                "name" => *field = Value::from(self.pseudonym("name", &text)),
+                "email" => *field = Value::from(self.pseudonymous_email(&text)),
+                "node_id" | "id" => *field = Value::from(self.pseudonym("node", &text)),
+                // strings rather than nulls keep records of the REST types valid
+                "gravatar_id" => *field = Value::from(""),
+                key if key.ends_with("url") || key.ends_with("Url") => *field = Value::from(""),
+                _ => {}
+            }
+        }
+    }
+
+    fn pseudonymous_email(&self, email: &str) -> String {
+        format!("{}@example.invalid", self.pseudonym("email", &email.to_lowercase()))
+    }
+
+    /// Replaces emails and then `@login` mentions with their pseudonyms.
+    fn anonymize_text(&self, text: &str) -> String {
+        let text = self
+            .email
+            .replace_all(text, |captures: &Captures| self.pseudonymous_email(&captures[0]));
+        self.anonymize_mentions(&text)
+    }
+
+    /// Replaces `@login` mentions with the pseudonyms of the logins.
+    fn anonymize_mentions(&self, text: &str) -> String {
+        let mut out = String::with_capacity(text.len());
+        let mut rest = text;
+        while let Some(at) = rest.find('@') {
+            out.push_str(&rest[..at]);
+            let after = &rest[at + 1..];
+            let len = after.find(|c: char| !(c.is_ascii_alphanumeric() || c == '-')).unwrap_or(after.len());
+            // an @ within a word is part of an email rather than a mention
+            let in_word = out.chars().next_back().map_or(false, |c| c.is_alphanumeric());
+            if len > 0 && !in_word {
+                out.push('@');
+                out.push_str(&self.pseudonym("user", &after[..len]));
+            } else {
+                out.push_str(&rest[at..at + 1 + len]);
+            }
+            rest = &after[len..];
+        }
+        out.push_str(rest);
+        out
+    }
+}
+
+/// Writes an anonymized copy of the snapshots below `cfg.input`.
+pub fn anonymize(cfg: AnonymizeConfig) -> Result<(), Box<dyn error::Error>> {
+    let anonymizer = Anonymizer::new(&cfg.key, cfg.drop_bodies)?;
+    let repos = snapshot::repositories(&cfg.input)?;
+    if repos.is_empty() {
+        return Err(format!("no snapshots in {}", cfg.input.display()).into());
+    }
+    let _lock = lock::lock(&cfg.output, false)?;
+    for (repo, dir) in &repos {
+        let out = repo.dir(&cfg.output);
+        fs::create_dir_all(&out)?;
+        let deduped = snapshot::find_named(dir, "users").is_some();
+        for entity in Entity::ALL {
+            if let Some((path, format)) = snapshot::find(dir, *entity) {
+                let mut records = snapshot::records(&path, format, *entity)?;
+                for record in &mut records {
+                    anonymizer.anonymize(record);
+                }
+                let path = out.join(format!("{}.{}", entity.name(), cfg.format.extension()));
+                snapshot::write(&records, *entity, !deduped, cfg.format, &path)?;
+                info!("{}: anonymized {} {}", repo, records.len(), entity.name());
+            }
+        }
+        if let Some((path, format)) = snapshot::find_named(dir, "users") {
+            let mut users: Vec<Value> = format::deserialize_from_file(format, &path)?;
+            for user in &mut users {
+                anonymizer.anonymize(user);
+            }
+            format::serialize_to_file(&users, cfg.format, &out.join(format!("users.{}", cfg.format.extension())))?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Anonymizer;
+
+    fn anonymizer() -> Anonymizer {
+        Anonymizer::new("key", false).unwrap()
+    }
+
+    #[test]
+    fn mentions_are_replaced() {
+        let anonymizer = anonymizer();
+        let octocat = anonymizer.pseudonym("user", "octocat");
+        assert_eq!(
+            anonymizer.anonymize_mentions("cc @octocat, thanks @octocat!"),
+            format!("cc @{}, thanks @{}!", octocat, octocat)
+        );
+        assert_eq!(
+            anonymizer.anonymize_mentions("@mona-lisa"),
+            format!("@{}", anonymizer.pseudonym("user", "mona-lisa"))
+        );
+    }
+
+    #[test]
+    fn ats_that_are_no_mentions_are_kept() {
+        let anonymizer = anonymizer();
+        for text in &["me@example.com", "a lone @ sign", "trailing @", "@@", "x@y"] {
+            assert_eq!(anonymizer.anonymize_mentions(text), *text);
+        }
+    }
+
+    #[test]
+    fn pseudonyms_depend_on_the_key() {
+        let other = Anonymizer::new("other key", false).unwrap();
+        assert_eq!(anonymizer().pseudonym("user", "octocat"), anonymizer().pseudonym("user", "octocat"));
+        assert_ne!(anonymizer().pseudonym("user", "octocat"), other.pseudonym("user", "octocat"));
+        assert!(anonymizer().pseudonym("user", "octocat").starts_with("user-"));
+    }
+
+    #[test]
+    fn titles_and_bodies_are_anonymized() {
+        let anonymizer = anonymizer();
+        let mut record = json!({
+            "title": "Ask @octocat",
+            "body": "Mail me@example.com",
+            "user": { "login": "octocat", "id": 1, "avatar_url": "https://avatars/1" },
+        });
+        anonymizer.anonymize(&mut record);
+        let octocat = anonymizer.pseudonym("user", "octocat");
+        assert_eq!(record["title"], json!(format!("Ask @{}", octocat)));
+        assert_eq!(record["body"], json!(format!("Mail {}", anonymizer.pseudonymous_email("me@example.com"))));
+        assert_eq!(record["user"]["login"], json!(octocat));
+        assert_eq!(record["user"]["id"], json!(anonymizer.pseudonymous_id("id", 1)));
+        assert_eq!(record["user"]["avatar_url"], json!(""));
+    }
+}
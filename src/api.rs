@@ -0,0 +1,121 @@
+use serde_json::Value;
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct User {
+    pub login: String,
+    pub id: u64,
+    pub avatar_url: String,
+    pub gravatar_id: String,
+    pub url: String,
+    pub html_url: String,
+    pub followers_url: String,
+    pub following_url: String,
+    pub gists_url: String,
+    pub starred_url: String,
+    pub subscriptions_url: String,
+    pub organizations_url: String,
+    pub repos_url: String,
+    pub events_url: String,
+    pub received_events_url: String,
+    pub site_admin: bool,
+    /// Global node id, shared with the GraphQL API.
+    #[serde(default)]
+    pub node_id: Option<String>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Label {
+    pub url: String,
+    pub name: String,
+    pub color: String,
+}
+
+/// Links of an issue that is a pull request.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct PullRef {
+    pub url: String,
+    pub html_url: String,
+    pub diff_url: String,
+    pub patch_url: String,
+}
+
+/// An issue or pull request of the issues listing.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Issue {
+    pub id: u64,
+    pub url: String,
+    pub repository_url: String,
+    pub labels_url: String,
+    pub comments_url: String,
+    pub events_url: String,
+    pub html_url: String,
+    pub number: u64,
+    pub state: String,
+    pub title: String,
+    pub body: Option<String>,
+    pub user: User,
+    pub labels: Vec<Label>,
+    pub assignee: Option<User>,
+    pub locked: bool,
+    pub comments: u64,
+    pub pull_request: Option<PullRef>,
+    pub closed_at: Option<String>,
+    pub created_at: String,
+    pub updated_at: String,
+    pub assignees: Vec<User>,
+    #[serde(default)]
+    pub node_id: Option<String>,
+}
+
+/// Head or base of a pull request.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Commit {
+    pub label: String,
+    #[serde(rename = "ref")]
+    pub commit_ref: String,
+    pub sha: String,
+    pub user: User,
+    /// The repository, `None` once a fork was deleted.
+    pub repo: Option<Value>,
+}
+
+/// Details of a pull request.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Pull {
+    pub id: u64,
+    pub url: String,
+    pub html_url: String,
+    pub diff_url: String,
+    pub patch_url: String,
+    pub issue_url: String,
+    pub commits_url: String,
+    pub review_comments_url: String,
+    pub review_comment_url: String,
+    pub comments_url: String,
+    pub statuses_url: String,
+    pub number: u64,
+    pub state: String,
+    pub title: String,
+    pub body: Option<String>,
+    pub created_at: String,
+    pub updated_at: String,
+    pub closed_at: Option<String>,
+    pub merged_at: Option<String>,
+    pub head: Commit,
+    pub base: Commit,
+    pub user: User,
+    pub assignee: Option<User>,
+    pub assignees: Vec<User>,
+    pub merge_commit_sha: Option<String>,
+    pub merged: Option<bool>,
+    pub mergeable: Option<bool>,
+    pub merged_by: Option<User>,
+    pub comments: Option<u64>,
+    pub commits: Option<u64>,
+    pub additions: Option<u64>,
+    pub deletions: Option<u64>,
+    pub changed_files: Option<u64>,
+    pub requested_reviewers: Option<Vec<User>>,
+    #[serde(default)]
+    pub node_id: Option<String>,
+}
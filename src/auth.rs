@@ -0,0 +1,143 @@
+use std::sync::{Arc, Mutex};
+
+use chrono::{DateTime, Duration, FixedOffset, Utc};
+use jsonwebtoken::{self, Algorithm, EncodingKey, Header};
+
+/// How a client authenticates its requests.
+#[derive(Clone, Debug)]
+pub enum Credentials {
+    /// A personal access or OAuth token.
+    Token(String),
+    /// Tokens of several accounts used in turn.
+    Tokens(TokenPool),
+    /// A GitHub App installation, whose tokens are valid for an hour.
+    App(AppCredentials),
+    /// No authentication, public data only with a quota of 60 requests an
+    /// hour.
+    Anonymous,
+}
+
+/// Tokens used one at a time, switching to the one with the most quota left
+/// once the current one has used up half of its own. Shared by the clients of
+/// all workers.
+#[derive(Clone, Debug)]
+pub struct TokenPool {
+    state: Arc<Mutex<PoolState>>,
+}
+
+#[derive(Debug)]
+struct PoolState {
+    tokens: Vec<String>,
+    /// Quota left per token, unknown until it was used.
+    remaining: Vec<Option<u64>>,
+    current: usize,
+}
+
+impl TokenPool {
+    pub fn new(tokens: Vec<String>) -> Self {
+        TokenPool {
+            state: Arc::new(Mutex::new(PoolState {
+                remaining: vec![None; tokens.len()],
+                tokens: tokens,
+                current: 0,
+            })),
+        }
+    }
+
+    pub fn current(&self) -> String {
+        let state = self.state.lock().unwrap();
+        state.tokens[state.current].clone()
+    }
+
+    /// Records the quota left for `token`. Returns the position of the token
+    /// switched to if the current one is running low.
+    pub fn update(&self, token: &str, limit: u64, remaining: u64) -> Option<usize> {
+        let mut state = self.state.lock().unwrap();
+        let index = state.tokens.iter().position(|t| t == token)?;
+        state.remaining[index] = Some(remaining);
+        if index != state.current || remaining * 2 >= limit {
+            return None;
+        }
+        // unused tokens are assumed to have their whole quota
+        let best = (0..state.tokens.len()).max_by_key(|&i| state.remaining[i].unwrap_or(limit))?;
+        if state.remaining[best].unwrap_or(limit) > remaining {
+            state.current = best;
+            Some(best)
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct AppCredentials {
+    pub app_id: u64,
+    pub installation_id: u64,
+    /// PEM encoded private key of the app.
+    private_key: Vec<u8>,
+    /// Current installation token, shared by the clients of all workers.
+    token: Arc<Mutex<Option<InstallationToken>>>,
+}
+
+/// An installation access token as returned by GitHub.
+#[derive(Clone, Debug, Deserialize)]
+pub struct InstallationToken {
+    pub token: String,
+    pub expires_at: String,
+}
+
+impl InstallationToken {
+    fn expires(&self) -> Option<DateTime<FixedOffset>> {
+        DateTime::parse_from_rfc3339(&self.expires_at).ok()
+    }
+}
+
+#[derive(Serialize)]
+struct Claims {
+    iat: i64,
+    exp: i64,
+    iss: String,
+}
+
+impl AppCredentials {
+    pub fn new(app_id: u64, installation_id: u64, private_key: Vec<u8>) -> Self {
+        AppCredentials {
+            app_id: app_id,
+            installation_id: installation_id,
+            private_key: private_key,
+            token: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    /// The installation token, unless there is none yet or it expires within
+    /// the next minute.
+    pub fn current(&self) -> Option<String> {
+        let deadline = Utc::now() + Duration::minutes(1);
+        self.token
+            .lock()
+            .unwrap()
+            .as_ref()
+            .filter(|token| token.expires().map_or(false, |expires| expires > deadline))
+            .map(|token| token.token.clone())
+    }
+
+    pub fn store(&self, token: InstallationToken) {
+        *self.token.lock().unwrap() = Some(token);
+    }
+
+    /// A JWT identifying the app for ten minutes, issued a minute in the past
+    /// to allow for clock drift.
+    pub fn jwt(&self) -> Result<String, jsonwebtoken::errors::Error> {
+        let now = Utc::now().timestamp();
+        let claims = Claims {
+            iat: now - 60,
+            exp: now + 9 * 60,
+            iss: self.app_id.to_string(),
+        };
+        jsonwebtoken::encode(
+            &Header::new(Algorithm::RS256),
+            &claims,
+            &EncodingKey::from_rsa_pem(&self.private_key)?,
+        )
+    }
+}
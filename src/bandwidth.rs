@@ -0,0 +1,48 @@
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Download rate limit shared by the clients of all workers.
+#[derive(Clone, Debug)]
+pub struct Bandwidth {
+    bytes_per_sec: u64,
+    /// When the bytes received so far are paid off.
+    next: Arc<Mutex<Instant>>,
+}
+
+impl Bandwidth {
+    pub fn new(bytes_per_sec: u64) -> Self {
+        Bandwidth {
+            bytes_per_sec: bytes_per_sec,
+            next: Arc::new(Mutex::new(Instant::now())),
+        }
+    }
+
+    /// Accounts for `bytes` just received, returning how long to pause reading
+    /// so the rate stays below the limit.
+    pub fn consume(&self, bytes: usize) -> Duration {
+        let mut next = self.next.lock().unwrap();
+        let now = Instant::now();
+        if *next < now {
+            *next = now;
+        }
+        *next += Duration::from_nanos(bytes as u64 * 1_000_000_000 / self.bytes_per_sec);
+        *next - now
+    }
+}
+
+/// Parses a rate in bytes per second like `800k`, `2M` or `1G`, with binary
+/// multiples.
+pub fn parse(value: &str) -> Result<u64, String> {
+    let (number, multiple) = match value.chars().last() {
+        Some('k') | Some('K') => (&value[..value.len() - 1], 1024),
+        Some('m') | Some('M') => (&value[..value.len() - 1], 1024 * 1024),
+        Some('g') | Some('G') => (&value[..value.len() - 1], 1024 * 1024 * 1024),
+        _ => (value, 1),
+    };
+    number
+        .parse::<u64>()
+        .ok()
+        .filter(|&n| n > 0)
+        .and_then(|n| n.checked_mul(multiple))
+        .ok_or_else(|| format!("invalid bandwidth {}, expected e.g. 500k or 2M bytes per second", value))
+}
@@ -0,0 +1,214 @@
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use futures::try_join;
+
+use crate::client::{Client, Error};
+use crate::entity::Entity;
+use crate::filter::Filters;
+use crate::repo::Repo;
+
+/// Remaining quota of a rate limit.
+#[derive(Debug, Deserialize)]
+pub struct Quota {
+    pub limit: u64,
+    pub remaining: u64,
+    /// Unix timestamp at which the quota is replenished.
+    pub reset: u64,
+}
+
+impl Quota {
+    pub fn resets_in(&self) -> Duration {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_else(|_| Duration::from_secs(0))
+            .as_secs();
+        Duration::from_secs(self.reset.saturating_sub(now))
+    }
+}
+
+#[derive(Deserialize)]
+struct RateLimitResources {
+    core: Quota,
+    graphql: Quota,
+}
+
+#[derive(Deserialize)]
+struct RateLimitResponse {
+    resources: RateLimitResources,
+}
+
+#[derive(Deserialize)]
+struct SearchCount {
+    total_count: u64,
+}
+
+/// Queries the current quota of the REST or the GraphQL API, which does not
+/// itself count against it.
+pub async fn quota(client: &Client, graphql: bool) -> Result<Quota, Error> {
+    let response = client.get::<RateLimitResponse>("/rate_limit").await?;
+    Ok(if graphql {
+        response.resources.graphql
+    } else {
+        response.resources.core
+    })
+}
+
+/// Cap on the requests a run may send, shared by all workers.
+#[derive(Clone, Debug)]
+pub struct RequestBudget {
+    limit: usize,
+    used: Arc<AtomicUsize>,
+}
+
+impl RequestBudget {
+    pub fn new(limit: usize) -> Self {
+        RequestBudget {
+            limit: limit,
+            used: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    pub fn limit(&self) -> usize {
+        self.limit
+    }
+
+    /// Takes a request from the budget, false if none are left.
+    pub fn take(&self) -> bool {
+        self.used.fetch_add(1, Ordering::SeqCst) < self.limit
+    }
+
+    pub fn exhausted(&self) -> bool {
+        self.used.load(Ordering::SeqCst) >= self.limit
+    }
+}
+
+/// Spreads requests over the rest of the rate limit window once less than
+/// half of the quota is left, so a run slows down instead of exhausting the
+/// quota and stalling until it resets. Shared by the clients of all workers.
+#[derive(Clone, Debug, Default)]
+pub struct Pacer {
+    pace: Arc<Mutex<Pace>>,
+    /// Spread them over the whole quota from the start.
+    steady: bool,
+}
+
+#[derive(Debug, Default)]
+struct Pace {
+    /// Time between two requests, zero while the quota is plentiful.
+    interval: Duration,
+    /// Earliest time the next request may be sent.
+    next: Option<Instant>,
+}
+
+impl Pacer {
+    /// Paces requests from the first response on, for quotas as small as the
+    /// 60 requests an hour of anonymous requests.
+    pub fn steady() -> Self {
+        Pacer {
+            pace: Arc::default(),
+            steady: true,
+        }
+    }
+
+    /// Adjusts the pace to the quota reported by a response. Returns the new
+    /// interval if pacing just started.
+    pub fn update(&self, limit: u64, remaining: u64, reset_in: Duration) -> Option<Duration> {
+        let mut pace = self.pace.lock().unwrap();
+        let was_pacing = pace.interval > Duration::from_secs(0);
+        pace.interval = if self.steady || remaining * 2 < limit {
+            reset_in / remaining.max(1) as u32
+        } else {
+            Duration::from_secs(0)
+        };
+        if !was_pacing && pace.interval > Duration::from_secs(0) {
+            Some(pace.interval)
+        } else {
+            None
+        }
+    }
+
+    /// Reserves the next slot for a request, returning how long to wait for it.
+    pub fn delay(&self) -> Duration {
+        let mut pace = self.pace.lock().unwrap();
+        let now = Instant::now();
+        let slot = match pace.next {
+            Some(next) if next > now => next,
+            _ => now,
+        };
+        pace.next = Some(slot + pace.interval);
+        slot - now
+    }
+}
+
+/// Rough size of an issue and of a pull request in a snapshot, in bytes.
+const ISSUE_BYTES: u64 = 4 * 1024;
+const PULL_BYTES: u64 = 8 * 1024;
+
+/// Number of issues and pull requests a fetch is going to touch.
+#[derive(Debug)]
+pub struct Estimate {
+    pub open_issues: u64,
+    pub closed_issues: u64,
+    pub pulls: u64,
+}
+
+impl Estimate {
+    /// Issues and pull requests in the issues listing.
+    pub fn items(&self) -> u64 {
+        self.open_issues + self.closed_issues + self.pulls
+    }
+
+    /// Issues or pull requests, by `entity`.
+    pub fn count(&self, entity: Entity) -> u64 {
+        match entity {
+            Entity::Issues => self.open_issues + self.closed_issues,
+            Entity::Pulls => self.pulls,
+        }
+    }
+
+    /// Requests needed for the listing pages of `per_page` items and the pull
+    /// request details.
+    pub fn requests(&self, per_page: u64) -> u64 {
+        (self.items() + per_page - 1) / per_page + self.pulls
+    }
+
+    /// Expected run time, with listing pages fetched one after another, pull
+    /// requests at `rate` per second and waits for the quota to reset.
+    pub fn duration(&self, per_page: u64, rate: u64, quota: &Quota) -> Duration {
+        let pages = self.requests(per_page) - self.pulls;
+        let mut secs = pages + (self.pulls + rate - 1) / rate;
+        let requests = self.requests(per_page);
+        if requests > quota.remaining && quota.limit > 0 {
+            let windows = (requests - quota.remaining - 1) / quota.limit;
+            secs += quota.resets_in().as_secs() + windows * 3600;
+        }
+        Duration::from_secs(secs)
+    }
+
+    /// Approximate size of the written snapshots.
+    pub fn disk_bytes(&self) -> u64 {
+        (self.open_issues + self.closed_issues) * ISSUE_BYTES + self.pulls * (ISSUE_BYTES + PULL_BYTES)
+    }
+}
+
+/// Counts the issues and pull requests to fetch through the search API, which
+/// has a quota of its own.
+pub async fn estimate(client: &Client, repo: &Repo, since: Option<&str>, filters: &Filters) -> Result<Estimate, Error> {
+    let mut query = format!("repo:{}{}", repo, filters.search_qualifiers());
+    if let Some(since) = since {
+        query.push_str(&format!("+updated:%3E%3D{}", since.replace('+', "%2B")));
+    }
+    let count = |qualifiers: &'static str| {
+        let path = format!("/search/issues?q={}+{}&per_page=1", query, qualifiers);
+        async move { Ok::<_, Error>(client.get::<SearchCount>(&path).await?.total_count) }
+    };
+    let (open_issues, closed_issues, pulls) =
+        try_join!(count("is:issue+is:open"), count("is:issue+is:closed"), count("is:pr"))?;
+    Ok(Estimate {
+        open_issues: open_issues,
+        closed_issues: closed_issues,
+        pulls: pulls,
+    })
+}
@@ -0,0 +1,214 @@
+use std::collections::BTreeMap;
+use std::error;
+use std::fs;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use chrono::{Duration, NaiveDate, Utc};
+
+use crate::snapshot::{self, Item};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SeriesFormat {
+    Csv,
+    Parquet,
+}
+
+impl FromStr for SeriesFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "csv" => Ok(SeriesFormat::Csv),
+            "parquet" => Ok(SeriesFormat::Parquet),
+            _ => Err(format!("unknown series format: {}", s)),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct BurndownConfig {
+    /// Output directory of a fetch, or the directory of one repository.
+    pub dir: PathBuf,
+    /// File to write the series to.
+    pub output: PathBuf,
+    pub format: SeriesFormat,
+    /// Labels to count the open items of separately.
+    pub labels: Vec<String>,
+}
+
+/// Open items at the end of a day.
+#[derive(Debug, PartialEq)]
+pub struct Day {
+    pub date: NaiveDate,
+    pub open_issues: u64,
+    pub open_pulls: u64,
+    /// Open issues and pull requests with each of the labels asked for.
+    pub labels: Vec<u64>,
+}
+
+/// Daily counts of the open `items` from the first opening to today, or the
+/// last closing if all are closed. Snapshots only have the current labels,
+/// so the backlog of a label counts the items that have it now.
+pub fn series(items: &[Item], labels: &[String]) -> Vec<Day> {
+    // changes of the counts by day, issues, pulls and labels
+    let mut deltas: BTreeMap<NaiveDate, Vec<i64>> = BTreeMap::new();
+    let mut all_closed = true;
+    for item in items {
+        let opened = match item.created_at {
+            Some(created) => created.naive_utc().date(),
+            None => continue,
+        };
+        let mut counts = vec![0; 2 + labels.len()];
+        counts[if item.pull { 1 } else { 0 }] = 1;
+        for (i, label) in labels.iter().enumerate() {
+            if item.labels.contains(label) {
+                counts[2 + i] = 1;
+            }
+        }
+        add(&mut deltas, opened, &counts, 1);
+        match item.merged_at.or(item.closed_at) {
+            Some(closed) => add(&mut deltas, closed.naive_utc().date(), &counts, -1),
+            None => all_closed = false,
+        }
+    }
+    let (first, last) = match (deltas.keys().next(), deltas.keys().next_back()) {
+        (Some(first), Some(last)) => (*first, if all_closed { *last } else { Utc::now().naive_utc().date() }),
+        _ => return Vec::new(),
+    };
+
+    let mut days = Vec::new();
+    let mut open = vec![0i64; 2 + labels.len()];
+    let mut date = first;
+    while date <= last {
+        if let Some(delta) = deltas.get(&date) {
+            for (count, delta) in open.iter_mut().zip(delta) {
+                *count += delta;
+            }
+        }
+        days.push(Day {
+            date: date,
+            open_issues: open[0].max(0) as u64,
+            open_pulls: open[1].max(0) as u64,
+            labels: open[2..].iter().map(|count| (*count).max(0) as u64).collect(),
+        });
+        date = date + Duration::days(1);
+    }
+    days
+}
+
+fn add(deltas: &mut BTreeMap<NaiveDate, Vec<i64>>, date: NaiveDate, counts: &[i64], sign: i64) {
+    let delta = deltas.entry(date).or_insert_with(|| vec![0; counts.len()]);
+    for (delta, count) in delta.iter_mut().zip(counts) {
+        *delta += sign * count;
+    }
+}
+
+/// Writes the daily backlog of the snapshots below `cfg.dir`, across all
+/// their repositories.
+pub fn burndown(cfg: BurndownConfig) -> Result<(), Box<dyn error::Error>> {
+    let repos = snapshot::repositories(&cfg.dir)?;
+    if repos.is_empty() {
+        return Err(format!("no snapshots in {}", cfg.dir.display()).into());
+    }
+    let mut items = Vec::new();
+    for (_, dir) in &repos {
+        items.extend(snapshot::items(dir)?);
+    }
+    let days = series(&items, &cfg.labels);
+    match cfg.format {
+        SeriesFormat::Csv => write_csv(&days, &cfg.labels, &cfg.output)?,
+        SeriesFormat::Parquet => columnar::write(&days, &cfg.labels, &cfg.output)?,
+    }
+    info!("Wrote {} days to {}", days.len(), cfg.output.display());
+    Ok(())
+}
+
+/// Names of the columns after the date.
+fn columns(labels: &[String]) -> Vec<String> {
+    let mut columns = vec!["open_issues".to_string(), "open_pulls".to_string()];
+    columns.extend(labels.iter().map(|label| format!("open_{}", label)));
+    columns
+}
+
+fn write_csv(days: &[Day], labels: &[String], path: &Path) -> Result<(), Box<dyn error::Error>> {
+    let field = |text: &str| {
+        if text.contains(|c| c == ',' || c == '"' || c == '\n') {
+            format!("\"{}\"", text.replace('"', "\"\""))
+        } else {
+            text.to_string()
+        }
+    };
+    let mut out = BufWriter::new(fs::File::create(path)?);
+    let header: Vec<String> = columns(labels).iter().map(|column| field(column)).collect();
+    writeln!(out, "date,{}", header.join(","))?;
+    for day in days {
+        let counts: Vec<String> = [day.open_issues, day.open_pulls]
+            .iter()
+            .chain(&day.labels)
+            .map(|count| count.to_string())
+            .collect();
+        writeln!(out, "{},{}", day.date.format("%Y-%m-%d"), counts.join(","))?;
+    }
+    out.flush()?;
+    Ok(())
+}
+
+#[cfg(feature = "parquet")]
+mod columnar {
+    use std::error;
+    use std::fs;
+    use std::path::Path;
+    use std::sync::Arc;
+
+    use arrow::array::{ArrayRef, Date32Array, UInt64Array};
+    use arrow::datatypes::{DataType, Field, Schema};
+    use arrow::record_batch::RecordBatch;
+    use chrono::NaiveDate;
+    use parquet::arrow::ArrowWriter;
+
+    use super::{columns, Day};
+
+    /// Writes the days as a Parquet file of a date column and a count column
+    /// each.
+    pub fn write(days: &[Day], labels: &[String], path: &Path) -> Result<(), Box<dyn error::Error>> {
+        let epoch = NaiveDate::from_ymd_opt(1970, 1, 1).unwrap();
+        let mut fields = vec![Field::new("date", DataType::Date32, false)];
+        let mut arrays: Vec<ArrayRef> = vec![Arc::new(Date32Array::from(
+            days.iter()
+                .map(|day| day.date.signed_duration_since(epoch).num_days() as i32)
+                .collect::<Vec<_>>(),
+        ))];
+        for (i, column) in columns(labels).into_iter().enumerate() {
+            let counts: Vec<u64> = days
+                .iter()
+                .map(|day| match i {
+                    0 => day.open_issues,
+                    1 => day.open_pulls,
+                    i => day.labels[i - 2],
+                })
+                .collect();
+            fields.push(Field::new(&column, DataType::UInt64, false));
+            arrays.push(Arc::new(UInt64Array::from(counts)));
+        }
+        let schema = Arc::new(Schema::new(fields));
+        let batch = RecordBatch::try_new(schema.clone(), arrays)?;
+        let mut writer = ArrowWriter::try_new(fs::File::create(path)?, schema, None)?;
+        writer.write(&batch)?;
+        writer.close()?;
+        Ok(())
+    }
+}
+
+#[cfg(not(feature = "parquet"))]
+mod columnar {
+    use std::error;
+    use std::path::Path;
+
+    use super::Day;
+
+    pub fn write(_days: &[Day], _labels: &[String], _path: &Path) -> Result<(), Box<dyn error::Error>> {
+        Err("--format parquet needs a build with the parquet feature".into())
+    }
+}
@@ -0,0 +1,97 @@
+use std::error;
+use std::time::Duration;
+
+use hyper::StatusCode;
+use serde_json::Value;
+use stream_throttle::{ThrottlePool, ThrottleRate};
+
+use github_data_fetch::auth::Credentials;
+use github_data_fetch::budget;
+use github_data_fetch::client::{Client, ClientConfig, Error};
+use github_data_fetch::exit::{ExitCode, Failed};
+use github_data_fetch::repo::Repo;
+
+#[derive(Debug)]
+pub struct CheckConfig {
+    pub client: ClientConfig,
+    /// Repositories to check access to.
+    pub repos: Vec<Repo>,
+}
+
+#[derive(Deserialize)]
+struct User {
+    login: String,
+}
+
+#[derive(Deserialize)]
+struct RepoAccess {
+    private: bool,
+    permissions: Option<Permissions>,
+}
+
+#[derive(Deserialize)]
+struct Permissions {
+    admin: bool,
+    push: bool,
+}
+
+/// Prints whether the credentials are accepted, the scopes of a token, the
+/// remaining quota and whether each repository can be read. Fails with the
+/// exit code for authentication failures if anything is inaccessible.
+pub async fn check(cfg: CheckConfig) -> Result<(), Box<dyn error::Error>> {
+    let throttle = ThrottlePool::new(ThrottleRate::new(5, Duration::from_secs(1)));
+    let client = Client::new(&cfg.client, throttle)?;
+
+    match cfg.client.credentials {
+        Credentials::Anonymous => println!("Credentials:  none, public data only"),
+        Credentials::App(_) => {
+            // only succeeds with a valid installation token
+            client.get::<Value>("/installation/repositories?per_page=1").await?;
+            println!("Credentials:  valid GitHub App installation");
+        }
+        _ => {
+            let (user, headers) = client.get_with_headers::<User>("/user").await?;
+            println!("Credentials:  valid token of {}", user.login);
+            match headers.get("x-oauth-scopes").and_then(|scopes| scopes.to_str().ok()) {
+                Some("") => println!("Scopes:       none"),
+                Some(scopes) => println!("Scopes:       {}", scopes),
+                None => println!("Scopes:       not reported, e.g. for fine-grained tokens"),
+            }
+        }
+    }
+
+    let quota = budget::quota(&client, false).await?;
+    println!(
+        "Rate limit:   {} of {} remaining, resets in {}s",
+        quota.remaining,
+        quota.limit,
+        quota.resets_in().as_secs()
+    );
+
+    let mut inaccessible = 0;
+    for repo in &cfg.repos {
+        match client.get::<RepoAccess>(&format!("/repos/{}", repo)).await {
+            Ok(access) => {
+                let permission = match access.permissions {
+                    Some(ref p) if p.admin => ", admin",
+                    Some(ref p) if p.push => ", push",
+                    _ => "",
+                };
+                let visibility = if access.private { "private" } else { "public" };
+                println!("{}: accessible, {}{}", repo, visibility, permission);
+            }
+            Err(Error::Status { status, .. }) if status == StatusCode::NOT_FOUND => {
+                println!("{}: not found or not accessible", repo);
+                inaccessible += 1;
+            }
+            Err(e) => return Err(e.into()),
+        }
+    }
+    if inaccessible > 0 {
+        return Err(Box::new(Failed {
+            code: ExitCode::Auth,
+            message: format!("{} of {} repositories are not accessible", inaccessible, cfg.repos.len()),
+        }));
+    }
+    Ok(())
+}
@@ -0,0 +1,1109 @@
+use std::error;
+use std::fmt;
+use std::fs;
+use std::future::Future;
+use std::io;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use futures::future::{BoxFuture, FutureExt, TryFutureExt};
+use futures::stream::{self, BoxStream, StreamExt, TryStreamExt};
+use http::response::Parts;
+use hyper::body::{Bytes, HttpBody};
+use hyper::client::connect::Connect;
+use hyper::client::HttpConnector;
+use hyper::header::HeaderValue;
+use hyper::header::{
+    ACCEPT, AUTHORIZATION, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED, LINK, RETRY_AFTER, USER_AGENT,
+};
+use hyper::{self, Body, HeaderMap, Request, Response, StatusCode};
+use hyper_proxy::ProxyConnector;
+use hyper_tls::HttpsConnector;
+use native_tls::TlsConnector;
+use serde::de::DeserializeOwned;
+use serde_json::{self, Value};
+use stream_throttle::ThrottlePool;
+use tokio::time;
+use tracing::{debug_span, field, Instrument, Span};
+
+use crate::auth::{AppCredentials, Credentials, InstallationToken};
+use crate::bandwidth::Bandwidth;
+use crate::budget::{Pacer, RequestBudget};
+use crate::dashboard::Dashboard;
+use crate::interrupt;
+use crate::proxy;
+use crate::retry::RetryPolicy;
+use crate::state::{CachedResponse, SyncDb};
+use crate::vcr::{Cassette, Mode};
+
+pub const API_URL: &str = "https://api.github.com";
+/// Idle connections kept per host by default, enough for the pulls fetched
+/// at the same time by a few workers.
+pub const POOL_SIZE: usize = 16;
+/// Idle connections are closed after this long.
+const POOL_IDLE_TIMEOUT: Duration = Duration::from_secs(90);
+/// Interval of TCP and HTTP/2 keep-alive probes on open connections.
+const KEEPALIVE: Duration = Duration::from_secs(30);
+/// Times a request waits out the secondary rate limit, one that is hit again
+/// and again is not lifted by waiting.
+const MAX_SECONDARY_WAITS: u32 = 3;
+
+#[derive(Debug)]
+pub enum Error {
+    /// The primary rate limit is exhausted until `reset` has passed.
+    RateLimit { reset: Duration },
+    /// A secondary (abuse) rate limit was hit, requests may be sent again
+    /// after `retry_after`.
+    SecondaryRateLimit { retry_after: Duration },
+    Status {
+        status: StatusCode,
+        url: String,
+        body: String,
+    },
+    Http(hyper::Error),
+    /// No complete response arrived within the request timeout.
+    Timeout(Duration),
+    Request(http::Error),
+    Json(serde_json::Error),
+    /// A GraphQL query was answered with errors.
+    GraphQl(String),
+    /// No installation token could be created for a GitHub App.
+    Auth(String),
+    /// The run has sent as many requests as `--max-requests` allows.
+    BudgetExhausted { limit: usize },
+    /// The run was interrupted, no further requests are sent.
+    Interrupted,
+    Io(io::Error),
+    /// Reading or writing the ETag cache failed.
+    Cache(rusqlite::Error),
+    /// A replayed request has no response on the cassette.
+    NotRecorded { method: String, url: String },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::RateLimit { reset } => write!(f, "rate limit exceeded, resets in {}s", reset.as_secs()),
+            Error::SecondaryRateLimit { retry_after } => {
+                write!(f, "secondary rate limit exceeded, retry after {}s", retry_after.as_secs())
+            }
+            Error::Status { status, ref url, ref body } => write!(f, "{} for {}: {}", status, url, body),
+            Error::Http(ref e) => write!(f, "http error: {}", e),
+            Error::Timeout(timeout) => write!(f, "request timed out after {}s", timeout.as_secs()),
+            Error::Request(ref e) => write!(f, "invalid request: {}", e),
+            Error::Json(ref e) => write!(f, "invalid response: {}", e),
+            Error::GraphQl(ref e) => write!(f, "graphql error: {}", e),
+            Error::Auth(ref e) => write!(f, "authentication failed: {}", e),
+            Error::BudgetExhausted { limit } => write!(f, "request budget of {} exhausted", limit),
+            Error::Interrupted => write!(f, "interrupted"),
+            Error::Io(ref e) => write!(f, "io error: {}", e),
+            Error::Cache(ref e) => write!(f, "cache error: {}", e),
+            Error::NotRecorded { ref method, ref url } => write!(f, "no recorded response for {} {}", method, url),
+        }
+    }
+}
+
+impl error::Error for Error {}
+
+impl Error {
+    /// Whether the request may succeed when sent again, true for network
+    /// errors and server side failures.
+    fn is_transient(&self) -> bool {
+        match *self {
+            Error::Http(_) | Error::Timeout(_) => true,
+            Error::Status { status, .. } => status.is_server_error(),
+            _ => false,
+        }
+    }
+
+    /// Whether only the requested item is affected, e.g. one that is gone or
+    /// keeps failing on the server, so that further requests may well
+    /// succeed.
+    pub fn affects_item_only(&self) -> bool {
+        match *self {
+            Error::Status { status, .. } => status != StatusCode::UNAUTHORIZED && status != StatusCode::FORBIDDEN,
+            Error::Json(_) => true,
+            _ => false,
+        }
+    }
+
+    /// Whether the requested item is deleted, transferred or blocked for
+    /// legal reasons, so that requesting it again won't help.
+    pub fn is_gone(&self) -> bool {
+        match *self {
+            Error::Status { status, .. } => {
+                status == StatusCode::NOT_FOUND
+                    || status == StatusCode::GONE
+                    || status == StatusCode::UNAVAILABLE_FOR_LEGAL_REASONS
+            }
+            _ => false,
+        }
+    }
+
+    /// Short name of the error for reports, e.g. `not_found` for a 404.
+    pub fn kind(&self) -> String {
+        let kind = match *self {
+            Error::Status { status, .. } => {
+                return status
+                    .canonical_reason()
+                    .map_or_else(|| status.as_str().to_string(), |reason| reason.to_lowercase().replace(' ', "_"))
+            }
+            Error::RateLimit { .. } => "rate_limited",
+            Error::SecondaryRateLimit { .. } => "secondary_rate_limited",
+            Error::Http(_) => "http",
+            Error::Timeout(_) => "timeout",
+            Error::Request(_) => "invalid_request",
+            Error::Json(_) => "invalid_response",
+            Error::GraphQl(_) => "graphql",
+            Error::Auth(_) => "auth",
+            Error::BudgetExhausted { .. } => "budget_exhausted",
+            Error::Interrupted => "interrupted",
+            Error::Io(_) => "io",
+            Error::Cache(_) => "cache",
+            Error::NotRecorded { .. } => "not_recorded",
+        };
+        kind.to_string()
+    }
+}
+
+impl From<hyper::Error> for Error {
+    fn from(e: hyper::Error) -> Self {
+        Error::Http(e)
+    }
+}
+impl From<http::Error> for Error {
+    fn from(e: http::Error) -> Self {
+        Error::Request(e)
+    }
+}
+impl From<serde_json::Error> for Error {
+    fn from(e: serde_json::Error) -> Self {
+        Error::Json(e)
+    }
+}
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+impl From<rusqlite::Error> for Error {
+    fn from(e: rusqlite::Error) -> Self {
+        Error::Cache(e)
+    }
+}
+
+#[derive(Deserialize)]
+struct GraphQlError {
+    message: String,
+}
+
+#[derive(Deserialize)]
+struct GraphQlResponse<T> {
+    data: Option<T>,
+    errors: Option<Vec<GraphQlError>>,
+}
+
+#[derive(Debug)]
+pub struct ClientConfig {
+    pub credentials: Credentials,
+    /// Base URL of the REST API, `/api/v3` below the host for Enterprise.
+    pub api_url: String,
+    pub graphql_url: String,
+    /// Sync state database to keep the ETag cache in.
+    pub cache_db: Option<PathBuf>,
+    pub retry: RetryPolicy,
+    /// Proxy for all requests, overriding the `*_PROXY` environment variables.
+    pub proxy: Option<String>,
+    pub connect_timeout: Option<Duration>,
+    /// Limit for sending a request and reading its whole response.
+    pub request_timeout: Option<Duration>,
+    /// Idle connections kept open per host for reuse.
+    pub pool_size: usize,
+    /// Speak HTTP/2 only, negotiated over TLS.
+    pub http2: bool,
+    /// Cassette to record the responses to or replay them from.
+    pub cassette: Option<Cassette>,
+    pub budget: Option<RequestBudget>,
+    pub bandwidth: Option<Bandwidth>,
+    pub pacer: Pacer,
+    /// Dashboard to report the requests and the quota to.
+    pub dashboard: Option<Dashboard>,
+}
+
+impl ClientConfig {
+    /// GraphQL endpoint belonging to a REST API base URL, Enterprise serves it
+    /// at `/api/graphql` next to `/api/v3`.
+    pub fn graphql_url_for(api_url: &str) -> String {
+        let api_url = api_url.trim_end_matches('/');
+        if api_url.ends_with("/v3") {
+            format!("{}/graphql", api_url.trim_end_matches("/v3"))
+        } else {
+            format!("{}/graphql", api_url)
+        }
+    }
+}
+
+/// Where the requests of a client end up, the GitHub API over HTTP or a fake
+/// answering them from memory, so that pagination, retries and the rest of
+/// the fetch logic can be exercised without the network.
+pub trait GithubApi: Send + Sync {
+    /// Sends a request, returning the response with its body still to read.
+    fn request(&self, request: Request<Body>) -> BoxFuture<'static, Result<Response<Body>, Error>>;
+}
+
+impl<C> GithubApi for hyper::Client<C>
+where
+    C: Connect + Clone + Send + Sync + 'static,
+{
+    fn request(&self, request: Request<Body>) -> BoxFuture<'static, Result<Response<Body>, Error>> {
+        hyper::Client::request(self, request).map_err(Error::from).boxed()
+    }
+}
+
+/// Minimal GitHub REST and GraphQL client.
+///
+/// Responses are cached in a sync state database together with their `ETag`
+/// and `Last-Modified` headers when one is given, repeated requests
+/// are sent conditionally and answered from the cache on `304 Not Modified`,
+/// which does not count against the rate limit.
+///
+/// Clones share the connection pool, the cache and the request counts.
+#[derive(Clone)]
+pub struct Client {
+    api: Arc<dyn GithubApi>,
+    credentials: Credentials,
+    api_url: String,
+    graphql_url: String,
+    cache: Option<Arc<Mutex<SyncDb>>>,
+    cache_hits: Arc<AtomicUsize>,
+    requests: Arc<AtomicUsize>,
+    retry: RetryPolicy,
+    request_timeout: Option<Duration>,
+    budget: Option<RequestBudget>,
+    bandwidth: Option<Bandwidth>,
+    pacer: Pacer,
+    throttle: ThrottlePool,
+    cassette: Option<Cassette>,
+    dashboard: Option<Dashboard>,
+}
+
+impl Client {
+    /// Creates a client sending requests at the rate `throttle` allows, which
+    /// may be shared with other clients.
+    pub fn new(cfg: &ClientConfig, throttle: ThrottlePool) -> Result<Self, Box<dyn error::Error>> {
+        let mut http = HttpConnector::new();
+        http.enforce_http(false);
+        http.set_connect_timeout(cfg.connect_timeout);
+        http.set_keepalive(Some(KEEPALIVE));
+        http.set_nodelay(true);
+        let mut tls = TlsConnector::builder();
+        if cfg.http2 {
+            tls.request_alpns(&["h2"]);
+        }
+        let https = HttpsConnector::from((http, tokio_native_tls::TlsConnector::from(tls.build()?)));
+        let mut connector = ProxyConnector::new(https)?;
+        for proxy in proxy::proxies(cfg.proxy.as_ref().map(|p| &p[..]))? {
+            connector.add_proxy(proxy);
+        }
+        let http = hyper::Client::builder()
+            .pool_max_idle_per_host(cfg.pool_size)
+            .pool_idle_timeout(POOL_IDLE_TIMEOUT)
+            .http2_only(cfg.http2)
+            .http2_adaptive_window(true)
+            .http2_keep_alive_interval(KEEPALIVE)
+            .build(connector);
+        Client::with_api(cfg, throttle, Arc::new(http))
+    }
+
+    /// Creates a client sending its requests to `api` rather than over HTTP,
+    /// the connection settings of `cfg` are ignored.
+    pub fn with_api(
+        cfg: &ClientConfig,
+        throttle: ThrottlePool,
+        api: Arc<dyn GithubApi>,
+    ) -> Result<Self, Box<dyn error::Error>> {
+        let cache = match cfg.cache_db {
+            Some(ref path) => {
+                if let Some(dir) = path.parent() {
+                    fs::create_dir_all(dir)?;
+                }
+                Some(Arc::new(Mutex::new(SyncDb::open(path)?)))
+            }
+            None => None,
+        };
+        Ok(Client {
+            api: api,
+            credentials: cfg.credentials.clone(),
+            api_url: cfg.api_url.trim_end_matches('/').to_string(),
+            graphql_url: cfg.graphql_url.clone(),
+            cache: cache,
+            cache_hits: Arc::new(AtomicUsize::new(0)),
+            requests: Arc::new(AtomicUsize::new(0)),
+            retry: cfg.retry.clone(),
+            request_timeout: cfg.request_timeout,
+            budget: cfg.budget.clone(),
+            bandwidth: cfg.bandwidth.clone(),
+            pacer: cfg.pacer.clone(),
+            throttle: throttle,
+            cassette: cfg.cassette.clone(),
+            dashboard: cfg.dashboard.clone(),
+        })
+    }
+
+    /// Number of responses served from the ETag cache so far.
+    pub fn cache_hits(&self) -> usize {
+        self.cache_hits.load(Ordering::SeqCst)
+    }
+
+    /// Number of requests sent so far, including conditional ones.
+    pub fn requests(&self) -> usize {
+        self.requests.load(Ordering::SeqCst)
+    }
+
+    /// Request budget of the run, shared by all clones, if limited.
+    pub fn budget(&self) -> Option<RequestBudget> {
+        self.budget.clone()
+    }
+
+    fn url(&self, path: &str) -> String {
+        if path.starts_with("http") {
+            path.to_string()
+        } else {
+            format!("{}{}", self.api_url, path)
+        }
+    }
+
+    fn cache(&self, url: &str) -> Option<Arc<Mutex<SyncDb>>> {
+        // the quota changes with every request, a cached answer is useless,
+        // and a cassette keeps complete responses rather than 304s
+        if url.ends_with("/rate_limit") || self.cassette.is_some() {
+            return None;
+        }
+        self.cache.clone()
+    }
+
+    /// Fetches a single resource and deserializes it.
+    pub async fn get<T>(&self, path: &str) -> Result<T, Error>
+    where
+        T: DeserializeOwned,
+    {
+        let (item, _) = self.get_page(self.url(path)).await?;
+        Ok(item)
+    }
+
+    /// Fetches a single resource along with the response headers, bypassing
+    /// the ETag cache.
+    pub async fn get_with_headers<T>(&self, path: &str) -> Result<(T, HeaderMap), Error>
+    where
+        T: DeserializeOwned,
+    {
+        let url = self.url(path);
+        self.retrying(|| self.try_get_with_headers(url.clone())).await
+    }
+
+    /// Fetches all pages of a list resource by following `Link: rel="next"`.
+    pub fn get_pages<T>(&self, path: &str) -> BoxStream<'static, Result<T, Error>>
+    where
+        T: DeserializeOwned + Send + 'static,
+    {
+        self.pages(path)
+            .map_ok(|(items, _)| stream::iter(items.into_iter().map(Ok)))
+            .try_flatten()
+            .boxed()
+    }
+
+    /// Like `get_pages`, but yields whole pages along with the URL of the
+    /// page following them, so pagination can be resumed later on.
+    pub fn pages<T>(&self, path: &str) -> BoxStream<'static, Result<(Vec<T>, Option<String>), Error>>
+    where
+        T: DeserializeOwned + Send + 'static,
+    {
+        let client = self.clone();
+        stream::try_unfold(Some(self.url(path)), move |next| {
+            let client = client.clone();
+            async move {
+                match next {
+                    Some(url) => {
+                        let span = debug_span!("page", url = %url);
+                        let (items, next) = client.get_page::<Vec<T>>(url).instrument(span).await?;
+                        Ok::<_, Error>(Some(((items, next.clone()), next)))
+                    }
+                    None => Ok(None),
+                }
+            }
+        })
+        .boxed()
+    }
+
+    /// Sends a GraphQL query and deserializes its `data`.
+    pub async fn graphql<T>(&self, query: &'static str, variables: Value) -> Result<T, Error>
+    where
+        T: DeserializeOwned,
+    {
+        let body = json!({ "query": query, "variables": variables }).to_string();
+        self.retrying(|| self.try_graphql(body.clone())).await
+    }
+
+    /// Fetches a single page, retrying transient failures according to the
+    /// client's retry policy and waiting out rate limits.
+    async fn get_page<T>(&self, url: String) -> Result<(T, Option<String>), Error>
+    where
+        T: DeserializeOwned,
+    {
+        self.retrying(|| self.try_page(url.clone())).await
+    }
+
+    /// Sends a request through `send` until it succeeds, waiting out rate
+    /// limits and retrying transient failures as the retry policy allows.
+    async fn retrying<T, F, R>(&self, send: F) -> Result<T, Error>
+    where
+        F: Fn() -> R,
+        R: Future<Output = Result<T, Error>>,
+    {
+        let mut attempt = 1;
+        let mut secondary_waits = 0;
+        loop {
+            let e = match send().await {
+                Ok(result) => return Ok(result),
+                Err(e) => e,
+            };
+            // waiting out a limit is expected to succeed, so it does not count
+            // as a failed attempt
+            match e {
+                Error::SecondaryRateLimit { retry_after } if secondary_waits < MAX_SECONDARY_WAITS => {
+                    warn!("secondary rate limit exceeded, waiting {}s", retry_after.as_secs());
+                    interrupt::sleep(retry_after).await;
+                    if interrupt::requested() {
+                        return Err(Error::Interrupted);
+                    }
+                    secondary_waits += 1;
+                    continue;
+                }
+                Error::RateLimit { reset } if self.retry.wait_for_rate_limit => {
+                    warn!("rate limit exceeded, waiting {}s for it to reset", reset.as_secs());
+                    interrupt::sleep(reset).await;
+                    if interrupt::requested() {
+                        return Err(Error::Interrupted);
+                    }
+                    continue;
+                }
+                _ => {}
+            }
+            if !e.is_transient() || attempt >= self.retry.max_attempts {
+                return Err(e);
+            }
+            let delay = self.retry.delay(attempt);
+            warn!(
+                "{}, retrying in {}ms ({}/{})",
+                e,
+                delay.as_millis(),
+                attempt + 1,
+                self.retry.max_attempts
+            );
+            time::sleep(delay).await;
+            attempt += 1;
+        }
+    }
+
+    /// Sends a request authenticated with the client's credentials.
+    async fn send(&self, mut request: Request<Body>) -> Result<(Parts, Bytes), Error> {
+        if let Some(auth) = self.authorization().await? {
+            let auth = HeaderValue::from_str(&auth).map_err(|e| Error::from(http::Error::from(e)))?;
+            request.headers_mut().insert(AUTHORIZATION, auth);
+        }
+        self.send_as_is(request).await
+    }
+
+    /// Value of the `Authorization` header, none for anonymous requests. An
+    /// app creates a new installation token once the current one is about to
+    /// expire.
+    async fn authorization(&self) -> Result<Option<String>, Error> {
+        let app = match self.credentials {
+            Credentials::Token(ref token) => return Ok(Some(format!("token {}", token))),
+            Credentials::Tokens(ref pool) => return Ok(Some(format!("token {}", pool.current()))),
+            Credentials::App(ref app) => app,
+            Credentials::Anonymous => return Ok(None),
+        };
+        if let Some(token) = app.current() {
+            return Ok(Some(format!("token {}", token)));
+        }
+        let token = self.retrying(|| self.try_installation_token(app)).await?;
+        Ok(Some(format!("token {}", token)))
+    }
+
+    async fn try_installation_token(&self, app: &AppCredentials) -> Result<String, Error> {
+        let jwt = app.jwt().map_err(|e| Error::Auth(e.to_string()))?;
+        let url = format!("{}/app/installations/{}/access_tokens", self.api_url, app.installation_id);
+        let request = Request::post(&url[..])
+            .header(USER_AGENT, concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION")))
+            .header(ACCEPT, "application/vnd.github.v3+json")
+            .header(AUTHORIZATION, &format!("Bearer {}", jwt)[..])
+            .body(Body::empty())?;
+        let (parts, body) = self.send_as_is(request).await?;
+        if !parts.status.is_success() {
+            return Err(error_for(&parts, url, &body));
+        }
+        let token: InstallationToken = serde_json::from_slice(&body)?;
+        debug!("Created an installation token valid until {}", token.expires_at);
+        let value = token.token.clone();
+        app.store(token);
+        Ok(value)
+    }
+
+    /// Sends a request as it is once the shared throttle and the pace of the
+    /// remaining quota let it pass, unless the request budget is used up or
+    /// the run was interrupted meanwhile. Quota queries are free and not
+    /// counted.
+    async fn send_as_is(&self, request: Request<Body>) -> Result<(Parts, Bytes), Error> {
+        if interrupt::requested() {
+            return Err(Error::Interrupted);
+        }
+        if let Some(ref budget) = self.budget {
+            if !request.uri().path().ends_with("/rate_limit") && !budget.take() {
+                return Err(Error::BudgetExhausted { limit: budget.limit() });
+            }
+        }
+        if let Some(cassette) = self.cassette.as_ref().filter(|c| c.is_replaying()) {
+            return replay(cassette, request).await;
+        }
+        let auth = header(request.headers(), AUTHORIZATION);
+        self.throttle.queue().await;
+        time::sleep(self.pacer.delay()).await;
+        if interrupt::requested() {
+            return Err(Error::Interrupted);
+        }
+        let (parts, body) = self.send_now(request).await?;
+        self.observe_quota(&parts.headers, auth);
+        Ok((parts, body))
+    }
+
+    /// Paces further requests by the quota a response to a request with the
+    /// `auth` header reports, and switches to another token of a pool once
+    /// half of it is used. The search API has a small quota of its own which
+    /// is not taken into account.
+    fn observe_quota(&self, headers: &HeaderMap, auth: Option<String>) {
+        if header(headers, "x-ratelimit-resource").map_or(false, |r| r == "search") {
+            return;
+        }
+        let quota = |name| header(headers, name).and_then(|value| value.parse::<u64>().ok());
+        if let (Some(limit), Some(remaining)) = (quota("x-ratelimit-limit"), quota("x-ratelimit-remaining")) {
+            if let Some(ref dashboard) = self.dashboard {
+                dashboard.quota(limit, remaining);
+            }
+            if let (&Credentials::Tokens(ref pool), Some(auth)) = (&self.credentials, auth) {
+                if let Some(index) = pool.update(auth.trim_start_matches("token "), limit, remaining) {
+                    info!("{} of {} requests left, switching to token {}", remaining, limit, index + 1);
+                }
+            }
+            if let Some(interval) = self.pacer.update(limit, remaining, reset_in(headers)) {
+                info!(
+                    "{} of {} requests left, pacing them every {}ms until the quota resets",
+                    remaining,
+                    limit,
+                    interval.as_millis()
+                );
+            }
+        }
+    }
+
+    /// Sends a request and reads the whole response, failing if that takes
+    /// longer than the request timeout. The response is written to the
+    /// cassette when recording.
+    async fn send_now(&self, request: Request<Body>) -> Result<(Parts, Bytes), Error> {
+        let (method, uri) = (request.method().clone(), request.uri().clone());
+        let recording = self.cassette.as_ref().filter(|c| c.mode == Mode::Record);
+        let (request, sent) = match recording {
+            Some(_) => {
+                let (parts, body) = request.into_parts();
+                let body = hyper::body::to_bytes(body).await?;
+                (Request::from_parts(parts, Body::from(body.clone())), body)
+            }
+            None => (request, Bytes::new()),
+        };
+        self.requests.fetch_add(1, Ordering::SeqCst);
+        if let Some(ref dashboard) = self.dashboard {
+            dashboard.request();
+        }
+        let span = debug_span!(
+            "request",
+            method = %method,
+            url = %uri,
+            status = field::Empty,
+            rate_limit_remaining = field::Empty,
+            rate_limit_reset = field::Empty,
+        );
+        let response = async {
+            let (parts, body) = self.api.request(request).await?.into_parts();
+            trace!("{} {} -> {}", method, uri, parts.status);
+            let span = Span::current();
+            span.record("status", &parts.status.as_u16());
+            let quota = |name| header(&parts.headers, name).and_then(|value| value.parse::<u64>().ok());
+            if let Some(remaining) = quota("x-ratelimit-remaining") {
+                span.record("rate_limit_remaining", &remaining);
+            }
+            if let Some(reset) = quota("x-ratelimit-reset") {
+                span.record("rate_limit_reset", &reset);
+            }
+            let body = self.read_body(body).await?;
+            Ok::<_, Error>((parts, body))
+        }
+        .instrument(span);
+        let (parts, body) = match self.request_timeout {
+            Some(timeout) => time::timeout(timeout, response).await.map_err(|_| Error::Timeout(timeout))??,
+            None => response.await?,
+        };
+        if let Some(cassette) = recording {
+            cassette.record(&method, &uri.to_string(), &sent, &parts, &body)?;
+        }
+        Ok((parts, body))
+    }
+
+    /// Reads a response body, pausing between chunks to stay within the
+    /// bandwidth limit.
+    async fn read_body(&self, mut body: Body) -> Result<Bytes, Error> {
+        let bandwidth = match self.bandwidth {
+            Some(ref bandwidth) => bandwidth,
+            None => return Ok(hyper::body::to_bytes(body).await?),
+        };
+        let mut buf = Vec::new();
+        while let Some(chunk) = body.data().await {
+            let chunk = chunk?;
+            buf.extend_from_slice(&chunk);
+            time::sleep(bandwidth.consume(chunk.len())).await;
+        }
+        Ok(Bytes::from(buf))
+    }
+
+    async fn try_graphql<T>(&self, body: String) -> Result<T, Error>
+    where
+        T: DeserializeOwned,
+    {
+        let url = self.graphql_url.clone();
+        let request = Request::post(&url[..])
+            .header(USER_AGENT, concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION")))
+            .body(Body::from(body))?;
+        let (parts, body) = self.send(request).await?;
+        if !parts.status.is_success() {
+            return Err(error_for(&parts, url, &body));
+        }
+        let response: GraphQlResponse<T> = serde_json::from_slice(&body)?;
+        match (response.data, response.errors) {
+            (Some(data), None) => Ok(data),
+            (_, errors) => Err(Error::GraphQl(
+                errors
+                    .unwrap_or_default()
+                    .into_iter()
+                    .map(|e| e.message)
+                    .collect::<Vec<_>>()
+                    .join(", "),
+            )),
+        }
+    }
+
+    async fn try_get_with_headers<T>(&self, url: String) -> Result<(T, HeaderMap), Error>
+    where
+        T: DeserializeOwned,
+    {
+        let request = Request::get(&url[..])
+            .header(USER_AGENT, concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION")))
+            .header(ACCEPT, "application/vnd.github.v3+json")
+            .body(Body::empty())?;
+        let (parts, body) = self.send(request).await?;
+        if !parts.status.is_success() {
+            return Err(error_for(&parts, url, &body));
+        }
+        Ok((serde_json::from_slice(&body)?, parts.headers))
+    }
+
+    async fn try_page<T>(&self, url: String) -> Result<(T, Option<String>), Error>
+    where
+        T: DeserializeOwned,
+    {
+        let cache = self.cache(&url);
+        let cached = match cache {
+            Some(ref cache) => {
+                let key = url.clone();
+                on_cache(cache, move |cache| cache.cached(&key)).await?
+            }
+            None => None,
+        };
+
+        let mut builder = Request::get(&url[..])
+            .header(USER_AGENT, concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION")))
+            .header(ACCEPT, "application/vnd.github.v3+json");
+        if let Some(ref entry) = cached {
+            if let Some(ref etag) = entry.etag {
+                builder = builder.header(IF_NONE_MATCH, &etag[..]);
+            }
+            if let Some(ref last_modified) = entry.last_modified {
+                builder = builder.header(IF_MODIFIED_SINCE, &last_modified[..]);
+            }
+        }
+        let request = builder.body(Body::empty())?;
+
+        let (parts, body) = self.send(request).await?;
+        let entry = match parts.status {
+            StatusCode::NOT_MODIFIED if cached.is_some() => {
+                self.cache_hits.fetch_add(1, Ordering::SeqCst);
+                cached.unwrap()
+            }
+            status if status.is_success() => {
+                let entry = CachedResponse {
+                    etag: header(&parts.headers, ETAG),
+                    last_modified: header(&parts.headers, LAST_MODIFIED),
+                    next: next_link(&parts.headers),
+                    body: String::from_utf8_lossy(&body).into_owned(),
+                };
+                match cache {
+                    Some(ref cache) => {
+                        let key = url.clone();
+                        on_cache(cache, move |cache| cache.cache(&key, &entry).map(|()| entry)).await?
+                    }
+                    None => entry,
+                }
+            }
+            _ => return Err(error_for(&parts, url, &body)),
+        };
+        let items = serde_json::from_str(&entry.body)?;
+        Ok((items, entry.next))
+    }
+}
+
+/// Runs `f` on the ETag cache on the blocking thread pool, SQLite blocks.
+async fn on_cache<T, F>(cache: &Arc<Mutex<SyncDb>>, f: F) -> Result<T, Error>
+where
+    F: FnOnce(&SyncDb) -> rusqlite::Result<T> + Send + 'static,
+    T: Send + 'static,
+{
+    let cache = cache.clone();
+    match tokio::task::spawn_blocking(move || f(&cache.lock().unwrap())).await {
+        Ok(result) => Ok(result?),
+        Err(e) => Err(Error::Io(e.into())),
+    }
+}
+
+/// Answers a request with the response recorded on `cassette`, failing for
+/// one that was never recorded.
+async fn replay(cassette: &Cassette, request: Request<Body>) -> Result<(Parts, Bytes), Error> {
+    let (parts, body) = request.into_parts();
+    let body = hyper::body::to_bytes(body).await?;
+    let url = parts.uri.to_string();
+    match cassette.replay(&parts.method, &url, &body)? {
+        Some(response) => Ok(response),
+        None => Err(Error::NotRecorded {
+            method: parts.method.to_string(),
+            url: url,
+        }),
+    }
+}
+
+fn header<K: hyper::header::AsHeaderName>(headers: &HeaderMap, name: K) -> Option<String> {
+    headers
+        .get(name)
+        .and_then(|value| value.to_str().ok())
+        .map(|value| value.to_string())
+}
+
+/// Time until the rate limit window given by `X-RateLimit-Reset` resets.
+fn reset_in(headers: &HeaderMap) -> Duration {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_else(|_| Duration::from_secs(0))
+        .as_secs();
+    let reset = header(headers, "x-ratelimit-reset")
+        .and_then(|reset| reset.parse::<u64>().ok())
+        .unwrap_or(now);
+    // one extra second to not race the reset
+    Duration::from_secs(reset.saturating_sub(now) + 1)
+}
+
+/// Error for an unsuccessful response.
+fn error_for(parts: &Parts, url: String, body: &[u8]) -> Error {
+    match parts.status {
+        StatusCode::FORBIDDEN if header(&parts.headers, "x-ratelimit-remaining") == Some("0".to_string()) => {
+            Error::RateLimit {
+                reset: reset_in(&parts.headers),
+            }
+        }
+        StatusCode::FORBIDDEN | StatusCode::TOO_MANY_REQUESTS
+            if is_secondary_rate_limit(&parts.headers, body) =>
+        {
+            Error::SecondaryRateLimit {
+                retry_after: retry_after(&parts.headers),
+            }
+        }
+        status => Error::Status {
+            status: status,
+            url: url,
+            body: String::from_utf8_lossy(body).into_owned(),
+        },
+    }
+}
+
+/// Whether a 403 or 429 response was caused by a secondary rate limit, which
+/// GitHub signals by a `Retry-After` header or in the error message only.
+fn is_secondary_rate_limit(headers: &HeaderMap, body: &[u8]) -> bool {
+    headers.contains_key(RETRY_AFTER)
+        || String::from_utf8_lossy(body).to_lowercase().contains("secondary rate limit")
+}
+
+/// Time to wait as given by `Retry-After`, a minute if the header is missing
+/// as recommended by GitHub.
+fn retry_after(headers: &HeaderMap) -> Duration {
+    let secs = header(headers, RETRY_AFTER)
+        .and_then(|secs| secs.trim().parse::<u64>().ok())
+        .unwrap_or(60);
+    Duration::from_secs(secs.max(1))
+}
+
+/// Extracts the `rel="next"` target from a `Link` header.
+fn next_link(headers: &HeaderMap) -> Option<String> {
+    header(headers, LINK).and_then(|link| {
+        link.split(',')
+            .find(|part| part.contains("rel=\"next\""))
+            .and_then(|part| {
+                let start = part.find('<')? + 1;
+                let end = part.find('>')?;
+                Some(part[start..end].to_string())
+            })
+    })
+}
+
+/// A `GithubApi` answering requests from canned responses, for tests.
+#[cfg(test)]
+pub mod fake {
+    use std::collections::{HashMap, VecDeque};
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    use futures::future::{self, BoxFuture, FutureExt};
+    use hyper::{Body, Request, Response, StatusCode};
+    use stream_throttle::{ThrottlePool, ThrottleRate};
+
+    use super::{Client, ClientConfig, Error, GithubApi, API_URL};
+    use crate::auth::Credentials;
+    use crate::budget::Pacer;
+    use crate::retry::RetryPolicy;
+
+    /// A canned response: status, headers and body.
+    type Canned = (StatusCode, Vec<(String, String)>, String);
+
+    /// Answers the requests for a URL with the responses added for it, in
+    /// the order they were added, and with `404 Not Found` once there are
+    /// none left. Requested URLs are kept for inspection.
+    #[derive(Default)]
+    pub struct FakeApi {
+        responses: Mutex<HashMap<String, VecDeque<Canned>>>,
+        requested: Mutex<Vec<String>>,
+    }
+
+    impl FakeApi {
+        /// Adds a response to the next request for `path` below the API URL.
+        pub fn respond(&self, path: &str, status: StatusCode, headers: &[(&str, &str)], body: &str) {
+            let headers = headers.iter().map(|&(k, v)| (k.to_string(), v.to_string())).collect();
+            self.responses
+                .lock()
+                .unwrap()
+                .entry(format!("{}{}", API_URL, path))
+                .or_insert_with(VecDeque::new)
+                .push_back((status, headers, body.to_string()));
+        }
+
+        /// URLs requested so far.
+        pub fn requested(&self) -> Vec<String> {
+            self.requested.lock().unwrap().clone()
+        }
+    }
+
+    impl GithubApi for FakeApi {
+        fn request(&self, request: Request<Body>) -> BoxFuture<'static, Result<Response<Body>, Error>> {
+            let url = request.uri().to_string();
+            self.requested.lock().unwrap().push(url.clone());
+            let canned = self.responses.lock().unwrap().get_mut(&url).and_then(|queue| queue.pop_front());
+            let (status, headers, body) = canned.unwrap_or((StatusCode::NOT_FOUND, Vec::new(), "{}".to_string()));
+            let mut response = Response::builder().status(status);
+            for (name, value) in headers {
+                response = response.header(&name[..], &value[..]);
+            }
+            future::ready(response.body(Body::from(body)).map_err(Error::from)).boxed()
+        }
+    }
+
+    /// Anonymous client sending its requests to `api` without throttling.
+    pub fn client(api: &Arc<FakeApi>, retry: RetryPolicy) -> Client {
+        let cfg = ClientConfig {
+            credentials: Credentials::Anonymous,
+            api_url: API_URL.to_string(),
+            graphql_url: ClientConfig::graphql_url_for(API_URL),
+            cache_db: None,
+            retry: retry,
+            proxy: None,
+            connect_timeout: None,
+            request_timeout: None,
+            pool_size: 1,
+            http2: false,
+            cassette: None,
+            budget: None,
+            bandwidth: None,
+            pacer: Pacer::default(),
+            dashboard: None,
+        };
+        let throttle = ThrottlePool::new(ThrottleRate::new(1000, Duration::from_secs(1)));
+        Client::with_api(&cfg, throttle, api.clone()).unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+    use futures::TryStreamExt;
+    use hyper::StatusCode;
+
+    use super::fake::{self, FakeApi};
+    use super::{Error, MAX_SECONDARY_WAITS};
+    use crate::retry::RetryPolicy;
+
+    fn retry(max_attempts: u32, wait_for_rate_limit: bool) -> RetryPolicy {
+        RetryPolicy {
+            max_attempts: max_attempts,
+            base_delay: Duration::from_millis(1),
+            jitter: 0.0,
+            wait_for_rate_limit: wait_for_rate_limit,
+        }
+    }
+
+    fn now() -> String {
+        SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs().to_string()
+    }
+
+    #[tokio::test]
+    async fn pages_follow_next_links() {
+        let api = Arc::new(FakeApi::default());
+        let next = |page| format!("<https://api.github.com/items?page={}>; rel=\"next\"", page);
+        api.respond("/items", StatusCode::OK, &[("link", &next(2))], "[1, 2]");
+        api.respond("/items?page=2", StatusCode::OK, &[("link", &next(3))], "[3, 4]");
+        api.respond("/items?page=3", StatusCode::OK, &[], "[5]");
+        let client = fake::client(&api, retry(1, false));
+
+        let items: Vec<u64> = client.get_pages::<u64>("/items").try_collect().await.unwrap();
+        assert_eq!(items, vec![1, 2, 3, 4, 5]);
+        assert_eq!(client.requests(), 3);
+    }
+
+    #[tokio::test]
+    async fn pages_yield_the_next_page() {
+        let api = Arc::new(FakeApi::default());
+        let next = "<https://api.github.com/items?page=2>; rel=\"next\"";
+        api.respond("/items", StatusCode::OK, &[("link", next)], "[1, 2]");
+        api.respond("/items?page=2", StatusCode::OK, &[], "[3]");
+        let client = fake::client(&api, retry(1, false));
+
+        let pages: Vec<(Vec<u64>, Option<String>)> = client.pages("/items").try_collect().await.unwrap();
+        assert_eq!(
+            pages,
+            vec![
+                (vec![1, 2], Some("https://api.github.com/items?page=2".to_string())),
+                (vec![3], None),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn server_errors_are_retried() {
+        let api = Arc::new(FakeApi::default());
+        api.respond("/item", StatusCode::BAD_GATEWAY, &[], "");
+        api.respond("/item", StatusCode::OK, &[], "42");
+        let client = fake::client(&api, retry(3, false));
+
+        assert_eq!(client.get::<u64>("/item").await.unwrap(), 42);
+        assert_eq!(client.requests(), 2);
+    }
+
+    #[tokio::test]
+    async fn retries_end_after_max_attempts() {
+        let api = Arc::new(FakeApi::default());
+        for _ in 0..3 {
+            api.respond("/item", StatusCode::INTERNAL_SERVER_ERROR, &[], "");
+        }
+        api.respond("/item", StatusCode::OK, &[], "42");
+        let client = fake::client(&api, retry(3, false));
+
+        match client.get::<u64>("/item").await {
+            Err(Error::Status { status, .. }) => assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR),
+            other => panic!("unexpected result {:?}", other),
+        }
+        assert_eq!(client.requests(), 3);
+    }
+
+    #[tokio::test]
+    async fn client_errors_are_not_retried() {
+        let api = Arc::new(FakeApi::default());
+        api.respond("/item", StatusCode::UNPROCESSABLE_ENTITY, &[], "{}");
+        api.respond("/item", StatusCode::OK, &[], "42");
+        let client = fake::client(&api, retry(3, false));
+
+        assert!(client.get::<u64>("/item").await.is_err());
+        assert_eq!(api.requested().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn exhausted_rate_limit_fails_unless_waited_for() {
+        let api = Arc::new(FakeApi::default());
+        let reset = now();
+        let headers = [("x-ratelimit-remaining", "0"), ("x-ratelimit-reset", &reset[..])];
+        api.respond("/item", StatusCode::FORBIDDEN, &headers, "{}");
+        api.respond("/item", StatusCode::OK, &[], "42");
+        let client = fake::client(&api, retry(1, false));
+
+        match client.get::<u64>("/item").await {
+            Err(Error::RateLimit { reset }) => assert!(reset <= Duration::from_secs(1)),
+            other => panic!("unexpected result {:?}", other),
+        }
+        assert_eq!(api.requested().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn exhausted_rate_limit_is_waited_out() {
+        let api = Arc::new(FakeApi::default());
+        let reset = now();
+        let headers = [("x-ratelimit-remaining", "0"), ("x-ratelimit-reset", &reset[..])];
+        api.respond("/item", StatusCode::FORBIDDEN, &headers, "{}");
+        api.respond("/item", StatusCode::OK, &[], "42");
+        // waiting out the limit is not a failed attempt
+        let client = fake::client(&api, retry(1, true));
+
+        assert_eq!(client.get::<u64>("/item").await.unwrap(), 42);
+        assert_eq!(api.requested().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn secondary_rate_limit_is_waited_out() {
+        let api = Arc::new(FakeApi::default());
+        api.respond("/item", StatusCode::TOO_MANY_REQUESTS, &[("retry-after", "1")], "{}");
+        api.respond("/item", StatusCode::OK, &[], "42");
+        let client = fake::client(&api, retry(1, false));
+
+        assert_eq!(client.get::<u64>("/item").await.unwrap(), 42);
+        assert_eq!(api.requested().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn repeated_secondary_rate_limit_fails() {
+        let api = Arc::new(FakeApi::default());
+        for _ in 0..=MAX_SECONDARY_WAITS {
+            api.respond("/item", StatusCode::TOO_MANY_REQUESTS, &[("retry-after", "1")], "{}");
+        }
+        api.respond("/item", StatusCode::OK, &[], "42");
+        let client = fake::client(&api, retry(1, false));
+
+        match client.get::<u64>("/item").await {
+            Err(Error::SecondaryRateLimit { .. }) => {}
+            other => panic!("expected a secondary rate limit error, got {:?}", other),
+        }
+        assert_eq!(api.requested().len(), MAX_SECONDARY_WAITS as usize + 1);
+    }
+}
@@ -0,0 +1,240 @@
+use std::env;
+use std::ffi::{OsStr, OsString};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use toml::Value;
+
+/// Configuration file read from the working directory unless `--config`
+/// names another one.
+pub const DEFAULT_FILE: &str = "github-data-fetch.toml";
+
+/// Prefix of the environment variables mirroring the arguments, which are
+/// named after the upper-case argument names, e.g. `GDF_OUTPUT_DIR`.
+pub const ENV_PREFIX: &str = "GDF_";
+
+/// Short flags and aliases of the options, by long name.
+const SHORT: &[(&str, char)] = &[
+    ("owner", 'O'),
+    ("repository", 'r'),
+    ("token", 't'),
+    ("output-directory", 'o'),
+    ("interactive", 'i'),
+    ("quiet", 'q'),
+];
+const ALIASES: &[(&str, &str)] = &[("output", "output-directory")];
+/// Argument names not derived from the long name, by long name.
+const NAMES: &[(&str, &str)] = &[("output-directory", "OUTPUT_DIR"), ("repository", "REPO")];
+
+/// Subcommands the configuration file provides arguments for.
+const SUBCOMMANDS: &[&str] = &["fetch", "watch", "list"];
+
+/// Flags that may be set in the environment, by long name: those taken
+/// before the subcommand, by fetch, watch and list, and by them and check.
+const GLOBAL_FLAGS: &[&str] = &["quiet"];
+const FETCH_FLAGS: &[&str] = &[
+    "interactive",
+    "include-forks",
+    "skip-forks",
+    "skip-archived",
+    "incremental",
+    "daemon",
+    "overwrite",
+    "resume",
+    "wait-for-lock",
+    "no-cache",
+    "dedupe-users",
+    "redact",
+    "fail-on-rate-limit",
+    "graphql",
+    "dry-run",
+    "events",
+    "tui",
+];
+const CONNECTION_FLAGS: &[&str] = &["anonymous", "http2"];
+
+/// Adds the settings of the configuration file to the arguments of a fetch,
+/// watch or list in `args`, those given on the command line or in the
+/// environment take precedence. A setting is left out if the arguments with
+/// it `conflict`, e.g. `since` of the file with `--incremental`.
+pub fn apply<F>(args: &mut Vec<OsString>, conflict: F) -> Result<(), String>
+where
+    F: Fn(&[OsString]) -> bool,
+{
+    let path = match path(args) {
+        Some(path) => path,
+        None => return Ok(()),
+    };
+    let at = match subcommand(args) {
+        Some(at) => at,
+        None => return Ok(()),
+    };
+    let mut file_args = Vec::new();
+    for setting in self::args(&path, &args[at..])? {
+        // checked against the given arguments only, conflicts within the
+        // file are reported
+        let mut with_setting = args.clone();
+        with_setting.splice(at + 1..at + 1, setting.iter().cloned());
+        if !conflict(&with_setting) {
+            file_args.extend(setting);
+        }
+    }
+    args.splice(at + 1..at + 1, file_args);
+    Ok(())
+}
+
+/// Adds the flags set to `true` or `1` by their environment variable, e.g.
+/// `GDF_INCREMENTAL=true`, to `args` unless they are given there.
+///
+/// Clap then checks them against the other arguments as it does the options
+/// it reads from the environment itself, rather than them overriding e.g.
+/// `--token` afterwards.
+pub fn apply_env(args: &mut Vec<OsString>) {
+    let at = match position(args) {
+        Some(at) => at,
+        None => return,
+    };
+    let flags: Vec<&str> = match &*args[at].to_string_lossy() {
+        name if SUBCOMMANDS.contains(&name) => FETCH_FLAGS.iter().chain(CONNECTION_FLAGS).cloned().collect(),
+        "check" => CONNECTION_FLAGS.to_vec(),
+        _ => Vec::new(),
+    };
+    let set = |flags: &[&str], args: &[OsString]| -> Vec<OsString> {
+        let given = given_options(args);
+        flags
+            .iter()
+            .filter(|flag| !given.iter().any(|option| *option == **flag))
+            .filter(|flag| env::var(env_var(flag)).map_or(false, |value| value == "true" || value == "1"))
+            .map(|flag| OsString::from(format!("--{}", flag)))
+            .collect()
+    };
+    let sub_flags = set(&flags, &args[at..]);
+    args.splice(at + 1..at + 1, sub_flags);
+    // global flags may also be given after the subcommand
+    let global_flags = set(GLOBAL_FLAGS, &args[..]);
+    args.splice(1..1, global_flags);
+}
+
+/// Position of the subcommand in `args` if it is one taking the
+/// configuration file.
+fn subcommand(args: &[OsString]) -> Option<usize> {
+    position(args).filter(|&at| SUBCOMMANDS.contains(&&*args[at].to_string_lossy()))
+}
+
+/// Position of the subcommand in `args`.
+fn position(args: &[OsString]) -> Option<usize> {
+    let mut i = 1;
+    while i < args.len() {
+        let arg = args[i].to_string_lossy();
+        if arg == "--config" || arg == "--color" {
+            i += 2;
+        } else if arg.starts_with('-') {
+            i += 1;
+        } else {
+            return Some(i);
+        }
+    }
+    None
+}
+
+/// Configuration file given by `--config` in `args` or `GDF_CONFIG`, or the
+/// default one if it exists.
+fn path(args: &[OsString]) -> Option<PathBuf> {
+    let mut args = args.iter().skip(1).map(|arg| arg.to_string_lossy());
+    while let Some(arg) = args.next() {
+        if arg == "--" {
+            break;
+        } else if arg == "--config" {
+            return args.next().map(|path| PathBuf::from(&*path));
+        } else if arg.starts_with("--config=") {
+            return Some(PathBuf::from(&arg["--config=".len()..]));
+        }
+    }
+    if let Some(path) = env::var_os(format!("{}CONFIG", ENV_PREFIX)) {
+        return Some(PathBuf::from(path));
+    }
+    Some(PathBuf::from(DEFAULT_FILE)).filter(|path| path.exists())
+}
+
+/// Arguments for the settings of the configuration file at `path` that the
+/// subcommand `args` doesn't give.
+///
+/// Keys are the long names of the options, e.g. `output-directory = "data"`,
+/// flags take `true` and options given multiple times an array. The
+/// arguments of each setting are kept together.
+fn args(path: &Path, args: &[OsString]) -> Result<Vec<Vec<OsString>>, String> {
+    let contents = fs::read_to_string(path).map_err(|e| format!("failed to read {}: {}", path.display(), e))?;
+    let value: Value = contents
+        .parse()
+        .map_err(|e| format!("invalid configuration file {}: {}", path.display(), e))?;
+    let table = value
+        .as_table()
+        .ok_or_else(|| format!("invalid configuration file {}", path.display()))?;
+    let given = given_options(args);
+    let mut file_args = Vec::new();
+    for (key, value) in table {
+        if key == "config" {
+            return Err(format!("{}: config can't be set in a configuration file", path.display()));
+        }
+        if given.iter().any(|option| option == key) || env::var_os(env_var(key)).is_some() {
+            continue;
+        }
+        let values = match *value {
+            Value::Array(ref values) => values.iter().collect(),
+            _ => vec![value],
+        };
+        let mut setting = Vec::new();
+        for value in values {
+            let value = match *value {
+                Value::Boolean(true) => None,
+                Value::Boolean(false) => continue,
+                Value::String(ref s) => Some(s.clone()),
+                Value::Integer(n) => Some(n.to_string()),
+                Value::Float(f) => Some(f.to_string()),
+                _ => return Err(format!("{}: unsupported value for {}", path.display(), key)),
+            };
+            setting.push(OsString::from(format!("--{}", key)));
+            setting.extend(value.map(OsString::from));
+        }
+        file_args.push(setting);
+    }
+    Ok(file_args)
+}
+
+/// Environment variable mirroring the option with the long name `key`.
+fn env_var(key: &str) -> String {
+    let name = NAMES
+        .iter()
+        .find(|&&(long, _)| long == key)
+        .map_or_else(|| key.to_uppercase().replace('-', "_"), |&(_, name)| name.to_string());
+    format!("{}{}", ENV_PREFIX, name)
+}
+
+/// Long names of the options the subcommand `args` gives.
+fn given_options(args: &[OsString]) -> Vec<String> {
+    args.iter()
+        .skip(1)
+        .take_while(|arg| *arg != OsStr::new("--"))
+        .filter_map(|arg| given_option(&arg.to_string_lossy()))
+        .collect()
+}
+
+/// Long name of the option `arg` gives, if it is one.
+fn given_option(arg: &str) -> Option<String> {
+    if arg.starts_with("--") {
+        let name = arg[2..].split('=').next().unwrap_or_default();
+        let name = ALIASES
+            .iter()
+            .find(|&&(alias, _)| alias == name)
+            .map_or(name, |&(_, long)| long);
+        Some(name.to_string())
+    } else if arg.starts_with('-') {
+        let short = arg[1..].chars().next()?;
+        SHORT
+            .iter()
+            .find(|&&(_, s)| s == short)
+            .map(|&(long, _)| long.to_string())
+    } else {
+        None
+    }
+}
@@ -0,0 +1,170 @@
+use std::collections::BTreeMap;
+use std::error;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Utc};
+use serde_json::Value;
+
+use crate::entity::Entity;
+use crate::format::{self, Format};
+use crate::lock;
+use crate::snapshot::{self, Item};
+
+/// Name of the dataset files, next to the snapshots.
+pub const DATASET: &str = "contributors";
+
+#[derive(Debug)]
+pub struct ContributorsConfig {
+    /// Output directory of a fetch, or the directory of one repository.
+    pub dir: PathBuf,
+    /// Output directory to write the datasets to, next to the snapshots if
+    /// none.
+    pub output: Option<PathBuf>,
+    pub format: Format,
+}
+
+/// What a user contributed to a repository. Comments and reviews are only
+/// known for issues and pull requests fetched through GraphQL.
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct Contributor {
+    pub login: String,
+    pub issues_opened: u64,
+    pub pulls_opened: u64,
+    /// Pull requests opened by the user that were merged.
+    pub pulls_merged: u64,
+    pub reviews: u64,
+    pub comments: u64,
+    pub first_activity: Option<String>,
+    pub last_activity: Option<String>,
+}
+
+impl Contributor {
+    /// All contributions, to rank contributors by.
+    pub fn total(&self) -> u64 {
+        self.issues_opened + self.pulls_opened + self.reviews + self.comments
+    }
+}
+
+/// Contributions of a user being counted, with the times of their first and
+/// last activity.
+#[derive(Default)]
+struct Tally {
+    contributor: Contributor,
+    first: Option<DateTime<Utc>>,
+    last: Option<DateTime<Utc>>,
+}
+
+impl Tally {
+    fn seen(&mut self, time: Option<DateTime<Utc>>) {
+        if let Some(time) = time {
+            self.first = Some(self.first.map_or(time, |first| first.min(time)));
+            self.last = Some(self.last.map_or(time, |last| last.max(time)));
+        }
+    }
+}
+
+/// Contributors to the issues and pull requests in the snapshots of the
+/// repository directory `dir`, the most active first.
+pub fn ranking(dir: &Path) -> Result<Vec<Contributor>, Box<dyn error::Error>> {
+    let mut tallies: BTreeMap<String, Tally> = BTreeMap::new();
+    for entity in Entity::ALL {
+        let pull = *entity == Entity::Pulls;
+        let (path, format) = match snapshot::find(dir, *entity) {
+            Some(found) => found,
+            None => continue,
+        };
+        for record in snapshot::records(&path, format, *entity)? {
+            let item = match Item::of(&record, pull) {
+                Some(item) => item,
+                None => continue,
+            };
+            if let Some(ref author) = item.author {
+                let tally = tally(&mut tallies, author);
+                if pull {
+                    tally.contributor.pulls_opened += 1;
+                    if item.merged_at.is_some() {
+                        tally.contributor.pulls_merged += 1;
+                    }
+                } else {
+                    tally.contributor.issues_opened += 1;
+                }
+                tally.seen(item.created_at);
+            }
+            for (login, time) in activities(&record, "/comments/nodes", "createdAt") {
+                let tally = tally(&mut tallies, &login);
+                tally.contributor.comments += 1;
+                tally.seen(time);
+            }
+            for (login, time) in activities(&record, "/reviews/nodes", "submittedAt") {
+                let tally = tally(&mut tallies, &login);
+                tally.contributor.reviews += 1;
+                tally.seen(time);
+            }
+        }
+    }
+    let mut contributors: Vec<Contributor> = tallies
+        .into_iter()
+        .map(|(_, tally)| Contributor {
+            first_activity: tally.first.map(|time| time.to_rfc3339()),
+            last_activity: tally.last.map(|time| time.to_rfc3339()),
+            ..tally.contributor
+        })
+        .collect();
+    contributors.sort_by(|a, b| b.total().cmp(&a.total()).then(a.login.cmp(&b.login)));
+    Ok(contributors)
+}
+
+fn tally<'a>(tallies: &'a mut BTreeMap<String, Tally>, login: &str) -> &'a mut Tally {
+    tallies.entry(login.to_string()).or_insert_with(|| Tally {
+        contributor: Contributor {
+            login: login.to_string(),
+            ..Contributor::default()
+        },
+        ..Tally::default()
+    })
+}
+
+/// Authors and times of the comments or reviews of a GraphQL node at
+/// `pointer`.
+fn activities(record: &Value, pointer: &str, time: &str) -> Vec<(String, Option<DateTime<Utc>>)> {
+    let nodes = match record.pointer(pointer).and_then(Value::as_array) {
+        Some(nodes) => nodes,
+        None => return Vec::new(),
+    };
+    nodes
+        .iter()
+        .filter_map(|node| {
+            let login = node.pointer("/author/login").and_then(Value::as_str)?;
+            let time = node
+                .get(time)
+                .and_then(Value::as_str)
+                .and_then(|time| DateTime::parse_from_rfc3339(time).ok())
+                .map(|time| time.with_timezone(&Utc));
+            Some((login.to_string(), time))
+        })
+        .collect()
+}
+
+/// Writes the `contributors` dataset of each repository with snapshots below
+/// `cfg.dir`.
+pub fn contributors(cfg: ContributorsConfig) -> Result<(), Box<dyn error::Error>> {
+    let repos = snapshot::repositories(&cfg.dir)?;
+    if repos.is_empty() {
+        return Err(format!("no snapshots in {}", cfg.dir.display()).into());
+    }
+    let _lock = lock::lock(cfg.output.as_ref().unwrap_or(&cfg.dir), false)?;
+    for (repo, dir) in &repos {
+        let contributors = ranking(dir)?;
+        let out = match cfg.output {
+            Some(ref output) => repo.dir(output),
+            None => dir.clone(),
+        };
+        fs::create_dir_all(&out)?;
+        let path = out.join(format!("{}.{}", DATASET, cfg.format.extension()));
+        format::serialize_to_file(&contributors, cfg.format, &path)?;
+        let top: Vec<&str> = contributors.iter().take(3).map(|c| &c.login[..]).collect();
+        info!("{}: {} contributors, most active {}", repo, contributors.len(), top.join(", "));
+    }
+    Ok(())
+}
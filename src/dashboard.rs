@@ -0,0 +1,237 @@
+use std::collections::{BTreeMap, VecDeque};
+use std::error;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// Recent warnings and errors kept for display.
+const EVENTS: usize = 8;
+
+/// What the dashboard shows, updated by the clients and the fetch as they
+/// go. Clones share the state, so one is handed to each of them.
+#[derive(Clone, Debug, Default)]
+pub struct Dashboard {
+    state: Arc<Mutex<State>>,
+    shown: Arc<AtomicBool>,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    repository: Option<String>,
+    /// Items done and expected per phase.
+    phases: BTreeMap<String, (u64, Option<u64>)>,
+    requests: u64,
+    /// Limit and remaining requests of the rate limit.
+    quota: Option<(u64, u64)>,
+    events: VecDeque<String>,
+}
+
+impl Dashboard {
+    /// Whether the dashboard is shown, which replaces progress bars and log
+    /// output.
+    pub fn shown(&self) -> bool {
+        self.shown.load(Ordering::SeqCst)
+    }
+
+    pub fn repository(&self, repository: &str) {
+        let mut state = self.state.lock().unwrap();
+        state.repository = Some(repository.to_string());
+        state.phases.clear();
+    }
+
+    pub fn progress(&self, phase: &str, done: u64, len: Option<u64>) {
+        self.state.lock().unwrap().phases.insert(phase.to_string(), (done, len));
+    }
+
+    pub fn request(&self) {
+        self.state.lock().unwrap().requests += 1;
+    }
+
+    pub fn quota(&self, limit: u64, remaining: u64) {
+        self.state.lock().unwrap().quota = Some((limit, remaining));
+    }
+
+    /// Requests left of the rate limit as last reported by the API.
+    pub fn remaining(&self) -> Option<u64> {
+        self.state.lock().unwrap().quota.map(|(_, remaining)| remaining)
+    }
+
+    /// Keeps a warning or error to show among the recent ones.
+    pub fn event(&self, message: String) {
+        let mut state = self.state.lock().unwrap();
+        if state.events.len() == EVENTS {
+            state.events.pop_front();
+        }
+        state.events.push_back(message);
+    }
+
+    /// Shows the dashboard on stderr until the returned guard is dropped.
+    #[cfg(feature = "tui")]
+    pub fn start(&self) -> Result<Guard, Box<dyn error::Error>> {
+        tui::start(self)
+    }
+
+    #[cfg(not(feature = "tui"))]
+    pub fn start(&self) -> Result<Guard, Box<dyn error::Error>> {
+        Err("--tui needs a build with the tui feature".into())
+    }
+}
+
+#[cfg(not(feature = "tui"))]
+pub struct Guard;
+
+#[cfg(feature = "tui")]
+pub use self::tui::Guard;
+
+#[cfg(feature = "tui")]
+mod tui {
+    use std::error;
+    use std::io::{self, Stderr};
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+    use std::thread::{self, JoinHandle};
+    use std::time::{Duration, Instant};
+
+    use crossterm::event::{self, Event, KeyCode, KeyModifiers};
+    use crossterm::execute;
+    use crossterm::terminal::{self, EnterAlternateScreen, LeaveAlternateScreen};
+    use ratatui::backend::CrosstermBackend;
+    use ratatui::layout::{Constraint, Direction, Layout};
+    use ratatui::style::{Color, Style};
+    use ratatui::widgets::{Block, Borders, Gauge, List, ListItem, Paragraph};
+    use ratatui::{Frame, Terminal};
+
+    use super::{Dashboard, State};
+    use crate::interrupt;
+
+    /// Time between redraws.
+    const TICK: Duration = Duration::from_millis(250);
+
+    /// Stops the dashboard and restores the terminal when dropped.
+    pub struct Guard {
+        shown: Arc<AtomicBool>,
+        stop: Arc<AtomicBool>,
+        thread: Option<JoinHandle<()>>,
+    }
+
+    impl Drop for Guard {
+        fn drop(&mut self) {
+            self.stop.store(true, Ordering::SeqCst);
+            if let Some(thread) = self.thread.take() {
+                let _ = thread.join();
+            }
+            self.shown.store(false, Ordering::SeqCst);
+        }
+    }
+
+    pub fn start(dashboard: &Dashboard) -> Result<Guard, Box<dyn error::Error>> {
+        // keystrokes are read rather than echoed, q and Ctrl-C interrupt
+        terminal::enable_raw_mode()?;
+        execute!(io::stderr(), EnterAlternateScreen)?;
+        let mut terminal = Terminal::new(CrosstermBackend::new(io::stderr()))?;
+        terminal.hide_cursor()?;
+        dashboard.shown.store(true, Ordering::SeqCst);
+
+        let stop = Arc::new(AtomicBool::new(false));
+        let (stopped, state) = (stop.clone(), dashboard.state.clone());
+        let thread = thread::spawn(move || {
+            let started = Instant::now();
+            let mut rate = Rate::default();
+            while !stopped.load(Ordering::SeqCst) {
+                let _ = terminal.draw(|frame| draw(frame, &state.lock().unwrap(), started, &mut rate));
+                if event::poll(TICK).unwrap_or(false) {
+                    if let Ok(Event::Key(key)) = event::read() {
+                        let ctrl_c = key.code == KeyCode::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL);
+                        if key.code == KeyCode::Char('q') || ctrl_c {
+                            interrupt::request();
+                        }
+                    }
+                }
+            }
+            restore(&mut terminal);
+        });
+        Ok(Guard {
+            shown: dashboard.shown.clone(),
+            stop: stop,
+            thread: Some(thread),
+        })
+    }
+
+    fn restore(terminal: &mut Terminal<CrosstermBackend<Stderr>>) {
+        let _ = terminal::disable_raw_mode();
+        let _ = execute!(terminal.backend_mut(), LeaveAlternateScreen);
+        let _ = terminal.show_cursor();
+    }
+
+    /// Requests per second over the last redraws.
+    #[derive(Default)]
+    struct Rate {
+        samples: Vec<(Instant, u64)>,
+    }
+
+    impl Rate {
+        fn update(&mut self, requests: u64) -> f64 {
+            let now = Instant::now();
+            self.samples.push((now, requests));
+            self.samples.retain(|&(at, _)| now - at <= Duration::from_secs(10));
+            let (first_at, first) = self.samples[0];
+            let secs = (now - first_at).as_secs_f64();
+            if secs > 0.0 {
+                (requests - first) as f64 / secs
+            } else {
+                0.0
+            }
+        }
+    }
+
+    fn draw(frame: &mut Frame, state: &State, started: Instant, rate: &mut Rate) {
+        let rows = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([
+                Constraint::Length(3),
+                Constraint::Length(3 * state.phases.len().max(1) as u16),
+                Constraint::Min(3),
+            ])
+            .split(frame.size());
+
+        let elapsed = started.elapsed().as_secs();
+        let quota = match state.quota {
+            Some((limit, remaining)) => format!("{}/{}", remaining, limit),
+            None => "unknown".to_string(),
+        };
+        let status = format!(
+            "{}   requests: {} ({:.1}/s)   quota left: {}   elapsed: {}h {}m {}s   q to stop",
+            state.repository.as_ref().map_or("", |r| &r[..]),
+            state.requests,
+            rate.update(state.requests),
+            quota,
+            elapsed / 3600,
+            elapsed / 60 % 60,
+            elapsed % 60
+        );
+        frame.render_widget(
+            Paragraph::new(status).block(Block::default().title(env!("CARGO_PKG_NAME")).borders(Borders::ALL)),
+            rows[0],
+        );
+
+        let phases = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints(vec![Constraint::Length(3); state.phases.len()])
+            .split(rows[1]);
+        for (area, (phase, &(done, len))) in phases.iter().zip(&state.phases) {
+            let block = Block::default().title(&phase[..]).borders(Borders::ALL);
+            let gauge = match len {
+                Some(len) if len > 0 => Gauge::default()
+                    .ratio((done as f64 / len as f64).min(1.0))
+                    .label(format!("{}/{}", done, len)),
+                _ => Gauge::default().ratio(0.0).label(done.to_string()),
+            };
+            frame.render_widget(gauge.block(block).gauge_style(Style::default().fg(Color::Green)), *area);
+        }
+
+        let events: Vec<ListItem> = state.events.iter().map(|e| ListItem::new(&e[..])).collect();
+        frame.render_widget(
+            List::new(events).block(Block::default().title("Recent errors").borders(Borders::ALL)),
+            rows[2],
+        );
+    }
+}
@@ -0,0 +1,261 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::error;
+use std::path::PathBuf;
+
+use crate::snapshot::{self, Item};
+
+#[derive(Debug)]
+pub struct DiffConfig {
+    /// Earlier snapshots, an output directory or that of one repository.
+    pub before: PathBuf,
+    /// Later snapshots of the same kind.
+    pub after: PathBuf,
+    pub json: bool,
+}
+
+/// How an issue or pull request changed between two snapshots.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChangeKind {
+    Added,
+    Removed,
+    Closed,
+    Merged,
+    Reopened,
+    Relabeled,
+    /// Retitled, or updated without any of the changes above.
+    Edited,
+}
+
+impl ChangeKind {
+    fn name(&self) -> &'static str {
+        match *self {
+            ChangeKind::Added => "added",
+            ChangeKind::Removed => "removed",
+            ChangeKind::Closed => "closed",
+            ChangeKind::Merged => "merged",
+            ChangeKind::Reopened => "reopened",
+            ChangeKind::Relabeled => "relabeled",
+            ChangeKind::Edited => "edited",
+        }
+    }
+}
+
+/// The changes of one issue or pull request.
+#[derive(Debug, Serialize)]
+pub struct Change {
+    pub repository: String,
+    pub number: u64,
+    pub pull: bool,
+    pub title: String,
+    pub kinds: Vec<ChangeKind>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub labels_added: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub labels_removed: Vec<String>,
+}
+
+/// Changes from the items of a repository in `before` to those in `after`.
+pub fn changes(repository: &str, before: &[Item], after: &[Item]) -> Vec<Change> {
+    let by_number = |items: &[Item]| -> BTreeMap<(bool, u64), Item> {
+        items.iter().map(|item| ((item.pull, item.number), item.clone())).collect()
+    };
+    let before = by_number(before);
+    let after = by_number(after);
+    let keys: BTreeSet<_> = before.keys().chain(after.keys()).cloned().collect();
+
+    let mut changes = Vec::new();
+    for key in keys {
+        let (kinds, labels_added, labels_removed, item) = match (before.get(&key), after.get(&key)) {
+            (None, Some(new)) => (vec![ChangeKind::Added], Vec::new(), Vec::new(), new),
+            (Some(old), None) => (vec![ChangeKind::Removed], Vec::new(), Vec::new(), old),
+            (Some(old), Some(new)) => {
+                let mut kinds = Vec::new();
+                match (&old.state[..], &new.state[..]) {
+                    ("open", "closed") => kinds.push(ChangeKind::Closed),
+                    (from, "merged") if from != "merged" => kinds.push(ChangeKind::Merged),
+                    (from, "open") if from != "open" => kinds.push(ChangeKind::Reopened),
+                    _ => {}
+                }
+                let old_labels: BTreeSet<_> = old.labels.iter().collect();
+                let new_labels: BTreeSet<_> = new.labels.iter().collect();
+                let added: Vec<String> = new_labels.difference(&old_labels).map(|l| l.to_string()).collect();
+                let removed: Vec<String> = old_labels.difference(&new_labels).map(|l| l.to_string()).collect();
+                if !added.is_empty() || !removed.is_empty() {
+                    kinds.push(ChangeKind::Relabeled);
+                }
+                if old.title != new.title || (kinds.is_empty() && old.updated_at != new.updated_at) {
+                    kinds.push(ChangeKind::Edited);
+                }
+                (kinds, added, removed, new)
+            }
+            (None, None) => unreachable!(),
+        };
+        if !kinds.is_empty() {
+            changes.push(Change {
+                repository: repository.to_string(),
+                number: item.number,
+                pull: item.pull,
+                title: item.title.clone(),
+                kinds: kinds,
+                labels_added: labels_added,
+                labels_removed: labels_removed,
+            });
+        }
+    }
+    changes
+}
+
+/// Prints what changed between the snapshots of two directories.
+pub fn diff(cfg: DiffConfig) -> Result<(), Box<dyn error::Error>> {
+    let before: BTreeMap<String, PathBuf> = snapshot::repositories(&cfg.before)?
+        .into_iter()
+        .map(|(repo, dir)| (repo.to_string(), dir))
+        .collect();
+    let after: BTreeMap<String, PathBuf> = snapshot::repositories(&cfg.after)?
+        .into_iter()
+        .map(|(repo, dir)| (repo.to_string(), dir))
+        .collect();
+    // two repository directories are compared whatever their names
+    let pairs: Vec<(String, Option<&PathBuf>, Option<&PathBuf>)> = if snapshot::is_repository(&cfg.before)
+        && snapshot::is_repository(&cfg.after)
+    {
+        let repo = after.keys().next().unwrap().clone();
+        vec![(repo, before.values().next(), after.values().next())]
+    } else {
+        before
+            .keys()
+            .chain(after.keys())
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .map(|repo| (repo.clone(), before.get(repo), after.get(repo)))
+            .collect()
+    };
+
+    let mut found = Vec::new();
+    for (repo, old, new) in pairs {
+        let old = match old {
+            Some(dir) => snapshot::items(dir)?,
+            None => Vec::new(),
+        };
+        let new = match new {
+            Some(dir) => snapshot::items(dir)?,
+            None => Vec::new(),
+        };
+        found.extend(changes(&repo, &old, &new));
+    }
+
+    if cfg.json {
+        println!("{}", serde_json::to_string_pretty(&found)?);
+        return Ok(());
+    }
+    let mut counts = BTreeMap::new();
+    for change in &found {
+        let mut line = format!("{}#{} {}", change.repository, change.number, join(&change.kinds));
+        if !change.labels_added.is_empty() || !change.labels_removed.is_empty() {
+            let labels: Vec<String> = change
+                .labels_added
+                .iter()
+                .map(|l| format!("+{}", l))
+                .chain(change.labels_removed.iter().map(|l| format!("-{}", l)))
+                .collect();
+            line.push_str(&format!(" ({})", labels.join(" ")));
+        }
+        println!("{}: {}", line, change.title);
+        for kind in &change.kinds {
+            *counts.entry(kind.name()).or_insert(0) += 1;
+        }
+    }
+    let summary: Vec<String> = counts.iter().map(|(kind, count)| format!("{} {}", count, kind)).collect();
+    if found.is_empty() {
+        println!("No changes");
+    } else {
+        println!("{} items changed: {}", found.len(), summary.join(", "));
+    }
+    Ok(())
+}
+
+fn join(kinds: &[ChangeKind]) -> String {
+    kinds.iter().map(ChangeKind::name).collect::<Vec<_>>().join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Utc};
+
+    use super::{changes, ChangeKind};
+    use crate::snapshot::Item;
+
+    fn item(number: u64, state: &str, labels: &[&str]) -> Item {
+        Item {
+            number: number,
+            pull: false,
+            title: format!("#{}", number),
+            state: state.to_string(),
+            author: None,
+            labels: labels.iter().map(|label| label.to_string()).collect(),
+            created_at: None,
+            updated_at: Some(Utc.with_ymd_and_hms(2024, 3, 1, 0, 0, 0).unwrap()),
+            closed_at: None,
+            merged_at: None,
+            comments: 0,
+        }
+    }
+
+    fn kinds(before: &[Item], after: &[Item]) -> Vec<(u64, Vec<ChangeKind>)> {
+        changes("o/r", before, after).into_iter().map(|change| (change.number, change.kinds)).collect()
+    }
+
+    #[test]
+    fn added_and_removed_items() {
+        assert_eq!(
+            kinds(&[item(1, "open", &[]), item(2, "open", &[])], &[item(2, "open", &[]), item(3, "open", &[])]),
+            vec![(1, vec![ChangeKind::Removed]), (3, vec![ChangeKind::Added])]
+        );
+    }
+
+    #[test]
+    fn state_changes() {
+        let mut merged = item(3, "merged", &[]);
+        merged.pull = true;
+        let mut open = item(3, "open", &[]);
+        open.pull = true;
+        let before = [item(1, "open", &[]), item(2, "closed", &[]), open];
+        let after = [item(1, "closed", &[]), item(2, "open", &[]), merged];
+        assert_eq!(
+            kinds(&before, &after),
+            vec![(1, vec![ChangeKind::Closed]), (2, vec![ChangeKind::Reopened]), (3, vec![ChangeKind::Merged])]
+        );
+    }
+
+    #[test]
+    fn issues_and_pulls_of_the_same_number_are_apart() {
+        let mut pull = item(1, "open", &[]);
+        pull.pull = true;
+        let found = changes("o/r", &[item(1, "open", &[])], &[item(1, "open", &[]), pull]);
+        assert_eq!(found.len(), 1);
+        assert!(found[0].pull);
+        assert_eq!(found[0].kinds, vec![ChangeKind::Added]);
+    }
+
+    #[test]
+    fn label_changes_are_listed() {
+        let found = changes("o/r", &[item(1, "open", &["bug", "triage"])], &[item(1, "closed", &["bug", "fixed"])]);
+        assert_eq!(found[0].kinds, vec![ChangeKind::Closed, ChangeKind::Relabeled]);
+        assert_eq!(found[0].labels_added, vec!["fixed"]);
+        assert_eq!(found[0].labels_removed, vec!["triage"]);
+    }
+
+    #[test]
+    fn other_updates_are_edits() {
+        let mut retitled = item(1, "open", &[]);
+        retitled.title = "Crash on start".to_string();
+        let mut updated = item(2, "open", &[]);
+        updated.updated_at = Some(Utc.with_ymd_and_hms(2024, 3, 2, 0, 0, 0).unwrap());
+        let before = [item(1, "open", &[]), item(2, "open", &[]), item(3, "open", &[])];
+        assert_eq!(
+            kinds(&before, &[retitled, updated, item(3, "open", &[])]),
+            vec![(1, vec![ChangeKind::Edited]), (2, vec![ChangeKind::Edited])]
+        );
+    }
+}
@@ -0,0 +1,112 @@
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use futures::stream::BoxStream;
+use serde_json::Value;
+
+use crate::client::{self, Client};
+use crate::repo::Repo;
+
+/// Entity types a fetch can produce, in the order they are fetched in.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Entity {
+    Issues,
+    Pulls,
+}
+
+impl Entity {
+    pub const ALL: &'static [Entity] = &[Entity::Issues, Entity::Pulls];
+
+    /// Name used on the command line and for output files.
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Entity::Issues => "issues",
+            Entity::Pulls => "pulls",
+        }
+    }
+
+    /// Singular tag of the records in NDJSON streams.
+    pub fn tag(&self) -> &'static str {
+        match *self {
+            Entity::Issues => "issue",
+            Entity::Pulls => "pull",
+        }
+    }
+
+    /// Entities in `only`, or all of them, without those in `skip`, in
+    /// fetch order.
+    pub fn select(only: Option<&[Entity]>, skip: &[Entity]) -> Vec<Entity> {
+        Entity::ALL
+            .iter()
+            .cloned()
+            .filter(|e| only.map_or(true, |only| only.contains(e)) && !skip.contains(e))
+            .collect()
+    }
+}
+
+impl FromStr for Entity {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Entity::ALL
+            .iter()
+            .cloned()
+            .find(|e| e.name() == s)
+            .ok_or_else(|| format!("unknown entity {}", s))
+    }
+}
+
+/// An entity type fetched per repository besides the built-in ones, e.g.
+/// releases, provided by the embedding program.
+pub trait EntityFetcher: Send + Sync {
+    /// Name used for the snapshot and the sync state, e.g. `releases`.
+    fn name(&self) -> &str;
+
+    /// Singular tag of the records in NDJSON streams.
+    fn tag(&self) -> &str;
+
+    /// Records of `repo` as they arrive, only those changed since `since` if
+    /// given and supported.
+    fn fetch(&self, client: &Client, repo: &Repo, since: Option<&str>)
+        -> BoxStream<'static, Result<Value, client::Error>>;
+
+    /// Identifies a record across runs, to merge it into an existing snapshot,
+    /// its `id` by default. A record without a key fails the merge.
+    fn key(&self, record: &Value) -> Option<u64> {
+        record.get("id").and_then(Value::as_u64)
+    }
+}
+
+/// Entity fetchers run after the built-in entities, in the order they were
+/// registered.
+#[derive(Clone, Default)]
+pub struct Registry {
+    fetchers: Vec<Arc<dyn EntityFetcher>>,
+}
+
+impl Registry {
+    /// Adds `fetcher`, replacing one of the same name.
+    pub fn register<F: EntityFetcher + 'static>(&mut self, fetcher: F) {
+        self.fetchers.retain(|f| f.name() != fetcher.name());
+        self.fetchers.push(Arc::new(fetcher));
+    }
+
+    pub fn get(&self, name: &str) -> Option<&dyn EntityFetcher> {
+        self.fetchers.iter().find(|f| f.name() == name).map(|f| &**f)
+    }
+
+    pub fn all(&self) -> Vec<&dyn EntityFetcher> {
+        self.fetchers.iter().map(|f| &**f).collect()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.fetchers.is_empty()
+    }
+}
+
+impl fmt::Debug for Registry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list().entries(self.fetchers.iter().map(|f| f.name())).finish()
+    }
+}
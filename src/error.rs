@@ -0,0 +1,33 @@
+use std::error;
+
+use crate::client;
+
+/// Failure of a fetch, along with the phase or item it occurred in. Requests
+/// that fail carry their URL in the `client::Error`.
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    /// A request for a single issue or pull request failed.
+    #[error("{entity} {number}: {source}")]
+    Item {
+        /// Singular tag of the entity, e.g. `pull`.
+        entity: &'static str,
+        number: u64,
+        source: client::Error,
+    },
+    /// A phase of fetching a repository failed, e.g. `listing` or `pulls`.
+    #[error("{phase}: {source}")]
+    Phase {
+        phase: String,
+        source: Box<dyn error::Error>,
+    },
+}
+
+impl Error {
+    /// The cause of `e` within phase `phase`.
+    pub fn phase(phase: &str, e: Box<dyn error::Error>) -> Box<dyn error::Error> {
+        Box::new(Error::Phase {
+            phase: phase.to_string(),
+            source: e,
+        })
+    }
+}
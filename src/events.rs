@@ -0,0 +1,51 @@
+use chrono::DateTime;
+use futures::future;
+use futures::stream::{BoxStream, StreamExt};
+use serde_json::Value;
+
+use crate::client::{self, Client};
+use crate::entity::EntityFetcher;
+use crate::labels;
+use crate::repo::Repo;
+
+/// Fetches the issue events of a repository, e.g. labels being added and
+/// removed, into the snapshot the labels subcommand reads the label
+/// transitions from.
+///
+/// The events are listed newest first, so the listing stops at the first one
+/// created before `since`.
+pub struct IssueEvents;
+
+impl EntityFetcher for IssueEvents {
+    fn name(&self) -> &str {
+        labels::EVENTS
+    }
+
+    fn tag(&self) -> &str {
+        "event"
+    }
+
+    fn fetch(
+        &self,
+        client: &Client,
+        repo: &Repo,
+        since: Option<&str>,
+    ) -> BoxStream<'static, Result<Value, client::Error>> {
+        let since = since.and_then(|since| DateTime::parse_from_rfc3339(since).ok());
+        client
+            .get_pages::<Value>(&format!("/repos/{}/issues/events?per_page=100", repo))
+            .take_while(move |event| {
+                let created = event
+                    .as_ref()
+                    .ok()
+                    .and_then(|event| event.get("created_at"))
+                    .and_then(Value::as_str)
+                    .and_then(|created| DateTime::parse_from_rfc3339(created).ok());
+                future::ready(match (since, created) {
+                    (Some(since), Some(created)) => created >= since,
+                    _ => true,
+                })
+            })
+            .boxed()
+    }
+}
@@ -0,0 +1,70 @@
+use std::error;
+use std::fmt;
+
+use hyper::StatusCode;
+
+use crate::client;
+use crate::error::Error;
+
+/// Exit status of the process, telling scripts and schedulers why a run
+/// failed.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExitCode {
+    /// Any failure not covered below.
+    Failure = 1,
+    /// Invalid or missing arguments.
+    Usage = 2,
+    /// The credentials were rejected or lack access.
+    Auth = 3,
+    /// The rate limit or the request budget ran out.
+    RateLimited = 4,
+    /// Some repositories were fetched, others failed.
+    Partial = 5,
+    /// Connections failed or timed out.
+    Network = 6,
+    /// Stopped by SIGINT or SIGTERM, as shells report a SIGINT.
+    Interrupted = 130,
+}
+
+impl ExitCode {
+    /// Classifies an error by the API failure it stems from, looking through
+    /// the errors giving it context.
+    pub fn of(e: &(dyn error::Error + 'static)) -> ExitCode {
+        if let Some(failed) = e.downcast_ref::<Failed>() {
+            return failed.code;
+        }
+        if e.is::<Error>() {
+            return e.source().map_or(ExitCode::Failure, ExitCode::of);
+        }
+        match e.downcast_ref::<client::Error>() {
+            Some(&client::Error::Auth(_)) => ExitCode::Auth,
+            Some(&client::Error::Status { status, .. })
+                if status == StatusCode::UNAUTHORIZED || status == StatusCode::FORBIDDEN =>
+            {
+                ExitCode::Auth
+            }
+            Some(&client::Error::RateLimit { .. })
+            | Some(&client::Error::SecondaryRateLimit { .. })
+            | Some(&client::Error::BudgetExhausted { .. }) => ExitCode::RateLimited,
+            Some(&client::Error::Http(_)) | Some(&client::Error::Timeout(_)) => ExitCode::Network,
+            Some(&client::Error::Interrupted) => ExitCode::Interrupted,
+            _ => ExitCode::Failure,
+        }
+    }
+}
+
+/// Outcome of a run that did not fetch everything.
+#[derive(Debug)]
+pub struct Failed {
+    pub code: ExitCode,
+    pub message: String,
+}
+
+impl fmt::Display for Failed {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl error::Error for Failed {}
@@ -0,0 +1,351 @@
+use std::collections::BTreeMap;
+use std::error;
+use std::fs;
+use std::path::PathBuf;
+
+use chrono::{DateTime, Utc};
+use regex::{Captures, Regex};
+use serde_json::Value;
+
+use crate::entity::Entity;
+use crate::html_report::escape;
+use crate::snapshot::{self, Item};
+
+const STYLE: &str = "
+body { font-family: -apple-system, 'Segoe UI', Helvetica, Arial, sans-serif; color: #1f2328; max-width: 960px;
+       margin: 2em auto; padding: 0 1em; }
+a { color: #0969da; text-decoration: none; }
+a:hover { text-decoration: underline; }
+nav { color: #656d76; margin-bottom: 1em; }
+h1 { font-size: 1.6em; margin-bottom: .3em; }
+h1 .number { color: #656d76; font-weight: normal; }
+h2 { font-size: 1.2em; margin-top: 2em; border-bottom: 1px solid #d0d7de; padding-bottom: .3em; }
+table { width: 100%; border-collapse: collapse; }
+td, th { text-align: left; padding: .4em .6em .4em 0; border-bottom: 1px solid #d0d7de; vertical-align: top; }
+.meta, .none { color: #656d76; }
+.state { display: inline-block; border-radius: 2em; padding: .1em .7em; color: #fff; font-size: .9em; }
+.open { background: #1a7f37; }
+.closed { background: #cf222e; }
+.merged { background: #8250df; }
+.label { display: inline-block; border: 1px solid #d0d7de; border-radius: 2em; padding: 0 .5em; font-size: .85em;
+         margin-right: .3em; }
+.post { border: 1px solid #d0d7de; border-radius: 6px; margin: 1em 0; }
+.post header { background: #f6f8fa; border-bottom: 1px solid #d0d7de; padding: .5em 1em; color: #656d76; }
+.post .body { padding: .5em 1em; white-space: pre-wrap; overflow-wrap: break-word; }
+";
+
+/// References to issues and pull requests of the same repository in escaped
+/// text, not preceded by a word character or the `&` of an entity.
+const REFERENCE: &str = r"(^|[^\w&/])#(\d+)\b";
+
+#[derive(Debug)]
+pub struct ExportSiteConfig {
+    /// Output directory of a fetch, or the directory of one repository.
+    pub dir: PathBuf,
+    /// Directory to write the site to.
+    pub output: PathBuf,
+}
+
+/// The reference pattern and the entity of each number of a repository, to
+/// link references to the pages of their issues and pull requests.
+struct Links<'a> {
+    reference: &'a Regex,
+    numbers: &'a BTreeMap<u64, Entity>,
+}
+
+/// An issue or pull request with the record it was read from.
+struct Page {
+    item: Item,
+    record: Value,
+}
+
+/// Writes a static HTML site of the snapshots below `cfg.dir` to
+/// `cfg.output`: an index of the repositories, lists of the issues and pull
+/// requests of each and a page for each of them with its comments and
+/// reviews, all linked relatively so the site can be opened from disk.
+pub fn export_site(cfg: ExportSiteConfig) -> Result<(), Box<dyn error::Error>> {
+    let repos = snapshot::repositories(&cfg.dir)?;
+    if repos.is_empty() {
+        return Err(format!("no snapshots in {}", cfg.dir.display()).into());
+    }
+    let mut index = String::from("<table>\n<tr><th>Repository</th><th>Issues</th><th>Pull requests</th></tr>\n");
+    let reference = Regex::new(REFERENCE).unwrap();
+    let mut written = 0;
+    for (repo, dir) in &repos {
+        let mut pages: Vec<(Entity, Vec<Page>)> = Vec::new();
+        for entity in Entity::ALL {
+            let pull = *entity == Entity::Pulls;
+            let records = match snapshot::find(dir, *entity) {
+                Some((path, format)) => snapshot::records(&path, format, *entity)?,
+                None => Vec::new(),
+            };
+            let mut entity_pages: Vec<Page> = records
+                .into_iter()
+                .filter_map(|record| Item::of(&record, pull).map(|item| Page { item: item, record: record }))
+                .collect();
+            entity_pages.sort_by(|a, b| b.item.number.cmp(&a.item.number));
+            pages.push((*entity, entity_pages));
+        }
+        // where each number is, as `#n` may refer to an issue or a pull request
+        let numbers: BTreeMap<u64, Entity> = pages
+            .iter()
+            .flat_map(|(entity, pages)| pages.iter().map(move |page| (page.item.number, *entity)))
+            .collect();
+
+        let links = Links {
+            reference: &reference,
+            numbers: &numbers,
+        };
+        let out = repo.dir(&cfg.output);
+        let name = repo.to_string();
+        for (entity, pages) in &pages {
+            let entity_dir = out.join(entity.name());
+            fs::create_dir_all(&entity_dir)?;
+            for page in pages {
+                let path = entity_dir.join(format!("{}.html", page.item.number));
+                fs::write(&path, render_item(&name, page, &links))?;
+                written += 1;
+            }
+            let title = format!("{} {}", name, heading(*entity));
+            let body = format!(
+                "<nav><a href=\"../../../index.html\">Repositories</a> / <a href=\"../index.html\">{}</a></nav>\n\
+                 <h1>{}</h1>\n{}",
+                escape(&name),
+                heading(*entity),
+                render_list(pages)
+            );
+            fs::write(entity_dir.join("index.html"), document(&title, &body))?;
+        }
+        let count = |entity: Entity| pages.iter().find(|(e, _)| *e == entity).map_or(0, |(_, pages)| pages.len());
+        let body = format!(
+            "<nav><a href=\"../../index.html\">Repositories</a></nav>\n<h1>{}</h1>\n\
+             <ul>\n<li><a href=\"issues/index.html\">Issues</a> ({})</li>\n\
+             <li><a href=\"pulls/index.html\">Pull requests</a> ({})</li>\n</ul>\n",
+            escape(&name),
+            count(Entity::Issues),
+            count(Entity::Pulls)
+        );
+        fs::write(out.join("index.html"), document(&name, &body))?;
+        index.push_str(&format!(
+            "<tr><td><a href=\"{}/index.html\">{}</a></td><td>{}</td><td>{}</td></tr>\n",
+            escape(&format!("{}/{}", repo.owner, repo.name)),
+            escape(&name),
+            count(Entity::Issues),
+            count(Entity::Pulls)
+        ));
+        info!("{}: {} issues and {} pull requests", repo, count(Entity::Issues), count(Entity::Pulls));
+    }
+    index.push_str("</table>\n");
+    let body = format!(
+        "<h1>Archive</h1>\n<p class=\"meta\">Generated {}</p>\n{}",
+        Utc::now().format("%Y-%m-%d %H:%M UTC"),
+        index
+    );
+    fs::write(cfg.output.join("index.html"), document("Archive", &body))?;
+    info!("Wrote {} pages to {}", written, cfg.output.display());
+    Ok(())
+}
+
+fn heading(entity: Entity) -> &'static str {
+    match entity {
+        Entity::Issues => "Issues",
+        Entity::Pulls => "Pull requests",
+    }
+}
+
+/// A complete HTML document.
+fn document(title: &str, body: &str) -> String {
+    format!(
+        "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n\
+         <meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">\n\
+         <title>{}</title>\n<style>{}</style>\n</head>\n<body>\n{}</body>\n</html>\n",
+        escape(title),
+        STYLE,
+        body
+    )
+}
+
+/// Table of `pages` linking to their pages in the same directory.
+fn render_list(pages: &[Page]) -> String {
+    if pages.is_empty() {
+        return "<p class=\"none\">None</p>\n".to_string();
+    }
+    let mut html = String::from("<table>\n");
+    html.push_str("<tr><th>#</th><th>Title</th><th>State</th><th>Author</th><th>Opened</th></tr>\n");
+    for page in pages {
+        let item = &page.item;
+        html.push_str(&format!(
+            "<tr><td>{}</td><td><a href=\"{}.html\">{}</a> {}</td><td>{}</td><td>{}</td><td>{}</td></tr>\n",
+            item.number,
+            item.number,
+            escape(&item.title),
+            render_labels(&item.labels),
+            render_state(&item.state),
+            escape(item.author.as_ref().map_or("", |author| &author[..])),
+            date(item.created_at, "%Y-%m-%d")
+        ));
+    }
+    html.push_str("</table>\n");
+    html
+}
+
+/// The page of an issue or pull request of the repository `name`.
+fn render_item(name: &str, page: &Page, links: &Links) -> String {
+    let item = &page.item;
+    let record = &page.record;
+    let entity = if item.pull { Entity::Pulls } else { Entity::Issues };
+    let mut html = format!(
+        "<nav><a href=\"../../../index.html\">Repositories</a> / <a href=\"../../index.html\">{}</a> / \
+         <a href=\"index.html\">{}</a></nav>\n<h1>{} <span class=\"number\">#{}</span></h1>\n",
+        escape(name),
+        heading(entity),
+        escape(&item.title),
+        item.number
+    );
+
+    let mut meta = vec![render_state(&item.state)];
+    if let Some(ref author) = item.author {
+        meta.push(format!("opened by <b>{}</b> on {}", escape(author), date(item.created_at, "%Y-%m-%d %H:%M UTC")));
+    }
+    if item.merged_at.is_some() {
+        let by = text(record, "/merged_by/login", "/mergedBy/login").map_or(String::new(), |login| {
+            format!(" by <b>{}</b>", escape(login))
+        });
+        meta.push(format!("merged{} on {}", by, date(item.merged_at, "%Y-%m-%d %H:%M UTC")));
+    } else if item.closed_at.is_some() {
+        meta.push(format!("closed on {}", date(item.closed_at, "%Y-%m-%d %H:%M UTC")));
+    }
+    html.push_str(&format!("<p class=\"meta\">{}</p>\n", meta.join(" · ")));
+    if item.pull {
+        let base = text(record, "/base/ref", "/baseRefName");
+        let head = text(record, "/head/ref", "/headRefName");
+        if let (Some(base), Some(head)) = (base, head) {
+            html.push_str(&format!("<p class=\"meta\">{} into {}", escape(head), escape(base)));
+            let changes = ["additions", "deletions", "changed_files", "changedFiles"]
+                .iter()
+                .map(|field| record.get(*field).and_then(Value::as_u64))
+                .collect::<Vec<_>>();
+            if let (Some(additions), Some(deletions)) = (changes[0], changes[1]) {
+                html.push_str(&format!(", +{} -{}", additions, deletions));
+            }
+            if let Some(files) = changes[2].or(changes[3]) {
+                html.push_str(&format!(" in {} files", files));
+            }
+            html.push_str("</p>\n");
+        }
+    }
+    if !item.labels.is_empty() {
+        html.push_str(&format!("<p>{}</p>\n", render_labels(&item.labels)));
+    }
+
+    let body = record
+        .get("body")
+        .and_then(Value::as_str)
+        .filter(|body| !body.trim().is_empty())
+        .unwrap_or("No description provided.");
+    let author = item.author.as_ref().map(|author| &author[..]);
+    html.push_str(&render_post(author, item.created_at, "opened", Some(body), links));
+
+    // comments and reviews, in order
+    let mut posts: Vec<(Option<DateTime<Utc>>, String)> = Vec::new();
+    for comment in nodes(record, "comments") {
+        let time = time(comment, "createdAt");
+        let author = comment.pointer("/author/login").and_then(Value::as_str);
+        let body = comment.get("body").and_then(Value::as_str);
+        posts.push((time, render_post(author, time, "commented", body, links)));
+    }
+    for review in nodes(record, "reviews") {
+        let time = time(review, "submittedAt");
+        let author = review.pointer("/author/login").and_then(Value::as_str);
+        let state = review.get("state").and_then(Value::as_str).unwrap_or("").replace('_', " ").to_lowercase();
+        let body = review.get("body").and_then(Value::as_str).filter(|body| !body.is_empty());
+        posts.push((time, render_post(author, time, &format!("reviewed: {}", state), body, links)));
+    }
+    posts.sort_by(|a, b| a.0.cmp(&b.0));
+    for (_, post) in &posts {
+        html.push_str(post);
+    }
+    let shown = nodes(record, "comments").len() as u64;
+    if item.comments > shown {
+        html.push_str(&format!(
+            "<p class=\"none\">{} of {} comments are not in the snapshot.</p>\n",
+            item.comments - shown,
+            item.comments
+        ));
+    }
+    document(&format!("{} #{} · {}", item.title, item.number, name), &html)
+}
+
+/// A comment box, by `author` at `time`, with the `body` as it was written
+/// and its references to other issues and pull requests linked.
+fn render_post(
+    author: Option<&str>,
+    time: Option<DateTime<Utc>>,
+    action: &str,
+    body: Option<&str>,
+    links: &Links,
+) -> String {
+    let body = match body.map(str::trim).filter(|body| !body.is_empty()) {
+        Some(body) => links
+            .reference
+            .replace_all(&escape(body), |captures: &Captures| {
+                let number: Option<u64> = captures[2].parse().ok();
+                match number.and_then(|number| links.numbers.get(&number)) {
+                    Some(entity) => format!(
+                        "{}<a href=\"../{}/{}.html\">#{}</a>",
+                        &captures[1],
+                        entity.name(),
+                        &captures[2],
+                        &captures[2]
+                    ),
+                    None => captures[0].to_string(),
+                }
+            })
+            .into_owned(),
+        None => String::new(),
+    };
+    format!(
+        "<article class=\"post\">\n<header><b>{}</b> {} {}</header>\n<div class=\"body\">{}</div>\n</article>\n",
+        escape(author.unwrap_or("ghost")),
+        action,
+        date(time, "%Y-%m-%d %H:%M UTC"),
+        body
+    )
+}
+
+fn render_state(state: &str) -> String {
+    format!("<span class=\"state {}\">{}</span>", escape(state), escape(state))
+}
+
+fn render_labels(labels: &[String]) -> String {
+    labels
+        .iter()
+        .map(|label| format!("<span class=\"label\">{}</span>", escape(label)))
+        .collect::<Vec<_>>()
+        .join("")
+}
+
+fn date(time: Option<DateTime<Utc>>, format: &str) -> String {
+    time.map_or(String::new(), |time| time.format(format).to_string())
+}
+
+/// A text field of a REST or GraphQL record.
+fn text<'a>(record: &'a Value, rest: &str, graphql: &str) -> Option<&'a str> {
+    record.pointer(rest).or_else(|| record.pointer(graphql)).and_then(Value::as_str)
+}
+
+fn time(node: &Value, field: &str) -> Option<DateTime<Utc>> {
+    node.get(field)
+        .and_then(Value::as_str)
+        .and_then(|time| DateTime::parse_from_rfc3339(time).ok())
+        .map(|time| time.with_timezone(&Utc))
+}
+
+/// Nodes of a GraphQL connection of a record, none for REST records.
+fn nodes<'a>(record: &'a Value, connection: &str) -> &'a [Value] {
+    record
+        .get(connection)
+        .and_then(|connection| connection.get("nodes"))
+        .and_then(Value::as_array)
+        .map_or(&[], |nodes| &nodes[..])
+}
@@ -0,0 +1,1637 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::error;
+use std::fs;
+use std::io;
+use std::iter;
+use std::mem;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use chrono::{self, Utc};
+use futures::channel::mpsc;
+use futures::future::{self, Either};
+use futures::stream::{self, BoxStream};
+use futures::{Stream, StreamExt, TryStreamExt};
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use stream_throttle::{ThrottlePool, ThrottleRate};
+use tracing::{info_span, Instrument};
+
+use crate::api;
+use crate::auth::Credentials;
+use crate::bandwidth::Bandwidth;
+use crate::budget::{self, Pacer, RequestBudget};
+use crate::client::{self, Client, ClientConfig};
+use crate::dashboard::Dashboard;
+use crate::entity::{Entity, EntityFetcher, Registry};
+use crate::error::Error;
+use crate::exit::{ExitCode, Failed};
+use crate::filter::{self, Filters, Order};
+use crate::format::{self, Format};
+use crate::graphql;
+use crate::interrupt;
+use crate::kafka_sink::{self, KafkaConfig};
+use crate::lock;
+use crate::merge;
+use crate::metrics;
+use crate::model::{Issue, Pull};
+use crate::pick;
+use crate::progress::{self, Progress};
+use crate::redact::Redactor;
+use crate::repo::{self, Repo, RepoFilter};
+use crate::report::{self, Failure, ItemFailure, RepoReport, Report};
+use crate::retry::RetryPolicy;
+use crate::sample;
+use crate::sink::{FileSink, Record, Sink, StdoutSink};
+use crate::state::{self, Checkpoint, RunState, SyncDb};
+use crate::users::{self, UserDimension};
+use crate::vcr::Cassette;
+
+/// Where fetched records end up.
+#[derive(Debug)]
+pub enum Output {
+    /// Snapshot files below `<dir>/<owner>/<repo>`.
+    Directory(PathBuf),
+    /// Tagged NDJSON records on stdout.
+    Stdout,
+}
+
+/// What to fetch and how, as the fetch, watch and list subcommands take it.
+#[derive(Debug)]
+pub struct FetchConfig {
+    pub repos: Vec<Repo>,
+    pub owner: Option<String>,
+    pub repos_file: Option<String>,
+    /// Choose among the repositories of the owner in the terminal.
+    pub interactive: bool,
+    pub org: Option<String>,
+    pub user: Option<String>,
+    pub include_forks: bool,
+    pub repo_filter: RepoFilter,
+    pub credentials: Credentials,
+    pub api_url: String,
+    pub graphql_url: String,
+    pub proxy: Option<String>,
+    pub connect_timeout: Option<Duration>,
+    pub request_timeout: Option<Duration>,
+    pub pool_size: usize,
+    pub http2: bool,
+    /// Cassette to record the API responses to or replay them from.
+    pub cassette: Option<Cassette>,
+    pub output: Output,
+    /// Format of the snapshot files in the output directory.
+    pub format: Format,
+    pub since: Option<String>,
+    pub incremental: bool,
+    pub overwrite: bool,
+    /// Interval between the incremental syncs of daemon mode.
+    pub daemon: Option<Duration>,
+    pub resume: bool,
+    pub wait_for_lock: bool,
+    pub cache_dir: Option<PathBuf>,
+    pub no_cache: bool,
+    pub dedupe_users: bool,
+    /// Mask emails, tokens and other secrets in bodies before writing them.
+    pub redact: bool,
+    pub retry: RetryPolicy,
+    pub max_requests: Option<usize>,
+    /// Download limit in bytes per second.
+    pub max_bandwidth: Option<u64>,
+    pub concurrency: usize,
+    pub rate: usize,
+    pub parallel: usize,
+    pub per_page: u64,
+    pub start_page: u64,
+    /// Number of most recent issues and pulls to fetch, of the selected
+    /// entities together rather than of each.
+    pub limit: Option<usize>,
+    pub order: Order,
+    /// Size of a random sample of issues and pulls to fetch instead of all.
+    pub sample: Option<usize>,
+    pub graphql: bool,
+    pub dry_run: bool,
+    pub filters: Filters,
+    pub entities: Vec<Entity>,
+    /// Entity types fetched after the selected built-in ones.
+    pub fetchers: Registry,
+    pub kafka: Option<KafkaConfig>,
+    /// File for the node exporter's textfile collector to write metrics to.
+    pub metrics_file: Option<PathBuf>,
+    /// Pushgateway to push metrics to.
+    pub pushgateway: Option<String>,
+    /// OTLP collector to export tracing spans to.
+    pub otlp_endpoint: Option<String>,
+    /// Show the dashboard instead of log output.
+    pub tui: bool,
+    /// What the dashboard shows, also kept for the remaining quota of the
+    /// JSON progress events.
+    pub dashboard: Option<Dashboard>,
+    pub progress: progress::Mode,
+}
+impl FetchConfig {
+    fn client_config(&self, cache_db: Option<PathBuf>) -> ClientConfig {
+        ClientConfig {
+            credentials: self.credentials.clone(),
+            api_url: self.api_url.clone(),
+            graphql_url: self.graphql_url.clone(),
+            cache_db: cache_db,
+            retry: self.retry.clone(),
+            proxy: self.proxy.clone(),
+            connect_timeout: self.connect_timeout,
+            request_timeout: self.request_timeout,
+            pool_size: self.pool_size,
+            http2: self.http2,
+            cassette: self.cassette.clone(),
+            budget: self.max_requests.map(RequestBudget::new),
+            bandwidth: self.max_bandwidth.map(Bandwidth::new),
+            pacer: match self.credentials {
+                Credentials::Anonymous => Pacer::steady(),
+                _ => Pacer::default(),
+            },
+            dashboard: self.dashboard.clone(),
+        }
+    }
+
+    /// Sink for the records of one worker, by the output.
+    fn sink(&self) -> Box<dyn Sink> {
+        match self.output {
+            Output::Directory(ref dir) => Box::new(FileSink::new(dir, self.format)),
+            Output::Stdout => Box::new(StdoutSink),
+        }
+    }
+
+    /// Items per listing page, no more than a limited fetch needs.
+    fn page_size(&self) -> u64 {
+        self.limit.map_or(self.per_page, |limit| self.per_page.min(limit as u64))
+    }
+
+    /// Progress of a fetch phase, bars are only drawn for a single worker as
+    /// those of several would overwrite each other.
+    fn progress(&self, repo: &Repo, label: &str, len: Option<usize>, done: usize) -> Progress {
+        let (len, done) = (len.map(|len| len as u64), done as u64);
+        if self.progress == progress::Mode::Json {
+            return Progress::json(&repo.to_string(), label, len, done, self.dashboard.clone());
+        }
+        let bars = self.parallel == 1 && log_enabled!(log::Level::Info);
+        Progress::new(label, len, done, bars, self.dashboard.clone())
+    }
+}
+
+/// Connections used by the fetches of one worker.
+struct Session {
+    client: Client,
+    /// Sync state of the output directory.
+    db: Option<SyncDb>,
+    /// Where the records are written to, shared with the blocking writes. The
+    /// output comes first, a Kafka sink is shared by all workers.
+    sinks: Vec<Arc<Mutex<Box<dyn Sink>>>>,
+    /// What was fetched for the current repository.
+    report: RepoReport,
+    redactor: Option<Redactor>,
+}
+
+impl Session {
+    /// Snapshot of `entity` for `repo` that one of the sinks keeps.
+    fn snapshot(&self, repo: &Repo, entity: &str) -> Option<(PathBuf, Format)> {
+        self.sinks.iter().filter_map(|sink| sink.lock().unwrap().snapshot(repo, entity)).next()
+    }
+}
+
+/// Pages between checkpoints while listing issues.
+const CHECKPOINT_PAGES: usize = 10;
+/// Pull requests between checkpoints while fetching pull details.
+const CHECKPOINT_PULLS: usize = 100;
+
+async fn save_checkpoint(
+    checkpoint: &mut Checkpoint,
+    db: Option<&SyncDb>,
+    repo: &Repo,
+) -> Result<(), Box<dyn error::Error>> {
+    let db = match db {
+        Some(db) => db.clone(),
+        None => return Ok(()),
+    };
+    let changes = checkpoint.changes()?;
+    let repo = repo.to_string();
+    blocking(move || Checkpoint::save(&db, &repo, &changes)).await?;
+    checkpoint.saved();
+    Ok(())
+}
+
+/// Runs blocking file, database, terminal or broker I/O on the blocking
+/// thread pool, so that the other workers, polled by the same task, keep
+/// fetching meanwhile.
+async fn blocking<T, F>(f: F) -> Result<T, Box<dyn error::Error>>
+where
+    F: FnOnce() -> Result<T, Box<dyn error::Error>> + Send + 'static,
+    T: Send + 'static,
+{
+    let result = tokio::task::spawn_blocking(move || f().map_err(|e| e.to_string())).await?;
+    Ok(result?)
+}
+
+/// Compares the requests the run is estimated to need against the remaining
+/// quota, failing preflight queries only produce a warning.
+async fn preflight(cfg: &FetchConfig, session: &mut Session, repo: &Repo, since: Option<&str>) {
+    let client = &session.client;
+    let checks = future::try_join(budget::quota(client, false), budget::estimate(client, repo, since, &cfg.filters));
+    match checks.await {
+        Ok((quota, estimate)) => {
+            info!(
+                "Estimated requests: {} for {} issues and pulls, remaining quota: {}/{}",
+                estimate.requests(cfg.per_page),
+                estimate.items(),
+                quota.remaining,
+                quota.limit
+            );
+            if estimate.requests(cfg.per_page) > quota.remaining {
+                warn!(
+                    "the remaining quota is insufficient, the run will pause until it resets in {}s",
+                    quota.resets_in().as_secs()
+                );
+            }
+        }
+        Err(e) => warn!("rate limit preflight failed: {}", e),
+    }
+}
+
+/// First page of the issues listing of `repo`, of those changed since `since`
+/// if given.
+fn listing_path(cfg: &FetchConfig, repo: &Repo, since: Option<&str>) -> String {
+    let mut path = format!(
+        "/repos/{}/issues?per_page={}{}{}",
+        repo,
+        cfg.page_size(),
+        cfg.order.query(),
+        cfg.filters.query()
+    );
+    if cfg.start_page > 1 {
+        path.push_str(&format!("&page={}", cfg.start_page));
+    }
+    if let Some(since) = since {
+        path.push_str(&format!("&since={}", filter::encode(since)));
+    }
+    path
+}
+
+/// Issues listing of `repo` as its pages arrive, filtered and limited like
+/// that of a fetch.
+fn listing(
+    cfg: Arc<FetchConfig>,
+    client: &Client,
+    repo: &Repo,
+) -> BoxStream<'static, Result<api::Issue, client::Error>> {
+    let path = listing_path(&cfg, repo, cfg.since.as_ref().map(|s| &s[..]));
+    let (limit, counting) = (cfg.limit.unwrap_or(usize::max_value()), cfg.clone());
+    let mut counted = 0;
+    client
+        .get_pages::<api::Issue>(&path)
+        .try_filter(move |i| future::ready(cfg.filters.in_range(&i.created_at, &i.updated_at)))
+        .try_take_while(move |i| {
+            let more = counted < limit;
+            counted += limited(&counting, i) as usize;
+            future::ready(Ok(more))
+        })
+        .boxed()
+}
+
+/// Lists the issues, with `with_pulls` fetching the details of the pull
+/// requests among them meanwhile, as soon as their numbers are listed.
+async fn handle_issues(
+    cfg: &FetchConfig,
+    session: &mut Session,
+    repo: &Repo,
+    checkpoint: &mut Checkpoint,
+    with_pulls: bool,
+) -> Result<(Vec<Issue>, Vec<u64>), Box<dyn error::Error>> {
+    let start = match checkpoint.issues_next.clone() {
+        Some(next) => {
+            info!("Resuming issues at {}", next);
+            next
+        }
+        None => {
+            if let Some(ref since) = checkpoint.since {
+                info!("Fetching changes since {}", since);
+            }
+            listing_path(cfg, repo, checkpoint.since.as_ref().map(|s| &s[..]))
+        }
+    };
+    let client = &session.client;
+    let db = session.db.as_ref();
+    let redactor = session.redactor.as_ref();
+    let limit = cfg.limit.unwrap_or(usize::max_value());
+    // pull numbers are queued while listing, the pulls end once the queue is
+    // dropped and those queued are fetched
+    let (sender, queued) = mpsc::unbounded();
+    let mut numbers = Some(sender).filter(|_| with_pulls);
+    if let Some(ref numbers) = numbers {
+        // pulls listed before the run was interrupted
+        let done: HashSet<u64> = checkpoint.pulls.iter().map(|p| p.number).collect();
+        for issue in checkpoint.listing.iter().filter(|i| i.pull_request.is_some() && !done.contains(&i.number)) {
+            let _ = numbers.unbounded_send(issue.number);
+        }
+    }
+    let mut pages = Some(client.pages::<api::Issue>(&start))
+        .filter(|_| !checkpoint.listing_done && limited_len(cfg, &checkpoint.listing) < limit);
+    if pages.is_none() {
+        // nothing more is listed, the pulls end with those queued
+        numbers = None;
+    }
+    let mut pulls = Some(pull_details(client, repo, queued, cfg.concurrency));
+    let mut fetched = 0;
+    let mut failed = Vec::new();
+    let mut progress = cfg.progress(repo, "Listing", None, checkpoint.listing.len());
+    let mut pulls_progress = if with_pulls {
+        Some(cfg.progress(repo, "Pulls", None, checkpoint.pulls.len()))
+    } else {
+        None
+    };
+    loop {
+        let next = match (pages.as_mut(), pulls.as_mut()) {
+            (Some(pages), Some(pulls)) => match future::select(pages.next(), pulls.next()).await {
+                Either::Left((page, _)) => Either::Left(page),
+                Either::Right((pull, _)) => Either::Right(pull),
+            },
+            (Some(pages), None) => Either::Left(pages.next().await),
+            (None, Some(pulls)) => Either::Right(pulls.next().await),
+            (None, None) => break,
+        };
+        match next {
+            Either::Left(Some(page)) => {
+                let (items, next) = page?;
+                let listed = checkpoint.listing.len();
+                checkpoint.listing.extend(
+                    items
+                        .into_iter()
+                        .filter(|i| cfg.filters.in_range(&i.created_at, &i.updated_at)),
+                );
+                let end = limited_end(cfg, &checkpoint.listing, limit);
+                checkpoint.listing.truncate(end);
+                // the checkpoint is written to disk as well
+                if let Some(redactor) = redactor {
+                    for issue in &mut checkpoint.listing[listed..] {
+                        redactor.redact_body(&mut issue.body);
+                    }
+                }
+                if let Some(ref numbers) = numbers {
+                    for issue in checkpoint.listing[listed..].iter().filter(|i| i.pull_request.is_some()) {
+                        let _ = numbers.unbounded_send(issue.number);
+                    }
+                }
+                progress.inc((checkpoint.listing.len() - listed) as u64);
+                checkpoint.issues_next = next;
+                fetched += 1;
+                if fetched % CHECKPOINT_PAGES == 0 {
+                    save_checkpoint(checkpoint, db, repo).await?;
+                }
+                if limited_len(cfg, &checkpoint.listing) >= limit {
+                    pages = None;
+                    numbers = None;
+                    checkpoint.listing_done = true;
+                    save_checkpoint(checkpoint, db, repo).await?;
+                }
+            }
+            Either::Left(None) => {
+                pages = None;
+                numbers = None;
+                // a resumed run only fetches the pulls not fetched yet
+                checkpoint.listing_done = true;
+                save_checkpoint(checkpoint, db, repo).await?;
+            }
+            Either::Right(Some(pull)) => {
+                if let Some(ref mut progress) = pulls_progress {
+                    progress.inc(1);
+                }
+                if record_pull(checkpoint, &mut failed, repo, redactor, pull?)
+                    && checkpoint.pulls.len() % CHECKPOINT_PULLS == 0
+                {
+                    save_checkpoint(checkpoint, db, repo).await?;
+                }
+            }
+            Either::Right(None) => pulls = None,
+        }
+    }
+    progress.finish();
+    if let Some(progress) = pulls_progress {
+        progress.finish();
+    }
+    session.report.failed_items.extend(failed);
+
+    let mut listing = mem::replace(&mut checkpoint.listing, Vec::new());
+    let end = limited_end(cfg, &listing, limit);
+    listing.truncate(end);
+    Ok(split_listing(listing))
+}
+
+/// Whether a listed issue or pull request counts towards `--limit`, only
+/// those of the entities fetched do.
+fn limited(cfg: &FetchConfig, issue: &api::Issue) -> bool {
+    let entity = if issue.pull_request.is_some() { Entity::Pulls } else { Entity::Issues };
+    cfg.entities.contains(&entity)
+}
+
+/// Number of the `listing` counting towards `--limit`.
+fn limited_len(cfg: &FetchConfig, listing: &[api::Issue]) -> usize {
+    listing.iter().filter(|i| limited(cfg, i)).count()
+}
+
+/// Length of the start of `listing` holding the first `limit` of it that
+/// count towards `--limit`.
+fn limited_end(cfg: &FetchConfig, listing: &[api::Issue], limit: usize) -> usize {
+    let mut counted = 0;
+    listing
+        .iter()
+        .position(|i| {
+            let end = counted == limit;
+            counted += limited(cfg, i) as usize;
+            end
+        })
+        .unwrap_or_else(|| listing.len())
+}
+
+/// Lists a uniform random sample of `size` issues and pull requests by probing
+/// random numbers up to the most recent one, until enough of them exist.
+async fn handle_sample(
+    cfg: &FetchConfig,
+    session: &mut Session,
+    repo: &Repo,
+    size: usize,
+) -> Result<(Vec<Issue>, Vec<u64>), Box<dyn error::Error>> {
+    let latest = sample::latest_number(&session.client, repo).await?.unwrap_or(0);
+    let mut candidates = sample::candidates(latest).into_iter();
+    let mut listing = Vec::new();
+    let mut probed = 0;
+    while listing.len() < size {
+        let numbers: Vec<u64> = candidates.by_ref().take(size - listing.len()).collect();
+        if numbers.is_empty() {
+            break;
+        }
+        probed += numbers.len();
+        let found: Vec<_> = sample::probe(&session.client, repo, numbers, cfg.concurrency)
+            .try_collect()
+            .await?;
+        let listed = listing.len();
+        listing.extend(
+            found
+                .into_iter()
+                .filter_map(|i| i)
+                .filter(|i| cfg.filters.in_range(&i.created_at, &i.updated_at)),
+        );
+        if let Some(ref redactor) = session.redactor {
+            for issue in &mut listing[listed..] {
+                redactor.redact_body(&mut issue.body);
+            }
+        }
+    }
+    info!("Sampled {} of {} numbers, {} probed", listing.len(), latest, probed);
+    listing.sort_by_key(|i| i.number);
+    Ok(split_listing(listing))
+}
+
+/// Splits the issues listing into issues and the numbers of pull requests.
+fn split_listing(listing: Vec<api::Issue>) -> (Vec<Issue>, Vec<u64>) {
+    let (issues, pr_nums): (Vec<_>, Vec<_>) = listing
+        .into_iter()
+        .partition(|i| !i.pull_request.is_some());
+
+    info!("Issues: {:?}", issues.len());
+    (
+        issues.into_iter().map(Issue::from).collect(),
+        pr_nums.into_iter().map(|i| i.number).collect(),
+    )
+}
+
+/// Details of the pull requests `numbers` of `repo`, `concurrency` of them
+/// fetched at a time. A pull that can't be fetched is passed on with its
+/// error, failures affecting every further request end the fetch.
+fn pull_details<'a, S>(
+    client: &'a Client,
+    repo: &'a Repo,
+    numbers: S,
+    concurrency: usize,
+) -> impl Stream<Item = Result<Result<api::Pull, (u64, client::Error)>, Error>> + Unpin + 'a
+where
+    S: Stream<Item = u64> + Unpin + 'a,
+{
+    numbers
+        .map(move |n| async move {
+            match client.get::<api::Pull>(&format!("/repos/{}/pulls/{}", repo, n)).await {
+                Ok(pull) => Ok(Ok(pull)),
+                Err(e) if e.affects_item_only() => Ok(Err((n, e))),
+                Err(e) => Err(Error::Item {
+                    entity: Entity::Pulls.tag(),
+                    number: n,
+                    source: e,
+                }),
+            }
+        })
+        .buffer_unordered(concurrency)
+}
+
+/// Adds a fetched pull to `checkpoint`, its body masked by `redactor`, or a
+/// failed one to `failed`, returns whether it was fetched.
+fn record_pull(
+    checkpoint: &mut Checkpoint,
+    failed: &mut Vec<ItemFailure>,
+    repo: &Repo,
+    redactor: Option<&Redactor>,
+    pull: Result<api::Pull, (u64, client::Error)>,
+) -> bool {
+    match pull {
+        Ok(mut pull) => {
+            if let Some(redactor) = redactor {
+                redactor.redact_body(&mut pull.body);
+            }
+            checkpoint.pulls.push(pull);
+            true
+        }
+        Err((number, e)) => {
+            warn!("failed to fetch pull {} of {}: {}", number, repo, e);
+            failed.push(ItemFailure {
+                repository: repo.to_string(),
+                entity: Entity::Pulls.name().to_string(),
+                number: number,
+                error: e.to_string(),
+                kind: e.kind(),
+                gone: e.is_gone(),
+            });
+            false
+        }
+    }
+}
+
+/// Fetches the details of the listed pull requests not fetched yet.
+async fn handle_pulls(
+    cfg: &FetchConfig,
+    session: &mut Session,
+    repo: &Repo,
+    checkpoint: &mut Checkpoint,
+) -> Result<Vec<Pull>, Box<dyn error::Error>> {
+    // those fetched along with the listing, failed ones included
+    let done: HashSet<u64> = checkpoint
+        .pulls
+        .iter()
+        .map(|p| p.number)
+        .chain(
+            session
+                .report
+                .failed_items
+                .iter()
+                .filter(|f| f.entity == Entity::Pulls.name())
+                .map(|f| f.number),
+        )
+        .collect();
+    let todo: Vec<u64> = checkpoint.pr_nums.iter().cloned().filter(|n| !done.contains(n)).collect();
+    if !todo.is_empty() {
+        if !done.is_empty() {
+            info!("Resuming pulls, {} already fetched", done.len());
+        }
+        let db = session.db.as_ref();
+        let redactor = session.redactor.as_ref();
+        let mut progress = cfg.progress(repo, "Pulls", Some(checkpoint.pr_nums.len()), done.len());
+        let mut failed = Vec::new();
+        let mut pulls = pull_details(&session.client, repo, stream::iter(todo), cfg.concurrency);
+        while let Some(pull) = pulls.try_next().await? {
+            progress.inc(1);
+            if record_pull(checkpoint, &mut failed, repo, redactor, pull)
+                && checkpoint.pulls.len() % CHECKPOINT_PULLS == 0
+            {
+                save_checkpoint(checkpoint, db, repo).await?;
+            }
+        }
+        progress.finish();
+        session.report.failed_items.extend(failed);
+    }
+
+    // back into listing order
+    let position: HashMap<u64, usize> = checkpoint
+        .pr_nums
+        .iter()
+        .enumerate()
+        .map(|(i, &n)| (n, i))
+        .collect();
+    let mut pulls = mem::replace(&mut checkpoint.pulls, Vec::new());
+    pulls.sort_by_key(|p| position.get(&p.number).cloned());
+    Ok(pulls.into_iter().map(Pull::from).collect())
+}
+
+async fn write_output<D, K>(
+    cfg: &FetchConfig,
+    session: &mut Session,
+    repo: &Repo,
+    entity: Entity,
+    records: &[D],
+    key: K,
+) -> Result<(), Box<dyn error::Error>>
+where
+    D: Serialize + DeserializeOwned + Clone + Send + 'static,
+    K: Fn(&D) -> Option<u64>,
+{
+    write_records(cfg, session, repo, entity.name(), entity.tag(), Some(cfg.graphql), records, key).await
+}
+
+/// Writes `records` to the sinks, to those keeping a snapshot of `entity`
+/// merged into it unless overwriting. A snapshot of another shape than the
+/// records, e.g. one written without `--dedupe-users` or through the other
+/// API than `graphql` tells for issues and pulls, fails the merge rather than
+/// being overwritten. The snapshot is read and the records written on the
+/// blocking thread pool.
+async fn write_records<D, K>(
+    cfg: &FetchConfig,
+    session: &mut Session,
+    repo: &Repo,
+    entity: &str,
+    tag: &str,
+    graphql: Option<bool>,
+    records: &[D],
+    key: K,
+) -> Result<(), Box<dyn error::Error>>
+where
+    D: Serialize + DeserializeOwned + Clone + Send + 'static,
+    K: Fn(&D) -> Option<u64>,
+{
+    // it couldn't be merged into the snapshot by the next run
+    if records.iter().any(|r| key(r).is_none()) {
+        return Err(format!("a record of {} has no key", entity).into());
+    }
+    let snapshot = session.snapshot(repo, entity).filter(|_| !cfg.overwrite);
+    let existing: Vec<D> = match snapshot {
+        Some((ref path, format)) => {
+            let path = path.clone();
+            blocking(move || {
+                let existing = match format::deserialize_from_file(format, &path) {
+                    Ok(existing) => existing,
+                    Err(e) => {
+                        // GraphQL nodes don't decode as REST records
+                        if let Some(graphql) = graphql {
+                            if let Ok(existing) = format::deserialize_from_file::<Value>(format, &path) {
+                                check_api(&path, &existing, graphql)?;
+                            }
+                        }
+                        let e = format!("can't merge into {}, written in another shape? {}", path.display(), e);
+                        return Err(e.into());
+                    }
+                };
+                if let Some(graphql) = graphql {
+                    check_api(&path, &existing, graphql)?;
+                }
+                Ok(existing)
+            }).await?
+        }
+        None => Vec::new(),
+    };
+    let merged: Option<Vec<D>> = if existing.is_empty() {
+        None
+    } else {
+        let path = snapshot.map_or_else(PathBuf::new, |(path, _)| path);
+        let merged = merge::upsert(&existing, records, &key)
+            .map_err(|e| format!("can't merge into {}: {}, fetch with --overwrite to replace it", path.display(), e))?;
+        let known: HashSet<Option<u64>> = existing.iter().map(|r| key(r)).collect();
+        let added = records.iter().filter(|r| !known.contains(&key(r))).count();
+        info!(
+            "Merged {}: {} new, {} updated, {} total",
+            entity,
+            added,
+            records.len() - added,
+            merged.len()
+        );
+        Some(merged.into_iter().cloned().collect())
+    };
+    let fetched = records.to_vec();
+    let bytes = {
+        let (sinks, repo, entity, tag) = (session.sinks.clone(), repo.clone(), entity.to_string(), tag.to_string());
+        blocking(move || {
+            let fetched: Vec<Record> = fetched.iter().map(|r| r as Record).collect();
+            let merged: Option<Vec<Record>> = merged.as_ref().map(|m| m.iter().map(|r| r as Record).collect());
+            let mut bytes = None;
+            for sink in &sinks {
+                let mut sink = sink.lock().unwrap();
+                // sinks keeping a snapshot get the records merged into it,
+                // the others only those fetched
+                let records = match merged {
+                    Some(ref merged) if sink.snapshot(&repo, &entity).is_some() => merged,
+                    _ => &fetched,
+                };
+                bytes = sink.write(&repo, &entity, &tag, records)?.or(bytes);
+            }
+            Ok(bytes)
+        }).await?
+    };
+    session.report.written(entity, records.len(), bytes);
+    Ok(())
+}
+
+/// Fails if the `existing` records of the snapshot at `path` were written
+/// through the other API, REST records and GraphQL nodes having different
+/// fields.
+fn check_api<D: Serialize>(path: &Path, existing: &[D], graphql: bool) -> Result<(), Box<dyn error::Error>> {
+    // both have a number, only GraphQL nodes a databaseId
+    let mut nodes = 0;
+    for record in existing {
+        if serde_json::to_value(record)?.get("databaseId").is_some() {
+            nodes += 1;
+        }
+    }
+    let (mixed, api) = if graphql {
+        (nodes < existing.len(), "REST")
+    } else {
+        (nodes > 0, "GraphQL")
+    };
+    if mixed {
+        return Err(format!(
+            "{} was written through the {} API, fetch into another directory or with --overwrite to replace it",
+            path.display(),
+            api
+        ).into());
+    }
+    Ok(())
+}
+
+/// Fetches issues and pull requests through the REST API, checkpointing
+/// progress in the output directory. Returns the start time of the run, which
+/// predates this invocation when resuming.
+async fn fetch_rest(
+    cfg: &FetchConfig,
+    session: &mut Session,
+    repo: &Repo,
+    started: String,
+    since: Option<String>,
+) -> Result<String, Box<dyn error::Error>> {
+    preflight(cfg, session, repo, since.as_ref().map(|s| &s[..])).await;
+
+    let mut checkpoint = match session.db {
+        Some(ref db) if cfg.resume => {
+            let (db, repo) = (db.clone(), repo.to_string());
+            blocking(move || Checkpoint::load(&db, &repo)).await?
+        }
+        _ => None,
+    }.unwrap_or_else(|| {
+        if cfg.resume {
+            info!("No checkpoint found, starting over");
+        }
+        Checkpoint::new(started, since)
+    });
+
+    if let Err(e) = fetch_entities(cfg, session, repo, &mut checkpoint).await {
+        // keep what was fetched so far for --resume
+        save_checkpoint(&mut checkpoint, session.db.as_ref(), repo).await?;
+        return Err(e);
+    }
+    Ok(checkpoint.started)
+}
+
+/// Fetches and writes the selected entities, recording progress in
+/// `checkpoint`.
+async fn fetch_entities(
+    cfg: &FetchConfig,
+    session: &mut Session,
+    repo: &Repo,
+    checkpoint: &mut Checkpoint,
+) -> Result<(), Box<dyn error::Error>> {
+    let listed = if checkpoint.issues_done {
+        None
+    } else {
+        let started = Instant::now();
+        let listing = async {
+            match cfg.sample {
+                Some(size) => handle_sample(cfg, session, repo, size).await,
+                None => {
+                    let with_pulls = cfg.entities.contains(&Entity::Pulls);
+                    handle_issues(cfg, session, repo, checkpoint, with_pulls).await
+                }
+            }
+        };
+        let (issues, pr_nums) = listing
+            .instrument(info_span!("phase", phase = "listing"))
+            .await
+            .map_err(|e| Error::phase("listing", e))?;
+        session.report.phase("listing", started);
+        checkpoint.pr_nums = pr_nums;
+        Some(issues)
+    };
+
+    for entity in &cfg.entities {
+        let started = Instant::now();
+        if *entity != Entity::Issues && !checkpoint.issues_done {
+            // the issues are written, the listing is no longer needed
+            checkpoint.issues_done = true;
+            save_checkpoint(checkpoint, session.db.as_ref(), repo).await?;
+        }
+        fetch_entity(cfg, session, repo, checkpoint, *entity, listed.as_ref())
+            .instrument(info_span!("phase", phase = entity.name()))
+            .await
+            .map_err(|e| Error::phase(entity.name(), e))?;
+        session.report.phase(entity.name(), started);
+    }
+
+    if cfg.dedupe_users {
+        let records = mem::replace(&mut checkpoint.users, UserDimension::default()).into_records();
+        info!("Users: {}", records.len());
+        write_records(cfg, session, repo, "users", "user", None, &records, |u| users::key_of(u, "id"))
+            .await
+            .map_err(|e| Error::phase("users", e))?;
+    }
+
+    Ok(())
+}
+
+/// Fetches and writes `entity`, the issues from the `listed` ones unless
+/// they are written already.
+async fn fetch_entity(
+    cfg: &FetchConfig,
+    session: &mut Session,
+    repo: &Repo,
+    checkpoint: &mut Checkpoint,
+    entity: Entity,
+    listed: Option<&Vec<Issue>>,
+) -> Result<(), Box<dyn error::Error>> {
+    match entity {
+        Entity::Issues => if let Some(issues) = listed {
+            if cfg.dedupe_users {
+                let records = checkpoint.users.extract(issues)?;
+                write_output(cfg, session, repo, entity, &records, |i| users::key_of(i, "number")).await?;
+            } else {
+                write_output(cfg, session, repo, entity, issues, |i| Some(i.number)).await?;
+            }
+        },
+        Entity::Pulls => {
+            info!("Pulls: {}", checkpoint.pr_nums.len());
+            let pulls = handle_pulls(cfg, session, repo, checkpoint).await?;
+            if cfg.dedupe_users {
+                let records = checkpoint.users.extract(&pulls)?;
+                write_output(cfg, session, repo, entity, &records, |p| users::key_of(p, "number")).await?;
+            } else {
+                write_output(cfg, session, repo, entity, &pulls, |p| Some(p.number)).await?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Fetches issues and pull requests along with their comments and reviews
+/// through the GraphQL API, a page of nodes per request.
+async fn fetch_graphql(
+    cfg: &FetchConfig,
+    session: &mut Session,
+    repo: &Repo,
+    since: Option<&str>,
+) -> Result<(), Box<dyn error::Error>> {
+    if let Some(since) = since {
+        info!("Fetching changes since {}", since);
+    }
+    let mut fetched = Vec::new();
+    for entity in &cfg.entities {
+        let started = Instant::now();
+        let records = graphql_nodes(cfg, session, repo, since, *entity)
+            .instrument(info_span!("phase", phase = entity.name()))
+            .await
+            .map_err(|e| Error::phase(entity.name(), e))?;
+        session.report.phase(entity.name(), started);
+        fetched.push((*entity, records));
+    }
+    if let Some(limit) = cfg.limit {
+        fetched = most_recent(cfg, fetched, limit);
+    }
+    for (entity, records) in fetched {
+        info!("{}: {}", entity.name(), records.len());
+        write_output(cfg, session, repo, entity, &records, |r| users::key_of(r, "number"))
+            .await
+            .map_err(|e| Error::phase(entity.name(), e))?;
+    }
+    Ok(())
+}
+
+/// Fetches the nodes of `entity` through the GraphQL API, no more than
+/// `--limit`, which the nodes of both entities share.
+async fn graphql_nodes(
+    cfg: &FetchConfig,
+    session: &Session,
+    repo: &Repo,
+    since: Option<&str>,
+    entity: Entity,
+) -> Result<Vec<Value>, Box<dyn error::Error>> {
+    let client = &session.client;
+    let nodes = match entity {
+        Entity::Issues => graphql::issues(client, repo, cfg.page_size(), since, &cfg.filters, &cfg.order),
+        Entity::Pulls => graphql::pulls(client, repo, cfg.page_size(), since, &cfg.filters, &cfg.order),
+    };
+    let mut progress = cfg.progress(repo, entity.name(), None, 0);
+    let nodes = nodes.inspect(|_| progress.inc(1));
+    let mut records: Vec<Value> = match cfg.limit {
+        Some(limit) => nodes.take(limit).try_collect().await?,
+        None => nodes.try_collect().await?,
+    };
+    progress.finish();
+    records.sort_by(|a, b| cfg.order.cmp_graphql_nodes(a, b));
+    // before the records reach any sink
+    redact(session, &mut records);
+    Ok(records)
+}
+
+/// The first `limit` of the `fetched` nodes of all entities together in the
+/// order of the fetch, as the REST listing of both limits them.
+fn most_recent(cfg: &FetchConfig, fetched: Vec<(Entity, Vec<Value>)>, limit: usize) -> Vec<(Entity, Vec<Value>)> {
+    let mut all: Vec<(Entity, Value)> = fetched
+        .into_iter()
+        .flat_map(|(entity, records)| records.into_iter().map(move |record| (entity, record)))
+        .collect();
+    // stable, so each entity keeps its order
+    all.sort_by(|a, b| cfg.order.cmp_graphql_nodes(&a.1, &b.1));
+    all.truncate(limit);
+    cfg.entities
+        .iter()
+        .map(|&entity| {
+            let records = all.iter().filter(|(e, _)| *e == entity).map(|(_, record)| record.clone()).collect();
+            (entity, records)
+        })
+        .collect()
+}
+
+/// Masks the secrets in the bodies of `records` if redacting, the REST issues
+/// and pulls are masked as they are listed and fetched instead.
+fn redact(session: &Session, records: &mut [Value]) {
+    if let Some(ref redactor) = session.redactor {
+        for record in records {
+            redactor.redact_value(record);
+        }
+    }
+}
+
+/// Fetches and writes the entities of the registered fetchers.
+async fn fetch_registered(
+    cfg: &FetchConfig,
+    session: &mut Session,
+    repo: &Repo,
+    since: Option<&str>,
+) -> Result<(), Box<dyn error::Error>> {
+    for fetcher in cfg.fetchers.all() {
+        let started = Instant::now();
+        fetch_registered_entity(cfg, session, repo, since, fetcher)
+            .instrument(info_span!("phase", phase = fetcher.name()))
+            .await
+            .map_err(|e| Error::phase(fetcher.name(), e))?;
+        session.report.phase(fetcher.name(), started);
+    }
+    Ok(())
+}
+
+/// Fetches and writes the entity of `fetcher`.
+async fn fetch_registered_entity(
+    cfg: &FetchConfig,
+    session: &mut Session,
+    repo: &Repo,
+    since: Option<&str>,
+    fetcher: &dyn EntityFetcher,
+) -> Result<(), Box<dyn error::Error>> {
+    let mut progress = cfg.progress(repo, fetcher.name(), None, 0);
+    let records = fetcher.fetch(&session.client, repo, since).inspect(|_| progress.inc(1));
+    let mut records: Vec<Value> = records.try_collect().await?;
+    progress.finish();
+    redact(session, &mut records);
+    info!("{}: {}", fetcher.name(), records.len());
+    write_records(cfg, session, repo, fetcher.name(), fetcher.tag(), None, &records, |r| fetcher.key(r)).await
+}
+
+/// Repositories listed in `file`, or on stdin for `-`.
+fn read_repos_file(file: &str, owner: Option<&str>) -> Result<Vec<Repo>, Box<dyn error::Error>> {
+    if file == "-" {
+        let stdin = io::stdin();
+        return repo::read_list(stdin.lock(), owner);
+    }
+    let reader = fs::File::open(file).map_err(|e| format!("failed to open {}: {}", file, e))?;
+    repo::read_list(io::BufReader::new(reader), owner)
+}
+
+/// Repositories given on the command line and in the repository file followed
+/// by those of the organization and the user, each only once.
+async fn resolve_repos(cfg: &FetchConfig, client: &Client) -> Result<Vec<Repo>, Box<dyn error::Error>> {
+    let mut repos = cfg.repos.clone();
+    let owner = cfg.owner.as_ref().map(|o| &o[..]);
+    if let Some(file) = cfg.repos_file.clone() {
+        let owner = cfg.owner.clone();
+        repos.extend(blocking(move || read_repos_file(&file, owner.as_ref().map(|o| &o[..]))).await?);
+    }
+    if let (true, Some(owner)) = (cfg.interactive, owner) {
+        let listed = repo::owner_repos(client, owner).await?;
+        let selectable: Vec<_> = listed.into_iter().filter(|info| cfg.repo_filter.matches(info)).collect();
+        repos.extend(blocking(move || pick::pick(&selectable)).await?);
+    }
+    if let Some(ref org) = cfg.org {
+        let listed = repo::org_repos(client, org).await?;
+        let selected: Vec<_> = listed.iter().filter(|info| cfg.repo_filter.matches(info)).collect();
+        info!("Organization {}: {} of {} repositories", org, selected.len(), listed.len());
+        repos.extend(selected.iter().map(|info| info.repo()));
+    }
+    if let Some(ref user) = cfg.user {
+        let listed = repo::user_repos(client, user).await?;
+        let selected: Vec<_> = listed
+            .iter()
+            .filter(|info| (cfg.include_forks || !info.fork) && cfg.repo_filter.matches(info))
+            .collect();
+        info!("User {}: {} of {} repositories", user, selected.len(), listed.len());
+        repos.extend(selected.iter().map(|info| info.repo()));
+    }
+    let mut seen = HashSet::new();
+    repos.retain(|repo| seen.insert(repo.to_string()));
+    Ok(repos)
+}
+
+/// Prints what a fetch with `cfg` would cost, counted through the search API.
+async fn dry_run(cfg: &FetchConfig) -> Result<(), Box<dyn error::Error>> {
+    let throttle = ThrottlePool::new(ThrottleRate::new(cfg.rate, Duration::from_secs(1)));
+    let client = Client::new(&cfg.client_config(None), throttle)?;
+    let quota = budget::quota(&client, false).await?;
+    let db = existing_db(cfg)?;
+
+    let mut requests = 0;
+    for repo in &resolve_repos(cfg, &client).await? {
+        let since = changes_since(cfg, db.as_ref(), repo)?;
+        let estimate = budget::estimate(&client, repo, since.as_ref().map(|s| &s[..]), &cfg.filters).await?;
+        let duration = estimate.duration(cfg.per_page, cfg.rate as u64, &quota).as_secs();
+        requests += estimate.requests(cfg.per_page);
+
+        println!("{}", repo);
+        if let Some(since) = since {
+            println!("  Changes since:  {}", since);
+        }
+        println!("  Issues:         {} open, {} closed", estimate.open_issues, estimate.closed_issues);
+        println!("  Pull requests:  {}", estimate.pulls);
+        println!("  Requests:       {}", estimate.requests(cfg.per_page));
+        println!("  Duration:       ~{}h {}m {}s", duration / 3600, duration / 60 % 60, duration % 60);
+        println!("  Disk space:     ~{:.1} MiB", estimate.disk_bytes() as f64 / (1024.0 * 1024.0));
+    }
+    println!("Requests: {} of {} remaining", requests, quota.remaining);
+    Ok(())
+}
+
+/// Prints the repositories and entities a fetch with `cfg` would fetch, with
+/// the number of items counted through the search API rather than paginated.
+async fn list(cfg: &FetchConfig) -> Result<(), Box<dyn error::Error>> {
+    let throttle = ThrottlePool::new(ThrottleRate::new(cfg.rate, Duration::from_secs(1)));
+    let client = Client::new(&cfg.client_config(None), throttle)?;
+    let db = existing_db(cfg)?;
+
+    let repos = resolve_repos(cfg, &client).await?;
+    let mut totals = vec![0; cfg.entities.len()];
+    for repo in &repos {
+        let since = changes_since(cfg, db.as_ref(), repo)?;
+        let estimate = budget::estimate(&client, repo, since.as_ref().map(|s| &s[..]), &cfg.filters).await?;
+
+        match since {
+            Some(since) => println!("{} (changes since {})", repo, since),
+            None => println!("{}", repo),
+        }
+        for (entity, total) in cfg.entities.iter().zip(&mut totals) {
+            let count = estimate.count(*entity);
+            // a limited or sampled fetch takes no more than that many of the
+            // entities together, assumed to be split as all of them are
+            let all: u64 = cfg.entities.iter().map(|entity| estimate.count(*entity)).sum();
+            let count = match cfg.limit.or(cfg.sample) {
+                Some(n) if all > n as u64 => count * n as u64 / all,
+                _ => count,
+            };
+            *total += count;
+            match *entity {
+                Entity::Issues if cfg.limit.is_none() && cfg.sample.is_none() => println!(
+                    "  {:<8} ~{} ({} open, {} closed)",
+                    entity.name(),
+                    count,
+                    estimate.open_issues,
+                    estimate.closed_issues
+                ),
+                _ => println!("  {:<8} ~{}", entity.name(), count),
+            }
+        }
+    }
+    println!("{} repositories", repos.len());
+    for (entity, total) in cfg.entities.iter().zip(&totals) {
+        println!("  {:<8} ~{}", entity.name(), total);
+    }
+    Ok(())
+}
+
+/// Sync state database of the output directory, only read so a missing one
+/// is not created.
+fn existing_db(cfg: &FetchConfig) -> Result<Option<SyncDb>, Box<dyn error::Error>> {
+    Ok(match cfg.output {
+        Output::Directory(ref dir) if dir.join(state::DB_FILE).exists() => {
+            Some(SyncDb::open(&dir.join(state::DB_FILE))?)
+        }
+        _ => None,
+    })
+}
+
+/// Time to fetch changes of `repo` since, given by `--since` or the last sync
+/// of an incremental fetch.
+fn changes_since(cfg: &FetchConfig, db: Option<&SyncDb>, repo: &Repo) -> Result<Option<String>, Box<dyn error::Error>> {
+    Ok(match (&cfg.since, &cfg.output) {
+        (&Some(ref since), _) => Some(since.clone()),
+        (&None, &Output::Directory(ref dir)) if cfg.incremental => last_synced(cfg, db, dir, repo)?,
+        _ => None,
+    })
+}
+
+/// Fetches a single repository into its directory below the output directory.
+async fn fetch_repo(cfg: &FetchConfig, session: &mut Session, repo: &Repo) -> Result<(), Box<dyn error::Error>> {
+    let started = Utc::now();
+    info!("Repository: {}", repo);
+    if let Some(ref dashboard) = cfg.dashboard {
+        dashboard.repository(&repo.to_string());
+    }
+
+    if let Output::Directory(ref dir) = cfg.output {
+        fs::create_dir_all(repo.dir(dir))?;
+    }
+
+    let since = changes_since(cfg, session.db.as_ref(), repo)?;
+
+    let started = started.format("%Y-%m-%dT%H:%M:%SZ").to_string();
+    let started = if cfg.graphql {
+        fetch_graphql(cfg, session, repo, since.as_ref().map(|s| &s[..])).await?;
+        started
+    } else {
+        fetch_rest(cfg, session, repo, started, since.clone()).await?
+    };
+    fetch_registered(cfg, session, repo, since.as_ref().map(|s| &s[..])).await?;
+
+    if let Some(ref db) = session.db {
+        // the next incremental run starts from the last one again to retry
+        // the failed items, unless they are gone for good
+        if session.report.failed_items.iter().all(|item| item.gone) {
+            for entity in entity_names(cfg) {
+                db.set_last_run(&repo.to_string(), entity, &started)?;
+            }
+        }
+        Checkpoint::remove(db, &repo.to_string())?;
+    }
+    Ok(())
+}
+
+/// Names of the selected and the registered entities.
+fn entity_names(cfg: &FetchConfig) -> Vec<&str> {
+    let registered = cfg.fetchers.all().into_iter().map(|f| f.name());
+    cfg.entities.iter().map(|e| e.name()).chain(registered).collect()
+}
+
+/// Earliest last sync of the selected entities of `repo`, `None` if one of
+/// them was never synced. Runs from before the sync state database are taken
+/// from the repository's `state.json`.
+fn last_synced(
+    cfg: &FetchConfig,
+    db: Option<&SyncDb>,
+    dir: &Path,
+    repo: &Repo,
+) -> Result<Option<String>, Box<dyn error::Error>> {
+    let mut since: Option<String> = None;
+    for entity in entity_names(cfg) {
+        let last_run = match db {
+            Some(db) => db.last_run(&repo.to_string(), entity)?,
+            None => None,
+        };
+        let last_run = match last_run.or(RunState::load(&repo.dir(dir))?.last_run) {
+            Some(last_run) => last_run,
+            None => return Ok(None),
+        };
+        if since.as_ref().map_or(true, |since| last_run < *since) {
+            since = Some(last_run);
+        }
+    }
+    Ok(since)
+}
+
+/// Fetches repositories from `queue` until it is empty, the request budget is
+/// used up or the run is interrupted, which puts the unfinished repository
+/// back. Returns what was fetched and which repositories failed.
+async fn fetch_worker(
+    cfg: &FetchConfig,
+    client: Client,
+    kafka: Option<Arc<Mutex<Box<dyn Sink>>>>,
+    queue: &Mutex<VecDeque<Repo>>,
+) -> Result<Report, Box<dyn error::Error>> {
+    let budget = client.budget();
+    let db = match cfg.output {
+        Output::Directory(ref dir) => Some(SyncDb::open(&dir.join(state::DB_FILE))?),
+        Output::Stdout => None,
+    };
+    let mut session = Session {
+        client: client,
+        db: db,
+        sinks: iter::once(Arc::new(Mutex::new(cfg.sink()))).chain(kafka).collect(),
+        report: RepoReport::default(),
+        redactor: if cfg.redact { Some(Redactor::new()) } else { None },
+    };
+    let stopped = || interrupt::requested() || budget.as_ref().map_or(false, |b| b.exhausted());
+    let mut report = Report::default();
+    while !stopped() {
+        let repo = match queue.lock().unwrap().pop_front() {
+            Some(repo) => repo,
+            None => break,
+        };
+        session.report = RepoReport::new(repo.to_string());
+        let result = fetch_repo(cfg, &mut session, &repo)
+            .instrument(info_span!("repository", repository = %repo))
+            .await;
+        report.repositories.push(mem::replace(&mut session.report, RepoReport::default()));
+        if let Err(e) = result {
+            if stopped() {
+                queue.lock().unwrap().push_front(repo);
+                break;
+            }
+            error!("failed to fetch {}: {}", repo, e);
+            report.failed.push(Failure {
+                repository: repo.to_string(),
+                error: e.to_string(),
+                kind: ExitCode::of(&*e),
+            });
+        }
+    }
+    Ok(report)
+}
+
+/// Fetches the repositories of `cfg` once, returning what was fetched and
+/// which repositories and items failed.
+async fn fetch(cfg: &FetchConfig) -> Result<Report, Box<dyn error::Error>> {
+    if cfg.entities.is_empty() && cfg.fetchers.is_empty() {
+        return Err("all entities are skipped, nothing to fetch".into());
+    }
+
+    let base_dir = match cfg.output {
+        Output::Directory(ref dir) => Some(dir.clone()),
+        Output::Stdout if cfg.incremental || cfg.resume => {
+            return Err("--incremental and --resume need an output directory to keep state in".into())
+        }
+        Output::Stdout => {
+            format::write_tagged_header(&mut io::stdout())?;
+            None
+        }
+    };
+
+    // also creates the directory for the sync state database the workers open
+    let _lock = match base_dir {
+        Some(ref dir) => Some(lock::lock(dir, cfg.wait_for_lock)?),
+        None => None,
+    };
+
+    let cache_db = match (&cfg.cache_dir, &base_dir) {
+        // the cache keeps the responses as they are, bodies included
+        _ if cfg.no_cache || cfg.redact => None,
+        (&Some(ref dir), _) => Some(dir.join(state::DB_FILE)),
+        (&None, &Some(ref dir)) => Some(dir.join(state::DB_FILE)),
+        (&None, &None) => None,
+    };
+    // one client for all workers, which share its connections
+    let throttle = ThrottlePool::new(ThrottleRate::new(cfg.rate, Duration::from_secs(1)));
+    let client = Client::new(&cfg.client_config(cache_db), throttle)?;
+
+    let kafka = match cfg.kafka {
+        Some(ref kafka_cfg) => Some(Arc::new(Mutex::new(kafka_sink::sink(kafka_cfg)?))),
+        None => None,
+    };
+
+    let started = Utc::now();
+    let repos = resolve_repos(cfg, &client).await?;
+    let queue = Mutex::new(repos.iter().cloned().collect::<VecDeque<_>>());
+    let parallel = cfg.parallel.min(repos.len());
+    let mut workers = stream::iter(0..parallel)
+        .map(|_| fetch_worker(cfg, client.clone(), kafka.clone(), &queue))
+        .buffer_unordered(parallel.max(1));
+
+    // the repositories of a worker that can't start are left to the others
+    let mut report = Report::default();
+    let mut worker_error = None;
+    while let Some(worker) = workers.next().await {
+        match worker {
+            Ok(worker) => report.merge(worker),
+            Err(e) => {
+                error!("fetch worker failed: {}", e);
+                worker_error = Some(e);
+            }
+        }
+    }
+    if let (Some(e), true) = (worker_error, report.repositories.is_empty()) {
+        return Err(e);
+    }
+    let finished = Utc::now();
+    report.started = started.format("%Y-%m-%dT%H:%M:%SZ").to_string();
+    report.finished = finished.format("%Y-%m-%dT%H:%M:%SZ").to_string();
+    report.duration_secs = finished.signed_duration_since(started).num_seconds() as u64;
+    report.requests = client.requests();
+    report.cache_hits = client.cache_hits();
+    report.unfinished = queue.lock().unwrap().iter().map(|repo| repo.to_string()).collect();
+    if let Some(ref dir) = base_dir {
+        report.write(dir)?;
+    }
+
+    if report.cache_hits > 0 {
+        info!("Unchanged responses served from cache: {}", report.cache_hits);
+    }
+    let quota = if interrupt::requested() {
+        None
+    } else {
+        match budget::quota(&client, cfg.graphql).await {
+            Ok(quota) => {
+                info!(
+                    "Remaining quota: {}/{}, resets in {}s",
+                    quota.remaining,
+                    quota.limit,
+                    quota.resets_in().as_secs()
+                );
+                Some(quota)
+            }
+            Err(e) => {
+                warn!("failed to query the remaining quota: {}", e);
+                None
+            }
+        }
+    };
+    for repo in &report.repositories {
+        let phases: Vec<String> = repo.phases.iter().map(|(phase, secs)| format!("{} {:.1}s", phase, secs)).collect();
+        info!("Timings of {}: {}", repo.repository, phases.join(", "));
+    }
+    if cfg.metrics_file.is_some() || cfg.pushgateway.is_some() {
+        let metrics = metrics::render(&report, quota.as_ref());
+        if let Some(ref path) = cfg.metrics_file {
+            if let Err(e) = metrics::write_textfile(path, &metrics) {
+                warn!("failed to write metrics to {}: {}", path.display(), e);
+            }
+        }
+        if let Some(ref url) = cfg.pushgateway {
+            if let Err(e) = metrics::push(url, metrics).await {
+                warn!("failed to push metrics to {}: {}", url, e);
+            }
+        }
+    }
+
+    let failed_items = report.failed_items().len();
+    if failed_items > 0 {
+        let kinds = report.failed_item_kinds();
+        let counts: Vec<String> = kinds.iter().map(|(kind, count)| format!("{} {}", count, kind)).collect();
+        info!("Skipped {} items: {}", failed_items, counts.join(", "));
+    }
+    let unfinished = report.unfinished.len();
+    if repos.len() > 1 {
+        info!(
+            "Fetched {} of {} repositories",
+            repos.len() - report.failed.len() - unfinished,
+            repos.len()
+        );
+        for failure in &report.failed {
+            info!("  {}: {}", failure.repository, failure.error);
+        }
+    }
+    Ok(report)
+}
+
+/// Fetches once or estimates a fetch, failing with the exit code for what
+/// could not be fetched.
+async fn run(cfg: &FetchConfig) -> Result<(), Box<dyn error::Error>> {
+    if cfg.dry_run {
+        return dry_run(cfg).await;
+    }
+    outcome(cfg, fetch(cfg).await?)
+}
+
+/// Fails with the exit code for the repositories and items of `report` that
+/// could not be fetched, if any.
+fn outcome(cfg: &FetchConfig, mut report: Report) -> Result<(), Box<dyn error::Error>> {
+    let unfinished = report.unfinished.len();
+    if interrupt::requested() {
+        return Err(Box::new(Failed {
+            code: ExitCode::Interrupted,
+            message: format!("interrupted with {} repositories unfinished, continue with --resume", unfinished),
+        }));
+    }
+    // a repository stopped by the request budget is both reported and unfinished
+    let repos: HashSet<&str> = report
+        .repositories
+        .iter()
+        .map(|r| &r.repository[..])
+        .chain(report.unfinished.iter().map(|r| &r[..]))
+        .collect();
+    let repos = repos.len();
+    let failed_items = report.failed_items().len();
+    let code = match report.failed.first() {
+        None if unfinished > 0 => ExitCode::RateLimited,
+        None if failed_items > 0 => ExitCode::Partial,
+        None => return Ok(()),
+        // one cause for all failures is reported as such
+        Some(first) if report.failed.len() == repos && report.failed.iter().all(|f| f.kind == first.kind) => {
+            first.kind
+        }
+        Some(_) => ExitCode::Partial,
+    };
+    let message = match report.failed.len() {
+        0 if unfinished == 0 => format!(
+            "{} items could not be fetched{}",
+            failed_items,
+            match cfg.output {
+                Output::Directory(ref dir) => format!(", see {}", dir.join(report::FAILURES_FILE).display()),
+                Output::Stdout => String::new(),
+            }
+        ),
+        0 => format!(
+            "request budget of {} exhausted with {} repositories unfinished, continue with --resume",
+            cfg.max_requests.unwrap_or_default(),
+            unfinished
+        ),
+        1 if repos == 1 => report.failed.pop().unwrap().error,
+        n => format!("{} repositories failed", n),
+    };
+    Err(Box::new(Failed {
+        code: code,
+        message: message,
+    }))
+}
+
+/// Runs a fetch every `interval` until interrupted, a failing cycle is logged
+/// and retried with the next one.
+async fn daemon(cfg: &FetchConfig, interval: Duration) -> Result<(), Box<dyn error::Error>> {
+    for cycle in 1.. {
+        let started = Utc::now();
+        let result = run(cfg).await;
+        let elapsed = Utc::now().signed_duration_since(started).to_std().unwrap_or_default();
+        let next = started + chrono::Duration::from_std(interval)?;
+        match result {
+            Ok(()) => info!(
+                "cycle={} status=ok started={} duration={}s next={}",
+                cycle,
+                started.format("%Y-%m-%dT%H:%M:%SZ"),
+                elapsed.as_secs(),
+                next.format("%Y-%m-%dT%H:%M:%SZ")
+            ),
+            Err(e) => error!(
+                "cycle={} status=failed started={} duration={}s next={} error={:?}",
+                cycle,
+                started.format("%Y-%m-%dT%H:%M:%SZ"),
+                elapsed.as_secs(),
+                next.format("%Y-%m-%dT%H:%M:%SZ"),
+                e.to_string()
+            ),
+        }
+        if !interrupt::requested() {
+            if let Some(wait) = interval.checked_sub(elapsed) {
+                interrupt::sleep(wait).await;
+            }
+        }
+        if interrupt::requested() {
+            return Err(Box::new(Failed {
+                code: ExitCode::Interrupted,
+                message: "interrupted".to_string(),
+            }));
+        }
+    }
+    Ok(())
+}
+
+/// Fetches the repositories of a configuration, the entry point for programs
+/// embedding the fetch.
+pub struct Fetcher {
+    cfg: Arc<FetchConfig>,
+}
+
+impl Fetcher {
+    pub fn new(cfg: FetchConfig) -> Self {
+        Fetcher { cfg: Arc::new(cfg) }
+    }
+
+    pub fn config(&self) -> &FetchConfig {
+        &self.cfg
+    }
+
+    /// Fetches all repositories once. Repositories and items that fail are
+    /// listed in the report rather than failing the fetch.
+    pub async fn fetch(&self) -> Result<Report, Box<dyn error::Error>> {
+        fetch(&self.cfg).await
+    }
+
+    /// Fetches all repositories once, or only estimates the fetch for
+    /// `dry_run`, failing with an `exit::Failed` if anything was not fetched.
+    pub async fn run(&self) -> Result<(), Box<dyn error::Error>> {
+        run(&self.cfg).await
+    }
+
+    /// Fetches every `interval` until interrupted.
+    pub async fn daemon(&self, interval: Duration) -> Result<(), Box<dyn error::Error>> {
+        daemon(&self.cfg, interval).await
+    }
+
+    /// Client for the streams below, throttled to the request rate of the
+    /// configuration. Responses aren't cached.
+    pub fn client(&self) -> Result<Client, Box<dyn error::Error>> {
+        let throttle = ThrottlePool::new(ThrottleRate::new(self.cfg.rate, Duration::from_secs(1)));
+        Client::new(&self.cfg.client_config(None), throttle)
+    }
+
+    /// Issues of `repo` as they are listed, without writing them anywhere.
+    /// The stream ends with the first error, e.g. when the rate limit is
+    /// exceeded.
+    pub fn issues(&self, client: &Client, repo: &Repo) -> BoxStream<'static, Result<Issue, client::Error>> {
+        listing(self.cfg.clone(), client, repo)
+            .try_filter(|i| future::ready(i.pull_request.is_none()))
+            .map_ok(Issue::from)
+            .boxed()
+    }
+
+    /// Pull requests of `repo` in listing order, `concurrency` of them
+    /// fetched at a time while the listing continues.
+    pub fn pulls(&self, client: &Client, repo: &Repo) -> BoxStream<'static, Result<Pull, client::Error>> {
+        let (pulls_client, repo_name) = (client.clone(), repo.to_string());
+        listing(self.cfg.clone(), client, repo)
+            .try_filter(|i| future::ready(i.pull_request.is_some()))
+            .map_ok(move |i| {
+                let client = pulls_client.clone();
+                let path = format!("/repos/{}/pulls/{}", repo_name, i.number);
+                async move { client.get::<api::Pull>(&path).await }
+            })
+            .try_buffered(self.cfg.concurrency)
+            .map_ok(Pull::from)
+            .boxed()
+    }
+
+    /// Prints what a fetch would cost.
+    pub async fn dry_run(&self) -> Result<(), Box<dyn error::Error>> {
+        dry_run(&self.cfg).await
+    }
+
+    /// Prints the repositories and entities a fetch would fetch.
+    pub async fn list(&self) -> Result<(), Box<dyn error::Error>> {
+        list(&self.cfg).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use futures::TryStreamExt;
+    use hyper::StatusCode;
+    use serde_json::Value;
+
+    use super::split_listing;
+    use crate::api;
+    use crate::client::fake::{self, FakeApi};
+    use crate::retry::RetryPolicy;
+
+    fn user() -> Value {
+        let mut user = json!({ "login": "octocat", "id": 1, "site_admin": false });
+        for field in &[
+            "avatar_url",
+            "gravatar_id",
+            "url",
+            "html_url",
+            "followers_url",
+            "following_url",
+            "gists_url",
+            "starred_url",
+            "subscriptions_url",
+            "organizations_url",
+            "repos_url",
+            "events_url",
+            "received_events_url",
+        ] {
+            user[*field] = json!("");
+        }
+        user
+    }
+
+    fn listed(number: u64, pull: bool) -> Value {
+        let mut issue = json!({
+            "id": number + 1000,
+            "number": number,
+            "state": "open",
+            "title": format!("#{}", number),
+            "body": null,
+            "user": user(),
+            "labels": [],
+            "assignee": null,
+            "locked": false,
+            "comments": 0,
+            "closed_at": null,
+            "created_at": "2019-01-01T00:00:00Z",
+            "updated_at": "2019-01-01T00:00:00Z",
+            "assignees": [],
+        });
+        for field in &["url", "repository_url", "labels_url", "comments_url", "events_url", "html_url"] {
+            issue[*field] = json!("");
+        }
+        if pull {
+            issue["pull_request"] = json!({ "url": "", "html_url": "", "diff_url": "", "patch_url": "" });
+        }
+        issue
+    }
+
+    #[tokio::test]
+    async fn listing_is_split_into_issues_and_pulls() {
+        let api = Arc::new(FakeApi::default());
+        let listing = json!([listed(1, false), listed(2, true), listed(3, false), listed(4, true)]);
+        api.respond("/repos/o/r/issues", StatusCode::OK, &[], &listing.to_string());
+        let client = fake::client(&api, RetryPolicy::default());
+
+        let listing: Vec<api::Issue> = client.get_pages("/repos/o/r/issues").try_collect().await.unwrap();
+        let (issues, pr_nums) = split_listing(listing);
+        assert_eq!(issues.iter().map(|i| i.number).collect::<Vec<_>>(), vec![1, 3]);
+        assert_eq!(pr_nums, vec![2, 4]);
+    }
+}
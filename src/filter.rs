@@ -0,0 +1,238 @@
+use std::cmp::Ordering;
+
+use chrono::DateTime;
+use serde_json::Value;
+
+/// Restrictions on the issues and pull requests to fetch, applied by the API
+/// wherever it supports them.
+///
+/// The listing endpoints can't restrict creation or update times other than by
+/// `since`, the date range is checked on the fetched records instead and only
+/// narrows the search used for estimates.
+#[derive(Clone, Debug, Default)]
+pub struct Filters {
+    /// `open` or `closed`, both if unset.
+    pub state: Option<String>,
+    /// Labels that all have to be present.
+    pub labels: Vec<String>,
+    pub author: Option<String>,
+    pub assignee: Option<String>,
+    /// RFC 3339 timestamps bounding creation and last update.
+    pub created_after: Option<String>,
+    pub updated_before: Option<String>,
+}
+
+impl Filters {
+    /// Parameters for the REST issues listing, starting with `&`.
+    pub fn query(&self) -> String {
+        let mut query = format!("&state={}", self.state.as_ref().map_or("all", |s| &s[..]));
+        if !self.labels.is_empty() {
+            let labels: Vec<String> = self.labels.iter().map(|l| encode(l)).collect();
+            query.push_str(&format!("&labels={}", labels.join(",")));
+        }
+        if let Some(ref author) = self.author {
+            query.push_str(&format!("&creator={}", encode(author)));
+        }
+        if let Some(ref assignee) = self.assignee {
+            query.push_str(&format!("&assignee={}", encode(assignee)));
+        }
+        query
+    }
+
+    /// Qualifiers for the search API, each starting with `+`.
+    pub fn search_qualifiers(&self) -> String {
+        let mut qualifiers = String::new();
+        if let Some(ref state) = self.state {
+            qualifiers.push_str(&format!("+is:{}", encode(state)));
+        }
+        for label in &self.labels {
+            qualifiers.push_str(&format!("+label:%22{}%22", encode(label)));
+        }
+        if let Some(ref author) = self.author {
+            qualifiers.push_str(&format!("+author:{}", encode(author)));
+        }
+        if let Some(ref assignee) = self.assignee {
+            qualifiers.push_str(&format!("+assignee:{}", encode(assignee)));
+        }
+        if let Some(ref created_after) = self.created_after {
+            qualifiers.push_str(&format!("+created:%3E%3D{}", encode(created_after)));
+        }
+        if let Some(ref updated_before) = self.updated_before {
+            qualifiers.push_str(&format!("+updated:%3C%3D{}", encode(updated_before)));
+        }
+        qualifiers
+    }
+
+    /// Variables for the `filterBy` argument of the GraphQL issues connection.
+    pub fn graphql_issue_filter(&self) -> Value {
+        json!({
+            "states": self.state.as_ref().map(|state| vec![state.to_uppercase()]),
+            "labels": if self.labels.is_empty() { None } else { Some(&self.labels) },
+            "createdBy": self.author,
+            "assignee": self.assignee,
+        })
+    }
+
+    /// States of the GraphQL pull request connection, closed includes merged
+    /// as in the REST API.
+    pub fn graphql_pull_states(&self) -> Option<Vec<&'static str>> {
+        self.state.as_ref().map(|state| match &state[..] {
+            "open" => vec!["OPEN"],
+            _ => vec!["CLOSED", "MERGED"],
+        })
+    }
+
+    /// Checks a fetched GraphQL node against the filters, as GraphQL matches
+    /// any of the given labels and can't filter pull requests by user.
+    pub fn matches_graphql_node(&self, node: &Value) -> bool {
+        let has = |connection: &str, field: &str, value: &str| {
+            node[connection]["nodes"]
+                .as_array()
+                .map_or(false, |nodes| nodes.iter().any(|n| n[field].as_str() == Some(value)))
+        };
+        let author_matches = self.author.as_ref().map_or(true, |author| {
+            node["author"]["login"].as_str() == Some(&author[..])
+        });
+        let assignee_matches = self.assignee
+            .as_ref()
+            .map_or(true, |assignee| has("assignees", "login", assignee));
+        let labels_match = self.labels.iter().all(|label| has("labels", "name", label));
+        author_matches && assignee_matches && labels_match
+            && self.in_range(
+                node["createdAt"].as_str().unwrap_or(""),
+                node["updatedAt"].as_str().unwrap_or(""),
+            )
+    }
+
+    /// Whether creation and update time are within the date range, records
+    /// with unparseable times are kept.
+    pub fn in_range(&self, created_at: &str, updated_at: &str) -> bool {
+        let earlier = |a: &str, b: &str| match (DateTime::parse_from_rfc3339(a), DateTime::parse_from_rfc3339(b)) {
+            (Ok(a), Ok(b)) => a < b,
+            _ => false,
+        };
+        let too_old = self.created_after
+            .as_ref()
+            .map_or(false, |limit| earlier(created_at, limit));
+        let too_new = self.updated_before
+            .as_ref()
+            .map_or(false, |limit| earlier(limit, updated_at));
+        !too_old && !too_new
+    }
+}
+
+/// Order in which issues and pull requests are listed and written.
+#[derive(Clone, Debug)]
+pub struct Order {
+    /// `created`, `updated` or `comments`.
+    pub sort: String,
+    pub descending: bool,
+}
+
+impl Default for Order {
+    fn default() -> Self {
+        Order {
+            sort: "created".to_string(),
+            descending: false,
+        }
+    }
+}
+
+impl Order {
+    /// Parameters for the REST issues listing, starting with `&`.
+    pub fn query(&self) -> String {
+        format!(
+            "&sort={}&direction={}",
+            self.sort,
+            if self.descending { "desc" } else { "asc" }
+        )
+    }
+
+    /// Value of the `orderBy` argument of the GraphQL issue and pull request
+    /// connections.
+    pub fn graphql(&self) -> Value {
+        json!({
+            "field": match &self.sort[..] {
+                "updated" => "UPDATED_AT",
+                "comments" => "COMMENTS",
+                _ => "CREATED_AT",
+            },
+            "direction": if self.descending { "DESC" } else { "ASC" },
+        })
+    }
+
+    /// Compares two fetched GraphQL nodes in this order.
+    pub fn cmp_graphql_nodes(&self, a: &Value, b: &Value) -> Ordering {
+        let ordering = match &self.sort[..] {
+            "updated" => a["updatedAt"].as_str().cmp(&b["updatedAt"].as_str()),
+            "comments" => a["comments"]["totalCount"]
+                .as_u64()
+                .cmp(&b["comments"]["totalCount"].as_u64()),
+            _ => a["createdAt"].as_str().cmp(&b["createdAt"].as_str()),
+        };
+        if self.descending {
+            ordering.reverse()
+        } else {
+            ordering
+        }
+    }
+}
+
+/// Percent-encodes `value` for use in a query string.
+pub fn encode(value: &str) -> String {
+    value
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => (b as char).to_string(),
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Filters;
+
+    fn range(created_after: Option<&str>, updated_before: Option<&str>) -> Filters {
+        Filters {
+            created_after: created_after.map(String::from),
+            updated_before: updated_before.map(String::from),
+            ..Filters::default()
+        }
+    }
+
+    #[test]
+    fn without_bounds_everything_is_in_range() {
+        assert!(range(None, None).in_range("2017-01-01T00:00:00Z", "2018-01-01T00:00:00Z"));
+    }
+
+    #[test]
+    fn records_created_before_the_range_are_excluded() {
+        let filters = range(Some("2018-01-01T00:00:00Z"), None);
+        assert!(!filters.in_range("2017-12-31T23:59:59Z", "2018-06-01T00:00:00Z"));
+        assert!(filters.in_range("2018-01-01T00:00:00Z", "2018-06-01T00:00:00Z"));
+        assert!(filters.in_range("2018-01-02T00:00:00Z", "2018-06-01T00:00:00Z"));
+    }
+
+    #[test]
+    fn records_updated_after_the_range_are_excluded() {
+        let filters = range(None, Some("2018-01-01T00:00:00Z"));
+        assert!(!filters.in_range("2017-01-01T00:00:00Z", "2018-01-01T00:00:01Z"));
+        assert!(filters.in_range("2017-01-01T00:00:00Z", "2018-01-01T00:00:00Z"));
+        assert!(filters.in_range("2017-01-01T00:00:00Z", "2017-06-01T00:00:00Z"));
+    }
+
+    #[test]
+    fn times_are_compared_across_offsets() {
+        let filters = range(Some("2018-01-01T00:00:00Z"), None);
+        assert!(!filters.in_range("2018-01-01T00:30:00+01:00", "2018-06-01T00:00:00Z"));
+        assert!(filters.in_range("2017-12-31T23:30:00-01:00", "2018-06-01T00:00:00Z"));
+    }
+
+    #[test]
+    fn unparseable_times_are_kept() {
+        let filters = range(Some("2018-01-01T00:00:00Z"), Some("2018-02-01T00:00:00Z"));
+        assert!(filters.in_range("yesterday", "2018-01-15T00:00:00Z"));
+        assert!(filters.in_range("2018-01-15T00:00:00Z", ""));
+    }
+}
@@ -0,0 +1,290 @@
+use std::error;
+use std::fs;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use rmp_serde::Serializer;
+use rmpv::Value;
+use serde::{Serialize, Serializer as SerdeSerializer};
+use serde::de::DeserializeOwned;
+use serde::ser::SerializeMap;
+
+use crate::manifest;
+use crate::model::{Issue, Pull};
+
+/// Version of the on-disk snapshot format written by this build.
+///
+/// Version 0 files are a bare msgpack array of records, starting with version
+/// 1 every file is a map holding the format version next to the records.
+/// NDJSON files carry the version in a header object on their first line.
+pub const FORMAT_VERSION: u64 = 1;
+
+const VERSION_KEY: &str = "format_version";
+const RECORDS_KEY: &str = "records";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Format {
+    Msgpack,
+    Ndjson,
+}
+impl Format {
+    pub fn extension(&self) -> &'static str {
+        match *self {
+            Format::Msgpack => "msgpack",
+            Format::Ndjson => "ndjson",
+        }
+    }
+}
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "msgpack" => Ok(Format::Msgpack),
+            "ndjson" => Ok(Format::Ndjson),
+            _ => Err(format!("unknown format: {}", s)),
+        }
+    }
+}
+
+struct Envelope<'a, D: 'a> {
+    records: &'a [D],
+}
+
+impl<'a, D: Serialize> Serialize for Envelope<'a, D> {
+    fn serialize<S: SerdeSerializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(2))?;
+        map.serialize_entry(VERSION_KEY, &FORMAT_VERSION)?;
+        map.serialize_entry(RECORDS_KEY, self.records)?;
+        map.end()
+    }
+}
+
+pub fn serialize_to_file<D>(
+    records: &[D],
+    format: Format,
+    filename: &Path,
+) -> Result<(), Box<dyn error::Error>>
+where
+    D: Serialize,
+{
+    // written aside and renamed, so that an interrupted write leaves the
+    // previous snapshot intact
+    let mut tmp = filename.as_os_str().to_owned();
+    tmp.push(".tmp");
+    let tmp = PathBuf::from(tmp);
+    {
+        let mut file = BufWriter::new(fs::File::create(&tmp)?);
+        match format {
+            Format::Msgpack => {
+                Envelope { records: records }.serialize(&mut Serializer::new(&mut file))?;
+            }
+            Format::Ndjson => {
+                writeln!(file, "{{\"{}\":{}}}", VERSION_KEY, FORMAT_VERSION)?;
+                for record in records {
+                    serde_json::to_writer(&mut file, record)?;
+                    file.write_all(b"\n")?;
+                }
+            }
+        }
+        file.flush()?;
+        file.get_ref().sync_all()?;
+    }
+    fs::rename(&tmp, filename)?;
+    manifest::update(filename)
+}
+
+/// Writes the header line of a tagged NDJSON stream.
+pub fn write_tagged_header<W: Write>(out: &mut W) -> Result<(), Box<dyn error::Error>> {
+    writeln!(out, "{{\"type\":\"format\",\"{}\":{}}}", VERSION_KEY, FORMAT_VERSION)?;
+    Ok(())
+}
+
+/// Writes records as NDJSON objects tagged with their entity type, e.g.
+/// `{"type":"issue","record":{...}}`.
+pub fn write_tagged<D, W>(tag: &str, records: &[D], out: &mut W) -> Result<(), Box<dyn error::Error>>
+where
+    D: Serialize,
+    W: Write,
+{
+    for record in records {
+        write!(out, "{{\"type\":\"{}\",\"record\":", tag)?;
+        serde_json::to_writer(&mut *out, record)?;
+        out.write_all(b"}\n")?;
+    }
+    out.flush()?;
+    Ok(())
+}
+
+fn check_version(version: u64) -> Result<(), Box<dyn error::Error>> {
+    if version != FORMAT_VERSION {
+        return Err(format!(
+            "snapshot has format version {}, expected {} (try the upgrade command)",
+            version, FORMAT_VERSION
+        ).into());
+    }
+    Ok(())
+}
+
+pub fn deserialize_from_file<D>(format: Format, filename: &Path) -> Result<Vec<D>, Box<dyn error::Error>>
+where
+    D: DeserializeOwned,
+{
+    let mut file = BufReader::new(fs::File::open(filename)?);
+    match format {
+        Format::Msgpack => {
+            let value = rmpv::decode::read_value(&mut file)?;
+            check_version(version_of(&value)?)?;
+            let records = match value {
+                Value::Map(entries) => entries
+                    .into_iter()
+                    .find(|&(ref k, _)| k.as_str() == Some(RECORDS_KEY))
+                    .map(|(_, v)| v)
+                    .ok_or("snapshot map without records")?,
+                _ => return Err("unrecognized snapshot layout".into()),
+            };
+            Ok(rmpv::ext::from_value(records)?)
+        }
+        Format::Ndjson => {
+            let mut lines = file.lines();
+            let header: serde_json::Value = match lines.next() {
+                Some(line) => serde_json::from_str(&line?)?,
+                None => return Err("empty NDJSON snapshot".into()),
+            };
+            let version = header
+                .get(VERSION_KEY)
+                .and_then(|v| v.as_u64())
+                .ok_or("NDJSON snapshot without format version header")?;
+            check_version(version)?;
+            let mut records = Vec::new();
+            for line in lines {
+                let line = line?;
+                if !line.is_empty() {
+                    records.push(serde_json::from_str(&line)?);
+                }
+            }
+            Ok(records)
+        }
+    }
+}
+
+/// Returns the format version of a decoded msgpack snapshot file.
+fn version_of(value: &Value) -> Result<u64, Box<dyn error::Error>> {
+    match *value {
+        Value::Array(_) => Ok(0),
+        Value::Map(ref entries) => entries
+            .iter()
+            .find(|&&(ref k, _)| k.as_str() == Some(VERSION_KEY))
+            .and_then(|&(_, ref v)| v.as_u64())
+            .ok_or_else(|| "snapshot map without format version".into()),
+        _ => Err("unrecognized snapshot layout".into()),
+    }
+}
+
+/// Migrates a snapshot from `version` to `version + 1`.
+fn migrate(version: u64, value: Value) -> Result<Value, Box<dyn error::Error>> {
+    match version {
+        0 => Ok(Value::Map(vec![
+            (Value::from(VERSION_KEY), Value::from(1u64)),
+            (Value::from(RECORDS_KEY), value),
+        ])),
+        _ => Err(format!("no migration from format version {}", version).into()),
+    }
+}
+
+fn upgrade_file(path: &Path) -> Result<Option<u64>, Box<dyn error::Error>> {
+    let mut value = rmpv::decode::read_value(&mut BufReader::new(fs::File::open(path)?))?;
+    let from = version_of(&value)?;
+    if from > FORMAT_VERSION {
+        return Err(format!(
+            "format version {} is newer than supported version {}",
+            from, FORMAT_VERSION
+        ).into());
+    }
+    if from == FORMAT_VERSION {
+        return Ok(None);
+    }
+    for version in from..FORMAT_VERSION {
+        value = migrate(version, value)?;
+    }
+
+    let tmp = path.with_extension("msgpack.tmp");
+    {
+        let mut file = BufWriter::new(fs::File::create(&tmp)?);
+        rmpv::encode::write_value(&mut file, &value)?;
+    }
+    fs::rename(&tmp, path)?;
+    manifest::update(path)?;
+    Ok(Some(from))
+}
+
+/// Upgrades all snapshot files below `dir` to the current format version.
+pub fn upgrade(dir: &Path) -> Result<(), Box<dyn error::Error>> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            upgrade(&path)?;
+        } else if path.extension().map_or(false, |ext| ext == "msgpack") {
+            match upgrade_file(&path)? {
+                Some(from) => info!(
+                    "Upgraded {}: {} -> {}",
+                    path.display(),
+                    from,
+                    FORMAT_VERSION
+                ),
+                None => info!("Up to date: {}", path.display()),
+            }
+        }
+    }
+    Ok(())
+}
+
+fn convert_records<D>(path: &Path, from: Format, to: Format) -> Result<(), Box<dyn error::Error>>
+where
+    D: Serialize + DeserializeOwned,
+{
+    let target = path.with_extension(to.extension());
+    match deserialize_from_file::<D>(from, path) {
+        Ok(records) => serialize_to_file(&records, to, &target),
+        // GraphQL nodes don't match the API types, like the records of
+        // deduplicated users they are converted as they are
+        Err(_) => {
+            let records: Vec<serde_json::Value> = deserialize_from_file(from, path)?;
+            serialize_to_file(&records, to, &target)
+        }
+    }
+}
+
+/// Re-encodes all snapshot files below `dir` from one format into another,
+/// writing the converted files next to the originals.
+pub fn convert(dir: &Path, from: Format, to: Format) -> Result<(), Box<dyn error::Error>> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            convert(&path, from, to)?;
+            continue;
+        }
+        if !path.extension().map_or(false, |ext| ext == from.extension()) {
+            continue;
+        }
+        // snapshots written with deduplicated users no longer match the
+        // API types, their records are self-describing maps instead
+        let deduped = path
+            .with_file_name(format!("users.{}", from.extension()))
+            .exists();
+        match path.file_stem().and_then(|s| s.to_str()) {
+            Some("issues") | Some("pulls") | Some("users") if deduped => {
+                convert_records::<serde_json::Value>(&path, from, to)?
+            }
+            Some("issues") => convert_records::<Issue>(&path, from, to)?,
+            Some("pulls") => convert_records::<Pull>(&path, from, to)?,
+            _ => {
+                warn!("skipping unknown snapshot file: {}", path.display());
+                continue;
+            }
+        }
+        info!("Converted {} to {}", path.display(), to.extension());
+    }
+    Ok(())
+}
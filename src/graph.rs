@@ -0,0 +1,322 @@
+use std::collections::BTreeMap;
+use std::error;
+use std::fs;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use regex::Regex;
+use serde_json::Value;
+
+use crate::entity::Entity;
+use crate::html_report::escape;
+use crate::repo::Repo;
+use crate::snapshot::{self, Item};
+
+/// A reference to an issue or pull request as `#12`, `owner/name#12` or its
+/// URL, with the closing keyword in front of it if any.
+const REFERENCE: &str = concat!(
+    r"(?i)(?:\b(close[sd]?|fix(?:e[sd])?|resolve[sd]?):?\s+)?",
+    r"(?:https?://[^/\s]+/([\w.-]+/[\w.-]+)/(?:issues|pull)/(\d+)|(?:\b([\w.-]+/[\w.-]+))?#(\d+)\b)",
+);
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum GraphFormat {
+    Dot,
+    Graphml,
+    /// Lists of the nodes and edges.
+    Json,
+}
+
+impl FromStr for GraphFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "dot" => Ok(GraphFormat::Dot),
+            "graphml" => Ok(GraphFormat::Graphml),
+            "json" => Ok(GraphFormat::Json),
+            _ => Err(format!("unknown graph format: {}", s)),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct GraphConfig {
+    /// Output directory of a fetch, or the directory of one repository.
+    pub dir: PathBuf,
+    pub format: GraphFormat,
+    /// File to write the graph to, stdout if none.
+    pub output: Option<PathBuf>,
+}
+
+/// How an issue or pull request refers to another, a pull request closes
+/// those it names after a closing keyword such as `fixes` in its body.
+#[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EdgeKind {
+    Mentions,
+    Closes,
+}
+
+impl EdgeKind {
+    fn name(&self) -> &'static str {
+        match *self {
+            EdgeKind::Mentions => "mentions",
+            EdgeKind::Closes => "closes",
+        }
+    }
+}
+
+/// An issue or pull request, those of other repositories only referred to
+/// have no title or state.
+#[derive(Debug, Serialize)]
+pub struct Node {
+    /// `owner/name#number`.
+    pub id: String,
+    pub repository: String,
+    pub number: u64,
+    pub pull: bool,
+    pub title: Option<String>,
+    pub state: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Edge {
+    pub source: String,
+    pub target: String,
+    pub kind: EdgeKind,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct Graph {
+    pub nodes: Vec<Node>,
+    pub edges: Vec<Edge>,
+}
+
+impl Graph {
+    /// Graph of the references between the issues and pull requests in the
+    /// bodies, comments and reviews of the snapshots of `repos`. References
+    /// to numbers of the same repository that are not in its snapshots are
+    /// left out, they are mostly deleted items or `#` used otherwise.
+    pub fn of(repos: &[(Repo, PathBuf)]) -> Result<Graph, Box<dyn error::Error>> {
+        let reference = Regex::new(REFERENCE).unwrap();
+        let mut nodes = BTreeMap::new();
+        let mut sources = Vec::new();
+        for (repo, dir) in repos {
+            for entity in Entity::ALL {
+                let pull = *entity == Entity::Pulls;
+                let (path, format) = match snapshot::find(dir, *entity) {
+                    Some(found) => found,
+                    None => continue,
+                };
+                for record in snapshot::records(&path, format, *entity)? {
+                    let item = match Item::of(&record, pull) {
+                        Some(item) => item,
+                        None => continue,
+                    };
+                    let id = format!("{}#{}", repo, item.number);
+                    sources.push((id.clone(), repo.to_string(), pull, texts(&record)));
+                    nodes.insert(
+                        id.clone(),
+                        Node {
+                            id: id,
+                            repository: repo.to_string(),
+                            number: item.number,
+                            pull: pull,
+                            title: Some(item.title),
+                            state: Some(item.state),
+                        },
+                    );
+                }
+            }
+        }
+
+        let mut edges = BTreeMap::new();
+        let mut referred = Vec::new();
+        for (source, repo, pull, texts) in &sources {
+            for (i, text) in texts.iter().enumerate() {
+                for (target_repo, number, closing) in references(&reference, text, repo) {
+                    let target = format!("{}#{}", target_repo, number);
+                    if target == *source || (target_repo == *repo && !nodes.contains_key(&target)) {
+                        continue;
+                    }
+                    // only the body of a pull request closes what it names
+                    let kind = if closing && *pull && i == 0 {
+                        EdgeKind::Closes
+                    } else {
+                        EdgeKind::Mentions
+                    };
+                    let edge = edges.entry((source.clone(), target.clone())).or_insert(kind);
+                    *edge = (*edge).max(kind);
+                    referred.push((target, target_repo, number));
+                }
+            }
+        }
+        for (id, repository, number) in referred {
+            nodes.entry(id.clone()).or_insert_with(|| Node {
+                id: id,
+                repository: repository,
+                number: number,
+                pull: false,
+                title: None,
+                state: None,
+            });
+        }
+        Ok(Graph {
+            nodes: nodes.into_iter().map(|(_, node)| node).collect(),
+            edges: edges
+                .into_iter()
+                .map(|((source, target), kind)| Edge {
+                    source: source,
+                    target: target,
+                    kind: kind,
+                })
+                .collect(),
+        })
+    }
+
+    pub fn write_dot<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        let quote = |text: &str| format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""));
+        writeln!(out, "digraph references {{")?;
+        for node in &self.nodes {
+            let label = match node.title {
+                Some(ref title) => format!("#{} {}", node.number, title),
+                None => node.id.clone(),
+            };
+            let shape = if node.pull { "box" } else { "ellipse" };
+            writeln!(out, "  {} [label={}, shape={}];", quote(&node.id), quote(&label), shape)?;
+        }
+        for edge in &self.edges {
+            let style = match edge.kind {
+                EdgeKind::Closes => "solid",
+                EdgeKind::Mentions => "dashed",
+            };
+            writeln!(
+                out,
+                "  {} -> {} [label={}, style={}];",
+                quote(&edge.source),
+                quote(&edge.target),
+                edge.kind.name(),
+                style
+            )?;
+        }
+        writeln!(out, "}}")
+    }
+
+    pub fn write_graphml<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        writeln!(out, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+        writeln!(out, r#"<graphml xmlns="http://graphml.graphdrawing.org/xmlns">"#)?;
+        for &(id, target, name, kind) in &[
+            ("repository", "node", "repository", "string"),
+            ("number", "node", "number", "long"),
+            ("pull", "node", "pull", "boolean"),
+            ("title", "node", "title", "string"),
+            ("state", "node", "state", "string"),
+            ("kind", "edge", "kind", "string"),
+        ] {
+            writeln!(
+                out,
+                r#"  <key id="{}" for="{}" attr.name="{}" attr.type="{}"/>"#,
+                id, target, name, kind
+            )?;
+        }
+        writeln!(out, r#"  <graph id="references" edgedefault="directed">"#)?;
+        for node in &self.nodes {
+            writeln!(out, r#"    <node id="{}">"#, escape(&node.id))?;
+            writeln!(out, r#"      <data key="repository">{}</data>"#, escape(&node.repository))?;
+            writeln!(out, r#"      <data key="number">{}</data>"#, node.number)?;
+            writeln!(out, r#"      <data key="pull">{}</data>"#, node.pull)?;
+            if let Some(ref title) = node.title {
+                writeln!(out, r#"      <data key="title">{}</data>"#, escape(title))?;
+            }
+            if let Some(ref state) = node.state {
+                writeln!(out, r#"      <data key="state">{}</data>"#, escape(state))?;
+            }
+            writeln!(out, "    </node>")?;
+        }
+        for edge in &self.edges {
+            writeln!(
+                out,
+                r#"    <edge source="{}" target="{}"><data key="kind">{}</data></edge>"#,
+                escape(&edge.source),
+                escape(&edge.target),
+                edge.kind.name()
+            )?;
+        }
+        writeln!(out, "  </graph>\n</graphml>")
+    }
+}
+
+/// Body of a record followed by those of its comments and reviews.
+fn texts(record: &Value) -> Vec<String> {
+    let body = |value: &Value| value.get("body").and_then(Value::as_str).map(|body| body.to_string());
+    let mut texts: Vec<String> = body(record).into_iter().collect();
+    if texts.is_empty() {
+        // keeps the body first for the closing keywords
+        texts.push(String::new());
+    }
+    for pointer in &["/comments/nodes", "/reviews/nodes"] {
+        if let Some(nodes) = record.pointer(pointer).and_then(Value::as_array) {
+            texts.extend(nodes.iter().filter_map(|node| body(node)));
+        }
+    }
+    texts
+}
+
+/// Repository, number and whether it follows a closing keyword of the
+/// references in `text`, found in the repository `repo`.
+fn references(reference: &Regex, text: &str, repo: &str) -> Vec<(String, u64, bool)> {
+    let mut found = Vec::new();
+    for captures in reference.captures_iter(text) {
+        let closing = captures.get(1).is_some();
+        let (target, number) = match (captures.get(3), captures.get(5)) {
+            (Some(number), _) => (captures.get(2), number),
+            (None, Some(number)) => {
+                // within a word or an HTML entity, e.g. `&#39;`
+                let hash = number.start() - 1;
+                let before = text[..hash].chars().next_back();
+                if captures.get(4).is_none() && before.map_or(false, |c| c.is_alphanumeric() || c == '&') {
+                    continue;
+                }
+                (captures.get(4), number)
+            }
+            (None, None) => continue,
+        };
+        if let Ok(number) = number.as_str().parse() {
+            let target = target.map_or(repo, |target| target.as_str());
+            found.push((target.to_string(), number, closing));
+        }
+    }
+    found
+}
+
+/// Writes the reference graph of the snapshots below `cfg.dir`.
+pub fn graph(cfg: GraphConfig) -> Result<(), Box<dyn error::Error>> {
+    let repos = snapshot::repositories(&cfg.dir)?;
+    if repos.is_empty() {
+        return Err(format!("no snapshots in {}", cfg.dir.display()).into());
+    }
+    let graph = Graph::of(&repos)?;
+    info!("{} nodes, {} edges", graph.nodes.len(), graph.edges.len());
+    match cfg.output {
+        Some(ref path) => write(&graph, cfg.format, &mut BufWriter::new(fs::File::create(path)?), path),
+        None => {
+            let stdout = io::stdout();
+            let mut out = stdout.lock();
+            write(&graph, cfg.format, &mut out, Path::new("stdout"))
+        }
+    }
+}
+
+fn write<W: Write>(graph: &Graph, format: GraphFormat, out: &mut W, path: &Path) -> Result<(), Box<dyn error::Error>> {
+    match format {
+        GraphFormat::Dot => graph.write_dot(out),
+        GraphFormat::Graphml => graph.write_graphml(out),
+        GraphFormat::Json => serde_json::to_writer_pretty(&mut *out, graph)
+            .map_err(io::Error::from)
+            .and_then(|()| writeln!(out)),
+    }
+    .and_then(|()| out.flush())
+    .map_err(|e| format!("failed to write {}: {}", path.display(), e).into())
+}
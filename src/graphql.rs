@@ -0,0 +1,191 @@
+use chrono::DateTime;
+use futures::future;
+use futures::stream::{self, BoxStream, StreamExt, TryStreamExt};
+use serde_json::Value;
+
+use crate::client::{Client, Error};
+use crate::filter::{Filters, Order};
+use crate::repo::Repo;
+
+/// Issues with their labels and comments.
+///
+/// Nested connections are limited to their first 100 nodes, `totalCount` is
+/// kept so truncated lists can be told apart.
+const ISSUES_QUERY: &str = r#"
+query($owner: String!, $repo: String!, $first: Int!, $after: String, $filterBy: IssueFilters,
+      $orderBy: IssueOrder!) {
+  repository(owner: $owner, name: $repo) {
+    items: issues(first: $first, after: $after, filterBy: $filterBy, orderBy: $orderBy) {
+      pageInfo { hasNextPage endCursor }
+      nodes {
+        databaseId number title body state createdAt updatedAt closedAt locked
+        author { login }
+        assignees(first: 100) { nodes { login } }
+        labels(first: 100) { totalCount nodes { name } }
+        milestone { number title }
+        comments(first: 100) {
+          totalCount
+          nodes { databaseId author { login } body createdAt updatedAt }
+        }
+      }
+    }
+  }
+}
+"#;
+
+/// Pull requests with their comments and reviews.
+const PULLS_QUERY: &str = r#"
+query($owner: String!, $repo: String!, $first: Int!, $after: String,
+      $states: [PullRequestState!], $labels: [String!], $orderBy: IssueOrder!) {
+  repository(owner: $owner, name: $repo) {
+    items: pullRequests(first: $first, after: $after, states: $states, labels: $labels,
+                        orderBy: $orderBy) {
+      pageInfo { hasNextPage endCursor }
+      nodes {
+        databaseId number title body state createdAt updatedAt closedAt mergedAt merged locked
+        baseRefName headRefName mergeCommit { oid }
+        additions deletions changedFiles
+        author { login }
+        mergedBy { login }
+        assignees(first: 100) { nodes { login } }
+        labels(first: 100) { totalCount nodes { name } }
+        milestone { number title }
+        comments(first: 100) {
+          totalCount
+          nodes { databaseId author { login } body createdAt updatedAt }
+        }
+        reviews(first: 100) {
+          totalCount
+          nodes { databaseId author { login } state body submittedAt }
+        }
+      }
+    }
+  }
+}
+"#;
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PageInfo {
+    has_next_page: bool,
+    end_cursor: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Connection {
+    #[serde(rename = "pageInfo")]
+    page_info: PageInfo,
+    nodes: Vec<Value>,
+}
+
+#[derive(Deserialize)]
+struct Repository {
+    items: Connection,
+}
+
+#[derive(Deserialize)]
+struct Data {
+    repository: Repository,
+}
+
+/// Issues, without pull requests, updated after `since` and matching
+/// `filters`, in `order`.
+pub fn issues(
+    client: &Client,
+    repo: &Repo,
+    per_page: u64,
+    since: Option<&str>,
+    filters: &Filters,
+    order: &Order,
+) -> BoxStream<'static, Result<Value, Error>> {
+    let mut filter_by = filters.graphql_issue_filter();
+    filter_by["since"] = json!(since);
+    let variables = json!({
+        "owner": repo.owner,
+        "repo": repo.name,
+        "first": per_page,
+        "filterBy": filter_by,
+        "orderBy": order.graphql(),
+    });
+    let filters = filters.clone();
+    nodes(client, ISSUES_QUERY, variables, None)
+        .try_filter(move |node| future::ready(filters.matches_graphql_node(node)))
+        .boxed()
+}
+
+/// Pull requests updated after `since` and matching `filters`, in `order`
+/// unless `since` is given. The connection cannot be filtered by update time,
+/// changes are then fetched most recently updated first instead.
+pub fn pulls(
+    client: &Client,
+    repo: &Repo,
+    per_page: u64,
+    since: Option<&str>,
+    filters: &Filters,
+    order: &Order,
+) -> BoxStream<'static, Result<Value, Error>> {
+    let order_by = match since {
+        Some(_) => json!({ "field": "UPDATED_AT", "direction": "DESC" }),
+        None => order.graphql(),
+    };
+    let variables = json!({
+        "owner": repo.owner,
+        "repo": repo.name,
+        "first": per_page,
+        "states": filters.graphql_pull_states(),
+        "labels": if filters.labels.is_empty() { None } else { Some(&filters.labels) },
+        "orderBy": order_by,
+    });
+    let filters = filters.clone();
+    nodes(client, PULLS_QUERY, variables, since.map(|s| s.to_string()))
+        .try_filter(move |node| future::ready(filters.matches_graphql_node(node)))
+        .boxed()
+}
+
+/// Follows the `items` connection of `query` page by page. Pages of nodes
+/// sorted by update time are cut off at the first node older than `until`.
+fn nodes(
+    client: &Client,
+    query: &'static str,
+    variables: Value,
+    until: Option<String>,
+) -> BoxStream<'static, Result<Value, Error>> {
+    let client = client.clone();
+    stream::try_unfold(Some(None), move |after: Option<Option<String>>| {
+        let (client, mut variables, until) = (client.clone(), variables.clone(), until.clone());
+        async move {
+            let after = match after {
+                Some(after) => after,
+                None => return Ok(None),
+            };
+            variables["after"] = json!(after);
+            let items = client.graphql::<Data>(query, variables).await?.repository.items;
+            let total = items.nodes.len();
+            let nodes: Vec<Value> = items
+                .nodes
+                .into_iter()
+                .take_while(|node| !updated_before(node, until.as_ref()))
+                .collect();
+            let next = if items.page_info.has_next_page && nodes.len() == total {
+                Some(items.page_info.end_cursor)
+            } else {
+                None
+            };
+            Ok::<_, Error>(Some((nodes, next)))
+        }
+    })
+    .map_ok(|nodes| stream::iter(nodes.into_iter().map(Ok)))
+    .try_flatten()
+    .boxed()
+}
+
+fn updated_before(node: &Value, until: Option<&String>) -> bool {
+    let until = match until.and_then(|until| DateTime::parse_from_rfc3339(until).ok()) {
+        Some(until) => until,
+        None => return false,
+    };
+    node["updatedAt"]
+        .as_str()
+        .and_then(|updated| DateTime::parse_from_rfc3339(updated).ok())
+        .map_or(false, |updated| updated < until)
+}
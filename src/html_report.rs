@@ -0,0 +1,349 @@
+use std::collections::BTreeMap;
+use std::error;
+use std::fs;
+use std::path::PathBuf;
+
+use chrono::{DateTime, Datelike, NaiveDate, Utc};
+
+use crate::snapshot::{self, Item};
+use crate::stats::{self, Stats};
+
+/// Labels and contributors shown.
+const TOP: usize = 15;
+/// Size of the charts in SVG units, they scale with the page.
+const WIDTH: f64 = 760.0;
+const HEIGHT: f64 = 240.0;
+/// Room for the axis labels left of and below the plot.
+const MARGIN: f64 = 40.0;
+/// Upper bounds in days of the buckets of the cycle time histogram.
+const CYCLE_BUCKETS: &[(f64, &str)] = &[
+    (1.0, "< 1 day"),
+    (3.0, "1-3 days"),
+    (7.0, "3-7 days"),
+    (14.0, "1-2 weeks"),
+    (30.0, "2-4 weeks"),
+    (90.0, "1-3 months"),
+    (std::f64::INFINITY, "> 3 months"),
+];
+const OPEN_COLOR: &str = "#1a7f37";
+const CLOSED_COLOR: &str = "#8250df";
+const PULLS_COLOR: &str = "#0969da";
+const STYLE: &str = "
+body { font-family: -apple-system, 'Segoe UI', Helvetica, Arial, sans-serif; color: #1f2328; max-width: 820px;
+       margin: 2em auto; padding: 0 1em; }
+h1 { font-size: 1.6em; margin-bottom: 0; }
+h2 { font-size: 1.2em; margin-top: 2em; border-bottom: 1px solid #d0d7de; padding-bottom: .3em; }
+.generated { color: #656d76; margin-top: .3em; }
+.figures { display: flex; flex-wrap: wrap; gap: 1em; margin-top: 1.5em; }
+.figure { border: 1px solid #d0d7de; border-radius: 6px; padding: .6em 1em; min-width: 8em; }
+.figure b { display: block; font-size: 1.5em; }
+.chart { width: 100%; height: auto; }
+.chart text { font-size: 11px; fill: #656d76; }
+.chart .axis { stroke: #d0d7de; }
+.chart polyline { fill: none; stroke-width: 2; }
+.legend span { margin-right: 1.5em; }
+.bars { width: 100%; border-collapse: collapse; }
+.bars td { padding: .15em .5em .15em 0; white-space: nowrap; }
+.bars td.bar { width: 100%; }
+.bars div { height: 1em; border-radius: 2px; }
+.none { color: #656d76; }
+";
+
+#[derive(Debug)]
+pub struct HtmlReportConfig {
+    /// Output directory of a fetch, or the directory of one repository.
+    pub dir: PathBuf,
+    /// HTML file to write.
+    pub output: PathBuf,
+    /// Heading of the page, the names of the repositories by default.
+    pub title: Option<String>,
+}
+
+/// Items opened and closed in a month and those open at its end.
+struct Month {
+    start: NaiveDate,
+    opened: u64,
+    closed: u64,
+    open_issues: u64,
+    open_pulls: u64,
+}
+
+/// Writes a self-contained HTML page with charts of the snapshots below
+/// `cfg.dir`.
+pub fn report(cfg: HtmlReportConfig) -> Result<(), Box<dyn error::Error>> {
+    let repos = snapshot::repositories(&cfg.dir)?;
+    if repos.is_empty() {
+        return Err(format!("no snapshots in {}", cfg.dir.display()).into());
+    }
+    let mut items = Vec::new();
+    for (_, dir) in &repos {
+        items.extend(snapshot::items(dir)?);
+    }
+    let title = cfg.title.clone().unwrap_or_else(|| match repos.len() {
+        1..=3 => repos.iter().map(|(repo, _)| repo.to_string()).collect::<Vec<_>>().join(", "),
+        count => format!("{} repositories", count),
+    });
+    fs::write(&cfg.output, render(&title, repos.len(), &items))?;
+    info!("Wrote the report of {} items to {}", items.len(), cfg.output.display());
+    Ok(())
+}
+
+/// The report page of the `items` of `repositories` repositories.
+pub fn render(title: &str, repositories: usize, items: &[Item]) -> String {
+    let stats = Stats::of(repositories, items);
+    let months = timeline(items);
+    let labels: Vec<String> = months.iter().map(|month| month.start.format("%Y-%m").to_string()).collect();
+
+    let mut html = String::new();
+    html.push_str("<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n");
+    html.push_str(&format!("<title>{}</title>\n<style>{}</style>\n</head>\n<body>\n", escape(title), STYLE));
+    html.push_str(&format!("<h1>{}</h1>\n", escape(title)));
+    html.push_str(&format!(
+        "<p class=\"generated\">Generated {}</p>\n",
+        Utc::now().format("%Y-%m-%d %H:%M UTC")
+    ));
+
+    let open = stats.states.get("open").cloned().unwrap_or(0);
+    let mut figures = vec![
+        ("Issues", stats.issues.to_string()),
+        ("Pull requests", stats.pulls.to_string()),
+        ("Open", open.to_string()),
+    ];
+    if let Some(days) = stats.median_days_to_close {
+        figures.push(("Median days to close", format!("{:.1}", days)));
+    }
+    if let Some(rate) = stats.merge_rate {
+        figures.push(("Merge rate", format!("{:.0}%", rate * 100.0)));
+    }
+    html.push_str("<div class=\"figures\">\n");
+    for (name, value) in figures {
+        html.push_str(&format!("<div class=\"figure\"><b>{}</b>{}</div>\n", value, name));
+    }
+    html.push_str("</div>\n");
+
+    html.push_str("<h2>Open over time</h2>\n");
+    html.push_str(&line_chart(
+        &labels,
+        &[
+            ("Open issues", OPEN_COLOR, months.iter().map(|m| m.open_issues as f64).collect()),
+            ("Open pull requests", PULLS_COLOR, months.iter().map(|m| m.open_pulls as f64).collect()),
+        ],
+    ));
+    html.push_str("<h2>Opened and closed per month</h2>\n");
+    html.push_str(&line_chart(
+        &labels,
+        &[
+            ("Opened", OPEN_COLOR, months.iter().map(|m| m.opened as f64).collect()),
+            ("Closed or merged", CLOSED_COLOR, months.iter().map(|m| m.closed as f64).collect()),
+        ],
+    ));
+
+    html.push_str("<h2>Top labels</h2>\n");
+    html.push_str(&bars(&top(&stats.labels), OPEN_COLOR));
+    html.push_str("<h2>Top contributors</h2>\n");
+    html.push_str(&bars(&top(&stats.authors), PULLS_COLOR));
+
+    html.push_str("<h2>Pull request cycle time</h2>\n");
+    html.push_str(&cycle_time(items));
+    html.push_str("</body>\n</html>\n");
+    html
+}
+
+/// Histogram of the days from opening to merging of the merged pull
+/// requests and their median by month of merging.
+fn cycle_time(items: &[Item]) -> String {
+    let mut by_month: BTreeMap<String, Vec<f64>> = BTreeMap::new();
+    let mut all = Vec::new();
+    for item in items.iter().filter(|item| item.pull) {
+        if let (Some(created), Some(merged)) = (item.created_at, item.merged_at) {
+            let days = stats::days(merged.signed_duration_since(created));
+            by_month.entry(merged.format("%Y-%m").to_string()).or_insert_with(Vec::new).push(days);
+            all.push(days);
+        }
+    }
+    let median = match stats::median(&mut all) {
+        Some(median) => median,
+        None => return "<p class=\"none\">No merged pull requests</p>\n".to_string(),
+    };
+    let mut html = format!(
+        "<p>{} merged pull requests, a median of {:.1} days from opening to merging.</p>\n",
+        all.len(),
+        median
+    );
+    let mut buckets = vec![0; CYCLE_BUCKETS.len()];
+    for days in &all {
+        buckets[CYCLE_BUCKETS.iter().position(|&(bound, _)| *days < bound).unwrap()] += 1;
+    }
+    let rows: Vec<(String, usize)> = CYCLE_BUCKETS
+        .iter()
+        .zip(buckets)
+        .map(|(&(_, name), count)| (name.to_string(), count))
+        .collect();
+    html.push_str(&bars(&rows, CLOSED_COLOR));
+    let labels: Vec<String> = by_month.keys().cloned().collect();
+    let medians: Vec<f64> = by_month.values_mut().map(|days| stats::median(days).unwrap_or(0.0)).collect();
+    html.push_str("<h3>Median days to merge by month</h3>\n");
+    html.push_str(&line_chart(&labels, &[("Median days", CLOSED_COLOR, medians)]));
+    html
+}
+
+/// The months from the first opening to the last closing among `items`.
+fn timeline(items: &[Item]) -> Vec<Month> {
+    let closed_at = |item: &Item| item.merged_at.or(item.closed_at);
+    let dates: Vec<NaiveDate> = items
+        .iter()
+        .flat_map(|item| item.created_at.into_iter().chain(closed_at(item)))
+        .map(|time| time.naive_utc().date())
+        .collect();
+    let (first, last) = match (dates.iter().min(), dates.iter().max()) {
+        (Some(first), Some(last)) => (month_of(*first), month_of(*last)),
+        _ => return Vec::new(),
+    };
+    let mut months = Vec::new();
+    let mut start = first;
+    while start <= last {
+        let next = next_month(start);
+        let before = |time: Option<DateTime<Utc>>, date: NaiveDate| time.map_or(false, |t| t.naive_utc().date() < date);
+        let within = |time: Option<DateTime<Utc>>| before(time, next) && !before(time, start);
+        let open = |pull: bool| {
+            items
+                .iter()
+                .filter(|item| item.pull == pull && before(item.created_at, next) && !before(closed_at(item), next))
+                .count() as u64
+        };
+        months.push(Month {
+            start: start,
+            opened: items.iter().filter(|item| within(item.created_at)).count() as u64,
+            closed: items.iter().filter(|item| within(closed_at(item))).count() as u64,
+            open_issues: open(false),
+            open_pulls: open(true),
+        });
+        start = next;
+    }
+    months
+}
+
+fn month_of(date: NaiveDate) -> NaiveDate {
+    NaiveDate::from_ymd_opt(date.year(), date.month(), 1).unwrap()
+}
+
+fn next_month(month: NaiveDate) -> NaiveDate {
+    if month.month() == 12 {
+        NaiveDate::from_ymd_opt(month.year() + 1, 1, 1).unwrap()
+    } else {
+        NaiveDate::from_ymd_opt(month.year(), month.month() + 1, 1).unwrap()
+    }
+}
+
+/// The most frequent of `counts`.
+fn top(counts: &BTreeMap<String, usize>) -> Vec<(String, usize)> {
+    let mut counts: Vec<(String, usize)> = counts.iter().map(|(name, count)| (name.clone(), *count)).collect();
+    counts.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+    counts.truncate(TOP);
+    counts
+}
+
+/// SVG chart of a line per series over `labels`, with a legend.
+fn line_chart(labels: &[String], series: &[(&str, &str, Vec<f64>)]) -> String {
+    if labels.is_empty() {
+        return "<p class=\"none\">No data</p>\n".to_string();
+    }
+    let max = series
+        .iter()
+        .flat_map(|&(_, _, ref values)| values.iter().cloned())
+        .fold(0.0, f64::max);
+    let max = if max > 0.0 { max } else { 1.0 };
+    let (plot_width, plot_height) = (WIDTH - MARGIN, HEIGHT - MARGIN / 2.0);
+    let x = |i: usize| match labels.len() {
+        1 => MARGIN + plot_width / 2.0,
+        len => MARGIN + i as f64 * (plot_width - 10.0) / (len - 1) as f64,
+    };
+    let y = |value: f64| plot_height - value / max * (plot_height - 10.0);
+
+    let mut svg = format!("<svg class=\"chart\" viewBox=\"0 0 {} {}\">\n", WIDTH, HEIGHT);
+    svg.push_str(&format!(
+        "<line class=\"axis\" x1=\"{0}\" y1=\"{1}\" x2=\"{2}\" y2=\"{1}\"/>\n",
+        MARGIN, plot_height, WIDTH
+    ));
+    svg.push_str(&format!(
+        "<line class=\"axis\" x1=\"{0}\" y1=\"10\" x2=\"{0}\" y2=\"{1}\"/>\n",
+        MARGIN, plot_height
+    ));
+    for &value in &[0.0, max / 2.0, max] {
+        svg.push_str(&format!(
+            "<text x=\"{}\" y=\"{:.1}\" text-anchor=\"end\">{}</text>\n",
+            MARGIN - 5.0,
+            y(value) + 4.0,
+            number(value)
+        ));
+    }
+    // about a dozen labels whatever the number of months
+    let step = (labels.len() + 11) / 12;
+    for (i, label) in labels.iter().enumerate().step_by(step) {
+        svg.push_str(&format!(
+            "<text x=\"{:.1}\" y=\"{}\" text-anchor=\"middle\">{}</text>\n",
+            x(i),
+            HEIGHT - 5.0,
+            escape(label)
+        ));
+    }
+    for &(_, color, ref values) in series {
+        let points: Vec<String> = values
+            .iter()
+            .enumerate()
+            .map(|(i, value)| format!("{:.1},{:.1}", x(i), y(*value)))
+            .collect();
+        svg.push_str(&format!("<polyline stroke=\"{}\" points=\"{}\"/>\n", color, points.join(" ")));
+    }
+    svg.push_str("</svg>\n<p class=\"legend\">");
+    for &(name, color, _) in series {
+        svg.push_str(&format!("<span><span style=\"color: {}\">&#9632;</span> {}</span>", color, escape(name)));
+    }
+    svg.push_str("</p>\n");
+    svg
+}
+
+/// Table of horizontal bars of the counts of `rows`.
+fn bars(rows: &[(String, usize)], color: &str) -> String {
+    let max = match rows.iter().map(|&(_, count)| count).max() {
+        Some(max) if max > 0 => max,
+        _ => return "<p class=\"none\">None</p>\n".to_string(),
+    };
+    let mut html = "<table class=\"bars\">\n".to_string();
+    for &(ref name, count) in rows {
+        html.push_str(&format!(
+            "<tr><td>{}</td><td class=\"bar\"><div style=\"width: {:.1}%; background: {}\"></div></td>\
+             <td>{}</td></tr>\n",
+            escape(name),
+            count as f64 * 100.0 / max as f64,
+            color,
+            count
+        ));
+    }
+    html.push_str("</table>\n");
+    html
+}
+
+fn number(value: f64) -> String {
+    if value.fract() == 0.0 {
+        format!("{:.0}", value)
+    } else {
+        format!("{:.1}", value)
+    }
+}
+
+/// `text` with the characters special to HTML escaped.
+pub fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
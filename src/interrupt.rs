@@ -0,0 +1,45 @@
+use std::process;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+use tokio::time;
+
+use crate::exit::ExitCode;
+
+static REQUESTED: AtomicBool = AtomicBool::new(false);
+
+/// Handles SIGINT and SIGTERM by letting the run wind down, no further
+/// requests are sent and the progress is checkpointed. A second signal exits
+/// right away.
+pub fn install() -> Result<(), ctrlc::Error> {
+    ctrlc::set_handler(|| {
+        if REQUESTED.swap(true, Ordering::SeqCst) {
+            process::exit(ExitCode::Interrupted as i32);
+        }
+        warn!("interrupted, saving progress, interrupt again to quit right away");
+    })
+}
+
+/// Interrupts the run as a first SIGINT does.
+pub fn request() {
+    if !REQUESTED.swap(true, Ordering::SeqCst) {
+        warn!("interrupted, saving progress");
+    }
+}
+
+/// Whether the run was interrupted.
+pub fn requested() -> bool {
+    REQUESTED.load(Ordering::SeqCst)
+}
+
+/// Sleeps for `duration`, returning early once the run is interrupted.
+pub async fn sleep(duration: Duration) {
+    let until = Instant::now() + duration;
+    while !requested() {
+        let now = Instant::now();
+        if now >= until {
+            break;
+        }
+        time::sleep((until - now).min(Duration::from_secs(1))).await;
+    }
+}
@@ -0,0 +1,121 @@
+use std::error;
+
+use crate::sink::Sink;
+
+#[derive(Debug)]
+pub struct KafkaConfig {
+    pub brokers: Vec<String>,
+    pub topic: String,
+}
+
+/// Sink publishing the fetched records to the Kafka topic of `cfg`.
+#[cfg(feature = "kafka")]
+pub fn sink(cfg: &KafkaConfig) -> Result<Box<dyn Sink>, Box<dyn error::Error>> {
+    Ok(Box::new(KafkaSink::new(cfg)?))
+}
+
+#[cfg(not(feature = "kafka"))]
+pub fn sink(_cfg: &KafkaConfig) -> Result<Box<dyn Sink>, Box<dyn error::Error>> {
+    Err("--kafka-brokers needs a build with the kafka feature".into())
+}
+
+#[cfg(feature = "kafka")]
+pub use self::producer::KafkaSink;
+
+#[cfg(feature = "kafka")]
+mod producer {
+    use std::error;
+    use std::time::Duration;
+
+    use kafka::producer::{Producer, Record, RequiredAcks};
+    use rmp_serde::Serializer;
+    use serde::Serialize;
+    use serde_json::Value;
+
+    use super::KafkaConfig;
+    use crate::repo::Repo;
+    use crate::sink::{Record as SinkRecord, Sink};
+
+    /// Number of records sent to the brokers in one produce request.
+    const BATCH_SIZE: usize = 100;
+
+    /// Publishes fetched records to a Kafka topic, one msgpack encoded message
+    /// per record keyed by its global node id, the `node_id` of REST records
+    /// and the `id` of GraphQL nodes, so that a record keeps its key whichever
+    /// API fetched it.
+    /// Unlike the snapshots it gets only the records of a fetch, not those
+    /// merged with the ones before.
+    ///
+    /// Every batch waits for acknowledgement from all in-sync replicas, errors
+    /// abort the run, so records are delivered at least once.
+    pub struct KafkaSink {
+        producer: Producer,
+        topic: String,
+    }
+
+    impl KafkaSink {
+        pub fn new(cfg: &KafkaConfig) -> Result<Self, Box<dyn error::Error>> {
+            let producer = Producer::from_hosts(cfg.brokers.clone())
+                .with_ack_timeout(Duration::from_secs(30))
+                .with_required_acks(RequiredAcks::All)
+                .create()?;
+            Ok(KafkaSink {
+                producer: producer,
+                topic: cfg.topic.clone(),
+            })
+        }
+
+        /// Sends messages of a key and a record in batches.
+        fn send(&mut self, messages: &[(String, Vec<u8>)]) -> Result<(), Box<dyn error::Error>> {
+            let topic = &self.topic;
+            let producer = &mut self.producer;
+            for chunk in messages.chunks(BATCH_SIZE) {
+                let records: Vec<_> = chunk
+                    .iter()
+                    .map(|&(ref key, ref buf)| Record::from_key_value(&topic[..], &key[..], &buf[..]))
+                    .collect();
+                for confirm in producer.send_all(&records)? {
+                    for partition in confirm.partition_confirms {
+                        if let Err(code) = partition.offset {
+                            return Err(format!(
+                                "Kafka rejected records for {}/{}: {:?}",
+                                confirm.topic, partition.partition, code
+                            ).into());
+                        }
+                    }
+                }
+            }
+            Ok(())
+        }
+    }
+
+    impl Sink for KafkaSink {
+        fn write(
+            &mut self,
+            _repo: &Repo,
+            _entity: &str,
+            _tag: &str,
+            records: &[SinkRecord],
+        ) -> Result<Option<u64>, Box<dyn error::Error>> {
+            let mut messages = Vec::with_capacity(records.len());
+            for record in records {
+                let mut buf = Vec::new();
+                record.serialize(&mut Serializer::new(&mut buf))?;
+                messages.push((key(*record)?, buf));
+            }
+            self.send(&messages)?;
+            Ok(None)
+        }
+    }
+
+    /// Global node id of `record`.
+    fn key(record: SinkRecord) -> Result<String, Box<dyn error::Error>> {
+        let value = serde_json::to_value(record)?;
+        value
+            .get("node_id")
+            .and_then(Value::as_str)
+            .or_else(|| value.get("id").and_then(Value::as_str))
+            .map(|id| id.to_string())
+            .ok_or_else(|| "a record without a node id can't be published".into())
+    }
+}
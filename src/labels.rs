@@ -0,0 +1,168 @@
+use std::collections::BTreeMap;
+use std::error;
+use std::path::PathBuf;
+
+use serde_json::Value;
+
+use crate::format;
+use crate::snapshot::{self, Item};
+
+/// Pairs and transitions listed in the text output, the JSON one has all.
+const TOP: usize = 20;
+/// Snapshot of the issue events of a repository, as the REST API lists them
+/// at `/repos/{owner}/{name}/issues/events`, written by `fetch --events`.
+pub const EVENTS: &str = "events";
+
+#[derive(Debug)]
+pub struct LabelsConfig {
+    /// Output directory of a fetch, or the directory of one repository.
+    pub dir: PathBuf,
+    pub json: bool,
+}
+
+/// How the labels of issues and pull requests are used together and one
+/// after another.
+#[derive(Debug, Default, Serialize)]
+pub struct LabelAnalysis {
+    /// Items by pair of labels they have both of, symmetric, with the items
+    /// of each label on the diagonal.
+    pub co_occurrence: BTreeMap<String, BTreeMap<String, usize>>,
+    /// Times a label was removed from an item and the other added next, by
+    /// the label removed and the one added. Only known with events
+    /// snapshots.
+    pub transitions: BTreeMap<String, BTreeMap<String, usize>>,
+    pub labeled: BTreeMap<String, usize>,
+    pub unlabeled: BTreeMap<String, usize>,
+}
+
+impl LabelAnalysis {
+    /// Adds the current labels of `items`.
+    pub fn add_items(&mut self, items: &[Item]) {
+        for item in items {
+            for a in &item.labels {
+                for b in &item.labels {
+                    *self
+                        .co_occurrence
+                        .entry(a.clone())
+                        .or_insert_with(BTreeMap::new)
+                        .entry(b.clone())
+                        .or_insert(0) += 1;
+                }
+            }
+        }
+    }
+
+    /// Adds the label changes among the issue `events` of a repository.
+    pub fn add_events(&mut self, events: &[Value]) {
+        // label events by item, in order
+        let mut changes: BTreeMap<u64, Vec<(&str, bool, &str)>> = BTreeMap::new();
+        for event in events {
+            let added = match event.get("event").and_then(Value::as_str) {
+                Some("labeled") => true,
+                Some("unlabeled") => false,
+                _ => continue,
+            };
+            let number = event.pointer("/issue/number").and_then(Value::as_u64);
+            let label = event.pointer("/label/name").and_then(Value::as_str);
+            let time = event.get("created_at").and_then(Value::as_str).unwrap_or("");
+            if let (Some(number), Some(label)) = (number, label) {
+                changes.entry(number).or_insert_with(Vec::new).push((time, added, label));
+            }
+        }
+        for changes in changes.values_mut() {
+            // RFC 3339 times of the API sort as text
+            changes.sort_by(|a, b| a.0.cmp(b.0));
+            let mut removed = None;
+            for &(_, added, label) in changes.iter() {
+                if added {
+                    *self.labeled.entry(label.to_string()).or_insert(0) += 1;
+                    if let Some(from) = removed.take() {
+                        *self
+                            .transitions
+                            .entry(String::from(from))
+                            .or_insert_with(BTreeMap::new)
+                            .entry(label.to_string())
+                            .or_insert(0) += 1;
+                    }
+                } else {
+                    *self.unlabeled.entry(label.to_string()).or_insert(0) += 1;
+                    removed = Some(label);
+                }
+            }
+        }
+    }
+}
+
+/// Prints the label co-occurrences and transitions of the snapshots below
+/// `cfg.dir`.
+pub fn labels(cfg: LabelsConfig) -> Result<(), Box<dyn error::Error>> {
+    let repos = snapshot::repositories(&cfg.dir)?;
+    if repos.is_empty() {
+        return Err(format!("no snapshots in {}", cfg.dir.display()).into());
+    }
+    let mut analysis = LabelAnalysis::default();
+    let mut with_events = 0;
+    for (_, dir) in &repos {
+        analysis.add_items(&snapshot::items(dir)?);
+        if let Some((path, format)) = snapshot::find_named(dir, EVENTS) {
+            let events: Vec<Value> = format::deserialize_from_file(format, &path)?;
+            analysis.add_events(&events);
+            with_events += 1;
+        }
+    }
+    if cfg.json {
+        println!("{}", serde_json::to_string_pretty(&analysis)?);
+        return Ok(());
+    }
+
+    let mut pairs: Vec<(&str, &str, usize)> = analysis
+        .co_occurrence
+        .iter()
+        .flat_map(|(a, counts)| counts.iter().map(move |(b, count)| (&a[..], &b[..], *count)))
+        .filter(|&(a, b, _)| a < b)
+        .collect();
+    println!("Labels used together:");
+    print_pairs(&mut pairs, "+", |a, b| {
+        // the share of the items of the rarer label that have the other
+        let items = |label: &str| analysis.co_occurrence[label][label];
+        items(a).min(items(b))
+    });
+    if with_events == 0 {
+        println!("No {} snapshots, label transitions unknown, fetch with --events", EVENTS);
+        return Ok(());
+    }
+    let mut transitions: Vec<(&str, &str, usize)> = analysis
+        .transitions
+        .iter()
+        .flat_map(|(from, counts)| counts.iter().map(move |(to, count)| (&from[..], &to[..], *count)))
+        .collect();
+    println!("Label transitions in {} of {} repositories:", with_events, repos.len());
+    print_pairs(&mut transitions, "->", |from, _| analysis.unlabeled[from]);
+    Ok(())
+}
+
+/// Prints the most frequent of `pairs`, their counts also as a share of what
+/// `of` gives for the pair.
+fn print_pairs<F>(pairs: &mut [(&str, &str, usize)], separator: &str, of: F)
+where
+    F: Fn(&str, &str) -> usize,
+{
+    if pairs.is_empty() {
+        println!("  none");
+        return;
+    }
+    pairs.sort_by(|a, b| b.2.cmp(&a.2).then(a.0.cmp(b.0)).then(a.1.cmp(b.1)));
+    for &(a, b, count) in pairs.iter().take(TOP) {
+        println!(
+            "  {} {} {}  {} ({:.0}%)",
+            a,
+            separator,
+            b,
+            count,
+            count as f64 * 100.0 / of(a, b).max(1) as f64
+        );
+    }
+    if pairs.len() > TOP {
+        println!("  ({} more)", pairs.len() - TOP);
+    }
+}
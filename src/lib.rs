@@ -0,0 +1,140 @@
+//! Fetches issues and pull requests of GitHub repositories into snapshots.
+//!
+//! The `github-data-fetch` binary is a command line interface to `Fetcher`,
+//! which other programs can use to embed the fetch:
+//!
+//! ```no_run
+//! # async fn fetch(cfg: github_data_fetch::fetcher::FetchConfig) -> Result<(), Box<dyn std::error::Error>> {
+//! let report = github_data_fetch::fetcher::Fetcher::new(cfg).fetch().await?;
+//! for repo in &report.repositories {
+//!     println!("{}: {:?}", repo.repository, repo.records);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Or stream the records of a repository as they arrive rather than writing
+//! snapshots:
+//!
+//! ```no_run
+//! # use futures::TryStreamExt;
+//! # async fn stream(cfg: github_data_fetch::fetcher::FetchConfig) -> Result<(), Box<dyn std::error::Error>> {
+//! let fetcher = github_data_fetch::fetcher::Fetcher::new(cfg);
+//! let client = fetcher.client()?;
+//! for repo in &fetcher.config().repos {
+//!     let mut issues = fetcher.issues(&client, repo);
+//!     while let Some(issue) = issues.try_next().await? {
+//!         println!("#{} {}", issue.number, issue.title);
+//!     }
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+#[cfg(feature = "parquet")]
+extern crate arrow;
+extern crate atty;
+extern crate chrono;
+#[cfg(feature = "tui")]
+extern crate crossterm;
+extern crate ctrlc;
+extern crate dialoguer;
+extern crate erased_serde;
+extern crate fs2;
+extern crate futures;
+extern crate fuzzy_matcher;
+extern crate glob;
+extern crate hmac;
+extern crate http;
+extern crate hyper;
+extern crate hyper_proxy;
+extern crate hyper_tls;
+extern crate indicatif;
+extern crate jsonwebtoken;
+#[cfg(feature = "kafka")]
+extern crate kafka;
+#[macro_use]
+extern crate log;
+extern crate native_tls;
+#[cfg(feature = "otlp")]
+extern crate opentelemetry;
+#[cfg(feature = "otlp")]
+extern crate opentelemetry_otlp;
+#[cfg(feature = "otlp")]
+extern crate opentelemetry_sdk;
+#[cfg(feature = "parquet")]
+extern crate parquet;
+extern crate rand;
+#[cfg(feature = "tui")]
+extern crate ratatui;
+extern crate regex;
+extern crate rmp_serde;
+extern crate rmpv;
+extern crate rusqlite;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+#[macro_use]
+extern crate serde_json;
+extern crate sha2;
+extern crate stream_throttle;
+extern crate thiserror;
+extern crate tokio;
+extern crate tokio_native_tls;
+extern crate tracing;
+#[cfg(feature = "otlp")]
+extern crate tracing_opentelemetry;
+#[cfg(feature = "otlp")]
+extern crate tracing_subscriber;
+
+pub mod anonymize;
+pub mod api;
+pub mod auth;
+pub mod bandwidth;
+pub mod budget;
+pub mod burndown;
+pub mod client;
+pub mod contributors;
+pub mod dashboard;
+pub mod diff;
+pub mod entity;
+pub mod error;
+pub mod events;
+pub mod exit;
+pub mod export_site;
+pub mod fetcher;
+pub mod filter;
+pub mod format;
+pub mod graph;
+mod graphql;
+pub mod html_report;
+#[doc(hidden)]
+pub mod interrupt;
+pub mod kafka_sink;
+pub mod labels;
+pub mod lock;
+mod manifest;
+pub mod merge;
+mod metrics;
+pub mod model;
+mod pick;
+pub mod pr_metrics;
+pub mod progress;
+mod proxy;
+pub mod query;
+pub mod redact;
+pub mod repo;
+pub mod report;
+pub mod retry;
+mod sample;
+pub mod serve;
+#[doc(hidden)]
+pub mod show;
+pub mod sink;
+pub mod snapshot;
+mod state;
+pub mod stats;
+pub mod telemetry;
+mod users;
+pub mod vcr;
+pub mod verify;
@@ -0,0 +1,211 @@
+use std::convert::Infallible;
+use std::error;
+use std::fs;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use hmac::{Hmac, Mac};
+use hyper::{self, Body, Request, Response, Server, StatusCode};
+use hyper::service::{make_service_fn, service_fn};
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use serde_json::{self, Value};
+use sha2::Sha256;
+
+use github_data_fetch::api;
+use github_data_fetch::entity::Entity;
+use github_data_fetch::format::{self, Format};
+use github_data_fetch::lock;
+use github_data_fetch::merge;
+use github_data_fetch::model::{Issue, Pull};
+use github_data_fetch::snapshot;
+
+#[derive(Debug)]
+pub struct ListenConfig {
+    pub addr: SocketAddr,
+    /// Secret configured for the webhook, used to verify payload signatures.
+    pub secret: String,
+    /// Output directory containing the snapshots to update.
+    pub dir: PathBuf,
+}
+
+struct Listener {
+    secret: String,
+    dir: PathBuf,
+    /// Serializes snapshot updates from concurrent deliveries.
+    writing: Mutex<()>,
+}
+
+/// Receives GitHub webhook deliveries and upserts the issues and pull requests
+/// they carry into the snapshots below the output directory.
+///
+/// Handles `issues`, `issue_comment` and `pull_request` events, deliveries
+/// without a valid `X-Hub-Signature-256` are rejected. Deliveries are answered
+/// with 503 while a fetch holds the output directory and with 409 if the
+/// snapshot was written through the GraphQL API or with deduplicated users.
+pub async fn listen(cfg: ListenConfig) -> Result<(), Box<dyn error::Error>> {
+    let listener = Arc::new(Listener {
+        secret: cfg.secret,
+        dir: cfg.dir,
+        writing: Mutex::new(()),
+    });
+    let server = Server::try_bind(&cfg.addr)?.serve(make_service_fn(move |_| {
+        let listener = listener.clone();
+        async move { Ok::<_, Infallible>(service_fn(move |req| handle(listener.clone(), req))) }
+    }));
+    info!("Listening for webhooks on {}", cfg.addr);
+    if let Err(e) = server.await {
+        error!("Server error: {}", e);
+    }
+    Ok(())
+}
+
+async fn handle(listener: Arc<Listener>, req: Request<Body>) -> Result<Response<Body>, hyper::Error> {
+    let event = header(&req, "x-github-event");
+    let signature = header(&req, "x-hub-signature-256");
+    let body = hyper::body::to_bytes(req.into_body()).await?;
+    let status = match (event, signature) {
+        (Some(event), Some(ref signature)) if verify(&listener.secret, &body, signature) => {
+            // reading and writing the snapshot blocks
+            let applied = {
+                let (listener, event) = (listener.clone(), event.clone());
+                tokio::task::spawn_blocking(move || apply(&listener, &event, &body).map_err(|e| e.to_string())).await
+            };
+            match applied.map_err(|e| e.to_string()).and_then(|applied| applied) {
+                Ok(Applied::Upserted) => StatusCode::NO_CONTENT,
+                Ok(Applied::Locked) => {
+                    warn!("{} event not applied, a fetch holds {}", event, listener.dir.display());
+                    StatusCode::SERVICE_UNAVAILABLE
+                }
+                Ok(Applied::Refused(reason)) => {
+                    error!("{} event not applied: {}", event, reason);
+                    StatusCode::CONFLICT
+                }
+                Err(e) => {
+                    error!("failed to apply {} event: {}", event, e);
+                    StatusCode::INTERNAL_SERVER_ERROR
+                }
+            }
+        }
+        (Some(_), _) => StatusCode::UNAUTHORIZED,
+        (None, _) => StatusCode::BAD_REQUEST,
+    };
+    let mut response = Response::new(Body::empty());
+    *response.status_mut() = status;
+    Ok(response)
+}
+
+fn header(req: &Request<Body>, name: &str) -> Option<String> {
+    req.headers()
+        .get(name)
+        .and_then(|value| value.to_str().ok())
+        .map(|value| value.to_string())
+}
+
+/// Checks a `sha256=<hex>` HMAC signature of `body`.
+fn verify(secret: &str, body: &[u8], signature: &str) -> bool {
+    let expected = match decode_hex(signature.trim_start_matches("sha256=")) {
+        Some(expected) => expected,
+        None => return false,
+    };
+    let mut mac = match Hmac::<Sha256>::new_varkey(secret.as_bytes()) {
+        Ok(mac) => mac,
+        Err(_) => return false,
+    };
+    mac.input(body);
+    mac.verify(&expected).is_ok()
+}
+
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if hex.len() % 2 != 0 {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| hex.get(i..i + 2).and_then(|pair| u8::from_str_radix(pair, 16).ok()))
+        .collect()
+}
+
+/// What became of a delivery.
+enum Applied {
+    Upserted,
+    /// A fetch holds the output directory, the delivery can be redelivered
+    /// once it is done.
+    Locked,
+    /// The snapshot is in a shape the records of a delivery can't be merged
+    /// into.
+    Refused(String),
+}
+
+fn apply(listener: &Listener, event: &str, body: &[u8]) -> Result<Applied, Box<dyn error::Error>> {
+    let payload: Value = serde_json::from_slice(body)?;
+    let repo = match payload["repository"]["full_name"].as_str() {
+        Some(repo) => listener.dir.join(repo),
+        None => return Ok(Applied::Upserted),
+    };
+    let _writing = listener.writing.lock().unwrap();
+    let _lock = match lock::try_lock(&listener.dir)? {
+        Some(lock) => lock,
+        None => return Ok(Applied::Locked),
+    };
+    fs::create_dir_all(&repo)?;
+    if let Some((path, _)) = snapshot::find_named(&repo, "users") {
+        return Ok(Applied::Refused(format!(
+            "{} holds deduplicated users, webhook records can't be merged into its snapshots",
+            path.display()
+        )));
+    }
+    match event {
+        "issues" | "issue_comment" => {
+            let issue: api::Issue = serde_json::from_value(payload["issue"].clone())?;
+            // comments on pull requests come with the pull request as issue,
+            // which does not belong into the issues snapshot
+            if issue.pull_request.is_none() {
+                info!("{} event for issue {}", event, issue.number);
+                return upsert(&repo, Entity::Issues, Issue::from(issue), |i| i.number);
+            }
+        }
+        "pull_request" => {
+            let pull: api::Pull = serde_json::from_value(payload["pull_request"].clone())?;
+            info!("{} event for pull {}", event, pull.number);
+            return upsert(&repo, Entity::Pulls, Pull::from(pull), |p| p.number);
+        }
+        _ => {}
+    }
+    Ok(Applied::Upserted)
+}
+
+fn upsert<D, K>(dir: &Path, entity: Entity, record: D, key: K) -> Result<Applied, Box<dyn error::Error>>
+where
+    D: Serialize + DeserializeOwned,
+    K: Fn(&D) -> u64,
+{
+    let (path, format, existing) = match snapshot::find(dir, entity) {
+        Some((path, format)) => match format::deserialize_from_file::<D>(format, &path) {
+            Ok(existing) => (path, format, existing),
+            Err(e) => {
+                // GraphQL nodes don't match the REST types, only they have a
+                // databaseId
+                let graphql = format::deserialize_from_file::<Value>(format, &path)
+                    .map(|records| records.iter().any(|r| r.get("databaseId").is_some()))
+                    .unwrap_or(false);
+                if graphql {
+                    return Ok(Applied::Refused(format!(
+                        "{} was written through the GraphQL API, webhook records can't be merged into it",
+                        path.display()
+                    )));
+                }
+                return Err(e);
+            }
+        },
+        None => {
+            let path = dir.join(format!("{}.{}", entity.name(), Format::Msgpack.extension()));
+            (path, Format::Msgpack, Vec::new())
+        }
+    };
+    let records = [record];
+    let merged = merge::upsert(&existing, &records, |record| Some(key(record)))?;
+    format::serialize_to_file(&merged, format, &path)?;
+    Ok(Applied::Upserted)
+}
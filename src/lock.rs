@@ -0,0 +1,71 @@
+use std::error;
+use std::fs::{self, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::process;
+
+use fs2::FileExt;
+
+const LOCK_FILE: &str = ".lock";
+
+/// Advisory lock on an output directory, released when dropped.
+pub struct DirLock {
+    file: fs::File,
+}
+
+impl Drop for DirLock {
+    fn drop(&mut self) {
+        let _ = self.file.unlock();
+    }
+}
+
+/// Locks `dir` against other runs writing to it. Fails right away if another
+/// process holds the lock, unless `wait` is set.
+pub fn lock(dir: &Path, wait: bool) -> Result<DirLock, Box<dyn error::Error>> {
+    let mut file = open(dir)?;
+    if let Err(e) = file.try_lock_exclusive() {
+        let mut holder = String::new();
+        file.read_to_string(&mut holder)?;
+        let holder = match holder.trim() {
+            "" => String::new(),
+            pid => format!(" (pid {})", pid),
+        };
+        if !wait {
+            return Err(format!(
+                "{} is locked by another run{}, pass --wait-for-lock to wait for it: {}",
+                dir.display(),
+                holder,
+                e
+            ).into());
+        }
+        info!("Waiting for another run{} to release {}", holder, dir.display());
+        file.lock_exclusive()?;
+    }
+    hold(file)
+}
+
+/// Locks `dir` unless another process holds the lock, `None` then.
+pub fn try_lock(dir: &Path) -> Result<Option<DirLock>, Box<dyn error::Error>> {
+    let file = open(dir)?;
+    if file.try_lock_exclusive().is_err() {
+        return Ok(None);
+    }
+    hold(file).map(Some)
+}
+
+fn open(dir: &Path) -> Result<fs::File, Box<dyn error::Error>> {
+    fs::create_dir_all(dir)?;
+    Ok(OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .open(dir.join(LOCK_FILE))?)
+}
+
+/// Records this process as the holder of the locked `file`.
+fn hold(mut file: fs::File) -> Result<DirLock, Box<dyn error::Error>> {
+    file.set_len(0)?;
+    file.seek(SeekFrom::Start(0))?;
+    write!(file, "{}", process::id())?;
+    Ok(DirLock { file: file })
+}
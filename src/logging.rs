@@ -0,0 +1,72 @@
+use std::env;
+use std::io::Write;
+
+use env_logger::fmt::Color;
+use env_logger::{Builder, WriteStyle};
+use log::{Level, LevelFilter};
+
+use github_data_fetch::dashboard::Dashboard;
+
+/// Sets up logging to stderr. Messages of this crate are shown down to info
+/// level, one level more per `verbosity` step and errors only with a negative
+/// one. `RUST_LOG` takes precedence, e.g. `RUST_LOG=hyper=debug`.
+///
+/// Errors and warnings are colored on a terminal unless `color` is `never`
+/// or `NO_COLOR` is set, `always` colors them regardless. While `dashboard`
+/// is shown they are listed on it instead and everything else is dropped.
+/// With `json` progress events they are JSON events as well, e.g.
+/// `{"level":"warn","message":"…"}`, and everything else is dropped too.
+pub fn init(verbosity: i64, color: &str, json: bool, dashboard: Option<Dashboard>) {
+    let level = match verbosity {
+        v if v < 0 => LevelFilter::Error,
+        0 => LevelFilter::Info,
+        1 => LevelFilter::Debug,
+        _ => LevelFilter::Trace,
+    };
+    let style = match color {
+        "always" => WriteStyle::Always,
+        "never" => WriteStyle::Never,
+        _ if env::var_os("NO_COLOR").map_or(false, |v| !v.is_empty()) => WriteStyle::Never,
+        _ => WriteStyle::Auto,
+    };
+    Builder::new()
+        .filter_level(LevelFilter::Warn)
+        .filter_module(&env!("CARGO_PKG_NAME").replace('-', "_"), level)
+        .parse_filters(&env::var("RUST_LOG").unwrap_or_default())
+        .write_style(style)
+        .format(move |buf, record| {
+            if json {
+                if record.level() <= Level::Warn {
+                    let event = serde_json::json!({
+                        "level": record.level().to_string().to_lowercase(),
+                        "message": record.args().to_string(),
+                    });
+                    writeln!(buf, "{}", event)?;
+                }
+                return Ok(());
+            }
+            if let Some(dashboard) = dashboard.as_ref().filter(|dashboard| dashboard.shown()) {
+                if record.level() <= Level::Warn {
+                    dashboard.event(format!("{}: {}", record.level(), record.args()));
+                }
+                return Ok(());
+            }
+            let mut style = buf.style();
+            match record.level() {
+                Level::Error => {
+                    style.set_color(Color::Red).set_bold(true);
+                    writeln!(buf, "{} {}", style.value("Error:"), record.args())
+                }
+                Level::Warn => {
+                    style.set_color(Color::Yellow).set_bold(true);
+                    writeln!(buf, "{} {}", style.value("Warning:"), record.args())
+                }
+                Level::Info => writeln!(buf, "{}", record.args()),
+                level => {
+                    style.set_color(Color::Black).set_intense(true);
+                    writeln!(buf, "{}", style.value(format!("{} {}: {}", level, record.target(), record.args())))
+                }
+            }
+        })
+        .init();
+}
@@ -0,0 +1,219 @@
+use std::collections::HashMap;
+use std::env;
+use std::error;
+use std::fs::{self, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::time::Duration;
+
+use hyper::{self, Body, Request};
+use hyper::client::HttpConnector;
+use hyper::header::{ACCEPT, CONTENT_TYPE, USER_AGENT};
+use hyper_tls::HttpsConnector;
+use serde::de::DeserializeOwned;
+use tokio::time;
+
+use github_data_fetch::filter::encode;
+
+#[derive(Debug)]
+pub struct LoginConfig {
+    /// Client ID of the OAuth app to authorize.
+    pub client_id: String,
+    pub scope: String,
+    /// Web host, `https://github.com` or that of an Enterprise server.
+    pub host: String,
+    /// Store the token in the system keyring instead of a file.
+    pub keyring: bool,
+}
+
+#[derive(Deserialize)]
+struct DeviceCode {
+    device_code: String,
+    user_code: String,
+    verification_uri: String,
+    interval: u64,
+}
+
+#[derive(Deserialize)]
+struct AccessToken {
+    access_token: Option<String>,
+    error: Option<String>,
+    error_description: Option<String>,
+}
+
+type HttpClient = hyper::Client<HttpsConnector<HttpConnector>>;
+
+/// Authorizes the OAuth app through the device flow, the user enters a code
+/// in the browser while the token is polled for, and stores the token for
+/// later runs.
+pub async fn login(cfg: LoginConfig) -> Result<(), Box<dyn error::Error>> {
+    if cfg.keyring && !cfg!(feature = "keyring") {
+        return Err("--keyring needs a build with the keyring feature".into());
+    }
+    let path = token_path().ok_or("no config directory, neither XDG_CONFIG_HOME nor HOME is set")?;
+    let http = hyper::Client::builder().build(HttpsConnector::new());
+    let host = cfg.host.trim_end_matches('/');
+
+    let code: DeviceCode = post_form(
+        &http,
+        &format!("{}/login/device/code", host),
+        &[("client_id", &cfg.client_id), ("scope", &cfg.scope)],
+    ).await?;
+    eprintln!("Open {} and enter the code {}", code.verification_uri, code.user_code);
+
+    let mut interval = code.interval;
+    let token = loop {
+        time::sleep(Duration::from_secs(interval)).await;
+        let response: AccessToken = post_form(
+            &http,
+            &format!("{}/login/oauth/access_token", host),
+            &[
+                ("client_id", &cfg.client_id),
+                ("device_code", &code.device_code),
+                ("grant_type", "urn:ietf:params:oauth:grant-type:device_code"),
+            ],
+        ).await?;
+        match (response.access_token, response.error) {
+            (Some(token), _) => break token,
+            (None, Some(ref error)) if error == "authorization_pending" => {}
+            // asked to poll less often
+            (None, Some(ref error)) if error == "slow_down" => interval += 5,
+            (None, error) => {
+                return Err(format!(
+                    "login failed: {}",
+                    response.error_description.or(error).unwrap_or_default()
+                ).into())
+            }
+        }
+    };
+
+    if cfg.keyring {
+        save_keyring_token(&token)?;
+        info!("Logged in, the token is stored in the system keyring");
+    } else {
+        save_token(&path, &token)?;
+        info!("Logged in, the token is stored in {}", path.display());
+    }
+    Ok(())
+}
+
+async fn post_form<T: DeserializeOwned>(
+    http: &HttpClient,
+    url: &str,
+    form: &[(&str, &str)],
+) -> Result<T, Box<dyn error::Error>> {
+    let body: Vec<String> = form
+        .iter()
+        .map(|&(key, value)| format!("{}={}", key, encode(value)))
+        .collect();
+    let request = Request::post(url)
+        .header(USER_AGENT, concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION")))
+        .header(ACCEPT, "application/json")
+        .header(CONTENT_TYPE, "application/x-www-form-urlencoded")
+        .body(Body::from(body.join("&")))?;
+    let response = http.request(request).await?;
+    let body = hyper::body::to_bytes(response.into_body()).await?;
+    Ok(serde_json::from_slice(&body)?)
+}
+
+/// File the token of `login` is kept in.
+pub fn token_path() -> Option<PathBuf> {
+    let config = env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    Some(config.join(env!("CARGO_PKG_NAME")).join("token"))
+}
+
+/// The token stored by a previous `login`, in the system keyring or a file.
+pub fn stored_token() -> Option<String> {
+    if let Some(token) = keyring_token() {
+        return Some(token);
+    }
+    let file = fs::File::open(token_path()?).ok()?;
+    let mut line = String::new();
+    BufReader::new(file).read_line(&mut line).ok()?;
+    Some(line.trim().to_string()).filter(|token| !token.is_empty())
+}
+
+/// Entry of a host in the gh CLI's `hosts.yml`.
+#[derive(Deserialize)]
+struct GhHost {
+    oauth_token: Option<String>,
+}
+
+/// The token the gh CLI is logged in to `host` with, read from its
+/// `hosts.yml` or, where gh keeps it in the system keyring, from
+/// `gh auth token`.
+pub fn gh_token(host: &str) -> Option<String> {
+    gh_hosts_token(host).or_else(|| {
+        let output = Command::new("gh")
+            .args(&["auth", "token", "--hostname", host])
+            .stdin(Stdio::null())
+            .stderr(Stdio::null())
+            .output()
+            .ok()?;
+        if !output.status.success() {
+            return None;
+        }
+        Some(String::from_utf8(output.stdout).ok()?.trim().to_string()).filter(|token| !token.is_empty())
+    })
+}
+
+fn gh_hosts_token(host: &str) -> Option<String> {
+    let dir = env::var_os("GH_CONFIG_DIR")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("XDG_CONFIG_HOME").map(|config| PathBuf::from(config).join("gh")))
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config").join("gh")))?;
+    let file = fs::File::open(dir.join("hosts.yml")).ok()?;
+    let mut hosts: HashMap<String, GhHost> = serde_yaml::from_reader(file).ok()?;
+    hosts
+        .remove(host)?
+        .oauth_token
+        .filter(|token| !token.is_empty())
+}
+
+/// Writes the token readable by the current user only.
+fn save_token(path: &PathBuf, token: &str) -> Result<(), Box<dyn error::Error>> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let mut options = OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    writeln!(options.open(path)?, "{}", token)?;
+    Ok(())
+}
+
+/// Account the token is kept under in the system keyring.
+#[cfg(feature = "keyring")]
+const KEYRING_USER: &str = "token";
+
+#[cfg(feature = "keyring")]
+fn keyring_token() -> Option<String> {
+    keyring::Keyring::new(env!("CARGO_PKG_NAME"), KEYRING_USER)
+        .get_password()
+        .ok()
+        .filter(|token| !token.is_empty())
+}
+
+#[cfg(not(feature = "keyring"))]
+fn keyring_token() -> Option<String> {
+    None
+}
+
+#[cfg(feature = "keyring")]
+fn save_keyring_token(token: &str) -> Result<(), Box<dyn error::Error>> {
+    keyring::Keyring::new(env!("CARGO_PKG_NAME"), KEYRING_USER)
+        .set_password(token)
+        .map_err(|e| format!("failed to store the token in the keyring: {}", e).into())
+}
+
+#[cfg(not(feature = "keyring"))]
+fn save_keyring_token(_token: &str) -> Result<(), Box<dyn error::Error>> {
+    Err("built without keyring support".into())
+}
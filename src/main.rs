@@ -1,147 +1,979 @@
+extern crate atty;
+extern crate chrono;
 #[macro_use]
 extern crate clap;
-extern crate futures;
-extern crate hubcaps;
+extern crate env_logger;
+extern crate github_data_fetch;
+extern crate glob;
+extern crate hmac;
 extern crate hyper;
-extern crate rmp_serde;
+extern crate hyper_tls;
+#[cfg(feature = "keyring")]
+extern crate keyring;
+#[macro_use]
+extern crate log;
 extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+extern crate serde_json;
+extern crate serde_yaml;
+extern crate sha2;
 extern crate stream_throttle;
-extern crate tokio_core;
+extern crate tokio;
+extern crate toml;
+
+mod check;
+mod config;
+mod listen;
+mod logging;
+mod login;
+mod manpage;
+mod update;
 
+use std::env;
 use std::error;
+use std::ffi::OsString;
 use std::fs;
+use std::io::{self, BufRead, Read};
+use std::net::SocketAddr;
 use std::path::{Path, PathBuf};
+use std::process;
 use std::time::Duration;
 
-use futures::Future;
-use futures::future;
-use futures::stream::Stream;
-use hubcaps::{Credentials, Github, ErrorKind};
-use hubcaps::repositories::Repository;
-use hubcaps::issues::{Issue, IssueListOptions, State};
-use hubcaps::pulls::Pull;
-use hyper::client::connect::Connect;
-use rmp_serde::Serializer;
-use serde::Serialize;
-use stream_throttle::{ThrottlePool, ThrottleRate};
-use tokio_core::reactor::{Core, Timeout};
+use chrono::DateTime;
+use clap::{App, ArgMatches, Shell};
+
+use github_data_fetch::anonymize::{self, AnonymizeConfig};
+use github_data_fetch::auth::{AppCredentials, Credentials, TokenPool};
+use github_data_fetch::bandwidth;
+use github_data_fetch::budget::Pacer;
+use github_data_fetch::burndown::{self, BurndownConfig, SeriesFormat};
+use github_data_fetch::client::{self, ClientConfig};
+use github_data_fetch::contributors::{self, ContributorsConfig};
+use github_data_fetch::dashboard::Dashboard;
+use github_data_fetch::diff::{self, DiffConfig};
+use github_data_fetch::entity::{Entity, Registry};
+use github_data_fetch::events::IssueEvents;
+use github_data_fetch::exit::ExitCode;
+use github_data_fetch::export_site::{self, ExportSiteConfig};
+use github_data_fetch::fetcher::{FetchConfig, Fetcher, Output};
+use github_data_fetch::filter::{Filters, Order};
+use github_data_fetch::format::{self, Format};
+use github_data_fetch::graph::{self, GraphConfig, GraphFormat};
+use github_data_fetch::html_report::{self, HtmlReportConfig};
+use github_data_fetch::interrupt;
+use github_data_fetch::kafka_sink::KafkaConfig;
+use github_data_fetch::labels::{self, LabelsConfig};
+use github_data_fetch::lock;
+use github_data_fetch::merge::{self, MergeConfig};
+use github_data_fetch::pr_metrics::{self, PrMetricsConfig};
+use github_data_fetch::progress;
+use github_data_fetch::query::{self, Query, QueryConfig};
+use github_data_fetch::repo::{Repo, RepoFilter};
+use github_data_fetch::retry::RetryPolicy;
+use github_data_fetch::serve::{self, ServeConfig};
+use github_data_fetch::show::{self, ShowConfig};
+use github_data_fetch::stats::{self, StatsConfig};
+use github_data_fetch::telemetry;
+use github_data_fetch::verify::{self, VerifyConfig};
+use github_data_fetch::vcr::{self, Cassette};
+
+use check::CheckConfig;
+use listen::ListenConfig;
+use login::LoginConfig;
+use update::UpdateConfig;
+
+/// Fetch configuration given by the arguments of fetch, watch or list.
+fn fetch_config(matches: &ArgMatches, dashboard: Option<Dashboard>) -> FetchConfig {
+    FetchConfig {
+        repos: repos(matches),
+        owner: matches.value_of("OWNER").map(|o| o.to_string()),
+        repos_file: matches.value_of("REPOS_FILE").map(|f| f.to_string()),
+        interactive: matches.is_present("INTERACTIVE"),
+        org: matches.value_of("ORG").map(|o| o.to_string()),
+        user: matches.value_of("USER").map(|u| u.to_string()),
+        include_forks: matches.is_present("INCLUDE_FORKS"),
+        repo_filter: RepoFilter {
+            include: patterns(matches, "INCLUDE"),
+            exclude: patterns(matches, "EXCLUDE"),
+            skip_forks: matches.is_present("SKIP_FORKS"),
+            skip_archived: matches.is_present("SKIP_ARCHIVED"),
+            min_stars: value_t!(matches, "MIN_STARS", u64).unwrap_or(0),
+        },
+        proxy: matches.value_of("PROXY").map(|p| p.to_string()),
+        connect_timeout: timeout(matches, "CONNECT_TIMEOUT", 30),
+        request_timeout: timeout(matches, "REQUEST_TIMEOUT", 120),
+        pool_size: value_t!(matches, "POOL_SIZE", usize).unwrap_or(client::POOL_SIZE),
+        http2: matches.is_present("HTTP2"),
+        cassette: cassette(matches),
+        // only list runs without one
+        output: match matches.value_of("OUTPUT_DIR").unwrap_or("-") {
+            "-" => Output::Stdout,
+            dir => Output::Directory(PathBuf::from(dir)),
+        },
+        format: value_t!(matches, "OUTPUT_FORMAT", Format).unwrap_or(Format::Msgpack),
+        credentials: required_credentials(matches),
+        api_url: matches.value_of("API_URL").unwrap_or(client::API_URL).to_string(),
+        graphql_url: match matches.value_of("GRAPHQL_URL") {
+            Some(url) => url.to_string(),
+            None => ClientConfig::graphql_url_for(matches.value_of("API_URL").unwrap_or(client::API_URL)),
+        },
+        since: matches.value_of("SINCE").map(|s| s.to_string()),
+        // every cycle but the first of a daemon only fetches changes
+        incremental: matches.is_present("INCREMENTAL") || matches.is_present("DAEMON"),
+        // existing snapshots are refreshed rather than replaced, keeping
+        // records deleted upstream
+        overwrite: matches.is_present("OVERWRITE"),
+        daemon: if matches.is_present("DAEMON") {
+            Some(parse_interval(matches.value_of("INTERVAL").unwrap_or("6h")).unwrap())
+        } else {
+            None
+        },
+        resume: matches.is_present("RESUME"),
+        wait_for_lock: matches.is_present("WAIT_FOR_LOCK"),
+        cache_dir: matches.value_of("CACHE_DIR").map(PathBuf::from),
+        no_cache: matches.is_present("NO_CACHE"),
+        dedupe_users: matches.is_present("DEDUPE_USERS"),
+        redact: matches.is_present("REDACT"),
+        retry: {
+            let default = RetryPolicy::default();
+            RetryPolicy {
+                max_attempts: value_t!(matches, "RETRIES", u32)
+                    .map(|retries| retries.saturating_add(1))
+                    .unwrap_or(default.max_attempts),
+                base_delay: value_t!(matches, "RETRY_DELAY", u64)
+                    .map(Duration::from_millis)
+                    .unwrap_or(default.base_delay),
+                jitter: value_t!(matches, "RETRY_JITTER", f64).unwrap_or(default.jitter),
+                wait_for_rate_limit: !matches.is_present("FAIL_ON_RATE_LIMIT"),
+            }
+        },
+        max_requests: value_t!(matches, "MAX_REQUESTS", usize).ok(),
+        max_bandwidth: matches
+            .value_of("MAX_BANDWIDTH")
+            .map(|rate| bandwidth::parse(rate).unwrap()),
+        concurrency: value_t!(matches, "CONCURRENCY", usize).unwrap_or(10),
+        // anonymous requests are spread over their quota of 60 an hour on top
+        rate: value_t!(matches, "RATE", usize).unwrap_or(if matches.is_present("ANONYMOUS") { 1 } else { 20 }),
+        parallel: value_t!(matches, "PARALLEL", usize).unwrap_or(1),
+        per_page: value_t!(matches, "PER_PAGE", u64).unwrap_or(100),
+        start_page: value_t!(matches, "START_PAGE", u64).unwrap_or(1),
+        limit: value_t!(matches, "LIMIT", usize).ok(),
+        order: Order {
+            sort: matches.value_of("SORT").unwrap_or("created").to_string(),
+            // a limited fetch keeps the most recent items
+            descending: match matches.value_of("DIRECTION") {
+                Some(direction) => direction == "desc",
+                None => matches.is_present("LIMIT"),
+            },
+        },
+        sample: value_t!(matches, "SAMPLE", usize).ok(),
+        graphql: matches.is_present("GRAPHQL"),
+        dry_run: matches.is_present("DRY_RUN"),
+        entities: Entity::select(
+            values_t!(matches, "ONLY", Entity).ok().as_ref().map(|only| &only[..]),
+            &values_t!(matches, "SKIP", Entity).unwrap_or_default(),
+        ),
+        fetchers: {
+            let mut fetchers = Registry::default();
+            if matches.is_present("EVENTS") {
+                fetchers.register(IssueEvents);
+            }
+            fetchers
+        },
+        filters: Filters {
+            state: matches.value_of("STATE").map(|s| s.to_string()),
+            labels: matches
+                .values_of("LABEL")
+                .map(|labels| labels.map(|l| l.to_string()).collect())
+                .unwrap_or_default(),
+            author: matches.value_of("AUTHOR").map(|a| a.to_string()),
+            assignee: matches.value_of("ASSIGNEE").map(|a| a.to_string()),
+            created_after: matches.value_of("CREATED_AFTER").map(|t| t.to_string()),
+            updated_before: matches.value_of("UPDATED_BEFORE").map(|t| t.to_string()),
+        },
+        kafka: matches.value_of("KAFKA_BROKERS").map(|brokers| KafkaConfig {
+            brokers: brokers.split(',').map(|b| b.trim().to_string()).collect(),
+            topic: matches.value_of("KAFKA_TOPIC").unwrap().to_string(),
+        }),
+        metrics_file: matches.value_of("METRICS_FILE").map(PathBuf::from),
+        pushgateway: matches.value_of("PUSHGATEWAY").map(|url| url.to_string()),
+        otlp_endpoint: matches.value_of("OTLP_ENDPOINT").map(|url| url.to_string()),
+        tui: matches.is_present("TUI"),
+        dashboard: dashboard,
+        progress: value_t!(matches, "PROGRESS", progress::Mode).unwrap_or(progress::Mode::Auto),
+    }
+}
+
+/// The command line interface, also used to generate shell completions.
+fn app() -> App<'static, 'static> {
+    clap_app!((env!("CARGO_PKG_NAME")) =>
+        (version: env!("CARGO_PKG_VERSION"))
+        (author: env!("CARGO_PKG_AUTHORS"))
+        (about: env!("CARGO_PKG_DESCRIPTION"))
+        (@setting SubcommandRequiredElseHelp)
+        (@arg VERBOSE: -v --verbose +multiple +global "Log more details, -vv also logs every request with its response status")
+        (@arg QUIET: -q --quiet +global conflicts_with[VERBOSE] "Only log errors, e.g. for cron")
+        (@arg COLOR: --color +global +takes_value env("GDF_COLOR") possible_value[auto always never] "When to color errors and warnings, auto colors them on a terminal unless NO_COLOR is set [default: auto]")
+        (@arg CONFIG: --config +takes_value env("GDF_CONFIG") "TOML file with default arguments of fetch, watch and list by their long name, e.g. output-directory = \"data\", overridden by those given on the command line [default: github-data-fetch.toml if it exists]")
+        (@subcommand upgrade =>
+            (about: "Migrates existing snapshots to the current format version")
+            (@arg DIR: +required "Output directory containing the snapshots")
+        )
+        (@subcommand listen =>
+            (about: "Receives GitHub webhooks and upserts the issues and pull requests they carry into existing snapshots")
+            (@arg BIND: --bind +takes_value env("GDF_BIND") default_value("127.0.0.1:8080") {is_socket_addr} "Address to listen on")
+            (@arg SECRET: --secret +required +takes_value env("GDF_SECRET") "Webhook secret to verify deliveries with")
+            (@arg DIR: +required "Output directory containing the snapshots")
+        )
+        (@subcommand login =>
+            (about: "Logs in through the OAuth device flow and stores the token for later runs")
+            (@arg CLIENT_ID: --("client-id") +required +takes_value "Client ID of the OAuth app to authorize")
+            (@arg SCOPE: --scope +takes_value default_value("repo") "Space-separated OAuth scopes to request")
+            (@arg HOST: --host +takes_value default_value("https://github.com") "GitHub host to log in at, e.g. https://ghe.example.com")
+            (@arg KEYRING: --keyring "Store the token in the system keyring instead of a file, needs the keyring feature")
+        )
+        (@subcommand completions =>
+            (about: "Prints completions of the command line arguments for a shell")
+            (@arg SHELL: +required possible_value[bash zsh fish powershell] "Shell to print completions for")
+        )
+        (@subcommand show =>
+            (about: "Prints the first records of a snapshot as a table of number, title, state, author and last update")
+            (@arg SNAPSHOT: +required "Snapshot file, or the directory of a repository to show its snapshot of --entity")
+            (@arg ENTITY: --entity +takes_value possible_value[issues pulls] "Entity to show from a repository directory [default: issues]")
+            (@arg LIMIT: --limit +takes_value {is_positive} "Number of records to show [default: 20]")
+        )
+        (@subcommand stats =>
+            (about: "Prints counts by state, label and author, the median time to close, the merge rate and the comments per month of existing snapshots")
+            (@arg DIR: +required "Output directory containing the snapshots, or the directory of one repository")
+            (@arg JSON: --json "Print the statistics as JSON")
+        )
+        (@subcommand diff =>
+            (about: "Lists the issues and pull requests added, removed, closed, merged, reopened, relabeled or edited between two snapshots")
+            (@arg BEFORE: +required "Earlier output directory, or the directory of one repository")
+            (@arg AFTER: +required "Later output directory, or the directory of one repository")
+            (@arg JSON: --json "Print the changes as JSON")
+        )
+        (@subcommand merge =>
+            (about: "Combines the snapshots of several runs into one, keeping the most recently updated version of each issue and pull request")
+            (@arg OUTPUT: -o --output +required +takes_value "Output directory to write the merged snapshots to")
+            (@arg OUTPUT_FORMAT: --("output-format") +takes_value possible_value[msgpack ndjson] "Format of the merged snapshots [default: msgpack]")
+            (@arg INPUTS: +required +multiple "Output directories or repository directories to merge, later ones win ties")
+        )
+        (@subcommand verify =>
+            (about: "Checks snapshots against the checksums of their manifests, that they can be read and hold each number once, that the pull requests issues refer to exist and reports gaps in the numbers")
+            (@arg DIR: +required "Output directory containing the snapshots, or the directory of one repository")
+        )
+        (@subcommand anonymize =>
+            (about: "Writes a copy of snapshots with logins, names and emails replaced by stable pseudonyms and without avatars and profile URLs, e.g. to share them for research")
+            (@arg KEY: --key +required +takes_value env("GDF_ANONYMIZE_KEY") "Secret the pseudonyms are derived from, the same key gives the same pseudonyms")
+            (@arg DROP_BODIES: --("drop-bodies") "Drop the bodies of issues and pull requests")
+            (@arg OUTPUT: -o --output +required +takes_value "Output directory to write the anonymized snapshots to")
+            (@arg OUTPUT_FORMAT: --("output-format") +takes_value possible_value[msgpack ndjson] "Format of the anonymized snapshots [default: msgpack]")
+            (@arg INPUT: +required "Output directory containing the snapshots, or the directory of one repository")
+        )
+        (@subcommand report =>
+            (about: "Writes a self-contained HTML page with charts of the open and closed items over time, the top labels and contributors and the cycle time of pull requests of existing snapshots")
+            (@arg OUTPUT: -o --output +required +takes_value "HTML file to write the report to")
+            (@arg TITLE: --title +takes_value "Heading of the report, the names of the repositories by default")
+            (@arg DIR: +required "Output directory containing the snapshots, or the directory of one repository")
+        )
+        (@subcommand graph =>
+            (about: "Exports the graph of the issues and pull requests mentioning or closing each other in existing snapshots, for Graphviz or Gephi")
+            (@arg FORMAT: --format +takes_value possible_value[dot graphml json] "Format of the graph, json lists the nodes and edges [default: dot]")
+            (@arg OUTPUT: -o --output +takes_value "File to write the graph to instead of stdout")
+            (@arg DIR: +required "Output directory containing the snapshots, or the directory of one repository")
+        )
+        (@subcommand contributors =>
+            (about: "Writes the contributors dataset of the issues and pull requests opened, pull requests merged, reviews, comments and first and last activity of each user of existing snapshots, reviews and comments are only known for --graphql fetches")
+            (@arg OUTPUT: -o --output +takes_value "Output directory to write the datasets to, next to the snapshots by default")
+            (@arg OUTPUT_FORMAT: --("output-format") +takes_value possible_value[msgpack ndjson] "Format of the datasets [default: msgpack]")
+            (@arg DIR: +required "Output directory containing the snapshots, or the directory of one repository")
+        )
+        (@subcommand burndown =>
+            (about: "Writes the daily counts of open issues and pull requests of existing snapshots as a time series, along with those of the open items with each --label")
+            (@arg OUTPUT: -o --output +required +takes_value "File to write the time series to")
+            (@arg FORMAT: --format +takes_value possible_value[csv parquet] "Format of the time series, parquet needs the parquet feature [default: csv]")
+            (@arg LABEL: --label +takes_value +multiple number_of_values(1) "Label to count the open items of in a column of its own, by their current labels, may be given multiple times")
+            (@arg DIR: +required "Output directory containing the snapshots, or the directory of one repository")
+        )
+        (@subcommand labels =>
+            (about: "Prints which labels existing snapshots use together and, from events snapshots of the issue events, which label replaces which")
+            (@arg DIR: +required "Output directory containing the snapshots, or the directory of one repository")
+            (@arg JSON: --json "Print the co-occurrence matrix and the transition counts as JSON")
+        )
+        (@subcommand query =>
+            (about: "Prints the issues and pull requests of existing snapshots matching a query, e.g. 'state=open AND label=bug AND updated>2024-01-01'")
+            (@arg ENTITY: --entity +takes_value possible_value[issues pulls] "Only search issues or pull requests")
+            (@arg NDJSON: --ndjson "Print the matching records as NDJSON instead of a table")
+            (@arg LIMIT: --limit +takes_value {is_positive} "Print no more than this many records")
+            (@arg DIR: +required "Output directory containing the snapshots, or the directory of one repository")
+            (@arg QUERY: +required "Conditions on number, type (issue or pull), state, label, author, title, comments, created, updated, closed or merged joined by AND and OR, compared with =, !=, <, <=, >, >= or ~ for substrings, dates as YYYY-MM-DD or RFC 3339")
+        )
+        (@subcommand serve =>
+            (about: "Serves existing snapshots read-only as a JSON API, listing issues and pull requests a page at a time at /OWNER/NAME/issues and /OWNER/NAME/pulls, filtered by the q, state, label, author and since parameters, and single ones at /OWNER/NAME/issues/NUMBER and /OWNER/NAME/pulls/NUMBER")
+            (@arg BIND: --bind +takes_value env("GDF_BIND") default_value("127.0.0.1:8080") {is_socket_addr} "Address to listen on")
+            (@arg DIR: +required "Output directory containing the snapshots, or the directory of one repository, which may then be left out of the paths")
+        )
+        (@subcommand manpage =>
+            (@setting Hidden)
+            (about: "Prints a man page generated from the help of all subcommands, for packaging")
+        )
+        (@subcommand convert =>
+            (about: "Re-encodes existing snapshots into another output format")
+            (@arg FROM: --from +required +takes_value possible_value[msgpack ndjson] "Format of the existing snapshots")
+            (@arg TO: --to +required +takes_value possible_value[msgpack ndjson] "Format to convert the snapshots to")
+            (@arg DIR: +required "Output directory containing the snapshots")
+        )
+    )
+    .subcommand(
+        connection_args(clap_app!(("check") =>
+            (about: "Checks that the credentials are valid and the repositories accessible, showing the token's scopes and the remaining quota")
+            (@arg OWNER: -O --owner +takes_value env("GDF_OWNER") "Owner of repositories given by name only")
+            (@arg REPO: -r --repository +takes_value env("GDF_REPO") +multiple number_of_values(1) "Repository to check access to, as name or owner/name, may be given multiple times")
+        )),
+    )
+    .subcommand(clap_app!(("pr-metrics") =>
+        (about: "Writes the pr_metrics dataset of the time to first review, time to merge, review rounds and reviewer response times of each pull request of existing snapshots, which need to be of --graphql fetches as only those have the reviews")
+        (@arg OUTPUT: -o --output +takes_value "Output directory to write the datasets to, next to the snapshots by default")
+        (@arg OUTPUT_FORMAT: --("output-format") +takes_value possible_value[msgpack ndjson] "Format of the datasets [default: msgpack]")
+        (@arg DIR: +required "Output directory containing the snapshots, or the directory of one repository")
+    ))
+    .subcommand(clap_app!(("export-site") =>
+        (about: "Writes a static HTML site of existing snapshots, with lists of the issues and pull requests and a page for each with its comments and reviews, as a permanent archive of a repository; comments and reviews are only known for --graphql fetches")
+        (@arg OUTPUT: -o --output +required +takes_value "Directory to write the site to")
+        (@arg DIR: +required "Output directory containing the snapshots, or the directory of one repository")
+    ))
+    .subcommand(clap_app!(("self-update") =>
+        (about: "Replaces this executable with the latest release for the platform after verifying its checksum")
+        (@arg CHECK: --check "Only report whether a newer release is available")
+    ))
+    .subcommand(fetch_app("fetch").about("Fetches issues and pull requests of repositories into snapshots"))
+    .subcommand(
+        fetch_app("watch")
+            .about("Keeps polling the repositories for changes since the last poll and upserts them into the snapshots"),
+    )
+    .subcommand(
+        fetch_app("list").about(
+            "Lists the repositories and entities a fetch with the same arguments would fetch, with estimated counts",
+        ),
+    )
+}
+
+/// Arguments of `fetch`, shared by `watch` and `list`.
+fn fetch_app(name: &'static str) -> App<'static, 'static> {
+    connection_args(clap_app!((name) =>
+        (after_help: "Options can also be given as GDF_ environment variables named as shown, flags by their \
+                      upper-case name, e.g. GDF_INCREMENTAL=true. The command line takes precedence over the \
+                      environment and both over the configuration file.")
+        (@arg OWNER: -O --owner +takes_value env("GDF_OWNER") "Owner of repositories given by name only")
+        (@arg REPO: -r --repository required_unless_one(&["ORG", "USER", "REPOS_FILE", "INTERACTIVE"]) +takes_value env("GDF_REPO") +multiple number_of_values(1) "Repository to fetch data for, as name or owner/name, may be given multiple times")
+        (@arg REPOS_FILE: --("repos-file") +takes_value env("GDF_REPOS_FILE") "File with one repository per line as name or owner/name, or - for stdin")
+        (@arg INTERACTIVE: -i --interactive requires[OWNER] conflicts_with[DAEMON] "Choose the repositories of --owner to fetch from a list searchable by name")
+        (@arg ORG: --org +takes_value env("GDF_ORG") "Fetch all repositories of this organization")
+        (@arg USER: --user +takes_value env("GDF_USER") "Fetch all repositories owned by this user, the private ones too if it is the owner of the token")
+        (@arg INCLUDE_FORKS: --("include-forks") requires[USER] "Also fetch the user's forks")
+        (@arg INCLUDE: --include +takes_value env("GDF_INCLUDE") +multiple number_of_values(1) {is_glob} "Only fetch listed repositories whose name matches this glob, may be given multiple times")
+        (@arg EXCLUDE: --exclude +takes_value env("GDF_EXCLUDE") +multiple number_of_values(1) {is_glob} "Don't fetch listed repositories whose name matches this glob, may be given multiple times")
+        (@arg SKIP_FORKS: --("skip-forks") conflicts_with[INCLUDE_FORKS] "Don't fetch forks among listed repositories, the default for --user")
+        (@arg SKIP_ARCHIVED: --("skip-archived") "Don't fetch archived listed repositories")
+        (@arg MIN_STARS: --("min-stars") +takes_value env("GDF_MIN_STARS") {is_number} "Don't fetch listed repositories with fewer stars")
+        (@arg OUTPUT_DIR: -o --("output-directory") required(name != "list") +takes_value env("GDF_OUTPUT_DIR") visible_alias("output") "Directory to output the data to, or - to stream NDJSON to stdout")
+        (@arg OUTPUT_FORMAT: --("output-format") +takes_value env("GDF_OUTPUT_FORMAT") possible_value[msgpack ndjson] "Format of the snapshot files, show and upgrade only read msgpack snapshots [default: msgpack]")
+        (@arg SINCE: --since +takes_value env("GDF_SINCE") {is_timestamp} "Only fetch issues and pulls updated after this RFC 3339 timestamp")
+        (@arg INCREMENTAL: --incremental conflicts_with[SINCE] "Only fetch issues and pulls updated since the last run")
+        (@arg DAEMON: --daemon conflicts_with[SINCE DRY_RUN] "Keep running and sync incrementally every --interval, implies --incremental")
+        (@arg INTERVAL: --interval +takes_value env("GDF_INTERVAL") {is_interval} "Time between the syncs of --daemon or the polls of watch, e.g. 30m, 6h or 1d [default: 6h, 5m for watch]")
+        (@arg OVERWRITE: --overwrite conflicts_with[SINCE INCREMENTAL DAEMON] "Replace existing output files instead of upserting the fetched records into them by number")
+        (@arg RESUME: --resume "Continue an interrupted run from its last checkpoint")
+        (@arg WAIT_FOR_LOCK: --("wait-for-lock") "Wait for other runs on the output directory to finish instead of failing")
+        (@arg CACHE_DIR: --("cache-dir") +takes_value env("GDF_CACHE_DIR") "Directory for the ETag response cache, kept in the sync state database of the output directory by default")
+        (@arg NO_CACHE: --("no-cache") conflicts_with[CACHE_DIR] "Don't send conditional requests from the ETag cache")
+        (@arg DEDUPE_USERS: --("dedupe-users") "Replace embedded users with id/login references and write them to a shared users file")
+        (@arg REDACT: --redact conflicts_with[RECORD] "Mask email addresses, tokens and other secrets in the bodies of issues, pulls and comments before writing them, the ETag cache is not used then")
+        (@arg RETRIES: --retries +takes_value env("GDF_RETRIES") {is_number} "How often to retry requests failing with network errors or 5xx responses [default: 4]")
+        (@arg RETRY_DELAY: --("retry-delay") +takes_value env("GDF_RETRY_DELAY") {is_number} "Delay before the first retry in milliseconds, doubled on every further one [default: 500]")
+        (@arg RETRY_JITTER: --("retry-jitter") +takes_value env("GDF_RETRY_JITTER") {is_fraction} "Fraction of the retry delay to randomize, between 0 and 1 [default: 0.5]")
+        (@arg FAIL_ON_RATE_LIMIT: --("fail-on-rate-limit") "Fail the repository instead of waiting for an exhausted rate limit to reset")
+        (@arg MAX_REQUESTS: --("max-requests") +takes_value env("GDF_MAX_REQUESTS") {is_positive} "Stop after this many requests, checkpointing progress for --resume")
+        (@arg MAX_BANDWIDTH: --("max-bandwidth") +takes_value env("GDF_MAX_BANDWIDTH") {is_bandwidth} "Maximum download rate across all repositories in bytes per second, e.g. 500k or 2M")
+        (@arg CONCURRENCY: --concurrency +takes_value env("GDF_CONCURRENCY") {is_positive} "Maximum number of pull requests fetched at the same time [default: 10]")
+        (@arg RATE: --rate +takes_value env("GDF_RATE") {is_positive} "Maximum number of requests started per second, across all repositories, lowered automatically once less than half of the quota is left [default: 20, 1 with --anonymous, whose requests are spread over the hour]")
+        (@arg PARALLEL: --parallel +takes_value env("GDF_PARALLEL") {is_positive} "Number of repositories fetched at the same time [default: 1]")
+        (@arg PER_PAGE: --("per-page") +takes_value env("GDF_PER_PAGE") {is_page_size} "Issues and pull requests requested per listing page, at most 100 [default: 100]")
+        (@arg START_PAGE: --("start-page") +takes_value env("GDF_START_PAGE") {is_positive} "Listing page to start fetching issues at [default: 1]")
+        (@arg LIMIT: --limit +takes_value env("GDF_LIMIT") {is_positive} conflicts_with[START_PAGE] "Only fetch this many of the most recent issues and pull requests, of the fetched entities together rather than of each")
+        (@arg SORT: --sort +takes_value env("GDF_SORT") possible_value[created updated comments] "Order to list and write issues and pull requests in [default: created]")
+        (@arg DIRECTION: --direction +takes_value env("GDF_DIRECTION") possible_value[asc desc] "Direction of --sort, descending with --limit [default: asc]")
+        (@arg SAMPLE: --sample +takes_value env("GDF_SAMPLE") {is_positive} conflicts_with[LIMIT START_PAGE SINCE INCREMENTAL DAEMON RESUME STATE LABEL AUTHOR ASSIGNEE] "Only fetch a uniform random sample of this many issues and pull requests")
+        (@arg GRAPHQL: --graphql conflicts_with[RESUME START_PAGE DEDUPE_USERS SAMPLE ANONYMOUS] "Fetch issues and pull requests with their comments and reviews through the GraphQL API, a page per request, not merged into snapshots of REST fetches")
+        (@arg ONLY: --only +takes_value env("GDF_ONLY") +use_delimiter possible_value[issues pulls] "Comma-separated entities to fetch, all by default")
+        (@arg SKIP: --skip +takes_value env("GDF_SKIP") +use_delimiter possible_value[issues pulls] conflicts_with[ONLY] "Comma-separated entities not to fetch")
+        (@arg STATE: --state +takes_value env("GDF_STATE") possible_value[open closed] "Only fetch open or closed issues and pull requests")
+        (@arg LABEL: --label +takes_value env("GDF_LABEL") +multiple number_of_values(1) "Only fetch issues and pull requests with this label, may be given multiple times")
+        (@arg AUTHOR: --author +takes_value env("GDF_AUTHOR") "Only fetch issues and pull requests opened by this user")
+        (@arg ASSIGNEE: --assignee +takes_value env("GDF_ASSIGNEE") "Only fetch issues and pull requests assigned to this user")
+        (@arg CREATED_AFTER: --("created-after") +takes_value env("GDF_CREATED_AFTER") {is_timestamp} "Only keep issues and pull requests created after this RFC 3339 timestamp")
+        (@arg UPDATED_BEFORE: --("updated-before") +takes_value env("GDF_UPDATED_BEFORE") {is_timestamp} "Only keep issues and pull requests last updated before this RFC 3339 timestamp")
+        (@arg EVENTS: --events "Also fetch the issue events, e.g. labels added and removed, for the label transitions of the labels subcommand")
+        (@arg DRY_RUN: --("dry-run") "Only estimate the requests, run time and disk space a fetch would take, without writing anything")
+        (@arg KAFKA_BROKERS: --("kafka-brokers") +takes_value env("GDF_KAFKA_BROKERS") requires[KAFKA_TOPIC] "Comma-separated Kafka brokers to publish records to, needs the kafka feature")
+        (@arg KAFKA_TOPIC: --("kafka-topic") +takes_value env("GDF_KAFKA_TOPIC") requires[KAFKA_BROKERS] "Kafka topic to publish records to")
+        (@arg METRICS_FILE: --("metrics-file") +takes_value env("GDF_METRICS_FILE") "File to write Prometheus metrics of the run to, e.g. for the node exporter's textfile collector")
+        (@arg PUSHGATEWAY: --pushgateway +takes_value env("GDF_PUSHGATEWAY") "URL of a Prometheus Pushgateway to push the metrics of the run to")
+        (@arg OTLP_ENDPOINT: --("otlp-endpoint") +takes_value env("GDF_OTLP_ENDPOINT") "OTLP collector to export tracing spans of the repositories, phases, pages and requests to, e.g. http://localhost:4317, needs the otlp feature")
+        (@arg PROGRESS: --progress +takes_value env("GDF_PROGRESS") possible_value[auto json] conflicts_with[TUI] "How to report progress, json emits an event per page or item with the repository, phase, count, total and remaining quota on stderr, along with warnings and errors as events instead of the log [default: auto]")
+        (@arg TUI: --tui "Show a full-screen dashboard of the progress, request rate, remaining quota and recent errors instead of log output, needs the tui feature")
+    ))
+}
+
+/// Arguments for authenticating and connecting to the API.
+fn connection_args(app: App<'static, 'static>) -> App<'static, 'static> {
+    clap_app!(@app (app)
+        (@arg TOKEN: -t --token +takes_value env("GDF_TOKEN") +multiple number_of_values(1) "Github API token to use, - to read it from the first line of stdin, read from GITHUB_TOKEN or GH_TOKEN or taken from the login subcommand or the gh CLI if not given. Several tokens are used in turn, switching once half of a token's quota is used")
+        (@arg TOKEN_FILE: --("token-file") +takes_value env("GDF_TOKEN_FILE") conflicts_with[TOKEN] "File to read the Github API tokens from, one per line")
+        (@arg ANONYMOUS: --anonymous conflicts_with[TOKEN TOKEN_FILE APP_ID] "Don't authenticate, only public repositories can be fetched at 60 requests an hour")
+        (@arg APP_ID: --("app-id") +takes_value env("GDF_APP_ID") {is_number} requires[PRIVATE_KEY INSTALLATION_ID] conflicts_with[TOKEN TOKEN_FILE] "Authenticate as the installation of this GitHub App")
+        (@arg PRIVATE_KEY: --("private-key") +takes_value env("GDF_PRIVATE_KEY") requires[APP_ID] "PEM file with the private key of the GitHub App")
+        (@arg INSTALLATION_ID: --("installation-id") +takes_value env("GDF_INSTALLATION_ID") {is_number} requires[APP_ID] "Installation of the GitHub App to authenticate as")
+        (@arg API_URL: --("api-url") +takes_value env("GDF_API_URL") "Base URL of the REST API, e.g. https://ghe.example.com/api/v3 for GitHub Enterprise Server [default: https://api.github.com]")
+        (@arg GRAPHQL_URL: --("graphql-url") +takes_value env("GDF_GRAPHQL_URL") "URL of the GraphQL API, derived from --api-url by default")
+        (@arg PROXY: --proxy +takes_value env("GDF_PROXY") "Proxy URL for all requests, defaults to HTTPS_PROXY/HTTP_PROXY honoring NO_PROXY")
+        (@arg CONNECT_TIMEOUT: --("connect-timeout") +takes_value env("GDF_CONNECT_TIMEOUT") {is_number} "Seconds to wait for a connection to be established, 0 to wait forever [default: 30]")
+        (@arg REQUEST_TIMEOUT: --("request-timeout") +takes_value env("GDF_REQUEST_TIMEOUT") {is_number} "Seconds to wait for a complete response, 0 to wait forever [default: 120]")
+        (@arg POOL_SIZE: --("pool-size") +takes_value env("GDF_POOL_SIZE") {is_positive} "Idle connections kept open per host for reuse [default: 16]")
+        (@arg HTTP2: --http2 "Speak HTTP/2 only, multiplexing all requests to a host over one connection")
+        (@arg RECORD: --record +takes_value env("GDF_RECORD") conflicts_with[REPLAY] "Directory to record the raw API responses to, for --replay")
+        (@arg REPLAY: --replay +takes_value env("GDF_REPLAY") "Directory to answer requests from as recorded by --record, without a token or network access")
+    )
+}
 
 #[derive(Debug)]
-struct Config {
-    owner: String,
-    repo: String,
-    token: String,
-    output_directory: PathBuf,
+enum Command {
+    Fetch(FetchConfig),
+    List(FetchConfig),
+    Upgrade(PathBuf),
+    Listen(ListenConfig),
+    Login(LoginConfig),
+    Completions(Shell),
+    Manpage,
+    Check(CheckConfig),
+    Show(ShowConfig),
+    Stats(StatsConfig),
+    Diff(DiffConfig),
+    Merge(MergeConfig),
+    Verify(VerifyConfig),
+    Anonymize(AnonymizeConfig),
+    Report(HtmlReportConfig),
+    Graph(GraphConfig),
+    PrMetrics(PrMetricsConfig),
+    Contributors(ContributorsConfig),
+    Burndown(BurndownConfig),
+    Labels(LabelsConfig),
+    Query(QueryConfig),
+    Serve(ServeConfig),
+    ExportSite(ExportSiteConfig),
+    SelfUpdate(UpdateConfig),
+    Convert {
+        dir: PathBuf,
+        from: Format,
+        to: Format,
+    },
 }
-impl Config {
+impl Command {
     fn from_args() -> Self {
-        let matches = clap_app!((env!("CARGO_PKG_NAME")) =>
-            (version: env!("CARGO_PKG_VERSION"))
-            (author: env!("CARGO_PKG_AUTHORS"))
-            (about: env!("CARGO_PKG_DESCRIPTION"))
-            (@arg OWNER: -O --owner +required +takes_value "Repository owner to fetch data for")
-            (@arg REPO: -r --repository +required +takes_value "Repository name to fetch data for")
-            (@arg TOKEN: -t --token +required +takes_value "Github API token to use")
-            (@arg OUTPUT_DIR: -o --("output-directory") +required +takes_value "Directory to output the data to")
-        ).get_matches();
-        Config {
-            owner: matches.value_of("OWNER").unwrap().to_string(),
-            repo: matches.value_of("REPO").unwrap().to_string(),
-            output_directory: PathBuf::from(matches.value_of("OUTPUT_DIR").unwrap()),
-            token: matches.value_of("TOKEN").unwrap().to_string(),
+        let mut args: Vec<OsString> = env::args_os().collect();
+        config::apply_env(&mut args);
+        config::apply(&mut args, |args| {
+            app().get_matches_from_safe(args.to_vec()).err().map_or(false, |e| e.kind == clap::ErrorKind::ArgumentConflict)
+        }).unwrap_or_else(|e| usage(clap::Error::with_description(&e, clap::ErrorKind::InvalidValue)));
+        let matches = app().get_matches_from_safe(args).unwrap_or_else(|e| usage(e));
+        let sub = matches.subcommand().1;
+        let verbosity = matches
+            .occurrences_of("VERBOSE")
+            .max(sub.map_or(0, |sub| sub.occurrences_of("VERBOSE")))
+            .max(env::var(config::ENV_PREFIX.to_string() + "VERBOSE").ok().and_then(|v| v.parse().ok()).unwrap_or(0));
+        let color = sub.and_then(|sub| sub.value_of("COLOR")).or(matches.value_of("COLOR")).unwrap_or("auto");
+        let json = sub.and_then(|sub| sub.value_of("PROGRESS")) == Some("json");
+        // JSON progress events report the quota the dashboard keeps
+        let dashboard = if json || sub.map_or(false, |sub| sub.is_present("TUI")) {
+            Some(Dashboard::default())
+        } else {
+            None
+        };
+        if matches.is_present("QUIET") || sub.map_or(false, |sub| sub.is_present("QUIET")) {
+            logging::init(-1, color, json, dashboard.clone());
+        } else {
+            logging::init(verbosity as i64, color, json, dashboard.clone());
+        }
+        match matches.subcommand() {
+            ("fetch", Some(sub)) => Command::Fetch(fetch_config(sub, dashboard)),
+            ("watch", Some(sub)) => {
+                reject(sub, "watch", &["SINCE", "DAEMON", "DRY_RUN", "INTERACTIVE"]);
+                let mut cfg = fetch_config(sub, dashboard);
+                cfg.incremental = true;
+                cfg.daemon = Some(parse_interval(sub.value_of("INTERVAL").unwrap_or("5m")).unwrap());
+                Command::Fetch(cfg)
+            }
+            ("list", Some(sub)) => {
+                reject(
+                    sub,
+                    "list",
+                    &[
+                        "DAEMON",
+                        "DRY_RUN",
+                        "RESUME",
+                        "KAFKA_BROKERS",
+                        "METRICS_FILE",
+                        "PUSHGATEWAY",
+                        "OTLP_ENDPOINT",
+                        "REDACT",
+                        "TUI",
+                        "OUTPUT_FORMAT",
+                    ],
+                );
+                Command::List(fetch_config(sub, dashboard))
+            }
+            ("upgrade", Some(sub)) => Command::Upgrade(PathBuf::from(sub.value_of("DIR").unwrap())),
+            ("listen", Some(sub)) => Command::Listen(ListenConfig {
+                addr: value_t!(sub, "BIND", SocketAddr).unwrap_or_else(|e| usage(e)),
+                secret: sub.value_of("SECRET").unwrap().to_string(),
+                dir: PathBuf::from(sub.value_of("DIR").unwrap()),
+            }),
+            ("login", Some(sub)) => Command::Login(LoginConfig {
+                client_id: sub.value_of("CLIENT_ID").unwrap().to_string(),
+                scope: sub.value_of("SCOPE").unwrap().to_string(),
+                host: sub.value_of("HOST").unwrap().to_string(),
+                keyring: sub.is_present("KEYRING"),
+            }),
+            ("check", Some(sub)) => Command::Check(CheckConfig {
+                client: ClientConfig {
+                    credentials: required_credentials(sub),
+                    api_url: sub.value_of("API_URL").unwrap_or(client::API_URL).to_string(),
+                    graphql_url: match sub.value_of("GRAPHQL_URL") {
+                        Some(url) => url.to_string(),
+                        None => ClientConfig::graphql_url_for(sub.value_of("API_URL").unwrap_or(client::API_URL)),
+                    },
+                    cache_db: None,
+                    // a check reports the exhausted rate limit
+                    retry: RetryPolicy {
+                        wait_for_rate_limit: false,
+                        ..RetryPolicy::default()
+                    },
+                    proxy: sub.value_of("PROXY").map(|p| p.to_string()),
+                    connect_timeout: timeout(sub, "CONNECT_TIMEOUT", 30),
+                    request_timeout: timeout(sub, "REQUEST_TIMEOUT", 120),
+                    pool_size: value_t!(sub, "POOL_SIZE", usize).unwrap_or(client::POOL_SIZE),
+                    http2: sub.is_present("HTTP2"),
+                    cassette: cassette(sub),
+                    budget: None,
+                    bandwidth: None,
+                    pacer: Pacer::default(),
+                    dashboard: None,
+                },
+                repos: repos(sub),
+            }),
+            ("show", Some(sub)) => {
+                let path = PathBuf::from(sub.value_of("SNAPSHOT").unwrap());
+                // a snapshot file is named after its entity
+                let stem = path.file_stem().and_then(|stem| stem.to_str()).map(|stem| stem.to_string());
+                Command::Show(ShowConfig {
+                    entity: match sub.value_of("ENTITY").or_else(|| stem.as_ref().map(|stem| &stem[..])) {
+                        Some("pulls") => Entity::Pulls,
+                        _ => Entity::Issues,
+                    },
+                    path: path,
+                    limit: value_t!(sub, "LIMIT", usize).unwrap_or(20),
+                })
+            }
+            ("stats", Some(sub)) => Command::Stats(StatsConfig {
+                dir: PathBuf::from(sub.value_of("DIR").unwrap()),
+                json: sub.is_present("JSON"),
+            }),
+            ("diff", Some(sub)) => Command::Diff(DiffConfig {
+                before: PathBuf::from(sub.value_of("BEFORE").unwrap()),
+                after: PathBuf::from(sub.value_of("AFTER").unwrap()),
+                json: sub.is_present("JSON"),
+            }),
+            ("merge", Some(sub)) => Command::Merge(MergeConfig {
+                inputs: sub.values_of("INPUTS").unwrap().map(PathBuf::from).collect(),
+                output: PathBuf::from(sub.value_of("OUTPUT").unwrap()),
+                format: value_t!(sub, "OUTPUT_FORMAT", Format).unwrap_or(Format::Msgpack),
+            }),
+            ("verify", Some(sub)) => Command::Verify(VerifyConfig {
+                dir: PathBuf::from(sub.value_of("DIR").unwrap()),
+            }),
+            ("anonymize", Some(sub)) => Command::Anonymize(AnonymizeConfig {
+                input: PathBuf::from(sub.value_of("INPUT").unwrap()),
+                output: PathBuf::from(sub.value_of("OUTPUT").unwrap()),
+                key: sub.value_of("KEY").unwrap().to_string(),
+                drop_bodies: sub.is_present("DROP_BODIES"),
+                format: value_t!(sub, "OUTPUT_FORMAT", Format).unwrap_or(Format::Msgpack),
+            }),
+            ("report", Some(sub)) => Command::Report(HtmlReportConfig {
+                dir: PathBuf::from(sub.value_of("DIR").unwrap()),
+                output: PathBuf::from(sub.value_of("OUTPUT").unwrap()),
+                title: sub.value_of("TITLE").map(|title| title.to_string()),
+            }),
+            ("graph", Some(sub)) => Command::Graph(GraphConfig {
+                dir: PathBuf::from(sub.value_of("DIR").unwrap()),
+                format: value_t!(sub, "FORMAT", GraphFormat).unwrap_or(GraphFormat::Dot),
+                output: sub.value_of("OUTPUT").map(PathBuf::from),
+            }),
+            ("pr-metrics", Some(sub)) => Command::PrMetrics(PrMetricsConfig {
+                dir: PathBuf::from(sub.value_of("DIR").unwrap()),
+                output: sub.value_of("OUTPUT").map(PathBuf::from),
+                format: value_t!(sub, "OUTPUT_FORMAT", Format).unwrap_or(Format::Msgpack),
+            }),
+            ("contributors", Some(sub)) => Command::Contributors(ContributorsConfig {
+                dir: PathBuf::from(sub.value_of("DIR").unwrap()),
+                output: sub.value_of("OUTPUT").map(PathBuf::from),
+                format: value_t!(sub, "OUTPUT_FORMAT", Format).unwrap_or(Format::Msgpack),
+            }),
+            ("burndown", Some(sub)) => Command::Burndown(BurndownConfig {
+                dir: PathBuf::from(sub.value_of("DIR").unwrap()),
+                output: PathBuf::from(sub.value_of("OUTPUT").unwrap()),
+                format: value_t!(sub, "FORMAT", SeriesFormat).unwrap_or(SeriesFormat::Csv),
+                labels: sub.values_of("LABEL").map_or_else(Vec::new, |labels| labels.map(|l| l.to_string()).collect()),
+            }),
+            ("labels", Some(sub)) => Command::Labels(LabelsConfig {
+                dir: PathBuf::from(sub.value_of("DIR").unwrap()),
+                json: sub.is_present("JSON"),
+            }),
+            ("query", Some(sub)) => Command::Query(QueryConfig {
+                dir: PathBuf::from(sub.value_of("DIR").unwrap()),
+                query: value_t!(sub, "QUERY", Query).unwrap_or_else(|e| usage(e)),
+                entity: value_t!(sub, "ENTITY", Entity).ok(),
+                ndjson: sub.is_present("NDJSON"),
+                limit: value_t!(sub, "LIMIT", usize).ok(),
+            }),
+            ("serve", Some(sub)) => Command::Serve(ServeConfig {
+                addr: value_t!(sub, "BIND", SocketAddr).unwrap_or_else(|e| usage(e)),
+                dir: PathBuf::from(sub.value_of("DIR").unwrap()),
+            }),
+            ("export-site", Some(sub)) => Command::ExportSite(ExportSiteConfig {
+                dir: PathBuf::from(sub.value_of("DIR").unwrap()),
+                output: PathBuf::from(sub.value_of("OUTPUT").unwrap()),
+            }),
+            ("self-update", Some(sub)) => Command::SelfUpdate(UpdateConfig {
+                check: sub.is_present("CHECK"),
+            }),
+            ("completions", Some(sub)) => {
+                Command::Completions(value_t!(sub, "SHELL", Shell).unwrap_or_else(|e| usage(e)))
+            }
+            ("manpage", Some(_)) => Command::Manpage,
+            ("convert", Some(sub)) => Command::Convert {
+                dir: PathBuf::from(sub.value_of("DIR").unwrap()),
+                from: value_t!(sub, "FROM", Format).unwrap_or_else(|e| usage(e)),
+                to: value_t!(sub, "TO", Format).unwrap_or_else(|e| usage(e)),
+            },
+            _ => unreachable!("clap requires a subcommand"),
         }
     }
 }
 
-fn handle_issues<C>(
-    cfg: &Config,
-    core: &mut Core,
-    github: &Github<C>,
-) -> Result<(Vec<Issue>, Vec<u64>), Box<error::Error>>
-  where C: Clone + Connect
-{
-    let repo = github.repo(cfg.owner.clone(), cfg.repo.clone());
-    let issues_stream = repo.issues()
-        .iter(&IssueListOptions::builder()
-            .state(State::All)
-            .asc()
-            .per_page(100)
-            .build());
-    let (issues, pr_nums): (Vec<_>, Vec<_>) = core.run(issues_stream.collect())?
+/// Reports invalid arguments and exits with `ExitCode::Usage`, help and
+/// version output exit successfully.
+fn usage(e: clap::Error) -> ! {
+    match e.kind {
+        clap::ErrorKind::HelpDisplayed | clap::ErrorKind::VersionDisplayed => e.exit(),
+        _ => {
+            eprintln!("{}", e.message);
+            process::exit(ExitCode::Usage as i32)
+        }
+    }
+}
+
+/// Exits if one of the fetch arguments `args` that `subcommand` doesn't take
+/// is given.
+fn reject(matches: &ArgMatches, subcommand: &str, args: &[&str]) {
+    if let Some(arg) = args.iter().find(|arg| matches.is_present(arg)) {
+        usage(clap::Error::with_description(
+            &format!("{} can't be used with {}", arg, subcommand),
+            clap::ErrorKind::ArgumentConflict,
+        ))
+    }
+}
+
+/// GitHub App given on the command line, or a token.
+fn credentials(matches: &ArgMatches) -> Result<Option<Credentials>, String> {
+    // replayed requests are never sent
+    if matches.is_present("ANONYMOUS") || matches.is_present("REPLAY") {
+        return Ok(Some(Credentials::Anonymous));
+    }
+    if let Ok(app_id) = value_t!(matches, "APP_ID", u64) {
+        let file = matches.value_of("PRIVATE_KEY").unwrap();
+        let mut private_key = Vec::new();
+        fs::File::open(file)
+            .and_then(|mut f| f.read_to_end(&mut private_key))
+            .map_err(|e| format!("failed to read the private key from {}: {}", file, e))?;
+        let installation_id = value_t!(matches, "INSTALLATION_ID", u64).map_err(|e| e.to_string())?;
+        return Ok(Some(Credentials::App(AppCredentials::new(app_id, installation_id, private_key))));
+    }
+    let mut tokens = tokens(matches)?;
+    Ok(match tokens.len() {
+        0 => None,
+        1 => tokens.pop().map(Credentials::Token),
+        _ => Some(Credentials::Tokens(TokenPool::new(tokens))),
+    })
+}
+
+/// Cassette of `--record` or `--replay`.
+fn cassette(matches: &ArgMatches) -> Option<Cassette> {
+    match (matches.value_of("RECORD"), matches.value_of("REPLAY")) {
+        (Some(dir), _) => Some(Cassette::new(Path::new(dir), vcr::Mode::Record)),
+        (None, Some(dir)) => Some(Cassette::new(Path::new(dir), vcr::Mode::Replay)),
+        (None, None) => None,
+    }
+}
+
+/// Repositories given by `--repository`, several may be separated by commas
+/// or whitespace as in `GDF_REPO`.
+fn repos(matches: &ArgMatches) -> Vec<Repo> {
+    matches
+        .values_of("REPO")
         .into_iter()
-        .partition(|i| !i.pull_request.is_some());
+        .flat_map(|repos| repos)
+        .flat_map(|repos| repos.split(|c: char| c == ',' || c.is_whitespace()))
+        .filter(|repo| !repo.is_empty())
+        .map(|repo| {
+            Repo::parse(repo, matches.value_of("OWNER"))
+                .unwrap_or_else(|e| usage(clap::Error::with_description(&e, clap::ErrorKind::ValueValidation)))
+        })
+        .collect()
+}
 
-    println!("Issues: {:?}", issues.len());
-    Ok((issues, pr_nums.into_iter().map(|i| i.number).collect()))
+/// Credentials given by the arguments, exiting if there are none.
+fn required_credentials(matches: &ArgMatches) -> Credentials {
+    match credentials(matches) {
+        Ok(Some(credentials)) => credentials,
+        Ok(None) => usage(clap::Error::with_description(
+            "a token is required, pass --token or --token-file, set GITHUB_TOKEN or GH_TOKEN, use the login subcommand \
+             or the gh CLI or pass --anonymous",
+            clap::ErrorKind::MissingRequiredArgument,
+        )),
+        Err(e) => usage(clap::Error::with_description(&e, clap::ErrorKind::InvalidValue)),
+    }
 }
 
-fn get_pull<C>(repo: Repository<C>, n: u64) -> Box<Future<Item=Pull, Error=std::io::Error>>
-  where C: Clone + Connect
-{
-    Box::new(repo.pulls().get(n).get().or_else(move |e| match e.kind() {
-        &ErrorKind::RateLimit { reset: dt } => {
-            let mut core = Core::new().expect("reactor fail");
-            future::result(Timeout::new(dt, &core.handle()))
-                .and_then(move |_| get_pull(repo, n))
+/// Tokens given on the command line, in a file, on stdin or in the
+/// environment.
+fn tokens(matches: &ArgMatches) -> Result<Vec<String>, String> {
+    if let Some(values) = matches.values_of("TOKEN") {
+        let mut tokens = Vec::new();
+        for value in values {
+            if value == "-" {
+                // only the first line, the repository list may follow
+                let stdin = io::stdin();
+                let mut line = String::new();
+                stdin
+                    .lock()
+                    .read_line(&mut line)
+                    .map_err(|e| format!("failed to read the token from stdin: {}", e))?;
+                match line.trim() {
+                    "" => return Err("no token on stdin".to_string()),
+                    token => tokens.push(token.to_string()),
+                }
+            } else {
+                tokens.push(value.to_string());
+            }
+        }
+        return Ok(tokens);
+    }
+    if let Some(file) = matches.value_of("TOKEN_FILE") {
+        let mut contents = String::new();
+        fs::File::open(file)
+            .and_then(|mut f| f.read_to_string(&mut contents))
+            .map_err(|e| format!("failed to read the token from {}: {}", file, e))?;
+        let tokens: Vec<String> = contents
+            .lines()
+            .map(|line| line.trim())
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(|line| line.to_string())
+            .collect();
+        if tokens.is_empty() {
+            return Err(format!("no token in {}", file));
         }
-        _ => panic!("{}", e),
-    }))
-}
-
-fn handle_pulls<C>(
-    pull_nums: Vec<u64>,
-    cfg: &Config,
-    github: &Github<C>,
-) -> Result<Vec<Pull>, Box<error::Error>>
-  where C: Clone + Connect
-{
-    let pool = ThrottlePool::new(ThrottleRate::new(20, Duration::from_secs(1)));
-    let pull_futs = pull_nums.into_iter().map(|n| {
-        println!("Pull: {}", n);
-        let repo = github.repo(cfg.owner.clone(), cfg.repo.clone());
-        get_pull(repo, n)
-    });
-    let mut core = Core::new().expect("reactor fail");
-    Ok(core.run(future::join_all(pull_futs))?)
-}
-
-fn serialize_to_file<D>(data: D, filename: &Path) -> Result<(), Box<error::Error>>
-where
-    D: Serialize,
-{
-    let mut file = fs::File::create(filename)?;
-    data.serialize(&mut Serializer::new(&mut file))?;
-    Ok(())
-}
-
-fn run() -> Result<(), Box<error::Error>> {
-    let cfg = Config::from_args();
-
-    let mut core = Core::new().expect("reactor fail");
-    let github = Github::new(
-        concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION")),
-        Credentials::Token(cfg.token.clone()),
-    );
-
-    let out_dir = cfg.output_directory.join(
-        format!("{}/{}", cfg.owner, cfg.repo),
-    );
-    fs::create_dir_all(&out_dir)?;
-
-    let (issues, pr_nums) = handle_issues(&cfg, &mut core, &github)?;
-    serialize_to_file(&issues, &out_dir.join("issues.msgpack"))?;
-
-    println!("Pulls: {}", pr_nums.len());
-    let pulls = handle_pulls(pr_nums, &cfg, &github)?;
-    serialize_to_file(&pulls, &out_dir.join("pulls.msgpack"))?;
-
-    Ok(())
-}
-
-fn main() {
-    run().unwrap();
-
-    // let mut buf = Vec::new();
-    // issues.serialize(&mut Serializer::new(&mut buf)).unwrap();
-    // println!("{:?}", buf);
+        return Ok(tokens);
+    }
+    Ok(["GITHUB_TOKEN", "GH_TOKEN"]
+        .iter()
+        .filter_map(|var| env::var(var).ok())
+        .find(|token| !token.is_empty())
+        .or_else(login::stored_token)
+        .or_else(|| login::gh_token(&gh_host(matches.value_of("API_URL").unwrap_or(client::API_URL))))
+        .into_iter()
+        .collect())
+}
+
+/// Host the gh CLI knows the server of `api_url` by, `github.com` for the
+/// public API.
+fn gh_host(api_url: &str) -> String {
+    let host = api_url
+        .split("://")
+        .last()
+        .unwrap_or(api_url)
+        .split('/')
+        .next()
+        .unwrap_or_default();
+    match host {
+        "api.github.com" => "github.com".to_string(),
+        host => host.to_string(),
+    }
+}
+
+/// Timeout given in seconds by `arg`, where 0 disables it.
+fn timeout(matches: &ArgMatches, arg: &str, default: u64) -> Option<Duration> {
+    match value_t!(matches, arg, u64).unwrap_or(default) {
+        0 => None,
+        secs => Some(Duration::from_secs(secs)),
+    }
+}
+
+fn patterns(matches: &ArgMatches, arg: &str) -> Vec<glob::Pattern> {
+    matches
+        .values_of(arg)
+        .map(|values| values.filter_map(|v| glob::Pattern::new(v).ok()).collect())
+        .unwrap_or_default()
+}
+
+fn is_glob(value: String) -> Result<(), String> {
+    glob::Pattern::new(&value)
+        .map(|_| ())
+        .map_err(|e| format!("invalid pattern {}: {}", value, e))
+}
+
+/// Parses a duration like `90s`, `30m`, `6h` or `1d`, plain numbers are seconds.
+fn parse_interval(value: &str) -> Result<Duration, String> {
+    let (number, unit) = match value.find(|c: char| !c.is_ascii_digit()) {
+        Some(i) => value.split_at(i),
+        None => (value, "s"),
+    };
+    let number: u64 = number.parse().map_err(|_| format!("invalid interval {}", value))?;
+    let secs = match unit {
+        "s" => number,
+        "m" => number * 60,
+        "h" => number * 60 * 60,
+        "d" => number * 24 * 60 * 60,
+        _ => return Err(format!("invalid interval {}, expected a unit of s, m, h or d", value)),
+    };
+    match secs {
+        0 => Err(format!("invalid interval {}, must be positive", value)),
+        secs => Ok(Duration::from_secs(secs)),
+    }
+}
+
+fn is_interval(value: String) -> Result<(), String> {
+    parse_interval(&value).map(|_| ())
+}
+
+fn is_bandwidth(value: String) -> Result<(), String> {
+    bandwidth::parse(&value).map(|_| ())
+}
+
+fn is_socket_addr(value: String) -> Result<(), String> {
+    value
+        .parse::<SocketAddr>()
+        .map(|_| ())
+        .map_err(|e| format!("invalid address {}: {}", value, e))
+}
+
+fn is_timestamp(value: String) -> Result<(), String> {
+    DateTime::parse_from_rfc3339(&value)
+        .map(|_| ())
+        .map_err(|e| format!("invalid timestamp {}: {}", value, e))
+}
+
+fn is_number(value: String) -> Result<(), String> {
+    value
+        .parse::<u64>()
+        .map(|_| ())
+        .map_err(|e| format!("invalid number {}: {}", value, e))
+}
+
+fn is_positive(value: String) -> Result<(), String> {
+    match value.parse::<usize>() {
+        Ok(n) if n > 0 => Ok(()),
+        _ => Err(format!("invalid number {}, expected a positive integer", value)),
+    }
+}
+
+fn is_page_size(value: String) -> Result<(), String> {
+    match value.parse::<u64>() {
+        Ok(n) if n > 0 && n <= 100 => Ok(()),
+        _ => Err(format!("invalid page size {}, expected a value between 1 and 100", value)),
+    }
+}
+
+fn is_fraction(value: String) -> Result<(), String> {
+    match value.parse::<f64>() {
+        Ok(f) if f >= 0.0 && f <= 1.0 => Ok(()),
+        _ => Err(format!("invalid fraction {}, expected a value between 0 and 1", value)),
+    }
+}
+
+async fn run() -> Result<(), Box<dyn error::Error>> {
+    match Command::from_args() {
+        Command::Fetch(cfg) => {
+            interrupt::install()?;
+            if cfg.tui && !atty::is(atty::Stream::Stderr) {
+                return Err("--tui needs a terminal".into());
+            }
+            let _dashboard = match cfg.dashboard {
+                Some(ref dashboard) if cfg.tui => Some(dashboard.start()?),
+                _ => None,
+            };
+            let _telemetry = match cfg.otlp_endpoint {
+                Some(ref endpoint) => Some(telemetry::start(endpoint)?),
+                None => None,
+            };
+            let daemon = cfg.daemon;
+            let fetcher = Fetcher::new(cfg);
+            match daemon {
+                Some(interval) => fetcher.daemon(interval).await,
+                None => fetcher.run().await,
+            }
+        }
+        Command::List(cfg) => Fetcher::new(cfg).list().await,
+        Command::Upgrade(dir) => {
+            let _lock = lock::lock(&dir, false)?;
+            format::upgrade(&dir)
+        }
+        Command::Listen(cfg) => listen::listen(cfg).await,
+        Command::Login(cfg) => login::login(cfg).await,
+        Command::Check(cfg) => check::check(cfg).await,
+        Command::Show(cfg) => show::show(cfg),
+        Command::Stats(cfg) => stats::stats(cfg),
+        Command::Diff(cfg) => diff::diff(cfg),
+        Command::Merge(cfg) => merge::merge(cfg),
+        Command::Verify(cfg) => verify::verify(cfg),
+        Command::Anonymize(cfg) => anonymize::anonymize(cfg),
+        Command::Report(cfg) => html_report::report(cfg),
+        Command::Graph(cfg) => graph::graph(cfg),
+        Command::PrMetrics(cfg) => pr_metrics::pr_metrics(cfg),
+        Command::Contributors(cfg) => contributors::contributors(cfg),
+        Command::Burndown(cfg) => burndown::burndown(cfg),
+        Command::Labels(cfg) => labels::labels(cfg),
+        Command::Query(cfg) => query::query(cfg),
+        Command::Serve(cfg) => serve::serve(cfg).await,
+        Command::ExportSite(cfg) => export_site::export_site(cfg),
+        Command::SelfUpdate(cfg) => update::self_update(cfg).await,
+        Command::Completions(shell) => {
+            app().gen_completions_to(env!("CARGO_PKG_NAME"), shell, &mut io::stdout());
+            Ok(())
+        }
+        Command::Manpage => manpage::write(app, &mut io::stdout()),
+        Command::Convert { dir, from, to } => {
+            let _lock = lock::lock(&dir, false)?;
+            format::convert(&dir, from, to)
+        }
+    }
+}
+
+#[tokio::main]
+async fn main() {
+    if let Err(e) = run().await {
+        error!("{}", e);
+        process::exit(ExitCode::of(&*e) as i32);
+    }
 }
@@ -0,0 +1,58 @@
+use std::collections::BTreeMap;
+use std::error;
+use std::fs;
+use std::io::{self, BufReader, BufWriter};
+use std::path::Path;
+
+use sha2::{Digest, Sha256};
+
+/// Checksums of the snapshot files of a repository, kept next to them.
+pub const MANIFEST_FILE: &str = "manifest.json";
+
+/// Snapshot files of a repository directory by name.
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct Manifest {
+    pub files: BTreeMap<String, FileEntry>,
+}
+
+#[derive(Debug, Deserialize, PartialEq, Serialize)]
+pub struct FileEntry {
+    pub sha256: String,
+    pub bytes: u64,
+}
+
+impl FileEntry {
+    /// Checksum and size of the file at `path` as it is now.
+    pub fn of(path: &Path) -> io::Result<FileEntry> {
+        let mut hasher = Sha256::new();
+        let bytes = io::copy(&mut BufReader::new(fs::File::open(path)?), &mut hasher)?;
+        Ok(FileEntry {
+            sha256: hasher.result().iter().map(|b| format!("{:02x}", b)).collect(),
+            bytes: bytes,
+        })
+    }
+}
+
+/// Manifest of the repository directory `dir`, none if it has none.
+pub fn read(dir: &Path) -> Result<Option<Manifest>, Box<dyn error::Error>> {
+    let path = dir.join(MANIFEST_FILE);
+    if !path.exists() {
+        return Ok(None);
+    }
+    Ok(Some(serde_json::from_reader(BufReader::new(fs::File::open(path)?))?))
+}
+
+/// Records the checksum of the snapshot file at `path`, just written, in the
+/// manifest of its directory.
+pub fn update(path: &Path) -> Result<(), Box<dyn error::Error>> {
+    let (dir, name) = match (path.parent(), path.file_name()) {
+        (Some(dir), Some(name)) => (dir, name.to_string_lossy().into_owned()),
+        _ => return Ok(()),
+    };
+    let mut manifest = read(dir)?.unwrap_or_default();
+    manifest.files.insert(name, FileEntry::of(path)?);
+    let tmp = dir.join(format!("{}.tmp", MANIFEST_FILE));
+    serde_json::to_writer_pretty(BufWriter::new(fs::File::create(&tmp)?), &manifest)?;
+    fs::rename(&tmp, dir.join(MANIFEST_FILE))?;
+    Ok(())
+}
@@ -0,0 +1,137 @@
+use std::error;
+use std::io::Write;
+
+use clap::{App, ErrorKind};
+
+/// Headings of the help output whose entries are options or arguments.
+const HEADING_SUFFIX: char = ':';
+
+/// Writes a man page in roff format built from the help of `app` and its
+/// subcommands, which `app` builds afresh for each.
+pub fn write<W, F>(app: F, out: &mut W) -> Result<(), Box<dyn error::Error>>
+where
+    W: Write,
+    F: Fn() -> App<'static, 'static>,
+{
+    let name = env!("CARGO_PKG_NAME");
+    let root = help(&app, &[])?;
+    writeln!(out, ".TH {} 1 \"\" \"{} {}\"", name.to_uppercase(), name, env!("CARGO_PKG_VERSION"))?;
+    writeln!(out, ".SH NAME")?;
+    writeln!(out, "{} \\- {}", name, escape(env!("CARGO_PKG_DESCRIPTION")))?;
+
+    let (_, sections) = parse(&root);
+    let mut subcommands = Vec::new();
+    for &(ref heading, ref lines) in &sections {
+        match &heading[..] {
+            "USAGE" => {
+                writeln!(out, ".SH SYNOPSIS")?;
+                for line in lines {
+                    writeln!(out, "{}", escape(line.trim()))?;
+                }
+            }
+            "SUBCOMMANDS" => subcommands = entries(lines).into_iter().map(|(term, _)| term).collect(),
+            _ => {
+                writeln!(out, ".SH {}", heading)?;
+                write_entries(out, lines)?;
+            }
+        }
+    }
+
+    writeln!(out, ".SH COMMANDS")?;
+    for subcommand in subcommands.iter().filter(|s| *s != "help") {
+        let (preamble, sections) = parse(&help(&app, &[&subcommand[..]])?);
+        writeln!(out, ".SS {}", escape(subcommand))?;
+        // the first line names the subcommand
+        for line in preamble.iter().skip(1).filter(|line| !line.trim().is_empty()) {
+            writeln!(out, "{}", escape(line.trim()))?;
+        }
+        for (heading, lines) in sections {
+            writeln!(out, ".PP\n\\fB{}\\fR", heading)?;
+            match &heading[..] {
+                "USAGE" => for line in &lines {
+                    writeln!(out, ".br\n{}", escape(line.trim()))?;
+                },
+                _ => write_entries(out, &lines)?,
+            }
+        }
+    }
+
+    writeln!(out, ".SH AUTHORS")?;
+    writeln!(out, "{}", escape(env!("CARGO_PKG_AUTHORS")))?;
+    Ok(())
+}
+
+/// Help output of the subcommand `path`, or of the app itself.
+fn help<F: Fn() -> App<'static, 'static>>(app: &F, path: &[&str]) -> Result<String, Box<dyn error::Error>> {
+    let mut args = vec![env!("CARGO_PKG_NAME")];
+    args.extend(path);
+    args.push("--help");
+    match app().get_matches_from_safe(args) {
+        Err(ref e) if e.kind == ErrorKind::HelpDisplayed => Ok(e.message.clone()),
+        Err(e) => Err(e.message.into()),
+        Ok(_) => Err("no help output".into()),
+    }
+}
+
+/// Splits help output into the lines before the first heading and the
+/// sections after each heading.
+fn parse(help: &str) -> (Vec<String>, Vec<(String, Vec<String>)>) {
+    let mut preamble = Vec::new();
+    let mut sections: Vec<(String, Vec<String>)> = Vec::new();
+    for line in help.lines() {
+        let is_heading = !line.starts_with(' ')
+            && line.ends_with(HEADING_SUFFIX)
+            && line.chars().all(|c| c.is_uppercase() || c == ' ' || c == HEADING_SUFFIX);
+        if is_heading {
+            sections.push((line.trim_end_matches(HEADING_SUFFIX).to_string(), Vec::new()));
+        } else if let Some(&mut (_, ref mut lines)) = sections.last_mut() {
+            lines.push(line.to_string());
+        } else {
+            preamble.push(line.to_string());
+        }
+    }
+    (preamble, sections)
+}
+
+/// Terms of a section with their descriptions, which follow a term on the
+/// same line after a gap or on further, deeper indented lines.
+fn entries(lines: &[String]) -> Vec<(String, String)> {
+    let mut entries: Vec<(String, String)> = Vec::new();
+    for line in lines.iter().filter(|line| !line.trim().is_empty()) {
+        let indent = line.len() - line.trim_start().len();
+        match entries.last_mut() {
+            Some(&mut (_, ref mut description)) if indent > 8 => {
+                if !description.is_empty() {
+                    description.push(' ');
+                }
+                description.push_str(line.trim());
+            }
+            _ => {
+                let line = line.trim();
+                let (term, description) = match line.find("  ") {
+                    Some(gap) => (&line[..gap], line[gap..].trim()),
+                    None => (line, ""),
+                };
+                entries.push((term.to_string(), description.to_string()));
+            }
+        }
+    }
+    entries
+}
+
+fn write_entries<W: Write>(out: &mut W, lines: &[String]) -> Result<(), Box<dyn error::Error>> {
+    for (term, description) in entries(lines) {
+        writeln!(out, ".TP\n\\fB{}\\fR\n{}", escape(&term).replace('-', "\\-"), escape(&description))?;
+    }
+    Ok(())
+}
+
+/// Escapes backslashes and lines that roff would take for requests.
+fn escape(text: &str) -> String {
+    let text = text.replace('\\', "\\e");
+    if text.starts_with('.') || text.starts_with('\'') {
+        format!("\\&{}", text)
+    } else {
+        text
+    }
+}
@@ -0,0 +1,147 @@
+use std::collections::BTreeMap;
+use std::error;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde_json::Value;
+
+use crate::entity::Entity;
+use crate::format::{self, Format};
+use crate::labels;
+use crate::lock;
+use crate::repo::Repo;
+use crate::snapshot;
+use crate::users;
+
+/// Merges `records` into `existing`, replacing records with the same key.
+///
+/// The result is ordered by key, records only present in `existing` are kept.
+/// A record without a key fails the merge rather than replacing another one,
+/// e.g. one of a snapshot written in another shape.
+pub fn upsert<'a, D, K>(existing: &'a [D], records: &'a [D], key: K) -> Result<Vec<&'a D>, String>
+where
+    K: Fn(&D) -> Option<u64>,
+{
+    let mut merged = BTreeMap::new();
+    for (i, record) in existing.iter().chain(records).enumerate() {
+        let key = key(record).ok_or_else(|| {
+            if i < existing.len() {
+                "a record of the existing snapshot has no key, it was written in another shape".to_string()
+            } else {
+                "a fetched record has no key".to_string()
+            }
+        })?;
+        merged.insert(key, record);
+    }
+    Ok(merged.into_iter().map(|(_, record)| record).collect())
+}
+
+#[derive(Debug)]
+pub struct MergeConfig {
+    /// Output directories or repository directories to combine, later ones
+    /// winning ties.
+    pub inputs: Vec<PathBuf>,
+    pub output: PathBuf,
+    pub format: Format,
+}
+
+/// Combines the snapshots of several directories, e.g. of incremental runs,
+/// into one below the output directory, keeping the most recently updated
+/// version of each record. Snapshots fetched through the REST and the GraphQL
+/// API differ in their fields and are not merged with each other.
+pub fn merge(cfg: MergeConfig) -> Result<(), Box<dyn error::Error>> {
+    let mut repos: BTreeMap<String, (Repo, Vec<PathBuf>)> = BTreeMap::new();
+    for input in &cfg.inputs {
+        for (repo, dir) in snapshot::repositories(input)? {
+            repos.entry(repo.to_string()).or_insert_with(|| (repo, Vec::new())).1.push(dir);
+        }
+    }
+    if repos.is_empty() {
+        return Err("no snapshots to merge".into());
+    }
+
+    let _lock = lock::lock(&cfg.output, false)?;
+    for (name, &(ref repo, ref dirs)) in &repos {
+        let out = repo.dir(&cfg.output);
+        fs::create_dir_all(&out)?;
+        // deduplicated records reference users and no longer match the API types
+        let deduped = dirs.iter().any(|dir| snapshot::find_named(dir, "users").is_some());
+        // whether the first snapshot read holds GraphQL nodes
+        let mut api: Option<(bool, PathBuf)> = None;
+        for entity in Entity::ALL {
+            let mut records = BTreeMap::new();
+            for dir in dirs {
+                if let Some((path, format)) = snapshot::find(dir, *entity) {
+                    let read = snapshot::records(&path, format, *entity)?;
+                    // both have a number, only GraphQL nodes a databaseId
+                    let graphql = read.iter().any(|record| record.get("databaseId").is_some());
+                    match api {
+                        Some((first, ref first_path)) if first != graphql => {
+                            return Err(format!(
+                                "{} and {} were fetched through different APIs and can't be merged",
+                                first_path.display(),
+                                path.display()
+                            ).into());
+                        }
+                        Some(_) => {}
+                        None => api = Some((graphql, path.clone())),
+                    }
+                    for record in read {
+                        let number = users::key_of(&record, "number")
+                            .ok_or_else(|| format!("a record of {} has no number", path.display()))?;
+                        if records.get(&number).map_or(true, |kept| updated_at(kept) <= updated_at(&record)) {
+                            records.insert(number, record);
+                        }
+                    }
+                }
+            }
+            if records.is_empty() {
+                continue;
+            }
+            let records: Vec<Value> = records.into_iter().map(|(_, record)| record).collect();
+            let path = out.join(format!("{}.{}", entity.name(), cfg.format.extension()));
+            snapshot::write(&records, *entity, !deduped, cfg.format, &path)?;
+            info!("{}: {} {}", name, records.len(), entity.name());
+        }
+        if deduped {
+            merge_by_id(dirs, "users", &out, cfg.format)?;
+        }
+        let events = merge_by_id(dirs, labels::EVENTS, &out, cfg.format)?;
+        if events > 0 {
+            info!("{}: {} {}", name, events, labels::EVENTS);
+        }
+    }
+    Ok(())
+}
+
+/// Merges the snapshots named `name` of `dirs`, users or events, into one in
+/// `out`, records with the same `id` are the same in all of them. Returns the
+/// number of records merged, no snapshot is written for none.
+fn merge_by_id(dirs: &[PathBuf], name: &str, out: &Path, format: Format) -> Result<usize, Box<dyn error::Error>> {
+    let mut records = BTreeMap::new();
+    for dir in dirs {
+        if let Some((path, from)) = snapshot::find_named(dir, name) {
+            for record in format::deserialize_from_file::<Value>(from, &path)? {
+                let id = users::key_of(&record, "id")
+                    .ok_or_else(|| format!("a record of {} has no id", path.display()))?;
+                records.insert(id, record);
+            }
+        }
+    }
+    if records.is_empty() {
+        return Ok(0);
+    }
+    let records: Vec<Value> = records.into_iter().map(|(_, record)| record).collect();
+    format::serialize_to_file(&records, format, &out.join(format!("{}.{}", name, format.extension())))?;
+    Ok(records.len())
+}
+
+/// When a REST record or GraphQL node was last updated, RFC 3339 timestamps
+/// in UTC compare as strings.
+fn updated_at(record: &Value) -> &str {
+    record
+        .get("updated_at")
+        .or_else(|| record.get("updatedAt"))
+        .and_then(Value::as_str)
+        .unwrap_or("")
+}
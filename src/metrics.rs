@@ -0,0 +1,118 @@
+use std::error;
+use std::fmt::Write as FmtWrite;
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+
+use chrono::DateTime;
+use hyper::header::{CONTENT_TYPE, USER_AGENT};
+use hyper::{self, Body, Request};
+use hyper_tls::HttpsConnector;
+
+use crate::budget::Quota;
+use crate::report::Report;
+
+/// Prefix of all metric names.
+const PREFIX: &str = "github_data_fetch";
+
+/// Renders the report of a run and the remaining quota in the Prometheus text
+/// exposition format.
+pub fn render(report: &Report, quota: Option<&Quota>) -> String {
+    let mut out = String::new();
+    let finished = DateTime::parse_from_rfc3339(&report.finished).map_or(0, |t| t.timestamp());
+    gauge(&mut out, "finished_timestamp_seconds", "Time the run finished at.", &[(vec![], finished as f64)]);
+    gauge(&mut out, "duration_seconds", "Duration of the run.", &[(vec![], report.duration_secs as f64)]);
+    gauge(&mut out, "requests", "Requests sent to the API.", &[(vec![], report.requests as f64)]);
+    gauge(&mut out, "cache_hits", "Responses served from the ETag cache.", &[(vec![], report.cache_hits as f64)]);
+    gauge(&mut out, "bytes_written", "Bytes of snapshots written.", &[(vec![], report.bytes_written as f64)]);
+    gauge(&mut out, "failed_repositories", "Repositories that failed.", &[(vec![], report.failed.len() as f64)]);
+    gauge(
+        &mut out,
+        "unfinished_repositories",
+        "Repositories not fetched since the run stopped early.",
+        &[(vec![], report.unfinished.len() as f64)],
+    );
+    gauge(
+        &mut out,
+        "failed_items",
+        "Issues and pull requests that could not be fetched.",
+        &[(vec![], report.failed_items().len() as f64)],
+    );
+
+    let mut records = Vec::new();
+    let mut phases = Vec::new();
+    for repo in &report.repositories {
+        for (entity, count) in &repo.records {
+            records.push((vec![("repository", &repo.repository[..]), ("entity", &entity[..])], *count as f64));
+        }
+        for (phase, secs) in &repo.phases {
+            phases.push((vec![("repository", &repo.repository[..]), ("phase", &phase[..])], *secs));
+        }
+    }
+    gauge(&mut out, "records", "Records written per repository and entity.", &records);
+    gauge(&mut out, "phase_seconds", "Time spent per repository and phase.", &phases);
+
+    if let Some(quota) = quota {
+        gauge(&mut out, "rate_limit", "Requests per hour the rate limit allows.", &[(vec![], quota.limit as f64)]);
+        gauge(
+            &mut out,
+            "rate_limit_remaining",
+            "Requests left until the rate limit resets.",
+            &[(vec![], quota.remaining as f64)],
+        );
+        gauge(
+            &mut out,
+            "rate_limit_reset_timestamp_seconds",
+            "Time the rate limit resets at.",
+            &[(vec![], quota.reset as f64)],
+        );
+    }
+    out
+}
+
+fn gauge(out: &mut String, name: &str, help: &str, samples: &[(Vec<(&str, &str)>, f64)]) {
+    let _ = writeln!(out, "# HELP {}_{} {}", PREFIX, name, help);
+    let _ = writeln!(out, "# TYPE {}_{} gauge", PREFIX, name);
+    for &(ref labels, value) in samples {
+        let labels: Vec<String> = labels
+            .iter()
+            .map(|&(label, value)| format!("{}=\"{}\"", label, escape(value)))
+            .collect();
+        if labels.is_empty() {
+            let _ = writeln!(out, "{}_{} {}", PREFIX, name, value);
+        } else {
+            let _ = writeln!(out, "{}_{}{{{}}} {}", PREFIX, name, labels.join(","), value);
+        }
+    }
+}
+
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+/// Writes the metrics for the node exporter's textfile collector, which must
+/// never see a partially written file.
+pub fn write_textfile(path: &Path, metrics: &str) -> Result<(), Box<dyn error::Error>> {
+    let tmp = path.with_extension("prom.tmp");
+    fs::File::create(&tmp)?.write_all(metrics.as_bytes())?;
+    fs::rename(&tmp, path)?;
+    Ok(())
+}
+
+/// Pushes the metrics to a Prometheus Pushgateway at `url`, replacing those
+/// of the previous run.
+pub async fn push(url: &str, metrics: String) -> Result<(), Box<dyn error::Error>> {
+    let http = hyper::Client::builder().build::<_, Body>(HttpsConnector::new());
+    let url = format!("{}/metrics/job/{}", url.trim_end_matches('/'), env!("CARGO_PKG_NAME"));
+    let request = Request::put(&url[..])
+        .header(USER_AGENT, concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION")))
+        .header(CONTENT_TYPE, "text/plain; version=0.0.4")
+        .body(Body::from(metrics))?;
+    let response = http.request(request).await?;
+    let status = response.status();
+    let body = hyper::body::to_bytes(response.into_body()).await?;
+    if !status.is_success() {
+        return Err(format!("{} from {}: {}", status, url, String::from_utf8_lossy(&body)).into());
+    }
+    Ok(())
+}
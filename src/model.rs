@@ -0,0 +1,307 @@
+use serde_json::Value;
+
+use crate::api;
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct User {
+    pub login: String,
+    pub id: u64,
+    pub avatar_url: String,
+    pub gravatar_id: String,
+    pub url: String,
+    pub html_url: String,
+    pub followers_url: String,
+    pub following_url: String,
+    pub gists_url: String,
+    pub starred_url: String,
+    pub subscriptions_url: String,
+    pub organizations_url: String,
+    pub repos_url: String,
+    pub events_url: String,
+    pub received_events_url: String,
+    pub site_admin: bool,
+    /// Global node id, shared with the GraphQL API.
+    #[serde(default)]
+    pub node_id: Option<String>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Label {
+    pub url: String,
+    pub name: String,
+    pub color: String,
+}
+
+/// Links of an issue that is a pull request.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct PullRef {
+    pub url: String,
+    pub html_url: String,
+    pub diff_url: String,
+    pub patch_url: String,
+}
+
+/// An issue as the snapshots store it, converted from the API response so
+/// that the format doesn't change along with the client.
+///
+/// Fields and their order follow hubcaps 0.5, which earlier snapshots were
+/// written with, as msgpack snapshots store structs positionally. New fields
+/// are only ever appended.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Issue {
+    pub id: u64,
+    pub url: String,
+    pub repository_url: String,
+    pub labels_url: String,
+    pub comments_url: String,
+    pub events_url: String,
+    pub html_url: String,
+    pub number: u64,
+    pub state: String,
+    pub title: String,
+    pub body: Option<String>,
+    pub user: User,
+    pub labels: Vec<Label>,
+    pub assignee: Option<User>,
+    pub locked: bool,
+    pub comments: u64,
+    pub pull_request: Option<PullRef>,
+    pub closed_at: Option<String>,
+    pub created_at: String,
+    pub updated_at: String,
+    pub assignees: Vec<User>,
+    #[serde(default)]
+    pub node_id: Option<String>,
+}
+
+/// Head or base of a pull request.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Commit {
+    pub label: String,
+    #[serde(rename = "ref")]
+    pub commit_ref: String,
+    pub sha: String,
+    pub user: User,
+    /// The repository, `None` once a fork was deleted.
+    pub repo: Option<Value>,
+}
+
+/// Details of a pull request, fields ordered like those of hubcaps 0.5 too.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Pull {
+    pub id: u64,
+    pub url: String,
+    pub html_url: String,
+    pub diff_url: String,
+    pub patch_url: String,
+    pub issue_url: String,
+    pub commits_url: String,
+    pub review_comments_url: String,
+    pub review_comment_url: String,
+    pub comments_url: String,
+    pub statuses_url: String,
+    pub number: u64,
+    pub state: String,
+    pub title: String,
+    pub body: Option<String>,
+    pub created_at: String,
+    pub updated_at: String,
+    pub closed_at: Option<String>,
+    pub merged_at: Option<String>,
+    pub head: Commit,
+    pub base: Commit,
+    pub user: User,
+    pub assignee: Option<User>,
+    pub assignees: Vec<User>,
+    pub merge_commit_sha: Option<String>,
+    pub merged: Option<bool>,
+    pub mergeable: Option<bool>,
+    pub merged_by: Option<User>,
+    pub comments: Option<u64>,
+    pub commits: Option<u64>,
+    pub additions: Option<u64>,
+    pub deletions: Option<u64>,
+    pub changed_files: Option<u64>,
+    pub requested_reviewers: Option<Vec<User>>,
+    #[serde(default)]
+    pub node_id: Option<String>,
+}
+
+impl From<api::User> for User {
+    fn from(user: api::User) -> Self {
+        User {
+            login: user.login,
+            id: user.id,
+            avatar_url: user.avatar_url,
+            gravatar_id: user.gravatar_id,
+            url: user.url,
+            html_url: user.html_url,
+            followers_url: user.followers_url,
+            following_url: user.following_url,
+            gists_url: user.gists_url,
+            starred_url: user.starred_url,
+            subscriptions_url: user.subscriptions_url,
+            organizations_url: user.organizations_url,
+            repos_url: user.repos_url,
+            events_url: user.events_url,
+            received_events_url: user.received_events_url,
+            site_admin: user.site_admin,
+            node_id: user.node_id,
+        }
+    }
+}
+
+impl From<api::Label> for Label {
+    fn from(label: api::Label) -> Self {
+        Label {
+            url: label.url,
+            name: label.name,
+            color: label.color,
+        }
+    }
+}
+
+impl From<api::PullRef> for PullRef {
+    fn from(pull_ref: api::PullRef) -> Self {
+        PullRef {
+            url: pull_ref.url,
+            html_url: pull_ref.html_url,
+            diff_url: pull_ref.diff_url,
+            patch_url: pull_ref.patch_url,
+        }
+    }
+}
+
+impl From<api::Issue> for Issue {
+    fn from(issue: api::Issue) -> Self {
+        Issue {
+            id: issue.id,
+            url: issue.url,
+            repository_url: issue.repository_url,
+            labels_url: issue.labels_url,
+            comments_url: issue.comments_url,
+            events_url: issue.events_url,
+            html_url: issue.html_url,
+            number: issue.number,
+            state: issue.state,
+            title: issue.title,
+            body: issue.body,
+            user: User::from(issue.user),
+            labels: issue.labels.into_iter().map(Label::from).collect(),
+            assignee: issue.assignee.map(User::from),
+            locked: issue.locked,
+            comments: issue.comments,
+            pull_request: issue.pull_request.map(PullRef::from),
+            closed_at: issue.closed_at,
+            created_at: issue.created_at,
+            updated_at: issue.updated_at,
+            assignees: issue.assignees.into_iter().map(User::from).collect(),
+            node_id: issue.node_id,
+        }
+    }
+}
+
+impl From<api::Commit> for Commit {
+    fn from(commit: api::Commit) -> Self {
+        Commit {
+            label: commit.label,
+            commit_ref: commit.commit_ref,
+            sha: commit.sha,
+            user: User::from(commit.user),
+            repo: commit.repo,
+        }
+    }
+}
+
+impl From<api::Pull> for Pull {
+    fn from(pull: api::Pull) -> Self {
+        Pull {
+            id: pull.id,
+            url: pull.url,
+            html_url: pull.html_url,
+            diff_url: pull.diff_url,
+            patch_url: pull.patch_url,
+            issue_url: pull.issue_url,
+            commits_url: pull.commits_url,
+            review_comments_url: pull.review_comments_url,
+            review_comment_url: pull.review_comment_url,
+            comments_url: pull.comments_url,
+            statuses_url: pull.statuses_url,
+            number: pull.number,
+            state: pull.state,
+            title: pull.title,
+            body: pull.body,
+            created_at: pull.created_at,
+            updated_at: pull.updated_at,
+            closed_at: pull.closed_at,
+            merged_at: pull.merged_at,
+            head: Commit::from(pull.head),
+            base: Commit::from(pull.base),
+            user: User::from(pull.user),
+            assignee: pull.assignee.map(User::from),
+            assignees: pull.assignees.into_iter().map(User::from).collect(),
+            merge_commit_sha: pull.merge_commit_sha,
+            merged: pull.merged,
+            mergeable: pull.mergeable,
+            merged_by: pull.merged_by.map(User::from),
+            comments: pull.comments,
+            commits: pull.commits,
+            additions: pull.additions,
+            deletions: pull.deletions,
+            changed_files: pull.changed_files,
+            requested_reviewers: pull.requested_reviewers.map(|users| users.into_iter().map(User::from).collect()),
+            node_id: pull.node_id,
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use rmp_serde::Serializer;
+    use rmpv::Value;
+    use serde::Serialize;
+
+    use super::User;
+
+    fn user() -> User {
+        let url = String::new;
+        User {
+            login: "octocat".to_string(),
+            id: 1,
+            avatar_url: url(),
+            gravatar_id: url(),
+            url: url(),
+            html_url: url(),
+            followers_url: url(),
+            following_url: url(),
+            gists_url: url(),
+            starred_url: url(),
+            subscriptions_url: url(),
+            organizations_url: url(),
+            repos_url: url(),
+            events_url: url(),
+            received_events_url: url(),
+            site_admin: false,
+            node_id: Some("MDQ6VXNlcjE=".to_string()),
+        }
+    }
+
+    #[test]
+    fn records_written_before_node_ids_are_read() {
+        let mut buf = Vec::new();
+        user().serialize(&mut Serializer::new(&mut buf)).unwrap();
+        // as written before node_id was appended
+        let mut fields = match rmpv::decode::read_value(&mut &buf[..]).unwrap() {
+            Value::Array(fields) => fields,
+            value => panic!("not stored positionally: {}", value),
+        };
+        fields.pop();
+        let mut old = Vec::new();
+        rmpv::encode::write_value(&mut old, &Value::Array(fields)).unwrap();
+
+        let read: User = rmp_serde::decode::from_read(&old[..]).unwrap();
+        assert_eq!(read.login, "octocat");
+        assert_eq!(read.node_id, None);
+    }
+}
@@ -0,0 +1,59 @@
+use std::error;
+
+use atty::{self, Stream};
+use dialoguer::{Confirm, Input, MultiSelect};
+use fuzzy_matcher::FuzzyMatcher;
+use fuzzy_matcher::skim::SkimMatcherV2;
+
+use crate::repo::{Repo, RepoInfo};
+
+/// Lets the user choose among `listed` in the terminal, each round narrowing
+/// the list down by a fuzzy search on the names.
+pub fn pick(listed: &[RepoInfo]) -> Result<Vec<Repo>, Box<dyn error::Error>> {
+    if !atty::is(Stream::Stdin) || !atty::is(Stream::Stderr) {
+        return Err("--interactive needs a terminal".into());
+    }
+    let matcher = SkimMatcherV2::default();
+    let mut picked: Vec<Repo> = Vec::new();
+    loop {
+        let query: String = Input::new()
+            .with_prompt("Search repositories (empty for all)")
+            .allow_empty(true)
+            .interact_text()?;
+        let mut found: Vec<(i64, &RepoInfo)> = listed
+            .iter()
+            .filter_map(|info| matcher.fuzzy_match(&info.name, &query).map(|score| (score, info)))
+            .collect();
+        // best matches first, by name for an empty search
+        found.sort_by(|a, b| b.0.cmp(&a.0).then_with(|| a.1.name.cmp(&b.1.name)));
+        if found.is_empty() {
+            eprintln!("No repository matches {}", query);
+        } else {
+            let items: Vec<String> = found.iter().map(|&(_, info)| describe(info)).collect();
+            let chosen = MultiSelect::new()
+                .with_prompt("Select with space, confirm with enter")
+                .items(&items)
+                .defaults(&found.iter().map(|&(_, info)| picked.contains(&info.repo())).collect::<Vec<_>>())
+                .interact()?;
+            for &(_, info) in &found {
+                picked.retain(|repo| *repo != info.repo());
+            }
+            picked.extend(chosen.into_iter().map(|i| found[i].1.repo()));
+        }
+        eprintln!("{} repositories selected", picked.len());
+        if !Confirm::new().with_prompt("Search for more?").default(false).interact()? {
+            return Ok(picked);
+        }
+    }
+}
+
+fn describe(info: &RepoInfo) -> String {
+    let mut description = format!("{} ({} stars)", info.name, info.stargazers_count);
+    if info.fork {
+        description.push_str(", fork");
+    }
+    if info.archived {
+        description.push_str(", archived");
+    }
+    description
+}
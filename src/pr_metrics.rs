@@ -0,0 +1,170 @@
+use std::collections::BTreeMap;
+use std::error;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Utc};
+use serde_json::Value;
+
+use crate::entity::Entity;
+use crate::format::{self, Format};
+use crate::lock;
+use crate::snapshot::{self, Item};
+use crate::stats;
+
+/// Name of the dataset files, next to the snapshots.
+pub const DATASET: &str = "pr_metrics";
+
+#[derive(Debug)]
+pub struct PrMetricsConfig {
+    /// Output directory of a fetch, or the directory of one repository.
+    pub dir: PathBuf,
+    /// Output directory to write the datasets to, next to the snapshots if
+    /// none.
+    pub output: Option<PathBuf>,
+    pub format: Format,
+}
+
+/// Cycle time and review latency of a pull request, in days. Reviews are
+/// only known for pull requests fetched through GraphQL, the review figures
+/// of a record without them are empty.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct PrMetrics {
+    pub number: u64,
+    pub author: Option<String>,
+    /// `open`, `closed` or `merged`.
+    pub state: String,
+    pub created_at: Option<String>,
+    pub merged_at: Option<String>,
+    /// From opening to merging.
+    pub days_to_merge: Option<f64>,
+    /// From opening to closing without merging.
+    pub days_to_close: Option<f64>,
+    /// From opening to the first review by someone other than the author.
+    pub days_to_first_review: Option<f64>,
+    pub reviews: Option<usize>,
+    /// Reviews requesting changes, plus one for the final round if there
+    /// were any reviews.
+    pub review_rounds: Option<usize>,
+    /// From opening to the first review of each reviewer.
+    pub reviewer_response_days: BTreeMap<String, f64>,
+}
+
+/// A submitted review of a GraphQL pull request node.
+struct Review {
+    author: Option<String>,
+    state: String,
+    submitted_at: DateTime<Utc>,
+}
+
+impl PrMetrics {
+    /// Metrics of a pull request record of a snapshot.
+    pub fn of(record: &Value) -> Option<PrMetrics> {
+        let item = Item::of(record, true)?;
+        let reviews = reviews(record);
+        let since = |time: DateTime<Utc>| {
+            item.created_at.map(|created| stats::days(time.signed_duration_since(created)))
+        };
+
+        let mut reviewer_response_days = BTreeMap::new();
+        let mut days_to_first_review = None;
+        if let Some(ref reviews) = reviews {
+            for review in reviews.iter().filter(|review| review.author != item.author) {
+                let days = match since(review.submitted_at) {
+                    Some(days) => days,
+                    None => continue,
+                };
+                days_to_first_review = days_to_first_review.or(Some(days));
+                if let Some(ref reviewer) = review.author {
+                    reviewer_response_days.entry(reviewer.clone()).or_insert(days);
+                }
+            }
+        }
+        Some(PrMetrics {
+            number: item.number,
+            author: item.author.clone(),
+            state: item.state.clone(),
+            created_at: item.created_at.map(|time| time.to_rfc3339()),
+            merged_at: item.merged_at.map(|time| time.to_rfc3339()),
+            days_to_merge: item.merged_at.and_then(&since),
+            days_to_close: item.closed_at.filter(|_| item.merged_at.is_none()).and_then(&since),
+            days_to_first_review: days_to_first_review,
+            reviews: reviews.as_ref().map(Vec::len),
+            review_rounds: reviews.as_ref().map(|reviews| match reviews.len() {
+                0 => 0,
+                _ => reviews.iter().filter(|review| review.state == "CHANGES_REQUESTED").count() + 1,
+            }),
+            reviewer_response_days: reviewer_response_days,
+        })
+    }
+}
+
+/// The submitted reviews of a pull request in order, none if the record
+/// has no reviews field.
+fn reviews(record: &Value) -> Option<Vec<Review>> {
+    let nodes = record.pointer("/reviews/nodes")?.as_array()?;
+    let mut reviews: Vec<Review> = nodes
+        .iter()
+        .filter_map(|node| {
+            let submitted_at = node.get("submittedAt").and_then(Value::as_str)?;
+            Some(Review {
+                author: node.pointer("/author/login").and_then(Value::as_str).map(|login| login.to_string()),
+                state: node.get("state").and_then(Value::as_str).unwrap_or("").to_string(),
+                submitted_at: DateTime::parse_from_rfc3339(submitted_at).ok()?.with_timezone(&Utc),
+            })
+        })
+        .filter(|review| review.state != "PENDING")
+        .collect();
+    reviews.sort_by_key(|review| review.submitted_at);
+    Some(reviews)
+}
+
+/// Metrics of the pull requests in the snapshots of the repository
+/// directory `dir`. Fails for pulls fetched through the REST API, which
+/// lack the reviews.
+pub fn metrics(dir: &Path) -> Result<Vec<PrMetrics>, Box<dyn error::Error>> {
+    let (path, format) = match snapshot::find(dir, Entity::Pulls) {
+        Some(snapshot) => snapshot,
+        None => return Ok(Vec::new()),
+    };
+    let records = snapshot::records(&path, format, Entity::Pulls)?;
+    if records.iter().any(|record| record.pointer("/reviews/nodes").is_none()) {
+        return Err(format!(
+            "{} has no reviews, the review metrics need pulls fetched with --graphql",
+            path.display()
+        ).into());
+    }
+    Ok(records.iter().filter_map(PrMetrics::of).collect())
+}
+
+/// Writes the `pr_metrics` dataset of each repository with snapshots below
+/// `cfg.dir`.
+pub fn pr_metrics(cfg: PrMetricsConfig) -> Result<(), Box<dyn error::Error>> {
+    let repos = snapshot::repositories(&cfg.dir)?;
+    if repos.is_empty() {
+        return Err(format!("no snapshots in {}", cfg.dir.display()).into());
+    }
+    let _lock = lock::lock(cfg.output.as_ref().unwrap_or(&cfg.dir), false)?;
+    for (repo, dir) in &repos {
+        let metrics = metrics(dir)?;
+        let out = match cfg.output {
+            Some(ref output) => repo.dir(output),
+            None => dir.clone(),
+        };
+        fs::create_dir_all(&out)?;
+        let path = out.join(format!("{}.{}", DATASET, cfg.format.extension()));
+        format::serialize_to_file(&metrics, cfg.format, &path)?;
+
+        let mut merge: Vec<f64> = metrics.iter().filter_map(|m| m.days_to_merge).collect();
+        let mut review: Vec<f64> = metrics.iter().filter_map(|m| m.days_to_first_review).collect();
+        let days = |median: Option<f64>| median.map_or_else(|| "-".to_string(), |days| format!("{:.1}", days));
+        info!(
+            "{}: {} pull requests, median days to merge {}, to first review {}",
+            repo,
+            metrics.len(),
+            days(stats::median(&mut merge)),
+            days(stats::median(&mut review))
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,163 @@
+use std::str::FromStr;
+
+use atty::{self, Stream};
+use indicatif::{ProgressBar, ProgressStyle};
+
+use crate::dashboard::Dashboard;
+
+/// Items between log lines when no progress bar is shown.
+const LOG_EVERY: u64 = 100;
+
+/// How progress is reported.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Mode {
+    /// Bars on a terminal, log lines otherwise.
+    Auto,
+    /// A JSON event per page or item on stderr, for wrappers to display. Log
+    /// output is left out but for warnings and errors, which are JSON events
+    /// as well.
+    Json,
+}
+
+impl FromStr for Mode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "auto" => Ok(Mode::Auto),
+            "json" => Ok(Mode::Json),
+            _ => Err(format!("unknown progress mode: {}", s)),
+        }
+    }
+}
+
+/// Progress event of `--progress json`.
+#[derive(Serialize)]
+struct Event<'a> {
+    repository: &'a str,
+    phase: &'a str,
+    count: u64,
+    total: Option<u64>,
+    rate_limit_remaining: Option<u64>,
+    finished: bool,
+}
+
+/// Progress of a fetch phase, drawn as a bar with rate and ETA on a terminal
+/// and logged every few items otherwise, or shown on the dashboard.
+pub struct Progress {
+    bar: Option<ProgressBar>,
+    /// Repository of the JSON events.
+    repository: String,
+    label: String,
+    count: u64,
+    len: Option<u64>,
+    json: bool,
+    /// Shown dashboard taking the progress, or the one keeping the quota of
+    /// the JSON events.
+    dashboard: Option<Dashboard>,
+}
+
+impl Progress {
+    /// Progress towards `len` items, of which `done` are fetched already, or
+    /// an open count without `len`. Bars are only drawn if `bars` is set and
+    /// stderr is a terminal, and `dashboard` isn't shown.
+    pub fn new(label: &str, len: Option<u64>, done: u64, bars: bool, dashboard: Option<Dashboard>) -> Self {
+        let dashboard = dashboard.filter(Dashboard::shown);
+        if let Some(ref dashboard) = dashboard {
+            dashboard.progress(label, done, len);
+        }
+        let bar = if bars && dashboard.is_none() && atty::is(Stream::Stderr) {
+            let bar = match len {
+                Some(len) => {
+                    let bar = ProgressBar::new(len);
+                    bar.set_style(ProgressStyle::default_bar().template(
+                        "{msg:>8} [{bar:40}] {pos}/{len} {per_sec} ETA {eta}",
+                    ).progress_chars("=> "));
+                    bar
+                }
+                None => {
+                    let bar = ProgressBar::new_spinner();
+                    bar.set_style(ProgressStyle::default_spinner().template("{msg:>8} {spinner} {pos} {per_sec}"));
+                    bar
+                }
+            };
+            bar.set_message(label);
+            bar.set_position(done);
+            Some(bar)
+        } else {
+            None
+        };
+        Progress {
+            bar: bar,
+            repository: String::new(),
+            label: label.to_string(),
+            count: done,
+            len: len,
+            json: false,
+            dashboard: dashboard,
+        }
+    }
+
+    /// Progress of `repository` reported as JSON events rather than drawn or
+    /// logged, with the remaining quota kept by `dashboard`.
+    pub fn json(repository: &str, label: &str, len: Option<u64>, done: u64, dashboard: Option<Dashboard>) -> Self {
+        let progress = Progress {
+            bar: None,
+            repository: repository.to_string(),
+            label: label.to_string(),
+            count: done,
+            len: len,
+            json: true,
+            dashboard: dashboard,
+        };
+        progress.event(false);
+        progress
+    }
+
+    pub fn inc(&mut self, n: u64) {
+        let before = self.count;
+        self.count += n;
+        match self.bar {
+            Some(ref bar) => bar.inc(n),
+            None if self.json => self.event(false),
+            None => match self.dashboard {
+                Some(ref dashboard) => dashboard.progress(&self.label, self.count, self.len),
+                None => if self.count / LOG_EVERY > before / LOG_EVERY {
+                    self.log();
+                },
+            },
+        }
+    }
+
+    pub fn finish(self) {
+        match self.bar {
+            Some(ref bar) => bar.finish(),
+            None if self.json => self.event(true),
+            None => match self.dashboard {
+                Some(ref dashboard) => dashboard.progress(&self.label, self.count, self.len),
+                None => self.log(),
+            },
+        }
+    }
+
+    fn event(&self, finished: bool) {
+        let event = Event {
+            repository: &self.repository,
+            phase: &self.label,
+            count: self.count,
+            total: self.len,
+            rate_limit_remaining: self.dashboard.as_ref().and_then(Dashboard::remaining),
+            finished: finished,
+        };
+        if let Ok(line) = serde_json::to_string(&event) {
+            eprintln!("{}", line);
+        }
+    }
+
+    fn log(&self) {
+        match self.len {
+            Some(len) => info!("{}: {}/{}", self.label, self.count, len),
+            None => info!("{}: {}", self.label, self.count),
+        }
+    }
+}
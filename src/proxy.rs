@@ -0,0 +1,62 @@
+use std::env;
+use std::error;
+
+use hyper::Uri;
+use hyper_proxy::{Custom, Intercept, Proxy};
+
+/// Proxies to route requests through.
+///
+/// An explicit `--proxy` is used for all requests, otherwise `HTTPS_PROXY`
+/// and `HTTP_PROXY` apply to their scheme. Hosts listed in `NO_PROXY` are
+/// always connected to directly.
+pub fn proxies(explicit: Option<&str>) -> Result<Vec<Proxy>, Box<dyn error::Error>> {
+    let no_proxy = var("NO_PROXY").map(|hosts| parse_no_proxy(&hosts)).unwrap_or_default();
+    let mut proxies = Vec::new();
+    match explicit {
+        Some(url) => proxies.push(proxy(None, url, &no_proxy)?),
+        None => {
+            if let Some(url) = var("HTTPS_PROXY") {
+                proxies.push(proxy(Some("https"), &url, &no_proxy)?);
+            }
+            if let Some(url) = var("HTTP_PROXY") {
+                proxies.push(proxy(Some("http"), &url, &no_proxy)?);
+            }
+        }
+    }
+    Ok(proxies)
+}
+
+/// Reads an environment variable in upper or lower case, as both are in use.
+fn var(name: &str) -> Option<String> {
+    env::var(name)
+        .or_else(|_| env::var(name.to_lowercase()))
+        .ok()
+        .filter(|value| !value.is_empty())
+}
+
+fn proxy(scheme: Option<&'static str>, url: &str, no_proxy: &[String]) -> Result<Proxy, Box<dyn error::Error>> {
+    let uri: Uri = url.parse().map_err(|e| format!("invalid proxy url {}: {}", url, e))?;
+    let no_proxy = no_proxy.to_vec();
+    let intercept = Custom::from(move |req_scheme: Option<&str>, host: Option<&str>, _port: Option<u16>| {
+        scheme.map_or(true, |scheme| req_scheme == Some(scheme))
+            && !host.map_or(false, |host| bypasses(host, &no_proxy))
+    });
+    Ok(Proxy::new(Intercept::Custom(intercept), uri))
+}
+
+fn parse_no_proxy(hosts: &str) -> Vec<String> {
+    hosts
+        .split(',')
+        .map(|host| host.trim().trim_start_matches('.').to_lowercase())
+        .filter(|host| !host.is_empty())
+        .collect()
+}
+
+/// Whether `host` is excluded from proxying, either by name, as a subdomain of
+/// an excluded domain or by a `*` entry.
+fn bypasses(host: &str, no_proxy: &[String]) -> bool {
+    let host = host.to_lowercase();
+    no_proxy.iter().any(|excluded| {
+        excluded == "*" || host == *excluded || host.ends_with(&format!(".{}", excluded))
+    })
+}
@@ -0,0 +1,441 @@
+use std::error;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use chrono::{DateTime, Duration, NaiveDate, TimeZone, Utc};
+use serde_json::Value;
+
+use crate::entity::Entity;
+use crate::show;
+use crate::snapshot::{self, Item};
+
+#[derive(Debug)]
+pub struct QueryConfig {
+    /// Output directory of a fetch, or the directory of one repository.
+    pub dir: PathBuf,
+    pub query: Query,
+    /// Only search this entity, issues and pull requests by default.
+    pub entity: Option<Entity>,
+    /// Print the matching records as NDJSON instead of a table.
+    pub ndjson: bool,
+    pub limit: Option<usize>,
+}
+
+/// Field of an item a condition tests.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Field {
+    Number,
+    /// `issue` or `pull`.
+    Type,
+    State,
+    Label,
+    Author,
+    Title,
+    Comments,
+    Created,
+    Updated,
+    Closed,
+    Merged,
+}
+
+impl FromStr for Field {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match &s.to_lowercase()[..] {
+            "number" => Ok(Field::Number),
+            "type" | "is" => Ok(Field::Type),
+            "state" => Ok(Field::State),
+            "label" => Ok(Field::Label),
+            "author" => Ok(Field::Author),
+            "title" => Ok(Field::Title),
+            "comments" => Ok(Field::Comments),
+            "created" => Ok(Field::Created),
+            "updated" => Ok(Field::Updated),
+            "closed" => Ok(Field::Closed),
+            "merged" => Ok(Field::Merged),
+            _ => Err(format!("unknown field {}", s)),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Op {
+    Eq,
+    Ne,
+    Gt,
+    Ge,
+    Lt,
+    Le,
+    /// Case-insensitive substring.
+    Contains,
+}
+
+/// Operators by their text, those starting with another one first.
+const OPS: &[(&str, Op)] = &[
+    (">=", Op::Ge),
+    ("<=", Op::Le),
+    ("!=", Op::Ne),
+    ("=", Op::Eq),
+    (">", Op::Gt),
+    ("<", Op::Lt),
+    ("~", Op::Contains),
+];
+
+/// A test of a field, e.g. `label=bug`.
+#[derive(Clone, Debug)]
+pub struct Condition {
+    pub field: Field,
+    pub op: Op,
+    pub value: String,
+}
+
+impl FromStr for Condition {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let at = s
+            .find(|c| "=!<>~".contains(c))
+            .ok_or_else(|| format!("no operator in {}", s))?;
+        let &(text, op) = OPS
+            .iter()
+            .find(|&&(text, _)| s[at..].starts_with(text))
+            .ok_or_else(|| format!("unknown operator in {}", s))?;
+        let field: Field = s[..at].trim().parse()?;
+        let value = s[at + text.len()..].trim().trim_matches('"').to_string();
+        let ordered = match field {
+            Field::Number | Field::Comments | Field::Created | Field::Updated | Field::Closed | Field::Merged => true,
+            _ => false,
+        };
+        match op {
+            Op::Contains if ordered => return Err(format!("~ doesn't apply to {}", s[..at].trim())),
+            Op::Gt | Op::Ge | Op::Lt | Op::Le if !ordered => {
+                return Err(format!("{} doesn't apply to {}", text, s[..at].trim()))
+            }
+            _ => {}
+        }
+        match field {
+            Field::Number | Field::Comments => {
+                value.parse::<u64>().map_err(|_| format!("{} is not a number", value))?;
+            }
+            Field::Created | Field::Updated | Field::Closed | Field::Merged => {
+                period(&value)?;
+            }
+            _ => {}
+        }
+        Ok(Condition {
+            field: field,
+            op: op,
+            value: value,
+        })
+    }
+}
+
+impl Condition {
+    pub fn matches(&self, item: &Item) -> bool {
+        match self.field {
+            Field::Number => self.compare(item.number),
+            Field::Comments => self.compare(item.comments),
+            Field::Type => self.text(if item.pull { "pull" } else { "issue" }),
+            // merged pull requests are closed as well
+            Field::State if self.value.eq_ignore_ascii_case("closed") && item.state == "merged" => self.text("closed"),
+            Field::State => self.text(&item.state),
+            Field::Label => {
+                let any = item.labels.iter().any(|label| Condition::text_matches(Op::Eq, label, &self.value));
+                match self.op {
+                    Op::Ne => !any,
+                    Op::Contains => item.labels.iter().any(|label| self.text(label)),
+                    _ => any,
+                }
+            }
+            Field::Author => item.author.as_ref().map_or(self.op == Op::Ne, |author| self.text(author)),
+            Field::Title => self.text(&item.title),
+            Field::Created => self.time(item.created_at),
+            Field::Updated => self.time(item.updated_at),
+            Field::Closed => self.time(item.merged_at.or(item.closed_at)),
+            Field::Merged => self.time(item.merged_at),
+        }
+    }
+
+    fn text(&self, text: &str) -> bool {
+        Condition::text_matches(self.op, text, &self.value)
+    }
+
+    fn text_matches(op: Op, text: &str, value: &str) -> bool {
+        match op {
+            Op::Eq => text.eq_ignore_ascii_case(value),
+            Op::Ne => !text.eq_ignore_ascii_case(value),
+            Op::Contains => text.to_lowercase().contains(&value.to_lowercase()),
+            _ => false,
+        }
+    }
+
+    fn compare(&self, number: u64) -> bool {
+        let value: u64 = self.value.parse().unwrap_or(0);
+        match self.op {
+            Op::Eq => number == value,
+            Op::Ne => number != value,
+            Op::Gt => number > value,
+            Op::Ge => number >= value,
+            Op::Lt => number < value,
+            Op::Le => number <= value,
+            Op::Contains => false,
+        }
+    }
+
+    /// Compares a time with a date as the whole day, `>` being after it,
+    /// or with an RFC 3339 time.
+    fn time(&self, time: Option<DateTime<Utc>>) -> bool {
+        let (time, (start, end)) = match (time, period(&self.value)) {
+            (Some(time), Ok(period)) => (time, period),
+            _ => return self.op == Op::Ne,
+        };
+        match self.op {
+            Op::Eq => start <= time && time < end,
+            Op::Ne => time < start || end <= time,
+            Op::Gt => end <= time,
+            Op::Ge => start <= time,
+            Op::Lt => time < start,
+            Op::Le => time < end,
+            Op::Contains => false,
+        }
+    }
+}
+
+/// Start and end of a date or a time to the second.
+fn period(value: &str) -> Result<(DateTime<Utc>, DateTime<Utc>), String> {
+    if let Ok(date) = NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+        let start = Utc.from_utc_datetime(&date.and_hms_opt(0, 0, 0).unwrap());
+        return Ok((start, start + Duration::days(1)));
+    }
+    match DateTime::parse_from_rfc3339(value) {
+        Ok(time) => {
+            let time = time.with_timezone(&Utc);
+            Ok((time, time + Duration::seconds(1)))
+        }
+        Err(_) => Err(format!("{} is neither a date nor an RFC 3339 time", value)),
+    }
+}
+
+/// Conditions joined by `AND` and `OR`, `AND` binding tighter, e.g.
+/// `state=open AND label=bug AND updated>2024-01-01`. Values with spaces
+/// are quoted, e.g. `title~"out of memory"`.
+#[derive(Debug)]
+pub struct Query {
+    /// Groups of conditions, an item matches if it meets all of any group.
+    pub any: Vec<Vec<Condition>>,
+}
+
+impl FromStr for Query {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut any = vec![Vec::new()];
+        let mut clause: Vec<String> = Vec::new();
+        let mut words = words(s)?;
+        // a trailing keyword ends the last clause
+        words.push("AND".to_string());
+        for word in words {
+            let keyword = word.to_uppercase();
+            if keyword != "AND" && keyword != "OR" {
+                clause.push(word);
+                continue;
+            }
+            if clause.is_empty() {
+                return Err(format!("{} without a condition before it", keyword));
+            }
+            any.last_mut().unwrap().push(clause.join(" ").parse()?);
+            clause.clear();
+            if keyword == "OR" {
+                any.push(Vec::new());
+            }
+        }
+        if any.iter().any(Vec::is_empty) {
+            return Err("OR without a condition after it".to_string());
+        }
+        Ok(Query { any: any })
+    }
+}
+
+impl Query {
+    pub fn matches(&self, item: &Item) -> bool {
+        self.any.iter().any(|all| all.iter().all(|condition| condition.matches(item)))
+    }
+}
+
+/// Words of a query split at whitespace, except within double quotes.
+fn words(s: &str) -> Result<Vec<String>, String> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let mut quoted = false;
+    for c in s.chars() {
+        match c {
+            '"' => {
+                quoted = !quoted;
+                word.push(c);
+            }
+            c if c.is_whitespace() && !quoted => if !word.is_empty() {
+                words.push(word.clone());
+                word.clear();
+            },
+            c => word.push(c),
+        }
+    }
+    if quoted {
+        return Err("unterminated quote".to_string());
+    }
+    if !word.is_empty() {
+        words.push(word);
+    }
+    Ok(words)
+}
+
+/// Prints the issues and pull requests of the snapshots below `cfg.dir`
+/// that match the query.
+pub fn query(cfg: QueryConfig) -> Result<(), Box<dyn error::Error>> {
+    let repos = snapshot::repositories(&cfg.dir)?;
+    if repos.is_empty() {
+        return Err(format!("no snapshots in {}", cfg.dir.display()).into());
+    }
+    let mut found = 0;
+    for (repo, dir) in &repos {
+        // the snapshots of the remaining repositories aren't read
+        if cfg.limit.map_or(false, |limit| found >= limit) {
+            break;
+        }
+        let mut matching: Vec<Value> = Vec::new();
+        for entity in Entity::ALL.iter().filter(|entity| cfg.entity.map_or(true, |e| e == **entity)) {
+            if let Some((path, format)) = snapshot::find(dir, *entity) {
+                let pull = *entity == Entity::Pulls;
+                matching.extend(
+                    snapshot::records(&path, format, *entity)?
+                        .into_iter()
+                        .filter(|record| Item::of(record, pull).map_or(false, |item| cfg.query.matches(&item))),
+                );
+            }
+        }
+        if let Some(limit) = cfg.limit {
+            matching.truncate(limit.saturating_sub(found));
+        }
+        found += matching.len();
+        if matching.is_empty() {
+            continue;
+        }
+        if cfg.ndjson {
+            for record in &matching {
+                println!("{}", serde_json::to_string(record)?);
+            }
+        } else {
+            if repos.len() > 1 {
+                println!("{}:", repo);
+            }
+            show::print_table(&matching.iter().collect::<Vec<_>>());
+        }
+    }
+    if !cfg.ndjson {
+        println!("{} matching", found);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Utc};
+
+    use super::{Condition, Field, Op, Query};
+    use crate::snapshot::Item;
+
+    fn item() -> Item {
+        Item {
+            number: 42,
+            pull: false,
+            title: "Out of memory on start".to_string(),
+            state: "open".to_string(),
+            author: Some("octocat".to_string()),
+            labels: vec!["bug".to_string(), "help wanted".to_string()],
+            created_at: Some(Utc.with_ymd_and_hms(2024, 3, 1, 12, 0, 0).unwrap()),
+            updated_at: Some(Utc.with_ymd_and_hms(2024, 3, 5, 8, 30, 0).unwrap()),
+            closed_at: None,
+            merged_at: None,
+            comments: 3,
+        }
+    }
+
+    fn matches(query: &str, item: &Item) -> bool {
+        query.parse::<Query>().unwrap().matches(item)
+    }
+
+    #[test]
+    fn conditions_are_parsed() {
+        let condition: Condition = "updated >= 2024-01-01".parse().unwrap();
+        assert_eq!((condition.field, condition.op, &condition.value[..]), (Field::Updated, Op::Ge, "2024-01-01"));
+        let condition: Condition = "title~\"out of\"".parse().unwrap();
+        assert_eq!((condition.field, condition.op, &condition.value[..]), (Field::Title, Op::Contains, "out of"));
+        let condition: Condition = "is!=pull".parse().unwrap();
+        assert_eq!((condition.field, condition.op), (Field::Type, Op::Ne));
+    }
+
+    #[test]
+    fn invalid_conditions_are_rejected() {
+        for condition in &["label", "milestone=1", "label>bug", "number~4", "comments=many", "created>yesterday"] {
+            assert!(condition.parse::<Condition>().is_err(), "{} was accepted", condition);
+        }
+    }
+
+    #[test]
+    fn and_binds_tighter_than_or() {
+        let query: Query = "state=closed AND label=bug OR number=42".parse().unwrap();
+        assert_eq!(query.any.len(), 2);
+        assert_eq!(query.any[0].len(), 2);
+        assert!(query.matches(&item()));
+        assert!(!matches("state=closed AND label=bug OR number=41", &item()));
+        assert!(matches("state=open and label=bug", &item()));
+    }
+
+    #[test]
+    fn keywords_need_conditions_on_both_sides() {
+        assert!("AND state=open".parse::<Query>().is_err());
+        assert!("state=open OR".parse::<Query>().is_err());
+        assert!("state=open OR AND label=bug".parse::<Query>().is_err());
+    }
+
+    #[test]
+    fn quoted_values_keep_their_spaces() {
+        assert!(matches("title~\"out of memory\"", &item()));
+        assert!(matches("label=\"help wanted\"", &item()));
+        assert!(!matches("title~\"out of disk\"", &item()));
+        assert!("title~\"out of memory".parse::<Query>().is_err());
+    }
+
+    #[test]
+    fn dates_compare_as_whole_days() {
+        assert!(matches("created=2024-03-01", &item()));
+        assert!(matches("created>=2024-03-01", &item()));
+        assert!(matches("created<=2024-03-01", &item()));
+        assert!(!matches("created>2024-03-01", &item()));
+        assert!(matches("created<2024-03-02", &item()));
+        assert!(matches("updated>2024-03-05T08:00:00Z", &item()));
+        // never closed
+        assert!(!matches("closed<2030-01-01", &item()));
+        assert!(matches("closed!=2024-03-01", &item()));
+    }
+
+    #[test]
+    fn merged_pull_requests_are_closed() {
+        let mut pull = item();
+        pull.pull = true;
+        pull.state = "merged".to_string();
+        assert!(matches("is=pull AND state=closed", &pull));
+        assert!(matches("state=merged", &pull));
+        assert!(!matches("state=open", &pull));
+    }
+
+    #[test]
+    fn missing_labels_and_authors_match_negations() {
+        let mut item = item();
+        item.author = None;
+        assert!(matches("label!=wontfix AND author!=octocat", &item));
+        assert!(!matches("author=octocat", &item));
+        assert!(matches("label~help", &item));
+    }
+}
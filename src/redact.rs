@@ -0,0 +1,81 @@
+use regex::Regex;
+use serde_json::Value;
+
+/// Fields holding the text of issues, pull requests, comments and reviews,
+/// in the REST and the GraphQL records.
+const BODY_FIELDS: &[&str] = &["body", "bodyText"];
+
+/// Email addresses, also replaced by the anonymization.
+pub const EMAIL: &str = r"(?i)\b[a-z0-9._%+-]+@[a-z0-9-]+(?:\.[a-z0-9-]+)*\.[a-z]{2,}\b";
+
+/// Patterns of secrets and what to replace them with, the more specific
+/// ones first as they would otherwise be masked in part by later ones.
+const PATTERNS: &[(&str, &str)] = &[
+    (
+        r"-----BEGIN [A-Z ]*PRIVATE KEY-----[\s\S]*?-----END [A-Z ]*PRIVATE KEY-----",
+        "[redacted private key]",
+    ),
+    (r"\b(?:gh[pousr]_[A-Za-z0-9]{36,}|github_pat_[A-Za-z0-9_]{22,})\b", "[redacted token]"),
+    (r"\bxox[abposr]-[A-Za-z0-9-]{10,}\b", "[redacted token]"),
+    (r"\b(?:AKIA|ASIA)[0-9A-Z]{16}\b", "[redacted key]"),
+    (r"\bAIza[0-9A-Za-z_-]{35}\b", "[redacted key]"),
+    (r"\beyJ[A-Za-z0-9_-]{8,}\.[A-Za-z0-9_-]{8,}\.[A-Za-z0-9_-]{8,}\b", "[redacted token]"),
+    (r"(?i)\b(bearer|token)(\s+)[A-Za-z0-9._~+/-]{20,}=*", "$1$2[redacted token]"),
+    (
+        concat!(
+            r"(?i)\b(password|passwd|secret|api[_-]?key|access[_-]?token|auth[_-]?token|client[_-]?secret)",
+            r#"(["']?\s*[:=]\s*)["']?[^\s"'`]{6,}["']?"#,
+        ),
+        "$1$2[redacted secret]",
+    ),
+    (EMAIL, "[redacted email]"),
+];
+
+/// Masks email addresses, tokens and other secrets in the bodies of records.
+pub struct Redactor {
+    patterns: Vec<(Regex, &'static str)>,
+}
+
+impl Redactor {
+    pub fn new() -> Self {
+        Redactor {
+            patterns: PATTERNS
+                .iter()
+                .map(|&(pattern, replacement)| (Regex::new(pattern).unwrap(), replacement))
+                .collect(),
+        }
+    }
+
+    /// `text` with its secrets masked.
+    pub fn redact(&self, text: &str) -> String {
+        let mut text = text.to_string();
+        for (pattern, replacement) in &self.patterns {
+            text = pattern.replace_all(&text, *replacement).into_owned();
+        }
+        text
+    }
+
+    /// Masks the secrets in a body, if there is one.
+    pub fn redact_body(&self, body: &mut Option<String>) {
+        if let Some(ref mut text) = *body {
+            *text = self.redact(text);
+        }
+    }
+
+    /// Masks the secrets in the bodies of a record and the records nested in
+    /// it, such as comments.
+    pub fn redact_value(&self, value: &mut Value) {
+        match *value {
+            Value::Object(ref mut fields) => for (key, field) in fields.iter_mut() {
+                match *field {
+                    Value::String(ref mut text) if BODY_FIELDS.contains(&&key[..]) => *text = self.redact(text),
+                    ref mut field => self.redact_value(field),
+                }
+            },
+            Value::Array(ref mut items) => for item in items {
+                self.redact_value(item);
+            },
+            _ => {}
+        }
+    }
+}
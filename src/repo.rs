@@ -0,0 +1,160 @@
+use std::error;
+use std::fmt;
+use std::io::BufRead;
+use std::path::{Path, PathBuf};
+
+use futures::TryStreamExt;
+use glob::Pattern;
+use hyper::StatusCode;
+
+use crate::client::{Client, Error};
+
+/// A repository to fetch.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Repo {
+    pub owner: String,
+    pub name: String,
+}
+
+impl Repo {
+    /// Parses `owner/name`, or a bare name belonging to `default_owner`.
+    pub fn parse(value: &str, default_owner: Option<&str>) -> Result<Self, String> {
+        let mut parts = value.splitn(2, '/');
+        match (parts.next(), parts.next(), default_owner) {
+            (Some(owner), Some(name), _) if !owner.is_empty() && !name.is_empty() && !name.contains('/') => Ok(Repo {
+                owner: owner.to_string(),
+                name: name.to_string(),
+            }),
+            (Some(name), None, Some(owner)) if !name.is_empty() => Ok(Repo {
+                owner: owner.to_string(),
+                name: name.to_string(),
+            }),
+            (_, None, None) => Err(format!("repository {} needs an --owner or the owner/name form", value)),
+            _ => Err(format!("invalid repository {}", value)),
+        }
+    }
+
+    /// Directory of the repository's snapshots below an output directory.
+    pub fn dir(&self, base: &Path) -> PathBuf {
+        base.join(&self.owner).join(&self.name)
+    }
+}
+
+impl fmt::Display for Repo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}/{}", self.owner, self.name)
+    }
+}
+
+/// Reads one repository per line, skipping blank lines and `#` comments.
+pub fn read_list<R: BufRead>(reader: R, default_owner: Option<&str>) -> Result<Vec<Repo>, Box<dyn error::Error>> {
+    let mut repos = Vec::new();
+    for line in reader.lines() {
+        let line = line?;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        repos.push(Repo::parse(line, default_owner)?);
+    }
+    Ok(repos)
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Owner {
+    pub login: String,
+}
+
+/// A repository as returned by the repository listings.
+#[derive(Debug, Deserialize)]
+pub struct RepoInfo {
+    pub name: String,
+    pub owner: Owner,
+    #[serde(default)]
+    pub fork: bool,
+    #[serde(default)]
+    pub archived: bool,
+    #[serde(default)]
+    pub stargazers_count: u64,
+}
+
+impl RepoInfo {
+    pub fn repo(&self) -> Repo {
+        Repo {
+            owner: self.owner.login.clone(),
+            name: self.name.clone(),
+        }
+    }
+}
+
+/// Selection among listed repositories by name and kind.
+#[derive(Debug, Default)]
+pub struct RepoFilter {
+    /// Name patterns of which one has to match, any name if empty.
+    pub include: Vec<Pattern>,
+    pub exclude: Vec<Pattern>,
+    pub skip_forks: bool,
+    pub skip_archived: bool,
+    pub min_stars: u64,
+}
+
+impl RepoFilter {
+    pub fn matches(&self, info: &RepoInfo) -> bool {
+        (self.include.is_empty() || self.include.iter().any(|p| p.matches(&info.name)))
+            && !self.exclude.iter().any(|p| p.matches(&info.name))
+            && !(self.skip_forks && info.fork)
+            && !(self.skip_archived && info.archived)
+            && info.stargazers_count >= self.min_stars
+    }
+}
+
+/// All repositories of an organization the token can see.
+pub async fn org_repos(client: &Client, org: &str) -> Result<Vec<RepoInfo>, Error> {
+    client
+        .get_pages::<RepoInfo>(&format!("/orgs/{}/repos?type=all&per_page=100", org))
+        .try_collect()
+        .await
+}
+
+#[derive(Deserialize)]
+struct Account {
+    #[serde(rename = "type")]
+    kind: String,
+}
+
+/// Repositories of `owner`, an organization or a user account.
+pub async fn owner_repos(client: &Client, owner: &str) -> Result<Vec<RepoInfo>, Error> {
+    let account = client.get::<Account>(&format!("/users/{}", owner)).await?;
+    match &account.kind[..] {
+        "Organization" => org_repos(client, owner).await,
+        _ => user_repos(client, owner).await,
+    }
+}
+
+/// Repositories owned by a user account, the private ones too if the token
+/// belongs to it.
+pub async fn user_repos(client: &Client, login: &str) -> Result<Vec<RepoInfo>, Error> {
+    let path = if authenticated_as(client, login).await? {
+        "/user/repos?affiliation=owner&per_page=100".to_string()
+    } else {
+        format!("/users/{}/repos?type=owner&per_page=100", login)
+    };
+    client.get_pages::<RepoInfo>(&path).try_collect().await
+}
+
+#[derive(Deserialize)]
+struct Login {
+    login: String,
+}
+
+/// Whether the token belongs to the user `login`, anonymous requests and
+/// GitHub App installations have no user.
+async fn authenticated_as(client: &Client, login: &str) -> Result<bool, Error> {
+    match client.get::<Login>("/user").await {
+        Ok(user) => Ok(user.login.eq_ignore_ascii_case(login)),
+        Err(Error::Status { status, .. }) if status == StatusCode::UNAUTHORIZED || status == StatusCode::FORBIDDEN => {
+            Ok(false)
+        }
+        Err(e) => Err(e),
+    }
+}
@@ -0,0 +1,128 @@
+use std::collections::BTreeMap;
+use std::error;
+use std::fs;
+use std::io::BufWriter;
+use std::path::Path;
+use std::time::Instant;
+
+use crate::exit::ExitCode;
+
+/// Summary of a run, kept next to the snapshots for orchestration to check.
+const REPORT_FILE: &str = "report.json";
+/// Items of the run that could not be fetched, only kept if there are any.
+pub const FAILURES_FILE: &str = "failures.json";
+
+/// Summary of a fetch run.
+#[derive(Debug, Default, Serialize)]
+pub struct Report {
+    pub started: String,
+    pub finished: String,
+    pub duration_secs: u64,
+    /// Requests sent to the API, including conditional ones.
+    pub requests: usize,
+    /// Responses served from the ETag cache.
+    pub cache_hits: usize,
+    pub bytes_written: u64,
+    pub repositories: Vec<RepoReport>,
+    pub failed: Vec<Failure>,
+    /// Repositories not fetched since the request budget ran out.
+    pub unfinished: Vec<String>,
+}
+
+/// What was fetched for one repository.
+#[derive(Debug, Default, Serialize)]
+pub struct RepoReport {
+    pub repository: String,
+    /// Records written per entity.
+    pub records: BTreeMap<String, usize>,
+    pub bytes_written: u64,
+    /// Seconds spent per phase.
+    pub phases: BTreeMap<String, f64>,
+    /// Items skipped since fetching them failed.
+    pub failed_items: Vec<ItemFailure>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Failure {
+    pub repository: String,
+    pub error: String,
+    /// Cause of the failure, as the exit status names it.
+    pub kind: ExitCode,
+}
+
+/// An issue or pull request that could not be fetched while the rest of its
+/// repository was.
+#[derive(Debug, Serialize)]
+pub struct ItemFailure {
+    pub repository: String,
+    pub entity: String,
+    pub number: u64,
+    pub error: String,
+    /// Short name of the error, e.g. `not_found` or `internal_server_error`.
+    pub kind: String,
+    /// Deleted, transferred or blocked for legal reasons, fetching the item
+    /// again won't help.
+    pub gone: bool,
+}
+
+impl Report {
+    /// Adds the repositories of a worker's report, the request counts are
+    /// taken from the client the workers share.
+    pub fn merge(&mut self, other: Report) {
+        self.bytes_written += other.repositories.iter().map(|r| r.bytes_written).sum::<u64>();
+        self.repositories.extend(other.repositories);
+        self.failed.extend(other.failed);
+    }
+
+    /// Items of all repositories that could not be fetched.
+    pub fn failed_items(&self) -> Vec<&ItemFailure> {
+        self.repositories.iter().flat_map(|r| &r.failed_items).collect()
+    }
+
+    /// Number of items that could not be fetched by the kind of error.
+    pub fn failed_item_kinds(&self) -> BTreeMap<&str, usize> {
+        let mut kinds = BTreeMap::new();
+        for item in self.failed_items() {
+            *kinds.entry(&item.kind[..]).or_insert(0) += 1;
+        }
+        kinds
+    }
+
+    /// Writes the report to the top of the output directory `dir`, along with
+    /// the failed items to re-fetch. Those of an earlier run are removed.
+    pub fn write(&self, dir: &Path) -> Result<(), Box<dyn error::Error>> {
+        let file = BufWriter::new(fs::File::create(dir.join(REPORT_FILE))?);
+        serde_json::to_writer_pretty(file, self)?;
+
+        let failures = self.failed_items();
+        let path = dir.join(FAILURES_FILE);
+        if !failures.is_empty() {
+            serde_json::to_writer_pretty(BufWriter::new(fs::File::create(&path)?), &failures)?;
+        } else if path.exists() {
+            fs::remove_file(&path)?;
+        }
+        Ok(())
+    }
+}
+
+impl RepoReport {
+    pub fn new(repository: String) -> Self {
+        RepoReport {
+            repository: repository,
+            ..RepoReport::default()
+        }
+    }
+
+    /// Records the time since `started` for `phase`.
+    pub fn phase(&mut self, phase: &str, started: Instant) {
+        let elapsed = started.elapsed();
+        let secs = elapsed.as_secs() as f64 + f64::from(elapsed.subsec_millis()) / 1000.0;
+        *self.phases.entry(phase.to_string()).or_insert(0.0) += secs;
+    }
+
+    /// Records `count` records of `entity` written in `bytes`, if known.
+    pub fn written(&mut self, entity: &str, count: usize, bytes: Option<u64>) {
+        *self.records.entry(entity.to_string()).or_insert(0) += count;
+        self.bytes_written += bytes.unwrap_or(0);
+    }
+}
@@ -0,0 +1,46 @@
+use std::time::Duration;
+
+use rand::{self, Rng};
+
+/// Longest pause between two attempts, however many have failed.
+const MAX_DELAY_MS: u64 = 60_000;
+
+/// How often and how patiently transient request failures are retried.
+///
+/// The delay before attempt `n + 1` is `base_delay * 2^(n - 1)`, capped at one
+/// minute, with up to `jitter` of it taken off at random so that concurrent
+/// requests failing together don't retry in lockstep.
+#[derive(Clone, Debug)]
+pub struct RetryPolicy {
+    /// Total attempts per request, including the first one.
+    pub max_attempts: u32,
+    pub base_delay: Duration,
+    /// Fraction of the delay, between 0 and 1, that is randomized.
+    pub jitter: f64,
+    /// Wait for an exhausted rate limit to reset rather than failing.
+    pub wait_for_rate_limit: bool,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: 5,
+            base_delay: Duration::from_millis(500),
+            jitter: 0.5,
+            wait_for_rate_limit: true,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Delay before retrying after `attempt` attempts have failed.
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let base = self.base_delay.as_secs() * 1000 + u64::from(self.base_delay.subsec_millis());
+        let backoff = 1u64
+            .checked_shl(attempt.saturating_sub(1))
+            .and_then(|factor| base.checked_mul(factor))
+            .map_or(MAX_DELAY_MS, |delay| delay.min(MAX_DELAY_MS));
+        let jitter = (backoff as f64 * self.jitter * rand::thread_rng().gen::<f64>()) as u64;
+        Duration::from_millis(backoff - jitter)
+    }
+}
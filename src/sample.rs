@@ -0,0 +1,56 @@
+use futures::stream::{self, BoxStream, StreamExt};
+use hyper::StatusCode;
+use rand::{self, Rng};
+
+use crate::api::Issue;
+use crate::client::{Client, Error};
+use crate::repo::Repo;
+
+/// Number of the most recently created issue or pull request, `None` for a
+/// repository without any.
+pub async fn latest_number(client: &Client, repo: &Repo) -> Result<Option<u64>, Error> {
+    let issues = client
+        .get::<Vec<Issue>>(&format!(
+            "/repos/{}/issues?state=all&sort=created&direction=desc&per_page=1",
+            repo
+        ))
+        .await?;
+    Ok(issues.first().map(|i| i.number))
+}
+
+/// The numbers up to `latest` in random order, issues and pull requests share
+/// one sequence.
+pub fn candidates(latest: u64) -> Vec<u64> {
+    let mut numbers: Vec<u64> = (1..latest + 1).collect();
+    rand::thread_rng().shuffle(&mut numbers);
+    numbers
+}
+
+/// Statuses of numbers without an issue or pull request in the repository.
+const GONE: &[StatusCode] = &[StatusCode::MOVED_PERMANENTLY, StatusCode::NOT_FOUND, StatusCode::GONE];
+
+/// Fetches the issue or pull request behind each of `numbers`, `None` for
+/// numbers that were deleted, transferred or belong to discussions.
+pub fn probe(
+    client: &Client,
+    repo: &Repo,
+    numbers: Vec<u64>,
+    concurrency: usize,
+) -> BoxStream<'static, Result<Option<Issue>, Error>> {
+    let client = client.clone();
+    let repo = repo.to_string();
+    stream::iter(numbers)
+        .map(move |n| {
+            let (client, path) = (client.clone(), format!("/repos/{}/issues/{}", repo, n));
+            async move {
+                match client.get::<Issue>(&path).await {
+                    Ok(issue) => Ok(Some(issue)),
+                    // transferred issues redirect to their new repository
+                    Err(Error::Status { status, .. }) if GONE.contains(&status) => Ok(None),
+                    Err(e) => Err(e),
+                }
+            }
+        })
+        .buffer_unordered(concurrency)
+        .boxed()
+}
@@ -0,0 +1,373 @@
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::error;
+use std::fs;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+use hyper::header::{CONTENT_TYPE, LINK};
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response, Server, StatusCode};
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::entity::Entity;
+use crate::filter::encode;
+use crate::format::Format;
+use crate::query::{Condition, Query};
+use crate::snapshot::{self, Item};
+
+/// Records per page unless `per_page` asks for another number.
+const PER_PAGE: usize = 30;
+const MAX_PER_PAGE: usize = 100;
+
+#[derive(Debug)]
+pub struct ServeConfig {
+    pub addr: SocketAddr,
+    /// Output directory of a fetch, or the directory of one repository.
+    pub dir: PathBuf,
+}
+
+/// Serves the snapshots below `cfg.dir` read-only over HTTP as JSON, read
+/// again once their files change so that updates by fetches show up.
+///
+/// `/{owner}/{name}/issues` and `/{owner}/{name}/pulls` list the records of
+/// a repository a page at a time, filtered by the `q` query and the `state`,
+/// `label`, `author` and `since` parameters, `/{owner}/{name}/issues/{number}`
+/// and `/{owner}/{name}/pulls/{number}` return one of them. The owner and
+/// name may be left out when serving a single repository.
+pub async fn serve(cfg: ServeConfig) -> Result<(), Box<dyn error::Error>> {
+    if snapshot::repositories(&cfg.dir)?.is_empty() {
+        return Err(format!("no snapshots in {}", cfg.dir.display()).into());
+    }
+    let state = Arc::new(State {
+        dir: cfg.dir,
+        cache: Cache::default(),
+    });
+    let server = Server::try_bind(&cfg.addr)?.serve(make_service_fn(move |_| {
+        let state = state.clone();
+        async move { Ok::<_, Infallible>(service_fn(move |req| handle(state.clone(), req))) }
+    }));
+    info!("Serving snapshots on http://{}", cfg.addr);
+    if let Err(e) = server.await {
+        error!("Server error: {}", e);
+    }
+    Ok(())
+}
+
+/// What the requests are answered from.
+struct State {
+    dir: PathBuf,
+    cache: Cache,
+}
+
+/// Records of the snapshots read so far by path, along with the time the
+/// snapshot was modified when they were read.
+#[derive(Default)]
+struct Cache {
+    records: Mutex<HashMap<PathBuf, (SystemTime, Arc<Vec<Value>>)>>,
+}
+
+impl Cache {
+    /// Records of the snapshot of `entity` at `path`, read again only once
+    /// the file was modified.
+    fn records(&self, path: &Path, format: Format, entity: Entity) -> Result<Arc<Vec<Value>>, Box<dyn error::Error>> {
+        let modified = fs::metadata(path)?.modified()?;
+        if let Some(&(read, ref records)) = self.records.lock().unwrap().get(path) {
+            if read == modified {
+                return Ok(records.clone());
+            }
+        }
+        let records = Arc::new(snapshot::records(path, format, entity)?);
+        self.records
+            .lock()
+            .unwrap()
+            .insert(path.to_path_buf(), (modified, records.clone()));
+        Ok(records)
+    }
+}
+
+async fn handle(state: Arc<State>, req: Request<Body>) -> Result<Response<Body>, Infallible> {
+    if req.method() != Method::GET {
+        return Ok(message(StatusCode::METHOD_NOT_ALLOWED, "Only GET is supported"));
+    }
+    let path = req.uri().path().to_string();
+    let params = params(req.uri().query().unwrap_or(""));
+    // reading and filtering snapshots blocks
+    let routed = {
+        let path = path.clone();
+        tokio::task::spawn_blocking(move || route(&state, &path, &params).map_err(|e| e.to_string())).await
+    };
+    let response = match routed {
+        Ok(Ok(response)) => response,
+        Ok(Err(e)) => {
+            error!("failed to serve {}: {}", path, e);
+            message(StatusCode::INTERNAL_SERVER_ERROR, "Snapshots can't be read")
+        }
+        Err(e) => {
+            error!("failed to serve {}: {}", path, e);
+            message(StatusCode::INTERNAL_SERVER_ERROR, "Internal Server Error")
+        }
+    };
+    debug!("GET {} {}", req.uri(), response.status());
+    Ok(response)
+}
+
+fn route(state: &State, path: &str, params: &[(String, String)]) -> Result<Response<Body>, Box<dyn error::Error>> {
+    let repos = snapshot::repositories(&state.dir)?;
+    let segments: Vec<&str> = path.split('/').filter(|segment| !segment.is_empty()).collect();
+    if segments.is_empty() {
+        let names: Vec<String> = repos.iter().map(|(repo, _)| repo.to_string()).collect();
+        return json(StatusCode::OK, &names);
+    }
+    let named = if segments.len() > 2 {
+        let name = format!("{}/{}", segments[0], segments[1]);
+        repos.iter().find(|(repo, _)| repo.to_string() == name)
+    } else {
+        None
+    };
+    // the only repository may be left out of the path
+    let (repo_dir, rest) = match named {
+        Some((_, repo_dir)) => (repo_dir, &segments[2..]),
+        None if repos.len() == 1 => (&repos[0].1, &segments[..]),
+        None => return Ok(message(StatusCode::NOT_FOUND, "Not Found")),
+    };
+    let entity: Entity = match rest.first().map(|entity| entity.parse()) {
+        Some(Ok(entity)) => entity,
+        _ => return Ok(message(StatusCode::NOT_FOUND, "Not Found")),
+    };
+    let records = match snapshot::find(repo_dir, entity) {
+        Some((path, format)) => state.cache.records(&path, format, entity)?,
+        None => Arc::new(Vec::new()),
+    };
+    match rest.len() {
+        1 => list(path, params, &records, entity),
+        2 => {
+            let number: Option<u64> = rest[1].parse().ok();
+            match records.iter().find(|record| record.get("number").and_then(Value::as_u64) == number) {
+                Some(record) => json(StatusCode::OK, record),
+                None => Ok(message(StatusCode::NOT_FOUND, "Not Found")),
+            }
+        }
+        _ => Ok(message(StatusCode::NOT_FOUND, "Not Found")),
+    }
+}
+
+/// A page of the `records` matching the filters of `params`, with the total
+/// in `X-Total-Count` and the other pages in `Link` as GitHub does.
+fn list(
+    path: &str,
+    params: &[(String, String)],
+    records: &[Value],
+    entity: Entity,
+) -> Result<Response<Body>, Box<dyn error::Error>> {
+    let param = |name: &str| params.iter().find(|(key, _)| key == name).map(|(_, value)| &value[..]);
+    let mut query = match param("q").map(str::parse::<Query>) {
+        Some(Ok(query)) => query,
+        Some(Err(e)) => return Ok(message(StatusCode::UNPROCESSABLE_ENTITY, &e)),
+        None => Query { any: vec![Vec::new()] },
+    };
+    let shorthands = [("state", "state="), ("label", "label="), ("author", "author="), ("since", "updated>=")];
+    for &(name, condition) in &shorthands {
+        if let Some(value) = param(name) {
+            let condition: Condition = match format!("{}\"{}\"", condition, value).parse() {
+                Ok(condition) => condition,
+                Err(e) => return Ok(message(StatusCode::UNPROCESSABLE_ENTITY, &e)),
+            };
+            for all in &mut query.any {
+                all.push(condition.clone());
+            }
+        }
+    }
+    let page: usize = param("page").and_then(|page| page.parse().ok()).filter(|&page| page > 0).unwrap_or(1);
+    let per_page = param("per_page")
+        .and_then(|per_page| per_page.parse().ok())
+        .filter(|&per_page| per_page > 0)
+        .map_or(PER_PAGE, |per_page: usize| per_page.min(MAX_PER_PAGE));
+
+    let pull = entity == Entity::Pulls;
+    let matching: Vec<&Value> = records
+        .iter()
+        .filter(|record| Item::of(record, pull).map_or(false, |item| query.matches(&item)))
+        .collect();
+    let last = ((matching.len() + per_page - 1) / per_page).max(1);
+    let skipped = match (page - 1).checked_mul(per_page) {
+        Some(skipped) => skipped,
+        None => return Ok(message(StatusCode::UNPROCESSABLE_ENTITY, "page is out of range")),
+    };
+    let shown: Vec<&Value> = matching.iter().cloned().skip(skipped).take(per_page).collect();
+
+    let link = |page: usize, rel: &str| {
+        let mut query: Vec<String> = params
+            .iter()
+            .filter(|(key, _)| key != "page")
+            .map(|(key, value)| format!("{}={}", encode(key), encode(value)))
+            .collect();
+        query.push(format!("page={}", page));
+        format!("<{}?{}>; rel=\"{}\"", path, query.join("&"), rel)
+    };
+    let mut links = Vec::new();
+    if page < last {
+        links.push(link(page + 1, "next"));
+        links.push(link(last, "last"));
+    }
+    if page > 1 {
+        links.push(link(1, "first"));
+        links.push(link(page - 1, "prev"));
+    }
+    let mut response = json(StatusCode::OK, &shown)?;
+    response.headers_mut().insert("x-total-count", matching.len().into());
+    if !links.is_empty() {
+        response.headers_mut().insert(LINK, links.join(", ").parse()?);
+    }
+    Ok(response)
+}
+
+fn json<T: Serialize + ?Sized>(status: StatusCode, body: &T) -> Result<Response<Body>, Box<dyn error::Error>> {
+    Ok(Response::builder()
+        .status(status)
+        .header(CONTENT_TYPE, "application/json")
+        .body(Body::from(serde_json::to_vec(body)?))?)
+}
+
+/// An error response with a body like those of the GitHub API.
+fn message(status: StatusCode, message: &str) -> Response<Body> {
+    let mut response = Response::new(Body::from(json!({ "message": message }).to_string()));
+    *response.status_mut() = status;
+    response
+        .headers_mut()
+        .insert(CONTENT_TYPE, "application/json".parse().unwrap());
+    response
+}
+
+/// Decoded parameters of a URL query.
+fn params(query: &str) -> Vec<(String, String)> {
+    query
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let mut parts = pair.splitn(2, '=');
+            let key = decode(parts.next().unwrap_or(""));
+            (key, decode(parts.next().unwrap_or("")))
+        })
+        .collect()
+}
+
+fn decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = text.get(i + 1..i + 3).and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+                continue;
+            }
+            (b'+', _) => decoded.push(b' '),
+            (byte, _) => decoded.push(byte),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use hyper::header::LINK;
+    use hyper::{Body, Response, StatusCode};
+    use serde_json::Value;
+
+    use super::{decode, list, params};
+    use crate::entity::Entity;
+
+    fn records() -> Vec<Value> {
+        (1..=5)
+            .map(|number| {
+                let state = if number == 3 { "closed" } else { "open" };
+                json!({ "number": number, "title": format!("#{}", number), "state": state })
+            })
+            .collect()
+    }
+
+    fn page(query: &str) -> Response<Body> {
+        list("/o/r/issues", &params(query), &records(), Entity::Issues).unwrap()
+    }
+
+    async fn numbers(response: Response<Body>) -> Vec<u64> {
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let records: Vec<Value> = serde_json::from_slice(&body).unwrap();
+        records.iter().filter_map(|record| record["number"].as_u64()).collect()
+    }
+
+    fn header<'a>(response: &'a Response<Body>, name: &str) -> Option<&'a str> {
+        response.headers().get(name).and_then(|value| value.to_str().ok())
+    }
+
+    #[test]
+    fn params_are_decoded() {
+        assert_eq!(
+            params("q=title%7E%22out+of%22&state=open&&flag"),
+            vec![
+                ("q".to_string(), "title~\"out of\"".to_string()),
+                ("state".to_string(), "open".to_string()),
+                ("flag".to_string(), String::new()),
+            ]
+        );
+    }
+
+    #[test]
+    fn malformed_escapes_are_kept() {
+        assert_eq!(decode("100%"), "100%");
+        assert_eq!(decode("%zz%41"), "%zzA");
+        assert_eq!(decode("%C3%A9"), "é");
+    }
+
+    #[tokio::test]
+    async fn pages_link_to_the_others() {
+        let response = page("per_page=2&page=2");
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(header(&response, "x-total-count"), Some("5"));
+        assert_eq!(
+            header(&response, LINK.as_str()),
+            Some(
+                "</o/r/issues?per_page=2&page=3>; rel=\"next\", </o/r/issues?per_page=2&page=3>; rel=\"last\", \
+                 </o/r/issues?per_page=2&page=1>; rel=\"first\", </o/r/issues?per_page=2&page=1>; rel=\"prev\""
+            )
+        );
+        assert_eq!(numbers(response).await, vec![3, 4]);
+    }
+
+    #[tokio::test]
+    async fn filters_apply_before_paging() {
+        let response = page("state=open&per_page=3");
+        assert_eq!(header(&response, "x-total-count"), Some("4"));
+        assert_eq!(
+            header(&response, LINK.as_str()),
+            Some(
+                "</o/r/issues?state=open&per_page=3&page=2>; rel=\"next\", \
+                 </o/r/issues?state=open&per_page=3&page=2>; rel=\"last\""
+            )
+        );
+        assert_eq!(numbers(response).await, vec![1, 2, 4]);
+        assert_eq!(numbers(page("q=number%3E4")).await, vec![5]);
+    }
+
+    #[tokio::test]
+    async fn pages_past_the_last_are_empty() {
+        let response = page("page=3");
+        assert_eq!(header(&response, "x-total-count"), Some("5"));
+        assert!(numbers(response).await.is_empty());
+        let overflowing = page(&format!("page={}&per_page=100", usize::max_value()));
+        assert_eq!(overflowing.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    }
+
+    #[test]
+    fn invalid_queries_are_unprocessable() {
+        assert_eq!(page("q=milestone%3D1").status(), StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(page("since=yesterday").status(), StatusCode::UNPROCESSABLE_ENTITY);
+    }
+}
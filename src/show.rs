@@ -0,0 +1,89 @@
+use std::error;
+use std::path::PathBuf;
+
+use serde_json::Value;
+
+use crate::entity::Entity;
+use crate::format;
+use crate::snapshot;
+
+/// Longest title shown before it is cut off.
+const TITLE_WIDTH: usize = 60;
+
+const HEADERS: [&str; 5] = ["NUMBER", "TITLE", "STATE", "AUTHOR", "UPDATED"];
+
+#[derive(Debug)]
+pub struct ShowConfig {
+    /// Snapshot file, or the directory of a repository holding one per entity.
+    pub path: PathBuf,
+    pub entity: Entity,
+    /// Number of records shown from the start of the snapshot.
+    pub limit: usize,
+}
+
+/// Prints the first records of a snapshot as a table.
+pub fn show(cfg: ShowConfig) -> Result<(), Box<dyn error::Error>> {
+    let (path, format) = if cfg.path.is_dir() {
+        snapshot::find(&cfg.path, cfg.entity)
+            .ok_or_else(|| format!("no {} snapshot in {}", cfg.entity.name(), cfg.path.display()))?
+    } else {
+        (cfg.path.clone(), snapshot::format_of(&cfg.path))
+    };
+    // read untyped, as snapshots of deduplicated users and of the GraphQL API
+    // don't match the REST types
+    let records: Vec<Value> = format::deserialize_from_file(format, &path)?;
+
+    let shown: Vec<&Value> = records.iter().take(cfg.limit).collect();
+    print_table(&shown);
+    if records.len() > shown.len() {
+        println!("{} of {} {}", shown.len(), records.len(), cfg.entity.name());
+    }
+    Ok(())
+}
+
+/// Prints REST or GraphQL records as a table of number, title, state, author
+/// and last update.
+pub fn print_table(records: &[&Value]) {
+    let rows: Vec<[String; 5]> = records.iter().map(|record| row(record)).collect();
+    let mut widths: Vec<usize> = HEADERS.iter().map(|header| header.len()).collect();
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row.iter()) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+    print_row(&widths, HEADERS.iter().map(|h| *h));
+    for row in &rows {
+        print_row(&widths, row.iter().map(|cell| &cell[..]));
+    }
+}
+
+/// Cells of a REST or GraphQL record.
+fn row(record: &Value) -> [String; 5] {
+    let text = |value: Option<&Value>| match value {
+        Some(&Value::String(ref s)) => s.clone(),
+        Some(&Value::Null) | None => String::new(),
+        Some(value) => value.to_string(),
+    };
+    let title = text(record.get("title"));
+    let title = if title.chars().count() > TITLE_WIDTH {
+        format!("{}…", title.chars().take(TITLE_WIDTH - 1).collect::<String>())
+    } else {
+        title
+    };
+    [
+        text(record.get("number")),
+        title,
+        text(record.get("state")).to_lowercase(),
+        text(record.pointer("/user/login").or_else(|| record.pointer("/author/login"))),
+        text(record.get("updated_at").or_else(|| record.get("updatedAt"))),
+    ]
+}
+
+fn print_row<'a, I: Iterator<Item = &'a str>>(widths: &[usize], cells: I) {
+    let line: Vec<String> = widths
+        .iter()
+        .zip(cells)
+        .map(|(width, cell)| format!("{}{}", cell, " ".repeat(width - cell.chars().count())))
+        .collect();
+    println!("{}", line.join("  ").trim_end());
+}
@@ -0,0 +1,84 @@
+use std::error;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::format::{self, Format};
+use crate::repo::Repo;
+
+/// A record of any entity, as sinks take them.
+pub type Record<'a> = &'a dyn erased_serde::Serialize;
+
+/// Where the fetched records of a repository end up.
+pub trait Sink: Send {
+    /// Writes the `records` of `entity` for `repo`, replacing those written
+    /// before. Returns the number of bytes written if known.
+    fn write(
+        &mut self,
+        repo: &Repo,
+        entity: &str,
+        tag: &str,
+        records: &[Record],
+    ) -> Result<Option<u64>, Box<dyn error::Error>>;
+
+    /// Snapshot of `entity` written for `repo` before, and its format, for
+    /// fetched records to be merged into.
+    fn snapshot(&self, _repo: &Repo, _entity: &str) -> Option<(PathBuf, Format)> {
+        None
+    }
+}
+
+/// Snapshot files named after their entity below `<dir>/<owner>/<repo>`.
+pub struct FileSink {
+    dir: PathBuf,
+    format: Format,
+}
+
+impl FileSink {
+    pub fn new(dir: &Path, format: Format) -> Self {
+        FileSink {
+            dir: dir.to_path_buf(),
+            format: format,
+        }
+    }
+
+    fn path(&self, repo: &Repo, entity: &str) -> PathBuf {
+        repo.dir(&self.dir).join(format!("{}.{}", entity, self.format.extension()))
+    }
+}
+
+impl Sink for FileSink {
+    fn write(
+        &mut self,
+        repo: &Repo,
+        entity: &str,
+        _tag: &str,
+        records: &[Record],
+    ) -> Result<Option<u64>, Box<dyn error::Error>> {
+        let path = self.path(repo, entity);
+        format::serialize_to_file(records, self.format, &path)?;
+        Ok(Some(fs::metadata(&path)?.len()))
+    }
+
+    fn snapshot(&self, repo: &Repo, entity: &str) -> Option<(PathBuf, Format)> {
+        Some(self.path(repo, entity)).filter(|path| path.exists()).map(|path| (path, self.format))
+    }
+}
+
+/// Tagged NDJSON records on stdout, after the header the fetch writes first.
+pub struct StdoutSink;
+
+impl Sink for StdoutSink {
+    fn write(
+        &mut self,
+        _repo: &Repo,
+        _entity: &str,
+        tag: &str,
+        records: &[Record],
+    ) -> Result<Option<u64>, Box<dyn error::Error>> {
+        let stdout = io::stdout();
+        let mut out = stdout.lock();
+        format::write_tagged(tag, records, &mut out)?;
+        Ok(None)
+    }
+}
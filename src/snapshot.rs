@@ -0,0 +1,202 @@
+use std::error;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Utc};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::entity::Entity;
+use crate::format::{self, Format};
+use crate::model::{Issue, Pull};
+use crate::repo::Repo;
+
+/// An issue or pull request of a snapshot, with the fields the analyses use
+/// in the same shape whether it was fetched through REST or GraphQL.
+#[derive(Clone, Debug)]
+pub struct Item {
+    pub number: u64,
+    pub pull: bool,
+    pub title: String,
+    /// `open` or `closed`, pull requests may also be `merged`.
+    pub state: String,
+    pub author: Option<String>,
+    pub labels: Vec<String>,
+    pub created_at: Option<DateTime<Utc>>,
+    pub updated_at: Option<DateTime<Utc>>,
+    pub closed_at: Option<DateTime<Utc>>,
+    pub merged_at: Option<DateTime<Utc>>,
+    pub comments: u64,
+}
+
+impl Item {
+    /// Reads an item from a REST record or a GraphQL node, users may be
+    /// deduplicated references.
+    pub fn of(record: &Value, pull: bool) -> Option<Item> {
+        let field = |rest: &str, graphql: &str| record.get(rest).or_else(|| record.get(graphql));
+        let time = |rest: &str, graphql: &str| {
+            field(rest, graphql)
+                .and_then(Value::as_str)
+                .and_then(|time| DateTime::parse_from_rfc3339(time).ok())
+                .map(|time| time.with_timezone(&Utc))
+        };
+        let merged_at = time("merged_at", "mergedAt");
+        let state = field("state", "state").and_then(Value::as_str).unwrap_or("").to_lowercase();
+        Some(Item {
+            number: record.get("number").and_then(Value::as_u64)?,
+            pull: pull,
+            title: record.get("title").and_then(Value::as_str).unwrap_or("").to_string(),
+            state: if merged_at.is_some() { "merged".to_string() } else { state },
+            author: record
+                .pointer("/user/login")
+                .or_else(|| record.pointer("/author/login"))
+                .and_then(Value::as_str)
+                .map(|login| login.to_string()),
+            labels: record
+                .get("labels")
+                .map(|labels| labels.pointer("/nodes").unwrap_or(labels))
+                .and_then(Value::as_array)
+                .map(|labels| {
+                    labels
+                        .iter()
+                        .filter_map(|label| label.get("name").and_then(Value::as_str))
+                        .map(|name| name.to_string())
+                        .collect()
+                })
+                .unwrap_or_default(),
+            created_at: time("created_at", "createdAt"),
+            updated_at: time("updated_at", "updatedAt"),
+            closed_at: time("closed_at", "closedAt"),
+            merged_at: merged_at,
+            comments: record
+                .get("comments")
+                .and_then(|comments| comments.as_u64().or_else(|| comments.pointer("/totalCount")?.as_u64()))
+                .unwrap_or(0),
+        })
+    }
+}
+
+/// Repositories with snapshots below the output directory `dir`, along with
+/// their directories, sorted by name. A repository directory itself is taken
+/// as the only one.
+pub fn repositories(dir: &Path) -> Result<Vec<(Repo, PathBuf)>, Box<dyn error::Error>> {
+    if is_repository(dir) {
+        let owner = dir.parent().map_or_else(String::new, file_name);
+        return Ok(vec![(
+            Repo {
+                owner: owner,
+                name: file_name(dir),
+            },
+            dir.to_path_buf(),
+        )]);
+    }
+    let mut repos = Vec::new();
+    for owner in fs::read_dir(dir)? {
+        let owner = owner?.path();
+        if !owner.is_dir() {
+            continue;
+        }
+        for repo in fs::read_dir(&owner)? {
+            let repo = repo?.path();
+            if is_repository(&repo) {
+                repos.push((
+                    Repo {
+                        owner: file_name(&owner),
+                        name: file_name(&repo),
+                    },
+                    repo,
+                ));
+            }
+        }
+    }
+    repos.sort_by(|a, b| a.0.to_string().cmp(&b.0.to_string()));
+    Ok(repos)
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name().map_or_else(String::new, |name| name.to_string_lossy().into_owned())
+}
+
+/// Whether `dir` is the directory of a repository, holding its snapshots.
+pub fn is_repository(dir: &Path) -> bool {
+    dir.is_dir() && Entity::ALL.iter().any(|entity| find(dir, *entity).is_some())
+}
+
+/// Snapshot file of `entity` in the repository directory `dir` and its
+/// format, msgpack ones preferred.
+pub fn find(dir: &Path, entity: Entity) -> Option<(PathBuf, Format)> {
+    find_named(dir, entity.name())
+}
+
+/// Snapshot file named `name` in the repository directory `dir`, e.g. that
+/// of the deduplicated users.
+pub fn find_named(dir: &Path, name: &str) -> Option<(PathBuf, Format)> {
+    [Format::Msgpack, Format::Ndjson]
+        .iter()
+        .map(|format| (dir.join(format!("{}.{}", name, format.extension())), *format))
+        .find(|&(ref path, _)| path.exists())
+}
+
+/// Format of a snapshot file by its extension.
+pub fn format_of(path: &Path) -> Format {
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some("ndjson") => Format::Ndjson,
+        _ => Format::Msgpack,
+    }
+}
+
+/// Records of a snapshot of `entity` as JSON objects. Records of the REST
+/// types are read as such first, since msgpack keeps their fields by
+/// position, those of deduplicated users and of the GraphQL API as they are.
+pub fn records(path: &Path, format: Format, entity: Entity) -> Result<Vec<Value>, Box<dyn error::Error>> {
+    let typed = match entity {
+        Entity::Issues => format::deserialize_from_file::<Issue>(format, path).and_then(|records| to_values(&records)),
+        Entity::Pulls => format::deserialize_from_file::<Pull>(format, path).and_then(|records| to_values(&records)),
+    };
+    typed.or_else(|_| format::deserialize_from_file(format, path))
+}
+
+fn to_values<D: Serialize>(records: &[D]) -> Result<Vec<Value>, Box<dyn error::Error>> {
+    Ok(records.iter().map(serde_json::to_value).collect::<Result<_, _>>()?)
+}
+
+/// Issues and pull requests of the repository directory `dir`.
+pub fn items(dir: &Path) -> Result<Vec<Item>, Box<dyn error::Error>> {
+    let mut items = Vec::new();
+    for entity in Entity::ALL {
+        if let Some((path, format)) = find(dir, *entity) {
+            let pull = *entity == Entity::Pulls;
+            items.extend(records(&path, format, *entity)?.iter().filter_map(|record| Item::of(record, pull)));
+        }
+    }
+    Ok(items)
+}
+
+/// Writes records of `entity` as read by `records`, as the REST types if
+/// `typed` is set and they match, so that msgpack keeps their fields by
+/// position as the fetch does.
+pub fn write(
+    records: &[Value],
+    entity: Entity,
+    typed: bool,
+    format: Format,
+    path: &Path,
+) -> Result<(), Box<dyn error::Error>> {
+    match entity {
+        Entity::Issues if typed => write_typed::<Issue>(records, format, path),
+        Entity::Pulls if typed => write_typed::<Pull>(records, format, path),
+        _ => format::serialize_to_file(records, format, path),
+    }
+}
+
+fn write_typed<D>(records: &[Value], format: Format, path: &Path) -> Result<(), Box<dyn error::Error>>
+where
+    D: DeserializeOwned + Serialize,
+{
+    let typed: Result<Vec<D>, _> = records.iter().cloned().map(serde_json::from_value).collect();
+    match typed {
+        Ok(typed) => format::serialize_to_file(&typed, format, path),
+        Err(_) => format::serialize_to_file(records, format, path),
+    }
+}
@@ -0,0 +1,313 @@
+use std::error;
+use std::fs;
+use std::io::BufReader;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use rmp_serde::{self, Serializer};
+use rusqlite::{self, Connection};
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+
+use crate::api::{Issue, Pull};
+use crate::users::UserDimension;
+
+/// Sync state database kept at the top of an output directory.
+pub const DB_FILE: &str = ".sync-state.sqlite";
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS runs (
+    repo TEXT NOT NULL,
+    entity TEXT NOT NULL,
+    last_run TEXT NOT NULL,
+    PRIMARY KEY (repo, entity)
+);
+CREATE TABLE IF NOT EXISTS checkpoints (
+    repo TEXT PRIMARY KEY,
+    data BLOB NOT NULL
+);
+CREATE TABLE IF NOT EXISTS checkpoint_records (
+    repo TEXT NOT NULL,
+    kind TEXT NOT NULL,
+    seq INTEGER NOT NULL,
+    data BLOB NOT NULL,
+    PRIMARY KEY (repo, kind, seq)
+);
+CREATE TABLE IF NOT EXISTS etags (
+    url TEXT PRIMARY KEY,
+    etag TEXT,
+    last_modified TEXT,
+    next TEXT,
+    body TEXT NOT NULL
+);
+";
+
+/// A response as kept in the ETag cache.
+#[derive(Debug)]
+pub struct CachedResponse {
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+    pub next: Option<String>,
+    pub body: String,
+}
+
+/// State shared between runs on an output directory: when each entity of a
+/// repository was last synced, checkpoints of interrupted runs and the ETag
+/// cache.
+///
+/// Every worker opens a connection of its own, SQLite serializes the writes.
+/// Clones share the connection, e.g. to write on the blocking thread pool.
+#[derive(Clone)]
+pub struct SyncDb {
+    conn: Arc<Mutex<Connection>>,
+}
+
+impl SyncDb {
+    pub fn open(path: &Path) -> rusqlite::Result<Self> {
+        let conn = Connection::open(path)?;
+        conn.busy_timeout(Duration::from_secs(30))?;
+        conn.execute_batch(SCHEMA)?;
+        Ok(SyncDb {
+            conn: Arc::new(Mutex::new(conn)),
+        })
+    }
+
+    /// Start time of the last successful sync of `entity`.
+    pub fn last_run(&self, repo: &str, entity: &str) -> rusqlite::Result<Option<String>> {
+        optional(self.conn.lock().unwrap().query_row(
+            "SELECT last_run FROM runs WHERE repo = ?1 AND entity = ?2",
+            &[&repo, &entity],
+            |row| row.get(0),
+        ))
+    }
+
+    pub fn set_last_run(&self, repo: &str, entity: &str, last_run: &str) -> rusqlite::Result<()> {
+        self.conn.lock().unwrap().execute(
+            "INSERT OR REPLACE INTO runs (repo, entity, last_run) VALUES (?1, ?2, ?3)",
+            &[&repo, &entity, &last_run],
+        )?;
+        Ok(())
+    }
+
+    pub fn cached(&self, url: &str) -> rusqlite::Result<Option<CachedResponse>> {
+        optional(self.conn.lock().unwrap().query_row(
+            "SELECT etag, last_modified, next, body FROM etags WHERE url = ?1",
+            &[&url],
+            |row| CachedResponse {
+                etag: row.get(0),
+                last_modified: row.get(1),
+                next: row.get(2),
+                body: row.get(3),
+            },
+        ))
+    }
+
+    pub fn cache(&self, url: &str, response: &CachedResponse) -> rusqlite::Result<()> {
+        self.conn.lock().unwrap().execute(
+            "INSERT OR REPLACE INTO etags (url, etag, last_modified, next, body) VALUES (?1, ?2, ?3, ?4, ?5)",
+            &[&url, &response.etag, &response.last_modified, &response.next, &response.body],
+        )?;
+        Ok(())
+    }
+}
+
+/// The row of a single row query, `None` if there is none.
+fn optional<T>(result: rusqlite::Result<T>) -> rusqlite::Result<Option<T>> {
+    match result {
+        Ok(row) => Ok(Some(row)),
+        Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+const STATE_FILE: &str = "state.json";
+
+/// Bookkeeping kept next to a snapshot before the sync state database, only
+/// read to carry the last run over.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct RunState {
+    /// Start time of the last successful run, used by `--incremental`.
+    pub last_run: Option<String>,
+}
+
+impl RunState {
+    pub fn load(dir: &Path) -> Result<Self, Box<dyn error::Error>> {
+        let path = dir.join(STATE_FILE);
+        if !path.exists() {
+            return Ok(RunState::default());
+        }
+        Ok(serde_json::from_reader(BufReader::new(fs::File::open(path)?))?)
+    }
+}
+
+/// Progress of an interrupted run, written periodically and removed once the
+/// run has completed.
+///
+/// The listing and the pulls, which only grow during a run, are saved as rows
+/// of their own, each save appending those added since the last one.
+#[derive(Default, Deserialize, Serialize)]
+pub struct Checkpoint {
+    /// Start time of the checkpointed run.
+    pub started: String,
+    pub since: Option<String>,
+    /// Next issues page to fetch, `None` until the first page is done.
+    pub issues_next: Option<String>,
+    /// Listing results fetched so far, issues and pull requests.
+    #[serde(skip)]
+    pub listing: Vec<Issue>,
+    /// Set once the issues are written and only pull details remain.
+    pub issues_done: bool,
+    pub pr_nums: Vec<u64>,
+    #[serde(skip)]
+    pub pulls: Vec<Pull>,
+    pub users: UserDimension,
+    /// Set once the last listing page is done, while pull details may still
+    /// be fetched alongside.
+    #[serde(default)]
+    pub listing_done: bool,
+    /// Listing results and pulls as of the last save.
+    #[serde(skip)]
+    saved_lens: (usize, usize),
+}
+
+/// Changes of a checkpoint since it was last saved, encoded separately so
+/// that only the write needs to block.
+pub struct CheckpointChanges {
+    state: Vec<u8>,
+    listing: Rows,
+    pulls: Rows,
+}
+
+/// Encoded records of a list from position `from` on, replacing any saved
+/// there before.
+struct Rows {
+    from: usize,
+    rows: Vec<Vec<u8>>,
+}
+
+impl Rows {
+    fn of<D: Serialize>(records: &[D], saved: usize) -> Result<Self, Box<dyn error::Error>> {
+        let from = saved.min(records.len());
+        Ok(Rows {
+            from: from,
+            rows: records[from..].iter().map(encode).collect::<Result<_, _>>()?,
+        })
+    }
+
+    fn save(&self, conn: &Connection, repo: &str, kind: &str) -> rusqlite::Result<()> {
+        conn.execute(
+            "DELETE FROM checkpoint_records WHERE repo = ?1 AND kind = ?2 AND seq >= ?3",
+            &[&repo, &kind, &(self.from as i64)],
+        )?;
+        for (i, row) in self.rows.iter().enumerate() {
+            conn.execute(
+                "INSERT INTO checkpoint_records (repo, kind, seq, data) VALUES (?1, ?2, ?3, ?4)",
+                &[&repo, &kind, &((self.from + i) as i64), row],
+            )?;
+        }
+        Ok(())
+    }
+}
+
+fn encode<D: Serialize>(value: &D) -> Result<Vec<u8>, Box<dyn error::Error>> {
+    let mut data = Vec::new();
+    value.serialize(&mut Serializer::new(&mut data))?;
+    Ok(data)
+}
+
+/// Records of `kind` saved with the checkpoint of `repo`, in order.
+fn rows<D: DeserializeOwned>(conn: &Connection, repo: &str, kind: &str) -> Result<Vec<D>, Box<dyn error::Error>> {
+    let mut stmt = conn.prepare("SELECT data FROM checkpoint_records WHERE repo = ?1 AND kind = ?2 ORDER BY seq")?;
+    let rows = stmt.query_map(&[&repo, &kind], |row| row.get::<_, Vec<u8>>(0))?;
+    let mut records = Vec::new();
+    for data in rows {
+        records.push(rmp_serde::decode::from_read(&data?[..])?);
+    }
+    Ok(records)
+}
+
+impl Checkpoint {
+    pub fn new(started: String, since: Option<String>) -> Self {
+        Checkpoint {
+            started: started,
+            since: since,
+            ..Checkpoint::default()
+        }
+    }
+
+    pub fn load(db: &SyncDb, repo: &str) -> Result<Option<Self>, Box<dyn error::Error>> {
+        let conn = db.conn.lock().unwrap();
+        let data: Option<Vec<u8>> = optional(conn.query_row(
+            "SELECT data FROM checkpoints WHERE repo = ?1",
+            &[&repo],
+            |row| row.get(0),
+        ))?;
+        let mut checkpoint: Checkpoint = match data {
+            Some(data) => rmp_serde::decode::from_read(&data[..])?,
+            None => return Ok(None),
+        };
+        checkpoint.listing = rows(&conn, repo, "listing")?;
+        checkpoint.pulls = rows(&conn, repo, "pulls")?;
+        checkpoint.saved_lens = (checkpoint.listing.len(), checkpoint.pulls.len());
+        Ok(Some(checkpoint))
+    }
+
+    /// The changes since the checkpoint was last saved.
+    pub fn changes(&self) -> Result<CheckpointChanges, Box<dyn error::Error>> {
+        Ok(CheckpointChanges {
+            state: encode(self)?,
+            listing: Rows::of(&self.listing, self.saved_lens.0)?,
+            pulls: Rows::of(&self.pulls, self.saved_lens.1)?,
+        })
+    }
+
+    /// Saves the `changes` of the checkpoint of `repo`.
+    pub fn save(db: &SyncDb, repo: &str, changes: &CheckpointChanges) -> Result<(), Box<dyn error::Error>> {
+        let mut conn = db.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        tx.execute(
+            "INSERT OR REPLACE INTO checkpoints (repo, data) VALUES (?1, ?2)",
+            &[&repo, &changes.state],
+        )?;
+        changes.listing.save(&tx, repo, "listing")?;
+        changes.pulls.save(&tx, repo, "pulls")?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Marks the changes as saved, once `save` has succeeded.
+    pub fn saved(&mut self) {
+        self.saved_lens = (self.listing.len(), self.pulls.len());
+    }
+
+    pub fn remove(db: &SyncDb, repo: &str) -> Result<(), Box<dyn error::Error>> {
+        let conn = db.conn.lock().unwrap();
+        conn.execute("DELETE FROM checkpoints WHERE repo = ?1", &[&repo])?;
+        conn.execute("DELETE FROM checkpoint_records WHERE repo = ?1", &[&repo])?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::{rows, Rows, SyncDb};
+
+    #[test]
+    fn saved_rows_are_appended_and_truncated() {
+        let db = SyncDb::open(Path::new(":memory:")).unwrap();
+        let conn = db.conn.lock().unwrap();
+        let save = |records: &[u64], saved: usize| Rows::of(records, saved).unwrap().save(&conn, "o/r", "listing");
+
+        save(&[1, 2, 3], 0).unwrap();
+        save(&[1, 2, 3, 4, 5], 3).unwrap();
+        assert_eq!(rows::<u64>(&conn, "o/r", "listing").unwrap(), vec![1, 2, 3, 4, 5]);
+        assert!(rows::<u64>(&conn, "o/r", "pulls").unwrap().is_empty());
+
+        save(&[1], 5).unwrap();
+        assert_eq!(rows::<u64>(&conn, "o/r", "listing").unwrap(), vec![1]);
+    }
+}
@@ -0,0 +1,144 @@
+use std::collections::BTreeMap;
+use std::error;
+use std::path::PathBuf;
+
+use crate::snapshot::{self, Item};
+
+/// Labels and authors listed in the text output, the JSON one has all.
+const TOP: usize = 10;
+
+#[derive(Debug)]
+pub struct StatsConfig {
+    /// Output directory of a fetch, or the directory of one repository.
+    pub dir: PathBuf,
+    pub json: bool,
+}
+
+/// Figures over the issues and pull requests of snapshots.
+#[derive(Debug, Default, Serialize)]
+pub struct Stats {
+    pub repositories: usize,
+    pub issues: usize,
+    pub pulls: usize,
+    /// Items by state, merged pull requests apart from the closed ones.
+    pub states: BTreeMap<String, usize>,
+    pub labels: BTreeMap<String, usize>,
+    pub authors: BTreeMap<String, usize>,
+    /// Median days from opening to closing or merging of the closed items.
+    pub median_days_to_close: Option<f64>,
+    /// Share of the closed pull requests that were merged.
+    pub merge_rate: Option<f64>,
+    /// Comments on the items opened per month, as `YYYY-MM`.
+    pub comments_per_month: BTreeMap<String, u64>,
+}
+
+impl Stats {
+    /// Computes the figures of the `items` of `repositories` repositories.
+    pub fn of(repositories: usize, items: &[Item]) -> Self {
+        let mut stats = Stats {
+            repositories: repositories,
+            ..Stats::default()
+        };
+        let mut days_to_close = Vec::new();
+        for item in items {
+            if item.pull {
+                stats.pulls += 1;
+            } else {
+                stats.issues += 1;
+            }
+            *stats.states.entry(item.state.clone()).or_insert(0) += 1;
+            for label in &item.labels {
+                *stats.labels.entry(label.clone()).or_insert(0) += 1;
+            }
+            if let Some(ref author) = item.author {
+                *stats.authors.entry(author.clone()).or_insert(0) += 1;
+            }
+            if let (Some(created), Some(closed)) = (item.created_at, item.merged_at.or(item.closed_at)) {
+                days_to_close.push(days(closed.signed_duration_since(created)));
+            }
+            if let Some(created) = item.created_at {
+                *stats.comments_per_month.entry(created.format("%Y-%m").to_string()).or_insert(0) += item.comments;
+            }
+        }
+        stats.median_days_to_close = median(&mut days_to_close);
+        let merged = items.iter().filter(|item| item.pull && item.state == "merged").count();
+        let closed = items.iter().filter(|item| item.pull && item.state != "open").count();
+        if closed > 0 {
+            stats.merge_rate = Some(merged as f64 / closed as f64);
+        }
+        stats
+    }
+}
+
+/// Fractional days of a duration.
+pub fn days(duration: chrono::Duration) -> f64 {
+    duration.num_seconds() as f64 / 86400.0
+}
+
+/// Median of `values`, sorting them, none if there are none.
+pub fn median(values: &mut [f64]) -> Option<f64> {
+    if values.is_empty() {
+        return None;
+    }
+    values.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    let mid = values.len() / 2;
+    Some(if values.len() % 2 == 0 {
+        (values[mid - 1] + values[mid]) / 2.0
+    } else {
+        values[mid]
+    })
+}
+
+/// Prints statistics of the snapshots in a directory.
+pub fn stats(cfg: StatsConfig) -> Result<(), Box<dyn error::Error>> {
+    let repos = snapshot::repositories(&cfg.dir)?;
+    if repos.is_empty() {
+        return Err(format!("no snapshots in {}", cfg.dir.display()).into());
+    }
+    let mut items = Vec::new();
+    for (_, dir) in &repos {
+        items.extend(snapshot::items(dir)?);
+    }
+    let stats = Stats::of(repos.len(), &items);
+    if cfg.json {
+        println!("{}", serde_json::to_string_pretty(&stats)?);
+        return Ok(());
+    }
+
+    println!("Repositories: {}", stats.repositories);
+    println!("Issues: {}", stats.issues);
+    println!("Pull requests: {}", stats.pulls);
+    print_counts("State", &stats.states);
+    print_counts("Labels", &stats.labels);
+    print_counts("Authors", &stats.authors);
+    if let Some(days) = stats.median_days_to_close {
+        println!("Median time to close: {:.1} days", days);
+    }
+    if let Some(rate) = stats.merge_rate {
+        println!("Merge rate: {:.1}%", rate * 100.0);
+    }
+    if !stats.comments_per_month.is_empty() {
+        println!("Comments per month:");
+        for (month, comments) in &stats.comments_per_month {
+            println!("  {}  {}", month, comments);
+        }
+    }
+    Ok(())
+}
+
+/// Prints the most frequent of `counts`.
+fn print_counts(title: &str, counts: &BTreeMap<String, usize>) {
+    if counts.is_empty() {
+        return;
+    }
+    let mut counts: Vec<_> = counts.iter().collect();
+    counts.sort_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0)));
+    let width = counts.iter().take(TOP).map(|(name, _)| name.chars().count()).max().unwrap_or(0);
+    println!("{}:", title);
+    for (name, count) in counts.iter().take(TOP) {
+        println!("  {}{}  {}", name, " ".repeat(width - name.chars().count()), count);
+    }
+    if counts.len() > TOP {
+        println!("  ({} more)", counts.len() - TOP);
+    }
+}
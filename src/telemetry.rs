@@ -0,0 +1,52 @@
+use std::error;
+
+/// Exports the spans of the fetch, its phases, pages and requests to the
+/// OTLP collector at `endpoint`, e.g. `http://localhost:4317` of Jaeger or
+/// Tempo, until the returned guard is dropped.
+#[cfg(feature = "otlp")]
+pub fn start(endpoint: &str) -> Result<Guard, Box<dyn error::Error>> {
+    otlp::start(endpoint)
+}
+
+#[cfg(not(feature = "otlp"))]
+pub fn start(_endpoint: &str) -> Result<Guard, Box<dyn error::Error>> {
+    Err("--otlp-endpoint needs a build with the otlp feature".into())
+}
+
+#[cfg(not(feature = "otlp"))]
+pub struct Guard;
+
+#[cfg(feature = "otlp")]
+pub use self::otlp::Guard;
+
+#[cfg(feature = "otlp")]
+mod otlp {
+    use std::error;
+
+    use opentelemetry::KeyValue;
+    use opentelemetry_otlp::WithExportConfig;
+    use opentelemetry_sdk::{runtime, trace, Resource};
+    use tracing_subscriber::layer::SubscriberExt;
+
+    /// Flushes the spans not yet exported once dropped.
+    pub struct Guard;
+
+    impl Drop for Guard {
+        fn drop(&mut self) {
+            opentelemetry::global::shutdown_tracer_provider();
+        }
+    }
+
+    pub fn start(endpoint: &str) -> Result<Guard, Box<dyn error::Error>> {
+        let tracer = opentelemetry_otlp::new_pipeline()
+            .tracing()
+            .with_exporter(opentelemetry_otlp::new_exporter().tonic().with_endpoint(endpoint))
+            .with_trace_config(
+                trace::config().with_resource(Resource::new(vec![KeyValue::new("service.name", env!("CARGO_PKG_NAME"))])),
+            )
+            .install_batch(runtime::Tokio)?;
+        let subscriber = tracing_subscriber::registry().with(tracing_opentelemetry::layer().with_tracer(tracer));
+        tracing::subscriber::set_global_default(subscriber)?;
+        Ok(Guard)
+    }
+}
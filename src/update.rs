@@ -0,0 +1,164 @@
+use std::env;
+use std::error;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+
+use hyper::body::{self, Bytes};
+use hyper::{self, Body, Request};
+use hyper::client::HttpConnector;
+use hyper::header::{ACCEPT, LOCATION, USER_AGENT};
+use hyper_tls::HttpsConnector;
+use sha2::{Digest, Sha256};
+
+/// Repository whose releases carry the binaries.
+const RELEASES: &str = "https://api.github.com/repos/mayflower/github-data-fetch/releases/latest";
+
+/// Redirects followed to the storage of a release asset.
+const MAX_REDIRECTS: u32 = 5;
+
+#[derive(Debug)]
+pub struct UpdateConfig {
+    /// Only report whether a newer release exists.
+    pub check: bool,
+}
+
+#[derive(Deserialize)]
+struct Release {
+    tag_name: String,
+    assets: Vec<Asset>,
+}
+
+#[derive(Deserialize)]
+struct Asset {
+    name: String,
+    browser_download_url: String,
+}
+
+type HttpClient = hyper::Client<HttpsConnector<HttpConnector>>;
+
+/// Replaces the running executable with the binary of the latest release for
+/// this platform.
+///
+/// Releases carry a binary named `github-data-fetch-<arch>-<os>`, e.g.
+/// `github-data-fetch-x86_64-linux` or `github-data-fetch-x86_64-windows.exe`,
+/// along with its SHA-256 checksum in a `.sha256` file of the same name.
+pub async fn self_update(cfg: UpdateConfig) -> Result<(), Box<dyn error::Error>> {
+    let http = hyper::Client::builder().build(HttpsConnector::new());
+
+    let release: Release = serde_json::from_slice(&download(&http, RELEASES.to_string()).await?)?;
+    let latest = release.tag_name.trim_start_matches('v');
+    let newer = match (version(latest), version(env!("CARGO_PKG_VERSION"))) {
+        (Some(latest), Some(running)) => latest > running,
+        _ => return Err(format!("release {} has no version number", release.tag_name).into()),
+    };
+    if !newer {
+        println!("{} is up to date", env!("CARGO_PKG_VERSION"));
+        return Ok(());
+    }
+    if cfg.check {
+        println!("{} is available, running {}", latest, env!("CARGO_PKG_VERSION"));
+        return Ok(());
+    }
+
+    let name = format!(
+        "{}-{}-{}{}",
+        env!("CARGO_PKG_NAME"),
+        env::consts::ARCH,
+        env::consts::OS,
+        env::consts::EXE_SUFFIX
+    );
+    let url = |name: &str| {
+        release
+            .assets
+            .iter()
+            .find(|asset| asset.name == name)
+            .map(|asset| asset.browser_download_url.clone())
+            .ok_or_else(|| format!("release {} has no {}", release.tag_name, name))
+    };
+    let binary_url = url(&name)?;
+    let checksum_url = url(&format!("{}.sha256", name))?;
+
+    info!("Downloading {} {}", name, latest);
+    let binary = download(&http, binary_url).await?;
+    let checksum = download(&http, checksum_url).await?;
+    // sha256sum output, the checksum followed by the file name
+    let expected = String::from_utf8_lossy(&checksum)
+        .split_whitespace()
+        .next()
+        .map(|hex| hex.to_lowercase())
+        .ok_or("empty checksum file")?;
+    let actual: String = Sha256::digest(&binary).iter().map(|b| format!("{:02x}", b)).collect();
+    if actual != expected {
+        return Err(format!("checksum mismatch for {}: expected {}, got {}", name, expected, actual).into());
+    }
+
+    replace_executable(&env::current_exe()?, &binary)?;
+    println!("Updated {} to {}", env!("CARGO_PKG_VERSION"), latest);
+    Ok(())
+}
+
+/// Major, minor and patch of a semantic version, and whether it is a release
+/// rather than a pre-release, which precedes the release.
+fn version(text: &str) -> Option<(u64, u64, u64, bool)> {
+    // build metadata doesn't take part in the order
+    let text = text.split('+').next()?;
+    let mut parts = text.splitn(2, '-');
+    let mut numbers = parts.next()?.split('.').map(|n| n.parse::<u64>());
+    let version = match (numbers.next(), numbers.next(), numbers.next(), numbers.next()) {
+        (Some(Ok(major)), Some(Ok(minor)), Some(Ok(patch)), None) => (major, minor, patch),
+        _ => return None,
+    };
+    Some((version.0, version.1, version.2, parts.next().is_none()))
+}
+
+/// Fetches `url`, following the redirects of release downloads.
+async fn download(http: &HttpClient, mut url: String) -> Result<Bytes, Box<dyn error::Error>> {
+    for _ in 0..=MAX_REDIRECTS {
+        let request = Request::get(&url[..])
+            .header(USER_AGENT, concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION")))
+            .header(ACCEPT, "application/octet-stream, application/vnd.github.v3+json")
+            .body(Body::empty())?;
+        let response = http.request(request).await?;
+        if response.status().is_redirection() {
+            url = response
+                .headers()
+                .get(LOCATION)
+                .and_then(|location| location.to_str().ok())
+                .map(|location| location.to_string())
+                .ok_or_else(|| format!("broken redirect for {}", url))?;
+            continue;
+        }
+        if !response.status().is_success() {
+            return Err(format!("{} for {}", response.status(), url).into());
+        }
+        return Ok(body::to_bytes(response.into_body()).await?);
+    }
+    Err(format!("too many redirects for {}", url).into())
+}
+
+/// Writes `binary` next to the executable at `exe` and moves it into place.
+/// Windows doesn't allow replacing a running executable, it is moved aside
+/// first.
+fn replace_executable(exe: &Path, binary: &[u8]) -> Result<(), Box<dyn error::Error>> {
+    let file_name = exe.file_name().ok_or("executable without a file name")?.to_string_lossy();
+    let new = exe.with_file_name(format!("{}.new", file_name));
+    let mut options = OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o755);
+    }
+    options
+        .open(&new)
+        .and_then(|mut file| file.write_all(binary))
+        .map_err(|e| format!("failed to write {}: {}", new.display(), e))?;
+    if cfg!(windows) {
+        let old = exe.with_file_name(format!("{}.old", file_name));
+        let _ = fs::remove_file(&old);
+        fs::rename(exe, &old)?;
+    }
+    fs::rename(&new, exe).map_err(|e| format!("failed to replace {}: {}", exe.display(), e))?;
+    Ok(())
+}
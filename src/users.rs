@@ -0,0 +1,70 @@
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+use serde_json::{self, Map, Value};
+
+/// Deduplicated set of the users embedded in fetched records.
+///
+/// Records passed through `extract` have every embedded user object replaced
+/// by an `{"id", "login"}` reference, the full objects are kept once per id.
+#[derive(Default, Deserialize, Serialize)]
+pub struct UserDimension {
+    users: BTreeMap<u64, Value>,
+}
+
+impl UserDimension {
+    pub fn extract<D>(&mut self, records: &[D]) -> Result<Vec<Value>, serde_json::Error>
+    where
+        D: Serialize,
+    {
+        records
+            .iter()
+            .map(|record| {
+                let mut value = serde_json::to_value(record)?;
+                self.replace_users(&mut value);
+                Ok(value)
+            })
+            .collect()
+    }
+
+    fn replace_users(&mut self, value: &mut Value) {
+        if let Some((id, login)) = user_ref(value) {
+            self.users.entry(id).or_insert_with(|| value.clone());
+            let mut reference = Map::new();
+            reference.insert("id".to_string(), Value::from(id));
+            reference.insert("login".to_string(), Value::from(login));
+            *value = Value::Object(reference);
+            return;
+        }
+        match *value {
+            Value::Object(ref mut fields) => for field in fields.values_mut() {
+                self.replace_users(field);
+            },
+            Value::Array(ref mut items) => for item in items {
+                self.replace_users(item);
+            },
+            _ => {}
+        }
+    }
+
+    pub fn into_records(self) -> Vec<Value> {
+        self.users.into_iter().map(|(_, user)| user).collect()
+    }
+}
+
+/// Returns id and login if `value` is a full GitHub user object.
+fn user_ref(value: &Value) -> Option<(u64, String)> {
+    if value.get("avatar_url").is_none() {
+        return None;
+    }
+    match (value.get("id").and_then(Value::as_u64), value.get("login").and_then(Value::as_str)) {
+        (Some(id), Some(login)) => Some((id, login.to_string())),
+        _ => None,
+    }
+}
+
+/// Key of a deduplicated record, used to upsert by number or id. `None` for
+/// records without the field, e.g. REST ones read as positional arrays.
+pub fn key_of(record: &Value, field: &str) -> Option<u64> {
+    record.get(field).and_then(Value::as_u64)
+}
@@ -0,0 +1,146 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use http::response::Parts;
+use hyper::body::Bytes;
+use hyper::{Method, Response};
+use sha2::{Digest, Sha256};
+
+/// Whether a cassette records responses or replays them.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Mode {
+    /// Requests are sent and their responses written to the cassette.
+    Record,
+    /// Requests are answered from the cassette without touching the network.
+    Replay,
+}
+
+/// Raw API responses kept in a directory, one JSON file per distinct
+/// request, for runs to be repeated offline and deterministically.
+#[derive(Clone, Debug)]
+pub struct Cassette {
+    pub dir: PathBuf,
+    pub mode: Mode,
+}
+
+/// A response as recorded, along with the request it answers.
+#[derive(Deserialize, Serialize)]
+struct Recording {
+    method: String,
+    url: String,
+    status: u16,
+    headers: Vec<(String, String)>,
+    body: String,
+}
+
+impl Cassette {
+    pub fn new(dir: &Path, mode: Mode) -> Self {
+        Cassette {
+            dir: dir.to_path_buf(),
+            mode: mode,
+        }
+    }
+
+    pub fn is_replaying(&self) -> bool {
+        self.mode == Mode::Replay
+    }
+
+    /// File of a request, named after the hash of its method, URL and body
+    /// since GraphQL queries all go to the same URL.
+    fn path(&self, method: &Method, url: &str, body: &[u8]) -> PathBuf {
+        let mut hasher = Sha256::new();
+        hasher.input(method.as_str().as_bytes());
+        hasher.input(b" ");
+        hasher.input(url.as_bytes());
+        hasher.input(b"\n");
+        hasher.input(body);
+        let hash: String = hasher.result().iter().map(|b| format!("{:02x}", b)).collect();
+        self.dir.join(format!("{}.json", hash))
+    }
+
+    /// Writes the response to a request, replacing the one recorded before.
+    /// Bodies are kept as text, a response that isn't UTF-8 can't be
+    /// recorded faithfully and fails.
+    pub fn record(&self, method: &Method, url: &str, body: &[u8], parts: &Parts, response: &[u8]) -> io::Result<()> {
+        let response = String::from_utf8(response.to_vec()).map_err(|_| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("the response to {} {} isn't UTF-8 and can't be recorded", method, url),
+            )
+        })?;
+        let recording = Recording {
+            method: method.to_string(),
+            url: url.to_string(),
+            status: parts.status.as_u16(),
+            headers: parts
+                .headers
+                .iter()
+                .filter_map(|(name, value)| value.to_str().ok().map(|value| (name.to_string(), value.to_string())))
+                .collect(),
+            body: response,
+        };
+        fs::create_dir_all(&self.dir)?;
+        let file = io::BufWriter::new(fs::File::create(self.path(method, url, body))?);
+        serde_json::to_writer_pretty(file, &recording)?;
+        Ok(())
+    }
+
+    /// The recorded response to a request, none if it was never recorded.
+    pub fn replay(&self, method: &Method, url: &str, body: &[u8]) -> io::Result<Option<(Parts, Bytes)>> {
+        let path = self.path(method, url, body);
+        if !path.exists() {
+            return Ok(None);
+        }
+        let recording: Recording = serde_json::from_reader(io::BufReader::new(fs::File::open(&path)?))?;
+        let mut response = Response::builder().status(recording.status);
+        for (name, value) in &recording.headers {
+            response = response.header(&name[..], &value[..]);
+        }
+        let (parts, _) = response
+            .body(())
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", path.display(), e)))?
+            .into_parts();
+        Ok(Some((parts, Bytes::from(recording.body))))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+    use std::path::Path;
+
+    use hyper::{Method, Response, StatusCode};
+
+    use super::{Cassette, Mode};
+
+    const URL: &str = "https://api.github.com/repos/octocat/hello-world/issues?per_page=100";
+
+    #[test]
+    fn replays_recorded_responses() {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("testdata/cassette");
+        let cassette = Cassette::new(&dir, Mode::Replay);
+
+        let (parts, body) = cassette.replay(&Method::GET, URL, b"").unwrap().unwrap();
+        assert_eq!(parts.status, StatusCode::OK);
+        assert_eq!(parts.headers["x-ratelimit-remaining"], "59");
+        assert_eq!(&body[..], "[{\"number\": 1, \"title\": \"Grüße\"}]".as_bytes());
+
+        assert!(cassette.replay(&Method::POST, URL, b"").unwrap().is_none());
+        assert!(cassette.replay(&Method::GET, URL, b"{}").unwrap().is_none());
+    }
+
+    #[test]
+    fn records_only_utf8_bodies() {
+        let dir = env::temp_dir().join(format!("github-data-fetch-vcr-{}", std::process::id()));
+        let cassette = Cassette::new(&dir, Mode::Record);
+        let (parts, _) = Response::builder().status(StatusCode::OK).body(()).unwrap().into_parts();
+
+        assert!(cassette.record(&Method::GET, URL, b"", &parts, &[0xff, 0xfe]).is_err());
+        cassette.record(&Method::GET, URL, b"", &parts, "[]".as_bytes()).unwrap();
+        let (_, body) = cassette.replay(&Method::GET, URL, b"").unwrap().unwrap();
+        assert_eq!(&body[..], b"[]");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,169 @@
+use std::collections::BTreeSet;
+use std::error;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde_json::Value;
+
+use crate::entity::Entity;
+use crate::format;
+use crate::manifest::{self, FileEntry, MANIFEST_FILE};
+use crate::snapshot;
+
+#[derive(Debug)]
+pub struct VerifyConfig {
+    /// Output directory of a fetch, or the directory of one repository.
+    pub dir: PathBuf,
+}
+
+/// What is wrong with the snapshots of a repository. Problems fail the
+/// verification, warnings such as gaps in the numbers may have innocent
+/// causes like deleted or transferred issues or a limited fetch.
+#[derive(Debug, Default)]
+struct Findings {
+    problems: Vec<String>,
+    warnings: Vec<String>,
+}
+
+/// Checks the snapshots below `cfg.dir` against their manifests, that they
+/// can be read and are consistent, failing if any are not.
+pub fn verify(cfg: VerifyConfig) -> Result<(), Box<dyn error::Error>> {
+    let repos = snapshot::repositories(&cfg.dir)?;
+    if repos.is_empty() {
+        return Err(format!("no snapshots in {}", cfg.dir.display()).into());
+    }
+    let mut failed = 0;
+    for (repo, dir) in &repos {
+        let findings = verify_repository(dir)?;
+        for warning in &findings.warnings {
+            warn!("{}: {}", repo, warning);
+        }
+        for problem in &findings.problems {
+            error!("{}: {}", repo, problem);
+        }
+        if findings.problems.is_empty() {
+            info!("{}: ok", repo);
+        } else {
+            failed += 1;
+        }
+    }
+    if failed > 0 {
+        return Err(format!("{} of {} repositories failed verification", failed, repos.len()).into());
+    }
+    Ok(())
+}
+
+fn verify_repository(dir: &Path) -> Result<Findings, Box<dyn error::Error>> {
+    let mut findings = Findings::default();
+    check_manifest(dir, &mut findings)?;
+
+    let mut numbers = BTreeSet::new();
+    let mut pulls = None;
+    let mut referenced = BTreeSet::new();
+    for entity in Entity::ALL {
+        let (path, format) = match snapshot::find(dir, *entity) {
+            Some(found) => found,
+            None => continue,
+        };
+        let records = match snapshot::records(&path, format, *entity) {
+            Ok(records) => records,
+            Err(e) => {
+                findings.problems.push(format!("{} can't be read: {}", file_name(&path), e));
+                continue;
+            }
+        };
+        let mut entity_numbers = BTreeSet::new();
+        for record in &records {
+            match record.get("number").and_then(Value::as_u64) {
+                Some(number) => if !entity_numbers.insert(number) {
+                    findings.problems.push(format!("{} holds #{} more than once", file_name(&path), number));
+                },
+                None => findings.problems.push(format!("{} holds a record without a number", file_name(&path))),
+            }
+            // a REST issue that is a pull request, e.g. upserted by a webhook
+            if *entity == Entity::Issues && record.get("pull_request").map_or(false, |pr| !pr.is_null()) {
+                referenced.extend(record.get("number").and_then(Value::as_u64));
+            }
+        }
+        if *entity == Entity::Pulls {
+            pulls = Some(entity_numbers.clone());
+        }
+        numbers.extend(entity_numbers);
+    }
+    if let Some((path, format)) = snapshot::find_named(dir, "users") {
+        if let Err(e) = format::deserialize_from_file::<Value>(format, &path) {
+            findings.problems.push(format!("{} can't be read: {}", file_name(&path), e));
+        }
+    }
+
+    if let Some(ref pulls) = pulls {
+        let missing: Vec<u64> = referenced.difference(pulls).cloned().collect();
+        if !missing.is_empty() {
+            findings.problems.push(format!("pull requests referenced by issues are missing: {}", ranges(&missing)));
+        }
+    }
+    // issues and pull requests share one sequence of numbers, gaps only show
+    // once both are there
+    let complete = Entity::ALL.iter().all(|entity| snapshot::find(dir, *entity).is_some());
+    if let (true, Some(&last)) = (complete, numbers.iter().next_back()) {
+        let gaps: Vec<u64> = (1..last).filter(|number| !numbers.contains(number)).collect();
+        if !gaps.is_empty() {
+            findings.warnings.push(format!("{} numbers up to #{} are missing: {}", gaps.len(), last, ranges(&gaps)));
+        }
+    }
+    Ok(findings)
+}
+
+/// Compares the snapshot files with the checksums of the manifest.
+fn check_manifest(dir: &Path, findings: &mut Findings) -> Result<(), Box<dyn error::Error>> {
+    let manifest = match manifest::read(dir) {
+        Ok(Some(manifest)) => manifest,
+        Ok(None) => {
+            findings.warnings.push(format!("no {}, checksums not verified", MANIFEST_FILE));
+            return Ok(());
+        }
+        Err(e) => {
+            findings.problems.push(format!("{} can't be read: {}", MANIFEST_FILE, e));
+            return Ok(());
+        }
+    };
+    for (name, expected) in &manifest.files {
+        let path = dir.join(name);
+        if !path.exists() {
+            findings.problems.push(format!("{} is missing", name));
+        } else if FileEntry::of(&path)? != *expected {
+            findings.problems.push(format!("{} does not match its checksum, it is truncated or corrupted", name));
+        }
+    }
+    for entry in fs::read_dir(dir)? {
+        let name = entry?.file_name().to_string_lossy().into_owned();
+        let snapshot = name.ends_with(".msgpack") || name.ends_with(".ndjson");
+        if snapshot && !manifest.files.contains_key(&name) {
+            findings.warnings.push(format!("{} is not in the manifest", name));
+        }
+    }
+    Ok(())
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name().map_or_else(String::new, |name| name.to_string_lossy().into_owned())
+}
+
+/// Sorted numbers as ranges, e.g. `3-5, 9`.
+fn ranges(numbers: &[u64]) -> String {
+    let mut ranges: Vec<(u64, u64)> = Vec::new();
+    for &number in numbers {
+        if let Some(last) = ranges.last_mut() {
+            if last.1 + 1 == number {
+                last.1 = number;
+                continue;
+            }
+        }
+        ranges.push((number, number));
+    }
+    ranges
+        .iter()
+        .map(|&(start, end)| if start == end { start.to_string() } else { format!("{}-{}", start, end) })
+        .collect::<Vec<_>>()
+        .join(", ")
+}